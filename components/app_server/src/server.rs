@@ -56,6 +56,8 @@ pub struct App<B: Clone> {
     opt_sender: Option<mpsc::Sender<AppServerToApp<B>>>,
     open_route_requests: HashSet<Uid>,
     open_send_funds_requests: HashSet<Uid>,
+    open_first_hop_requests: HashSet<Uid>,
+    open_simulate_payment_requests: HashSet<Uid>,
 }
 
 impl<B> App<B>
@@ -68,6 +70,8 @@ where
             opt_sender: Some(sender),
             open_route_requests: HashSet::new(),
             open_send_funds_requests: HashSet::new(),
+            open_first_hop_requests: HashSet::new(),
+            open_simulate_payment_requests: HashSet::new(),
         }
     }
 
@@ -114,6 +118,13 @@ fn check_permissions<B>(app_permissions: &AppPermissions, app_request: &AppReque
         AppRequest::RequestRoutes(_) => app_permissions.routes,
         AppRequest::AddIndexServer(_) => app_permissions.config,
         AppRequest::RemoveIndexServer(_) => app_permissions.config,
+        AppRequest::SuggestFirstHop(_) => app_permissions.send_funds,
+        AppRequest::SimulatePayment(_) => app_permissions.send_funds,
+        AppRequest::WarmFriend(_) => app_permissions.send_funds,
+        AppRequest::GetRecentReceipts => app_permissions.send_funds,
+        AppRequest::GetPendingRequests(_) => app_permissions.config,
+        AppRequest::GetResetToken(_) => app_permissions.config,
+        AppRequest::RequestRemoveFriend(_) => app_permissions.config,
     }
 }
 
@@ -194,6 +205,20 @@ where
         }
     }
 
+    /// Send a message to all connected apps with the required permissions.
+    /// Used for funder replies that can not be matched to the app that issued the request.
+    async fn broadcast_permitted(
+        &mut self,
+        is_permitted: fn(&AppPermissions) -> bool,
+        message: AppServerToApp<B>,
+    ) {
+        for app in self.apps.values_mut() {
+            if is_permitted(&app.permissions) {
+                await!(app.send(message.clone()));
+            }
+        }
+    }
+
     pub async fn handle_from_funder(
         &mut self,
         funder_message: FunderOutgoingControl<B>,
@@ -213,6 +238,61 @@ where
                     }
                 }
             }
+            FunderOutgoingControl::FirstHopSuggestion(first_hop_suggestion) => {
+                // Find the app that issued the request, and forward the suggestion to this app:
+                for app in self.apps.values_mut() {
+                    if app
+                        .open_first_hop_requests
+                        .remove(&first_hop_suggestion.request_id)
+                    {
+                        await!(app.send(AppServerToApp::FirstHopSuggestion(
+                            first_hop_suggestion.clone()
+                        )));
+                    }
+                }
+            }
+            FunderOutgoingControl::PaymentSimulation(payment_simulation) => {
+                // Find the app that issued the request, and forward the simulation to this app:
+                for app in self.apps.values_mut() {
+                    if app
+                        .open_simulate_payment_requests
+                        .remove(&payment_simulation.request_id)
+                    {
+                        await!(app.send(AppServerToApp::PaymentSimulation(
+                            payment_simulation.clone()
+                        )));
+                    }
+                }
+            }
+            FunderOutgoingControl::PendingRequests(pending_requests) => await!(self
+                .broadcast_permitted(
+                    |permissions| permissions.config,
+                    AppServerToApp::PendingRequests(pending_requests)
+                )),
+            FunderOutgoingControl::FriendWarmed(friend_warmed) => await!(self.broadcast_permitted(
+                |permissions| permissions.send_funds,
+                AppServerToApp::FriendWarmed(friend_warmed)
+            )),
+            FunderOutgoingControl::FundsReceived(funds_received) => await!(self
+                .broadcast_permitted(
+                    |permissions| permissions.send_funds,
+                    AppServerToApp::FundsReceived(funds_received)
+                )),
+            FunderOutgoingControl::RecentReceipts(recent_receipts) => await!(self
+                .broadcast_permitted(
+                    |permissions| permissions.send_funds,
+                    AppServerToApp::RecentReceipts(recent_receipts)
+                )),
+            FunderOutgoingControl::ResetToken(reset_token) => await!(self.broadcast_permitted(
+                |permissions| permissions.config,
+                AppServerToApp::ResetToken(reset_token)
+            )),
+            FunderOutgoingControl::RemoveFriendConsequences(remove_friend_consequences) => {
+                await!(self.broadcast_permitted(
+                    |permissions| permissions.config,
+                    AppServerToApp::RemoveFriendConsequences(remove_friend_consequences)
+                ))
+            }
             FunderOutgoingControl::ReportMutations(funder_report_mutations) => {
                 let mut index_mutations = Vec::new();
                 for funder_report_mutation in &funder_report_mutations.mutations {
@@ -450,6 +530,58 @@ where
                     IndexClientRequest::RemoveIndexServer(index_server_address)
                 ))))
             .map_err(|_| AppServerError::SendToIndexClientError),
+            AppRequest::SuggestFirstHop(suggest_first_hop) => {
+                // Keep track of which application issued this request:
+                app.open_first_hop_requests
+                    .insert(suggest_first_hop.request_id);
+                await!(self.to_funder.send(FunderIncomingControl::new(
+                    app_request_id,
+                    FunderControl::SuggestFirstHop(suggest_first_hop)
+                )))
+                .map_err(|_| AppServerError::SendToFunderError)
+            }
+            AppRequest::SimulatePayment(user_request_send_funds) => {
+                // Keep track of which application issued this request:
+                app.open_simulate_payment_requests
+                    .insert(user_request_send_funds.request_id);
+                await!(self.to_funder.send(FunderIncomingControl::new(
+                    app_request_id,
+                    FunderControl::SimulatePayment(user_request_send_funds)
+                )))
+                .map_err(|_| AppServerError::SendToFunderError)
+            }
+            AppRequest::WarmFriend(friend_public_key) => {
+                await!(self.to_funder.send(FunderIncomingControl::new(
+                    app_request_id,
+                    FunderControl::WarmFriend(friend_public_key)
+                )))
+                .map_err(|_| AppServerError::SendToFunderError)
+            }
+            AppRequest::GetRecentReceipts => await!(self.to_funder.send(
+                FunderIncomingControl::new(app_request_id, FunderControl::GetRecentReceipts)
+            ))
+            .map_err(|_| AppServerError::SendToFunderError),
+            AppRequest::GetPendingRequests(friend_public_key) => {
+                await!(self.to_funder.send(FunderIncomingControl::new(
+                    app_request_id,
+                    FunderControl::GetPendingRequests(friend_public_key)
+                )))
+                .map_err(|_| AppServerError::SendToFunderError)
+            }
+            AppRequest::GetResetToken(friend_public_key) => {
+                await!(self.to_funder.send(FunderIncomingControl::new(
+                    app_request_id,
+                    FunderControl::GetResetToken(friend_public_key)
+                )))
+                .map_err(|_| AppServerError::SendToFunderError)
+            }
+            AppRequest::RequestRemoveFriend(friend_public_key) => {
+                await!(self.to_funder.send(FunderIncomingControl::new(
+                    app_request_id,
+                    FunderControl::RequestRemoveFriend(friend_public_key)
+                )))
+                .map_err(|_| AppServerError::SendToFunderError)
+            }
         }
     }
 
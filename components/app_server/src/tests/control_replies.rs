@@ -0,0 +1,167 @@
+use futures::channel::mpsc;
+use futures::executor::ThreadPool;
+use futures::task::Spawn;
+use futures::{SinkExt, StreamExt};
+
+use crypto::identity::{PublicKey, Signature, PUBLIC_KEY_LEN, SIGNATURE_LEN};
+use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::app_server::messages::{AppPermissions, AppRequest, AppServerToApp, AppToAppServer};
+use proto::funder::messages::{
+    FirstHopSuggestion, FriendResetToken, FriendWarmed, FunderControl, FunderOutgoingControl,
+    FundsReceived, SuggestFirstHop,
+};
+
+use super::utils::spawn_dummy_app_server;
+
+async fn task_app_server_loop_control_replies<S>(spawner: S)
+where
+    S: Spawn + Clone + Send + 'static,
+{
+    let (
+        mut funder_sender,
+        mut funder_receiver,
+        _index_client_sender,
+        _index_client_receiver,
+        mut connections_sender,
+        _initial_node_report,
+    ) = spawn_dummy_app_server(spawner.clone());
+
+    // Connect two apps. app0 may only send funds, app1 may only configure:
+    let (mut app_sender0, app_server_receiver) = mpsc::channel(0);
+    let (app_server_sender, mut app_receiver0) = mpsc::channel(0);
+    let app_server_conn_pair = (app_server_sender, app_server_receiver);
+    let app_permissions = AppPermissions {
+        routes: false,
+        send_funds: true,
+        config: false,
+    };
+    await!(connections_sender.send((app_permissions, app_server_conn_pair))).unwrap();
+
+    let (mut app_sender1, app_server_receiver) = mpsc::channel(0);
+    let (app_server_sender, mut app_receiver1) = mpsc::channel(0);
+    let app_server_conn_pair = (app_server_sender, app_server_receiver);
+    let app_permissions = AppPermissions {
+        routes: false,
+        send_funds: false,
+        config: true,
+    };
+    await!(connections_sender.send((app_permissions, app_server_conn_pair))).unwrap();
+
+    // The apps should receive the current node report as the first message:
+    let _to_app_message = await!(app_receiver0.next()).unwrap();
+    let _to_app_message = await!(app_receiver1.next()).unwrap();
+
+    let pk_e = PublicKey::from(&[0xee; PUBLIC_KEY_LEN]);
+    let pk_f = PublicKey::from(&[0xff; PUBLIC_KEY_LEN]);
+
+    // app0 asks for a first hop suggestion:
+    let suggest_first_hop = SuggestFirstHop {
+        request_id: Uid::from(&[3; UID_LEN]),
+        dest_public_key: pk_f.clone(),
+        dest_payment: 20,
+    };
+    let to_app_server = AppToAppServer::new(
+        Uid::from(&[22; UID_LEN]),
+        AppRequest::SuggestFirstHop(suggest_first_hop.clone()),
+    );
+    await!(app_sender0.send(to_app_server)).unwrap();
+
+    let funder_incoming_control = await!(funder_receiver.next()).unwrap();
+    assert_eq!(
+        funder_incoming_control.app_request_id,
+        Uid::from(&[22; UID_LEN])
+    );
+    assert_eq!(
+        funder_incoming_control.funder_control,
+        FunderControl::SuggestFirstHop(suggest_first_hop.clone())
+    );
+
+    // app1 is not allowed to ask for a first hop suggestion. The request is discarded:
+    let to_app_server = AppToAppServer::new(
+        Uid::from(&[23; UID_LEN]),
+        AppRequest::SuggestFirstHop(suggest_first_hop),
+    );
+    await!(app_sender1.send(to_app_server)).unwrap();
+
+    // app1 is allowed to ask for a reset token:
+    let to_app_server = AppToAppServer::new(
+        Uid::from(&[24; UID_LEN]),
+        AppRequest::GetResetToken(pk_e.clone()),
+    );
+    await!(app_sender1.send(to_app_server)).unwrap();
+
+    let funder_incoming_control = await!(funder_receiver.next()).unwrap();
+    assert_eq!(
+        funder_incoming_control.app_request_id,
+        Uid::from(&[24; UID_LEN])
+    );
+    assert_eq!(
+        funder_incoming_control.funder_control,
+        FunderControl::GetResetToken(pk_e.clone())
+    );
+
+    // The suggestion is sent only to the app that asked for it:
+    let first_hop_suggestion = FirstHopSuggestion {
+        request_id: Uid::from(&[3; UID_LEN]),
+        opt_friend_public_key: Some(pk_e.clone()),
+    };
+    let funder_message = FunderOutgoingControl::FirstHopSuggestion(first_hop_suggestion.clone());
+    await!(funder_sender.send(funder_message)).unwrap();
+    assert_eq!(
+        await!(app_receiver0.next()).unwrap(),
+        AppServerToApp::FirstHopSuggestion(first_hop_suggestion.clone())
+    );
+
+    // Incoming funds are sent to apps that may send funds:
+    let funds_received = FundsReceived {
+        request_id: Uid::from(&[4; UID_LEN]),
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        dest_payment: 30,
+        memo: b"Invoice 1".to_vec(),
+    };
+    let funder_message = FunderOutgoingControl::FundsReceived(funds_received.clone());
+    await!(funder_sender.send(funder_message)).unwrap();
+    assert_eq!(
+        await!(app_receiver0.next()).unwrap(),
+        AppServerToApp::FundsReceived(funds_received)
+    );
+
+    // Reset tokens are sent to apps that may configure friends.
+    // app1 did not get any of the previous messages:
+    let friend_reset_token = FriendResetToken {
+        friend_public_key: pk_e.clone(),
+        reset_token: Signature::from(&[5; SIGNATURE_LEN]),
+        balance_for_reset: -10,
+    };
+    let funder_message = FunderOutgoingControl::ResetToken(friend_reset_token.clone());
+    await!(funder_sender.send(funder_message)).unwrap();
+    assert_eq!(
+        await!(app_receiver1.next()).unwrap(),
+        AppServerToApp::ResetToken(friend_reset_token)
+    );
+
+    // The same suggestion again does not correspond to any open request, and is discarded:
+    let funder_message = FunderOutgoingControl::FirstHopSuggestion(first_hop_suggestion);
+    await!(funder_sender.send(funder_message)).unwrap();
+
+    // Friend warmed messages are sent to apps that may send funds.
+    // app0 did not get the reset token, nor the second suggestion:
+    let friend_warmed = FriendWarmed {
+        friend_public_key: pk_e.clone(),
+        is_ready: true,
+    };
+    let funder_message = FunderOutgoingControl::FriendWarmed(friend_warmed.clone());
+    await!(funder_sender.send(funder_message)).unwrap();
+    assert_eq!(
+        await!(app_receiver0.next()).unwrap(),
+        AppServerToApp::FriendWarmed(friend_warmed)
+    );
+}
+
+#[test]
+fn test_app_server_loop_control_replies() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_app_server_loop_control_replies(thread_pool.clone()));
+}
@@ -1,4 +1,5 @@
 mod all_apps_closed;
+mod control_replies;
 mod funder_command;
 mod index_client_command;
 mod request_routes;
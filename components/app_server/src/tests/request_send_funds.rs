@@ -63,6 +63,8 @@ where
         },
         invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
         dest_payment: 20,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
     };
 
     let to_app_server = AppToAppServer::new(
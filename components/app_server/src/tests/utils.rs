@@ -64,6 +64,8 @@ where
             .collect(),
         friends: ImHashMap::new(),
         num_ready_receipts: 0,
+        total_credit_extended: 0,
+        total_credit_received: 0,
     };
 
     let server100 = NamedIndexServerAddress {
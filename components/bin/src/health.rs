@@ -0,0 +1,180 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::channel::oneshot;
+use futures::task::{Spawn, SpawnExt};
+use futures::{SinkExt, StreamExt};
+
+use common::conn::Listener;
+
+use net::TcpListener;
+use timer::TimerClient;
+
+/// Maximum length of a frame sent or received by the health endpoint.
+const HEALTH_MAX_FRAME_LENGTH: usize = 0x20;
+
+const HEALTHY_RESPONSE: &[u8] = b"OK";
+const UNHEALTHY_RESPONSE: &[u8] = b"UNAVAILABLE";
+
+#[derive(Debug)]
+pub enum HealthServerError {
+    SpawnError,
+}
+
+/// Serve a minimal health check endpoint on `health_addr`.
+/// Every incoming connection receives a single frame and is then closed.
+/// The frame is `OK` as long as the server loop is running and the last timer tick was received
+/// no more than `max_tick_silence` ago, and `UNAVAILABLE` otherwise.
+///
+/// The server loop is considered running until the sender side of `server_closed_receiver` is
+/// used or dropped.
+pub fn spawn_health_server<S>(
+    health_addr: SocketAddr,
+    mut timer_client: TimerClient,
+    max_tick_silence: Duration,
+    mut server_closed_receiver: oneshot::Receiver<()>,
+    mut spawner: S,
+) -> Result<(), HealthServerError>
+where
+    S: Spawn + Clone + Send + 'static,
+{
+    let tcp_listener = TcpListener::new(HEALTH_MAX_FRAME_LENGTH, spawner.clone());
+    let (_config_sender, mut incoming_conns) = tcp_listener.listen(health_addr);
+
+    let last_tick = Arc::new(Mutex::new(Instant::now()));
+
+    // Keep track of the last received timer tick:
+    let c_last_tick = last_tick.clone();
+    spawner
+        .spawn(
+            async move {
+                let mut timer_stream = match await!(timer_client.request_timer_stream()) {
+                    Ok(timer_stream) => timer_stream,
+                    Err(e) => {
+                        error!("Health endpoint: request_timer_stream() error: {:?}", e);
+                        return;
+                    }
+                };
+                while let Some(_) = await!(timer_stream.next()) {
+                    *c_last_tick.lock().unwrap() = Instant::now();
+                }
+            },
+        )
+        .map_err(|_| HealthServerError::SpawnError)?;
+
+    let mut c_spawner = spawner.clone();
+    spawner
+        .spawn(
+            async move {
+                while let Some((mut sender, _receiver)) = await!(incoming_conns.next()) {
+                    let is_server_running = server_closed_receiver.try_recv() == Ok(None);
+                    let is_timer_ticking = last_tick.lock().unwrap().elapsed() <= max_tick_silence;
+                    let response = if is_server_running && is_timer_ticking {
+                        HEALTHY_RESPONSE
+                    } else {
+                        UNHEALTHY_RESPONSE
+                    };
+                    // The connection is closed when the sender is dropped:
+                    let send_fut = async move {
+                        let _ = await!(sender.send(response.to_vec()));
+                    };
+                    if c_spawner.spawn(send_fut).is_err() {
+                        error!("Health endpoint: Failed to spawn response");
+                        return;
+                    }
+                }
+            },
+        )
+        .map_err(|_| HealthServerError::SpawnError)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::TryInto;
+    use std::net::{IpAddr, Ipv4Addr, TcpListener as StdTcpListener};
+
+    use futures::channel::mpsc;
+    use futures::executor::ThreadPool;
+
+    use common::conn::FutTransform;
+    use net::NetConnector;
+    use proto::net::messages::NetAddress;
+
+    use timer::create_timer_incoming;
+
+    /// Get an available port we can listen on
+    fn get_available_port_v4() -> u16 {
+        // Assigning port 0 requests the OS to assign a free port
+        let loopback = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0);
+        let listener = StdTcpListener::bind(&loopback).unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    async fn health_request<S>(health_addr: SocketAddr, spawner: S) -> Vec<u8>
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let mut net_connector =
+            NetConnector::new(HEALTH_MAX_FRAME_LENGTH, spawner.clone(), spawner);
+        let net_address: NetAddress = health_addr.to_string().try_into().unwrap();
+        let (_sender, mut receiver) = await!(net_connector.transform(net_address)).unwrap();
+        let response = await!(receiver.next()).unwrap();
+        // The connection is closed after the response:
+        assert!(await!(receiver.next()).is_none());
+        response
+    }
+
+    async fn task_health_server<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let (_tick_sender, tick_receiver) = mpsc::channel::<()>(0);
+        let timer_client = create_timer_incoming(tick_receiver, spawner.clone()).unwrap();
+
+        let loopback = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+
+        // The server loop is running:
+        let health_addr = SocketAddr::new(loopback, get_available_port_v4());
+        let (server_closed_sender, server_closed_receiver) = oneshot::channel();
+        spawn_health_server(
+            health_addr,
+            timer_client.clone(),
+            Duration::from_secs(3600),
+            server_closed_receiver,
+            spawner.clone(),
+        )
+        .unwrap();
+        let response = await!(health_request(health_addr, spawner.clone()));
+        assert_eq!(response, HEALTHY_RESPONSE);
+
+        // The server loop has exited:
+        drop(server_closed_sender);
+        let response = await!(health_request(health_addr, spawner.clone()));
+        assert_eq!(response, UNHEALTHY_RESPONSE);
+
+        // No timer ticks are allowed to be missed, hence the timer is considered stalled:
+        let health_addr = SocketAddr::new(loopback, get_available_port_v4());
+        let (_server_closed_sender, server_closed_receiver) = oneshot::channel();
+        spawn_health_server(
+            health_addr,
+            timer_client,
+            Duration::new(0, 0),
+            server_closed_receiver,
+            spawner.clone(),
+        )
+        .unwrap();
+        let response = await!(health_request(health_addr, spawner.clone()));
+        assert_eq!(response, UNHEALTHY_RESPONSE);
+    }
+
+    #[test]
+    fn test_health_server() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_health_server(thread_pool.clone()));
+    }
+}
@@ -12,6 +12,11 @@
     clippy::new_without_default
 )]
 
+#[macro_use]
+extern crate log;
+
+mod health;
+
 pub mod stindexlib;
 pub mod stmgrlib;
 pub mod stnodelib;
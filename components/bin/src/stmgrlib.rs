@@ -11,7 +11,7 @@ use proto::index_server::messages::IndexServerAddress;
 use proto::net::messages::{NetAddress, NetAddressError};
 use proto::node::types::NodeAddress;
 
-use database::file_db::FileDb;
+use database::file_db::{FileDb, FileDbFormat};
 use node::NodeState;
 
 use proto::file::app::{store_trusted_app_to_file, TrustedApp};
@@ -35,6 +35,9 @@ pub struct InitNodeDbCmd {
     /// Database output file path
     #[structopt(parse(from_os_str), short = "o", long = "output")]
     pub output: PathBuf,
+    /// Store the database in a human readable (json) format, useful for debugging
+    #[structopt(long = "json")]
+    pub json: bool,
 }
 
 #[derive(Debug, StructOpt)]
@@ -127,7 +130,13 @@ pub enum StMgrCmd {
     NodeTicket(NodeTicketCmd),
 }
 
-fn init_node_db(InitNodeDbCmd { idfile, output }: InitNodeDbCmd) -> Result<(), InitNodeDbError> {
+fn init_node_db(
+    InitNodeDbCmd {
+        idfile,
+        output,
+        json,
+    }: InitNodeDbCmd,
+) -> Result<(), InitNodeDbError> {
     // Make sure that output does not exist.
     // This program should never override any file!
     // (Otherwise users might erase their database by
@@ -143,7 +152,13 @@ fn init_node_db(InitNodeDbCmd { idfile, output }: InitNodeDbCmd) -> Result<(), I
 
     // Create a new database file:
     let initial_state = NodeState::<NetAddress>::new(local_public_key);
-    let _ = FileDb::create(output, initial_state).map_err(|_| InitNodeDbError::FileDbError)?;
+    let format = if json {
+        FileDbFormat::Json
+    } else {
+        FileDbFormat::Bincode
+    };
+    let _ = FileDb::create_with_format(output, initial_state, format)
+        .map_err(|_| InitNodeDbError::FileDbError)?;
 
     Ok(())
 }
@@ -17,14 +17,14 @@ use crypto::crypto_rand::system_random;
 use identity::{create_identity, IdentityClient};
 use timer::create_timer;
 
-use node::{net_node, NetNodeError, NodeConfig, NodeState};
+use node::{net_node, ControlStats, NetNodeError, NodeConfig, NodeState};
 
 use database::file_db::FileDb;
 
 use net::{NetConnector, TcpListener};
 use proto::consts::{
-    KEEPALIVE_TICKS, MAX_FRAME_LENGTH, MAX_NODE_RELAYS, MAX_OPERATIONS_IN_BATCH, TICKS_TO_REKEY,
-    TICK_MS,
+    KEEPALIVE_TICKS, MAX_FRAME_LENGTH, MAX_NODE_RELAYS, MAX_OPERATIONS_IN_BATCH, MAX_SEND_FRIENDS,
+    TICKS_TO_REKEY, TICK_MS,
 };
 use proto::net::messages::NetAddress;
 
@@ -40,6 +40,14 @@ const BACKOFF_TICKS: usize = 0x8;
 const MAX_CONCURRENT_ENCRYPT: usize = 0x8;
 /// The size we allocate for the user send funds requests queue.
 const MAX_PENDING_USER_REQUESTS: usize = 0x20;
+/// Maximum amount of requests we queue for a friend while waiting to forward them.
+const MAX_PENDING_REQUESTS: usize = 0x40;
+/// Log and count duplicate move tokens received from friends
+const MONITOR_DUPLICATE_MOVE_TOKENS: bool = false;
+/// Reject payment requests that reuse an invoice id of an in flight request
+const REJECT_DUPLICATE_INVOICE_ID: bool = false;
+/// Amount of acked receipts we keep, so that they can be retrieved again
+const MAX_RECENT_RECEIPTS: usize = 0x20;
 /// Maximum amount of concurrent index client requests:
 const MAX_OPEN_INDEX_CLIENT_REQUESTS: usize = 0x8;
 /// The amount of ticks we are willing to wait until a connection is established (Through
@@ -123,18 +131,30 @@ pub fn stnode(st_node_cmd: StNodeCmd) -> Result<(), NodeBinError> {
         backoff_ticks: BACKOFF_TICKS,
         /// The amount of ticks we wait until we decide an idle connection has timed out.
         keepalive_ticks: KEEPALIVE_TICKS,
+        /// The amount of ticks a friend connection may stay idle before we consider the friend
+        /// offline.
+        liveness_ticks: KEEPALIVE_TICKS,
         /// Amount of ticks to wait until the next rekeying (Channel encryption)
         ticks_to_rekey: TICKS_TO_REKEY,
         /// Maximum amount of encryption set ups (diffie hellman) that we allow to occur at the same
         /// time.
         max_concurrent_encrypt: MAX_CONCURRENT_ENCRYPT,
+        opt_max_concurrent_encrypt_per_relay: None,
         /// The amount of ticks we are willing to wait until a connection is established (Through
         /// the relay)
         conn_timeout_ticks: CONN_TIMEOUT_TICKS,
         /// Maximum amount of operations in one move token message
         max_operations_in_batch: MAX_OPERATIONS_IN_BATCH,
+        /// Maximum amount of friends we send messages to while handling a single event
+        max_send_friends: MAX_SEND_FRIENDS,
         /// The size we allocate for the user send funds requests queue.
         max_pending_user_requests: MAX_PENDING_USER_REQUESTS,
+        max_pending_requests: MAX_PENDING_REQUESTS,
+        monitor_duplicate_move_tokens: MONITOR_DUPLICATE_MOVE_TOKENS,
+        /// Reject payment requests that reuse an invoice id of an in flight request
+        reject_duplicate_invoice_id: REJECT_DUPLICATE_INVOICE_ID,
+        /// Amount of acked receipts we keep, so that they can be retrieved again
+        max_recent_receipts: MAX_RECENT_RECEIPTS,
         /// Maximum amount of concurrent index client requests:
         max_open_index_client_requests: MAX_OPEN_INDEX_CLIENT_REQUESTS,
         /// Maximum amount of relays a node may use.
@@ -178,6 +198,9 @@ pub fn stnode(st_node_cmd: StNodeCmd) -> Result<(), NodeBinError> {
         node_config,
         get_trusted_apps,
         atomic_db,
+        ControlStats::new(),
+        None,
+        None,
         file_system_thread_pool.clone(),
         file_system_thread_pool.clone(),
         thread_pool.clone(),
@@ -2,6 +2,7 @@ use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::time::Duration;
 
+use futures::channel::oneshot;
 use futures::executor::ThreadPool;
 use futures::task::SpawnExt;
 
@@ -17,16 +18,22 @@ use proto::consts::{MAX_FRAME_LENGTH, TICK_MS};
 use common::int_convert::usize_to_u64;
 
 use net::TcpListener;
-use relay::{net_relay_server, NetRelayServerError};
+use relay::{net_relay_server, FrameLimits, NetRelayServerError};
 use timer::create_timer;
 
 use proto::file::identity::load_identity_from_file;
 
+use crate::health::{spawn_health_server, HealthServerError};
+
 // TODO; Maybe take as a command line argument in the future?
 /// Maximum amount of concurrent encrypted channel set-ups.
 /// We set this number to avoid DoS from half finished encrypted channel negotiations.
 pub const MAX_CONCURRENT_ENCRYPT: usize = 0x200;
 
+/// Amount of timer ticks that may pass without receiving a tick before the
+/// health endpoint reports the relay as unhealthy.
+const HEALTH_MAX_SILENT_TICKS: u32 = 8;
+
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum RelayServerBinError {
@@ -34,6 +41,7 @@ pub enum RelayServerBinError {
     LoadIdentityError,
     CreateIdentityError,
     CreateTimerError,
+    HealthServerError(HealthServerError),
     NetRelayServerError(NetRelayServerError),
 }
 
@@ -48,10 +56,37 @@ pub struct StRelayCmd {
     /// Listening address (Example: 0.0.0.0:1337)
     #[structopt(short = "l", long = "laddr")]
     pub laddr: SocketAddr,
+    /// Health check endpoint listening address (Example: 127.0.0.1:8080)
+    #[structopt(long = "health-addr")]
+    pub opt_health_addr: Option<SocketAddr>,
+    /// Maximum amount of pending incoming connections (Uses the system default if not
+    /// specified)
+    #[structopt(long = "backlog")]
+    pub opt_backlog: Option<i32>,
+    /// Maximum amount of bytes per timer tick passing through a single tunnel, in each
+    /// direction (Unlimited if not specified)
+    #[structopt(long = "tunnel-rate")]
+    pub opt_max_tunnel_bytes_per_tick: Option<usize>,
+    /// Maximum length of a single control message sent by a listening client (Uses the maximum
+    /// frame length if not specified, and can not exceed it)
+    #[structopt(long = "max-listen-frame")]
+    pub opt_max_listen_frame_length: Option<usize>,
+    /// Maximum length of a single message passing through a tunnel (Uses the maximum frame
+    /// length if not specified, and can not exceed it)
+    #[structopt(long = "max-tunnel-frame")]
+    pub opt_max_tunnel_frame_length: Option<usize>,
 }
 
 pub fn strelay(st_relay_cmd: StRelayCmd) -> Result<(), RelayServerBinError> {
-    let StRelayCmd { idfile, laddr } = st_relay_cmd;
+    let StRelayCmd {
+        idfile,
+        laddr,
+        opt_health_addr,
+        opt_backlog,
+        opt_max_tunnel_bytes_per_tick,
+        opt_max_listen_frame_length,
+        opt_max_tunnel_frame_length,
+    } = st_relay_cmd;
 
     // Parse identity file:
     let identity =
@@ -72,9 +107,30 @@ pub fn strelay(st_relay_cmd: StRelayCmd) -> Result<(), RelayServerBinError> {
     let timer_client = create_timer(dur, thread_pool.clone())
         .map_err(|_| RelayServerBinError::CreateTimerError)?;
 
+    // The health endpoint reports the relay as unhealthy once the relay server loop exits:
+    let (server_closed_sender, server_closed_receiver) = oneshot::channel::<()>();
+    if let Some(health_addr) = opt_health_addr {
+        spawn_health_server(
+            health_addr,
+            timer_client.clone(),
+            dur * HEALTH_MAX_SILENT_TICKS,
+            server_closed_receiver,
+            thread_pool.clone(),
+        )
+        .map_err(RelayServerBinError::HealthServerError)?;
+    }
+
+    let frame_limits = FrameLimits {
+        max_listen_frame_length: opt_max_listen_frame_length.unwrap_or(MAX_FRAME_LENGTH),
+        max_tunnel_frame_length: opt_max_tunnel_frame_length.unwrap_or(MAX_FRAME_LENGTH),
+    };
+
     let rng = system_random();
 
-    let tcp_listener = TcpListener::new(MAX_FRAME_LENGTH, thread_pool.clone());
+    let tcp_listener = match opt_backlog {
+        Some(backlog) => TcpListener::with_backlog(MAX_FRAME_LENGTH, backlog, thread_pool.clone()),
+        None => TcpListener::new(MAX_FRAME_LENGTH, thread_pool.clone()),
+    };
     let (_config_sender, incoming_raw_conns) = tcp_listener.listen(laddr);
 
     let relay_server_fut = net_relay_server(
@@ -83,10 +139,47 @@ pub fn strelay(st_relay_cmd: StRelayCmd) -> Result<(), RelayServerBinError> {
         timer_client,
         rng,
         MAX_CONCURRENT_ENCRYPT,
+        opt_max_tunnel_bytes_per_tick,
+        frame_limits,
         thread_pool.clone(),
     );
 
+    let relay_server_fut = async move {
+        let res = await!(relay_server_fut);
+        drop(server_closed_sender);
+        res
+    };
+
     thread_pool
         .run(relay_server_fut)
         .map_err(RelayServerBinError::NetRelayServerError)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strelay_cmd_backlog() {
+        let st_relay_cmd = StRelayCmd::from_iter(&[
+            "strelay",
+            "--idfile",
+            "relay.ident",
+            "--laddr",
+            "127.0.0.1:1337",
+            "--backlog",
+            "4096",
+        ]);
+        assert_eq!(st_relay_cmd.opt_backlog, Some(4096));
+
+        // The backlog is optional:
+        let st_relay_cmd = StRelayCmd::from_iter(&[
+            "strelay",
+            "--idfile",
+            "relay.ident",
+            "--laddr",
+            "127.0.0.1:1337",
+        ]);
+        assert_eq!(st_relay_cmd.opt_backlog, None);
+    }
+}
@@ -10,7 +10,9 @@ use futures::{future, select, stream, FutureExt, Sink, SinkExt, Stream, StreamEx
 use common::conn::{FutTransform, Listener};
 use common::select_streams::{select_streams, BoxStream};
 use crypto::identity::{compare_public_key, PublicKey};
-use proto::funder::messages::{ChannelerToFunder, ChannelerUpdateFriend, FunderToChanneler};
+use proto::funder::messages::{
+    ChannelerRelaysChanged, ChannelerToFunder, ChannelerUpdateFriend, FunderToChanneler,
+};
 
 use crate::connect_pool::{ConnectPoolControl, CpConfigClient, CpConnectClient};
 use crate::listen_pool::LpConfig;
@@ -103,6 +105,8 @@ struct Friends<RA> {
     in_friends: HashMap<PublicKey, InFriend>,
     /// Friends that wait for our connection:
     out_friends: HashMap<PublicKey, OutFriend<RA>>,
+    /// Last known relays used to reach each friend:
+    relays: HashMap<PublicKey, Vec<RA>>,
 }
 
 impl<RA> Friends<RA> {
@@ -110,6 +114,7 @@ impl<RA> Friends<RA> {
         Friends {
             in_friends: HashMap::new(),
             out_friends: HashMap::new(),
+            relays: HashMap::new(),
         }
     }
 
@@ -147,14 +152,14 @@ struct Channeler<RA, C, S, TF> {
 
 impl<RA, C, S, TF> Channeler<RA, C, S, TF>
 where
-    RA: Clone + Send + Sync + 'static,
+    RA: Clone + PartialEq + Send + Sync + 'static,
     C: FutTransform<Input = PublicKey, Output = ConnectPoolControl<RA>>
         + Clone
         + Send
         + Sync
         + 'static,
     S: Spawn + Clone + Send + Sync + 'static,
-    TF: Sink<SinkItem = ChannelerToFunder> + Send + Unpin,
+    TF: Sink<SinkItem = ChannelerToFunder<RA>> + Send + Unpin,
 {
     fn new(
         local_public_key: PublicKey,
@@ -245,6 +250,23 @@ where
         Ok(())
     }
 
+    /// Disconnect from a friend
+    async fn remove_friend<'a>(
+        &'a mut self,
+        friend_public_key: &'a PublicKey,
+    ) -> Result<(), ChannelerError> {
+        if self.friends.in_friends.remove(friend_public_key).is_some() {
+            let lp_config = LpConfig::RemoveFriend(friend_public_key.clone());
+            await!(self.listen_config.send(lp_config))
+                .map_err(|_| ChannelerError::ListenerConfigError)?;
+            return Ok(());
+        }
+
+        self.friends.out_friends.remove(friend_public_key);
+
+        Ok(())
+    }
+
     async fn handle_from_funder(
         &mut self,
         funder_to_channeler: FunderToChanneler<RA>,
@@ -285,31 +307,54 @@ where
 
                 await!(self.try_create_friend(&friend_public_key))?;
 
-                if let Some(_in_friend) = self.friends.in_friends.get(&friend_public_key) {
+                let new_relays = if let Some(_in_friend) =
+                    self.friends.in_friends.get(&friend_public_key)
+                {
                     let lp_config =
-                        LpConfig::UpdateFriend((friend_public_key.clone(), local_relays));
+                        LpConfig::UpdateFriend((friend_public_key.clone(), local_relays.clone()));
                     await!(self.listen_config.send(lp_config))
                         .map_err(|_| ChannelerError::ListenerConfigError)?;
+                    local_relays
                 } else if let Some(out_friend) =
                     self.friends.out_friends.get_mut(&friend_public_key)
                 {
-                    await!(out_friend.config_client.config(friend_relays))
+                    await!(out_friend.config_client.config(friend_relays.clone()))
                         .map_err(|_| ChannelerError::ConnectorConfigError)?;
+                    friend_relays
+                } else {
+                    return Ok(());
+                };
+
+                let opt_old_relays = self
+                    .friends
+                    .relays
+                    .insert(friend_public_key.clone(), new_relays.clone());
+
+                // Notify Funder if the relays of an existing friend have changed:
+                if let Some(old_relays) = opt_old_relays {
+                    if old_relays != new_relays {
+                        let relays_changed = ChannelerRelaysChanged {
+                            friend_public_key,
+                            old_relays,
+                            new_relays,
+                        };
+                        await!(self
+                            .to_funder
+                            .send(ChannelerToFunder::RelaysChanged(relays_changed)))
+                        .map_err(|_| ChannelerError::SendToFunderFailed)?;
+                    }
                 }
 
                 Ok(())
             }
             FunderToChanneler::RemoveFriend(friend_public_key) => {
-                if self.friends.in_friends.remove(&friend_public_key).is_some() {
-                    let lp_config = LpConfig::RemoveFriend(friend_public_key.clone());
-                    await!(self.listen_config.send(lp_config))
-                        .map_err(|_| ChannelerError::ListenerConfigError)?;
-                    return Ok(());
-                }
-
-                self.friends.out_friends.remove(&friend_public_key);
-
-                Ok(())
+                // The friend might be enabled again, so we keep its last known relays.
+                await!(self.remove_friend(&friend_public_key))
+            }
+            FunderToChanneler::PurgeFriend(friend_public_key) => {
+                // The friend is gone for good, we forget everything we know about it:
+                self.friends.relays.remove(&friend_public_key);
+                await!(self.remove_friend(&friend_public_key))
             }
         }
     }
@@ -457,8 +502,8 @@ pub async fn channeler_loop<FF, TF, RA, C, L, S>(
 ) -> Result<(), ChannelerError>
 where
     FF: Stream<Item = FunderToChanneler<RA>> + Send + Unpin,
-    TF: Sink<SinkItem = ChannelerToFunder> + Send + Unpin,
-    RA: Clone + Send + Sync + Debug + 'static,
+    TF: Sink<SinkItem = ChannelerToFunder<RA>> + Send + Unpin,
+    RA: Clone + PartialEq + Send + Sync + Debug + 'static,
     C: FutTransform<Input = PublicKey, Output = ConnectPoolControl<RA>>
         + Clone
         + Send
@@ -777,6 +822,32 @@ mod tests {
             LpConfig::UpdateFriend((pks[2].clone(), vec![0x2u32, 0x3u32]))
         );
 
+        // Change the relays used for the friend:
+        let channeler_update_friend = ChannelerUpdateFriend {
+            friend_public_key: pks[2].clone(),
+            friend_relays: vec![0x0u32],
+            local_relays: vec![0x4u32],
+        };
+        await!(funder_sender.send(FunderToChanneler::UpdateFriend(channeler_update_friend)))
+            .unwrap();
+
+        let lp_config = await!(listener_request.config_receiver.next()).unwrap();
+        assert_eq!(
+            lp_config,
+            LpConfig::UpdateFriend((pks[2].clone(), vec![0x4u32]))
+        );
+
+        // Funder should be notified about the relays change:
+        let channeler_to_funder = await!(funder_receiver.next()).unwrap();
+        match channeler_to_funder {
+            ChannelerToFunder::RelaysChanged(relays_changed) => {
+                assert_eq!(relays_changed.friend_public_key, pks[2]);
+                assert_eq!(relays_changed.old_relays, vec![0x2u32, 0x3u32]);
+                assert_eq!(relays_changed.new_relays, vec![0x4u32]);
+            }
+            _ => unreachable!(),
+        };
+
         // Set up connection, exchange messages and close the connection a few times:
         for _ in 0..3 {
             // The channeler now listens. It waits for an incoming connection from pks[2]
@@ -63,13 +63,16 @@ pub enum ConnectPoolError {
     MultipleConnectRequests,
 }
 
+/// Identifies a single connection attempt.
+type AttemptId = u64;
+
 #[derive(Debug)]
 enum CpEvent<RA> {
     ConnectRequest(CpConnectRequest),
     ConnectRequestClosed,
     ConfigRequest(Vec<RA>),
     ConfigRequestClosed,
-    ConnectAttemptDone(Option<RawConn>),
+    ConnectAttemptDone((AttemptId, Option<RawConn>)),
     TimerTick,
     TimerClosed,
 }
@@ -77,15 +80,27 @@ enum CpEvent<RA> {
 enum CpStatus<RA> {
     NoRequest,
     Waiting((usize, oneshot::Sender<RawConn>)),
-    Connecting((RA, oneshot::Sender<()>, oneshot::Sender<RawConn>)),
+    /// (attempt_id, address, remaining_ticks, canceler, response_sender)
+    Connecting(
+        (
+            AttemptId,
+            RA,
+            usize,
+            oneshot::Sender<()>,
+            oneshot::Sender<RawConn>,
+        ),
+    ),
 }
 
 struct ConnectPool<RA, C, ET, S> {
     friend_public_key: PublicKey,
     addresses: VecDeque<RA>,
     status: CpStatus<RA>,
-    conn_done_sender: mpsc::Sender<Option<RawConn>>,
+    conn_done_sender: mpsc::Sender<(AttemptId, Option<RawConn>)>,
+    /// Id of the next connection attempt
+    next_attempt_id: AttemptId,
     backoff_ticks: usize,
+    conn_timeout_ticks: usize,
     client_connector: C,
     encrypt_transform: ET,
     spawner: S,
@@ -131,8 +146,9 @@ where
 {
     pub fn new(
         friend_public_key: PublicKey,
-        conn_done_sender: mpsc::Sender<Option<RawConn>>,
+        conn_done_sender: mpsc::Sender<(AttemptId, Option<RawConn>)>,
         backoff_ticks: usize,
+        conn_timeout_ticks: usize,
         client_connector: C,
         encrypt_transform: ET,
         spawner: S,
@@ -142,7 +158,9 @@ where
             addresses: VecDeque::new(),
             status: CpStatus::NoRequest,
             conn_done_sender,
+            next_attempt_id: 0,
             backoff_ticks,
+            conn_timeout_ticks,
             client_connector,
             encrypt_transform,
             spawner,
@@ -150,11 +168,14 @@ where
     }
 
     /// Start a connection attempt through a relay with a given address.
-    /// Returns a canceler.
+    /// Returns the id of the attempt and a canceler.
     fn create_conn_attempt(
         &mut self,
         address: RA,
-    ) -> Result<oneshot::Sender<()>, ConnectPoolError> {
+    ) -> Result<(AttemptId, oneshot::Sender<()>), ConnectPoolError> {
+        let attempt_id = self.next_attempt_id;
+        self.next_attempt_id = self.next_attempt_id.wrapping_add(1);
+
         let (cancel_sender, cancel_receiver) = oneshot::channel();
         let c_friend_public_key = self.friend_public_key.clone();
         let c_client_connector = self.client_connector.clone();
@@ -169,14 +190,14 @@ where
                 c_encrypt_transform.clone(),
                 cancel_receiver
             ));
-            let _ = await!(c_conn_done_sender.send(opt_conn));
+            let _ = await!(c_conn_done_sender.send((attempt_id, opt_conn)));
         };
 
         self.spawner
             .spawn(conn_fut)
             .map_err(|_| ConnectPoolError::SpawnError)?;
 
-        Ok(cancel_sender)
+        Ok((attempt_id, cancel_sender))
     }
 
     pub fn handle_connect_request(
@@ -197,8 +218,14 @@ where
             Some(address) => address,
         };
 
-        let canceler = self.create_conn_attempt(address.clone())?;
-        self.status = CpStatus::Connecting((address, canceler, connect_request.response_sender));
+        let (attempt_id, canceler) = self.create_conn_attempt(address.clone())?;
+        self.status = CpStatus::Connecting((
+            attempt_id,
+            address,
+            self.conn_timeout_ticks,
+            canceler,
+            connect_request.response_sender,
+        ));
         Ok(())
     }
 
@@ -212,8 +239,14 @@ where
         match (was_empty, status) {
             (true, CpStatus::Waiting((_remaining_ticks, response_sender))) => {
                 let address = self.addresses.pop_front().unwrap();
-                let canceler = self.create_conn_attempt(address.clone())?;
-                self.status = CpStatus::Connecting((address, canceler, response_sender));
+                let (attempt_id, canceler) = self.create_conn_attempt(address.clone())?;
+                self.status = CpStatus::Connecting((
+                    attempt_id,
+                    address,
+                    self.conn_timeout_ticks,
+                    canceler,
+                    response_sender,
+                ));
             }
             (_, status) => self.status = status,
         };
@@ -227,20 +260,38 @@ where
             CpStatus::Waiting(waiting) => {
                 self.status = CpStatus::Waiting(waiting);
             }
-            CpStatus::Connecting((cur_address, canceler, response_sender)) => {
+            CpStatus::Connecting((
+                cur_attempt_id,
+                cur_address,
+                remaining_ticks,
+                canceler,
+                response_sender,
+            )) => {
                 if address == cur_address {
                     // We were trying to connect to the address being removed:
                     let _ = canceler.send(());
                     if let Some(address) = self.addresses.pop_front() {
                         // There is another address we can use:
-                        let canceler = self.create_conn_attempt(address.clone())?;
-                        self.status = CpStatus::Connecting((address, canceler, response_sender));
+                        let (attempt_id, canceler) = self.create_conn_attempt(address.clone())?;
+                        self.status = CpStatus::Connecting((
+                            attempt_id,
+                            address,
+                            self.conn_timeout_ticks,
+                            canceler,
+                            response_sender,
+                        ));
                     } else {
                         // There is no other address:
                         self.status = CpStatus::Waiting((0, response_sender));
                     }
                 } else {
-                    self.status = CpStatus::Connecting((cur_address, canceler, response_sender));
+                    self.status = CpStatus::Connecting((
+                        cur_attempt_id,
+                        cur_address,
+                        remaining_ticks,
+                        canceler,
+                        response_sender,
+                    ));
                 }
             }
         };
@@ -264,9 +315,10 @@ where
 
     pub fn handle_timer_tick(&mut self) -> Result<(), ConnectPoolError> {
         let waiting = match mem::replace(&mut self.status, CpStatus::NoRequest) {
+            CpStatus::NoRequest => return Ok(()),
             CpStatus::Waiting(waiting) => waiting,
-            other_status => {
-                self.status = other_status;
+            CpStatus::Connecting(connecting) => {
+                self.handle_connecting_tick(connecting);
                 return Ok(());
             }
         };
@@ -275,8 +327,14 @@ where
         backoff_ticks = backoff_ticks.saturating_sub(1);
         if backoff_ticks == 0 {
             if let Some(address) = self.addresses.pop_front() {
-                let canceler = self.create_conn_attempt(address.clone())?;
-                self.status = CpStatus::Connecting((address, canceler, response_sender));
+                let (attempt_id, canceler) = self.create_conn_attempt(address.clone())?;
+                self.status = CpStatus::Connecting((
+                    attempt_id,
+                    address,
+                    self.conn_timeout_ticks,
+                    canceler,
+                    response_sender,
+                ));
             } else {
                 self.status = CpStatus::Waiting((self.backoff_ticks, response_sender));
             }
@@ -286,13 +344,64 @@ where
         Ok(())
     }
 
-    pub fn handle_connect_attempt_done(&mut self, opt_conn: Option<RawConn>) {
+    /// Give up on a connection attempt that takes too long.
+    /// This prevents us from waiting for the OS to time out connections to unresponsive addresses.
+    fn handle_connecting_tick(
+        &mut self,
+        connecting: (
+            AttemptId,
+            RA,
+            usize,
+            oneshot::Sender<()>,
+            oneshot::Sender<RawConn>,
+        ),
+    ) {
+        let (attempt_id, address, mut remaining_ticks, canceler, response_sender) = connecting;
+        remaining_ticks = remaining_ticks.saturating_sub(1);
+        if remaining_ticks > 0 {
+            self.status = CpStatus::Connecting((
+                attempt_id,
+                address,
+                remaining_ticks,
+                canceler,
+                response_sender,
+            ));
+            return;
+        }
+
+        warn!(
+            "handle_connecting_tick(): Timeout occurred during connection attempt to {:?}",
+            address
+        );
+        let _ = canceler.send(());
+        self.addresses.push_back(address);
+        self.status = CpStatus::Waiting((self.backoff_ticks, response_sender));
+    }
+
+    pub fn handle_connect_attempt_done(
+        &mut self,
+        attempt_id: AttemptId,
+        opt_conn: Option<RawConn>,
+    ) {
         let connecting = match mem::replace(&mut self.status, CpStatus::NoRequest) {
-            CpStatus::NoRequest | CpStatus::Waiting(_) => unreachable!(),
-            CpStatus::Connecting(connecting) => connecting,
+            CpStatus::Connecting(connecting) => {
+                if connecting.0 != attempt_id {
+                    // A late result of a connection attempt we have already given up on, while
+                    // a newer attempt is in progress:
+                    self.status = CpStatus::Connecting(connecting);
+                    return;
+                }
+                connecting
+            }
+            other_status => {
+                // A late result of a connection attempt we have already given up on
+                // (For example, due to a timeout):
+                self.status = other_status;
+                return;
+            }
         };
 
-        let (address, _canceler, response_sender) = connecting;
+        let (_attempt_id, address, _remaining_ticks, _canceler, response_sender) = connecting;
         self.addresses.push_back(address);
 
         if let Some(conn) = opt_conn {
@@ -316,6 +425,7 @@ async fn connect_pool_loop<RA, ET, TS, C, S>(
     encrypt_transform: ET,
     friend_public_key: PublicKey,
     backoff_ticks: usize,
+    conn_timeout_ticks: usize,
     client_connector: C,
     spawner: S,
     mut opt_event_sender: Option<mpsc::Sender<()>>,
@@ -335,6 +445,7 @@ where
         friend_public_key,
         conn_done_sender,
         backoff_ticks,
+        conn_timeout_ticks,
         client_connector,
         encrypt_transform,
         spawner.clone(),
@@ -380,8 +491,8 @@ where
                 info!("connect_pool_loop(): timer closed");
                 break;
             }
-            CpEvent::ConnectAttemptDone(opt_conn) => {
-                connect_pool.handle_connect_attempt_done(opt_conn)
+            CpEvent::ConnectAttemptDone((attempt_id, opt_conn)) => {
+                connect_pool.handle_connect_attempt_done(attempt_id, opt_conn)
             }
         }
         if let Some(ref mut event_sender) = opt_event_sender {
@@ -399,6 +510,7 @@ pub fn create_connect_pool<RA, ET, TS, C, S>(
     encrypt_transform: ET,
     friend_public_key: PublicKey,
     backoff_ticks: usize,
+    conn_timeout_ticks: usize,
     client_connector: C,
     mut spawner: S,
 ) -> Result<ConnectPoolControl<RA>, ConnectPoolError>
@@ -422,6 +534,7 @@ where
         encrypt_transform,
         friend_public_key,
         backoff_ticks,
+        conn_timeout_ticks,
         client_connector,
         spawner.clone(),
         None,
@@ -445,6 +558,7 @@ pub struct PoolConnector<RA, C, ET, S> {
     client_connector: C,
     encrypt_transform: ET,
     backoff_ticks: usize,
+    conn_timeout_ticks: usize,
     spawner: S,
     phantom_b: PhantomData<RA>,
 }
@@ -464,6 +578,7 @@ where
         client_connector: C,
         encrypt_transform: ET,
         backoff_ticks: usize,
+        conn_timeout_ticks: usize,
         spawner: S,
     ) -> Self {
         PoolConnector {
@@ -471,6 +586,7 @@ where
             client_connector,
             encrypt_transform,
             backoff_ticks,
+            conn_timeout_ticks,
             spawner,
             phantom_b: PhantomData,
         }
@@ -500,6 +616,7 @@ where
                     self.encrypt_transform.clone(),
                     friend_public_key,
                     self.backoff_ticks,
+                    self.conn_timeout_ticks,
                     self.client_connector.clone(),
                     self.spawner.clone(),
                 )
@@ -528,6 +645,7 @@ mod tests {
         let (mut tick_sender_receiver, timer_client) = dummy_timer_multi_sender(spawner.clone());
 
         let backoff_ticks = 2;
+        let conn_timeout_ticks = 8;
 
         let (conn_request_sender, mut conn_request_receiver) = mpsc::channel(0);
         let client_connector = DummyConnector::new(conn_request_sender);
@@ -542,6 +660,7 @@ mod tests {
             client_connector,
             encrypt_transform,
             backoff_ticks,
+            conn_timeout_ticks,
             spawner,
         );
 
@@ -659,6 +778,7 @@ mod tests {
             dummy_timer_multi_sender(spawner.clone());
 
         let backoff_ticks = 2;
+        let conn_timeout_ticks = 8;
 
         let (conn_request_sender, mut conn_request_receiver) = mpsc::channel(0);
         let client_connector = DummyConnector::new(conn_request_sender);
@@ -692,6 +812,7 @@ mod tests {
             encrypt_transform,
             pk_b.clone(), // friend_public_key
             backoff_ticks,
+            conn_timeout_ticks,
             client_connector,
             spawner.clone(),
             Some(event_sender),
@@ -762,4 +883,174 @@ mod tests {
         let mut thread_pool = ThreadPool::new().unwrap();
         thread_pool.run(task_pool_connector_backoff_ticks(thread_pool.clone()));
     }
+
+    async fn task_pool_connector_conn_timeout<S>(mut spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        // Create a mock time service:
+        let (mut tick_sender_receiver, mut timer_client) =
+            dummy_timer_multi_sender(spawner.clone());
+
+        let backoff_ticks = 2;
+        let conn_timeout_ticks = 3;
+
+        let (conn_request_sender, mut conn_request_receiver) = mpsc::channel(0);
+        let client_connector = DummyConnector::new(conn_request_sender);
+
+        // We don't need encryption for this test:
+        let encrypt_transform = FuncFutTransform::new(|(_public_key, conn_pair)| {
+            Box::pin(future::ready(Some(conn_pair)))
+        });
+
+        let timer_stream = await!(timer_client.request_timer_stream()).unwrap();
+        let mut tick_sender = await!(tick_sender_receiver.next()).unwrap();
+
+        // Used for debugging the loop:
+        let (event_sender, mut event_receiver) = mpsc::channel(0);
+
+        let (request_sender, incoming_requests) = mpsc::channel(0);
+        let (config_sender, incoming_config) = mpsc::channel(0);
+
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+
+        let loop_fut = connect_pool_loop(
+            incoming_requests,
+            incoming_config,
+            timer_stream,
+            encrypt_transform,
+            pk_b.clone(), // friend_public_key
+            backoff_ticks,
+            conn_timeout_ticks,
+            client_connector,
+            spawner.clone(),
+            Some(event_sender),
+        )
+        .map_err(|e| error!("connect_pool_loop() error: {:?}", e))
+        .map(|_| ());
+
+        spawner.spawn(loop_fut).unwrap();
+
+        let mut connect_client = CpConnectClient::new(request_sender);
+        let mut config_client = CpConfigClient::new(config_sender);
+
+        await!(config_client.config(vec![0x0u32])).unwrap();
+        await!(event_receiver.next()).unwrap();
+
+        let connect_fut = connect_client.connect();
+        let handle_connect_fut = async {
+            await!(event_receiver.next()).unwrap(); // Connection request event
+
+            // The remote side never answers this connection attempt:
+            let conn_request = await!(conn_request_receiver.next()).unwrap();
+            let (address, pk) = &conn_request.address;
+            assert_eq!(address, &0x0u32);
+            assert_eq!(pk, &pk_b);
+
+            for _ in 0..conn_timeout_ticks {
+                await!(tick_sender.send(TimerTick)).unwrap();
+                await!(event_receiver.next()).unwrap(); // timer tick event
+            }
+            // The canceled connection attempt is done:
+            await!(event_receiver.next()).unwrap();
+
+            // Wait backoff_ticks:
+            for _ in 0..backoff_ticks {
+                await!(tick_sender.send(TimerTick)).unwrap();
+                await!(event_receiver.next()).unwrap(); // timer tick event
+            }
+
+            // A new connection attempt is made, this time successfully:
+            let conn_request = await!(conn_request_receiver.next()).unwrap();
+            let (address, pk) = &conn_request.address;
+            assert_eq!(address, &0x0u32);
+            assert_eq!(pk, &pk_b);
+
+            let (local_sender, remote_receiver) = mpsc::channel(0);
+            let (remote_sender, local_receiver) = mpsc::channel(0);
+            conn_request.reply(Some((local_sender, local_receiver)));
+            await!(event_receiver.next()).unwrap(); // connection attempt done event
+            (remote_sender, remote_receiver)
+        };
+        let (_local_conn, _remote_conn) = await!(connect_fut.join(handle_connect_fut));
+    }
+
+    #[test]
+    fn test_pool_connector_conn_timeout() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_pool_connector_conn_timeout(thread_pool.clone()));
+    }
+
+    async fn task_connect_pool_stale_attempt_done<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let backoff_ticks = 2;
+        let conn_timeout_ticks = 3;
+
+        let (conn_request_sender, _conn_request_receiver) = mpsc::channel(0);
+        let client_connector = DummyConnector::new(conn_request_sender);
+
+        // We don't need encryption for this test:
+        let encrypt_transform = FuncFutTransform::new(|(_public_key, conn_pair)| {
+            Box::pin(future::ready(Some(conn_pair)))
+        });
+
+        let (conn_done_sender, _incoming_conn_done) = mpsc::channel(0);
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let mut connect_pool = ConnectPool::new(
+            pk_b.clone(),
+            conn_done_sender,
+            backoff_ticks,
+            conn_timeout_ticks,
+            client_connector,
+            encrypt_transform,
+            spawner,
+        );
+
+        connect_pool.handle_config_request(vec![0x0u32]).unwrap();
+        let (response_sender, mut response_receiver) = oneshot::channel();
+        connect_pool
+            .handle_connect_request(CpConnectRequest { response_sender })
+            .unwrap();
+
+        let first_attempt_id = match &connect_pool.status {
+            CpStatus::Connecting((attempt_id, ..)) => *attempt_id,
+            _ => unreachable!(),
+        };
+
+        // The first attempt times out, and a second attempt is made after backoff_ticks:
+        for _ in 0..conn_timeout_ticks + backoff_ticks {
+            connect_pool.handle_timer_tick().unwrap();
+        }
+        let second_attempt_id = match &connect_pool.status {
+            CpStatus::Connecting((attempt_id, ..)) => *attempt_id,
+            _ => unreachable!(),
+        };
+        assert_ne!(first_attempt_id, second_attempt_id);
+
+        // A late result of the first attempt is dropped:
+        let (local_sender, _remote_receiver) = mpsc::channel(0);
+        let (_remote_sender, local_receiver) = mpsc::channel(0);
+        connect_pool
+            .handle_connect_attempt_done(first_attempt_id, Some((local_sender, local_receiver)));
+        match &connect_pool.status {
+            CpStatus::Connecting((attempt_id, ..)) => assert_eq!(*attempt_id, second_attempt_id),
+            _ => unreachable!(),
+        };
+        assert_eq!(response_receiver.try_recv().unwrap().map(|_| ()), None);
+
+        // The result of the second attempt is used:
+        connect_pool.handle_connect_attempt_done(second_attempt_id, None);
+        match &connect_pool.status {
+            CpStatus::Waiting((remaining_ticks, _)) => assert_eq!(*remaining_ticks, backoff_ticks),
+            _ => unreachable!(),
+        };
+    }
+
+    #[test]
+    fn test_connect_pool_stale_attempt_done() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_connect_pool_stale_attempt_done(thread_pool.clone()));
+    }
 }
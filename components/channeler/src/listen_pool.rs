@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::marker::PhantomData;
@@ -9,8 +9,8 @@ use futures::{future, stream, FutureExt, SinkExt, Stream, StreamExt, TryFutureEx
 
 use common::access_control::AccessControlOp;
 use common::conn::{FutTransform, Listener};
-use common::select_streams::{select_streams, BoxStream};
-use common::transform_pool::transform_pool_loop;
+use common::select_streams::{prio_select_streams, BoxStream, PrioSelectStreams};
+use common::transform_pool::keyed_transform_pool_loop;
 
 use timer::TimerClient;
 
@@ -50,6 +50,10 @@ impl<RA> LpConfigClient<RA> {
 }
 */
 
+/// An incoming plain (not yet encrypted) connection, together with the address of the relay
+/// it arrived from.
+type PlainConn<RA> = (PublicKey, RA, RawConn);
+
 #[derive(Debug)]
 enum ListenPoolError {
     // ConfigClosed,
@@ -57,25 +61,52 @@ enum ListenPoolError {
     SpawnError,
 }
 
+#[derive(Debug)]
+enum HandleConfigError<RA> {
+    ListenPoolError(ListenPoolError),
+    /// Access control operations could not be sent to the listeners of these relays.
+    /// The listeners are probably closed.
+    AccessControlSendFailed(Vec<RA>),
+}
+
+impl<RA> From<ListenPoolError> for HandleConfigError<RA> {
+    fn from(e: ListenPoolError) -> Self {
+        HandleConfigError::ListenPoolError(e)
+    }
+}
+
+/// Identifies a single listener spawned for a relay. A new generation is used every time we
+/// (re)connect to a relay.
+type Generation = u64;
+
 enum LpEvent<RA> {
     Config(LpConfig<RA>),
     ConfigClosed,
-    RelayClosed(RA),
+    RelayClosed((RA, Generation)),
+    /// The consumer of incoming plain connections is gone:
+    PlainConnClosed,
     TimerTick,
     TimerClosed,
 }
 
 enum RelayStatus {
     Waiting(usize), // ticks left to start listening again
-    Connected(mpsc::Sender<AccessControlOpPk>),
+    Connected((Generation, mpsc::Sender<AccessControlOpPk>)),
 }
 
 struct ListenPool<RA, L, S> {
     state: ListenPoolState<RA, PublicKey, RelayStatus>,
-    plain_conn_sender: mpsc::Sender<(PublicKey, RawConn)>,
-    relay_closed_sender: mpsc::Sender<RA>,
+    /// Access control of every relay, updated incrementally as friends are added or removed.
+    /// A copy is handed to the listener whenever we (re)connect to the relay, so that we don't
+    /// have to rebuild it from the relay's friends.
+    access_controls: HashMap<RA, AccessControlPk>,
+    plain_conn_sender: mpsc::Sender<PlainConn<RA>>,
+    relay_closed_sender: mpsc::Sender<(RA, Generation)>,
+    plain_conn_closed_sender: mpsc::Sender<()>,
     listener: L,
     backoff_ticks: usize,
+    /// Generation of the next spawned listener
+    next_generation: Generation,
     spawner: S,
 }
 
@@ -91,68 +122,115 @@ where
     S: Spawn + Clone,
 {
     pub fn new(
-        plain_conn_sender: mpsc::Sender<(PublicKey, RawConn)>,
-        relay_closed_sender: mpsc::Sender<RA>,
+        plain_conn_sender: mpsc::Sender<PlainConn<RA>>,
+        relay_closed_sender: mpsc::Sender<(RA, Generation)>,
+        plain_conn_closed_sender: mpsc::Sender<()>,
         listener: L,
         backoff_ticks: usize,
         spawner: S,
     ) -> Self {
         ListenPool {
             state: ListenPoolState::new(),
+            access_controls: HashMap::new(),
             plain_conn_sender,
             relay_closed_sender,
+            plain_conn_closed_sender,
             listener,
             backoff_ticks,
+            next_generation: 0,
             spawner,
         }
     }
 
+    /// Spawn a listener for a relay.
+    /// Returns the generation of the new listener, and a sender for its access control updates.
     fn spawn_listen(
-        &self,
+        &mut self,
         address: RA,
-        relay_friends: &HashSet<PublicKey>,
-    ) -> Result<mpsc::Sender<AccessControlOpPk>, ListenPoolError> {
-        // Fill in access_control:
-        let mut access_control = AccessControlPk::new();
-
-        for friend_public_key in relay_friends {
-            access_control.apply_op(AccessControlOp::Add(friend_public_key.clone()));
-        }
+        access_control: AccessControlPk,
+    ) -> Result<(Generation, mpsc::Sender<AccessControlOpPk>), ListenPoolError> {
+        let generation = self.next_generation;
+        self.next_generation = self.next_generation.wrapping_add(1);
 
-        let (access_control_sender, mut connections_receiver) = self
+        let (access_control_sender, connections_receiver) = self
             .listener
             .clone()
             .listen((address.clone(), access_control));
         // TODO: Do we need the listener.clone() here? Maybe Listen doesn't need to take ownership
         // over self?
 
+        // Attach the relay address to every incoming connection:
+        let c_address = address.clone();
+        let mut connections_receiver = connections_receiver
+            .map(move |(public_key, raw_conn)| (public_key, c_address.clone(), raw_conn));
+
         let mut c_plain_conn_sender = self.plain_conn_sender.clone();
         let mut c_relay_closed_sender = self.relay_closed_sender.clone();
+        let mut c_plain_conn_closed_sender = self.plain_conn_closed_sender.clone();
         let send_fut = async move {
-            let _ = await!(c_plain_conn_sender.send_all(&mut connections_receiver));
-            // Notify that this listener was closed:
-            let _ = await!(c_relay_closed_sender.send(address));
+            if await!(c_plain_conn_sender.send_all(&mut connections_receiver)).is_err() {
+                // Nobody wants our incoming connections anymore:
+                let _ = await!(c_plain_conn_closed_sender.send(()));
+            } else {
+                // Notify that this listener was closed:
+                let _ = await!(c_relay_closed_sender.send((address, generation)));
+            }
         };
         self.spawner
             .clone()
             .spawn(send_fut)
             .map_err(|_| ListenPoolError::SpawnError)?;
 
-        Ok(access_control_sender)
+        Ok((generation, access_control_sender))
+    }
+
+    /// Apply an access control operation to the access control of a relay, and forward it to
+    /// the relay's listener if we are currently connected.
+    /// Returns false if the operation could not be forwarded to the relay's listener.
+    async fn apply_access_control_op<'a>(
+        &'a mut self,
+        address: &'a RA,
+        access_control_op: AccessControlOpPk,
+    ) -> bool {
+        if let Some(access_control) = self.access_controls.get_mut(address) {
+            access_control.apply_op(access_control_op.clone());
+        }
+
+        if let Some(relay) = self.state.relays.get_mut(address) {
+            if let RelayStatus::Connected((_generation, access_control_sender)) = &mut relay.status
+            {
+                return await!(access_control_sender.send(access_control_op)).is_ok();
+            }
+        }
+        true
     }
 
-    pub async fn handle_config(&mut self, config: LpConfig<RA>) -> Result<(), ListenPoolError> {
+    /// Apply a configuration change.
+    /// Relays whose listener could not be updated are reported using
+    /// `HandleConfigError::AccessControlSendFailed`, after the whole change was applied.
+    pub async fn handle_config(
+        &mut self,
+        config: LpConfig<RA>,
+    ) -> Result<(), HandleConfigError<RA>> {
+        let mut failed_addresses = Vec::new();
         match config {
             LpConfig::SetLocalAddresses(local_addresses) => {
                 let (relay_friends, addresses) = self.state.set_local_addresses(local_addresses);
+
+                // Fill in access_control:
+                let mut access_control = AccessControlPk::new();
+                for friend_public_key in &relay_friends {
+                    access_control.apply_op(AccessControlOp::Allow(friend_public_key.clone()));
+                }
+
                 for address in addresses {
-                    let access_control_sender =
-                        self.spawn_listen(address.clone(), &relay_friends)?;
+                    let connected = self.spawn_listen(address.clone(), access_control.clone())?;
                     let relay = Relay {
                         friends: relay_friends.clone(),
-                        status: RelayStatus::Connected(access_control_sender),
+                        status: RelayStatus::Connected(connected),
                     };
-                    self.state.relays.insert(address, relay);
+                    self.state.relays.insert(address.clone(), relay);
+                    self.access_controls.insert(address, access_control.clone());
                 }
             }
             LpConfig::UpdateFriend((friend_public_key, addresses)) => {
@@ -161,64 +239,94 @@ where
                     .update_friend(friend_public_key.clone(), addresses);
 
                 for address in relays_add {
-                    if let Some(relay) = self.state.relays.get_mut(&address) {
-                        if let RelayStatus::Connected(access_control_sender) = &mut relay.status {
-                            // TODO: Error checking here?
-                            let _ = await!(access_control_sender
-                                .send(AccessControlOp::Add(friend_public_key.clone())));
-                        }
+                    if !await!(self.apply_access_control_op(
+                        &address,
+                        AccessControlOp::Allow(friend_public_key.clone())
+                    )) {
+                        failed_addresses.push(address);
                     }
                 }
 
                 for address in relays_remove {
-                    if let Some(relay) = self.state.relays.get_mut(&address) {
-                        if let RelayStatus::Connected(access_control_sender) = &mut relay.status {
-                            // TODO: Error checking here?
-                            let _ = await!(access_control_sender
-                                .send(AccessControlOp::Remove(friend_public_key.clone())));
-                        }
+                    if !await!(self.apply_access_control_op(
+                        &address,
+                        AccessControlOp::Deny(friend_public_key.clone())
+                    )) {
+                        failed_addresses.push(address);
                     }
                 }
 
                 for address in relays_spawn {
+                    let mut access_control = AccessControlPk::new();
+                    access_control.apply_op(AccessControlOp::Allow(friend_public_key.clone()));
+                    let connected = self.spawn_listen(address.clone(), access_control.clone())?;
+
                     let mut relay_friends = HashSet::new();
                     relay_friends.insert(friend_public_key.clone());
-                    let access_control_sender =
-                        self.spawn_listen(address.clone(), &relay_friends)?;
                     let relay = Relay {
                         friends: relay_friends,
-                        status: RelayStatus::Connected(access_control_sender),
+                        status: RelayStatus::Connected(connected),
                     };
                     self.state.relays.insert(address.clone(), relay);
+                    self.access_controls.insert(address, access_control);
                 }
             }
             LpConfig::RemoveFriend(friend_public_key) => {
                 let remove_relays = self.state.remove_friend(&friend_public_key);
 
                 for address in remove_relays {
-                    if let Some(relay) = self.state.relays.get_mut(&address) {
-                        if let RelayStatus::Connected(access_control_sender) = &mut relay.status {
-                            // TODO: Error checking here?
-                            let _ = await!(access_control_sender
-                                .send(AccessControlOp::Remove(friend_public_key.clone())));
-                        }
+                    if !await!(self.apply_access_control_op(
+                        &address,
+                        AccessControlOp::Deny(friend_public_key.clone())
+                    )) {
+                        failed_addresses.push(address);
                     }
                 }
             }
         };
+
+        // Forget the access control of relays we no longer listen to:
+        let relays = &self.state.relays;
+        self.access_controls
+            .retain(|address, _| relays.contains_key(address));
+
+        if !failed_addresses.is_empty() {
+            return Err(HandleConfigError::AccessControlSendFailed(failed_addresses));
+        }
         Ok(())
     }
 
-    pub fn handle_relay_closed(&mut self, address: RA) -> Result<(), ListenPoolError> {
-        let relay = match self.state.relays.get_mut(&address) {
-            Some(relay) => relay,
-            None => return Ok(()), // TODO: Could this happen?
+    /// Handle the closing of a relay listener.
+    /// Closing of a listener of an older generation is ignored: We have already reconnected to
+    /// the relay (Or stopped listening to it), and the current listener is not affected.
+    pub fn handle_relay_closed(
+        &mut self,
+        address: RA,
+        generation: Generation,
+    ) -> Result<(), ListenPoolError> {
+        let is_current = match self.state.relays.get(&address) {
+            Some(relay) => match &relay.status {
+                RelayStatus::Connected((current_generation, _)) => {
+                    *current_generation == generation
+                }
+                RelayStatus::Waiting(_) => false,
+            },
+            None => false,
         };
 
-        relay.status = RelayStatus::Waiting(self.backoff_ticks);
+        if is_current {
+            self.backoff_relay(&address);
+        }
         Ok(())
     }
 
+    /// Stop using the current listener of a relay. We will listen again after `backoff_ticks`.
+    pub fn backoff_relay(&mut self, address: &RA) {
+        if let Some(relay) = self.state.relays.get_mut(address) {
+            relay.status = RelayStatus::Waiting(self.backoff_ticks);
+        }
+    }
+
     pub fn handle_timer_tick(&mut self) -> Result<(), ListenPoolError> {
         let mut spawn_addresses = Vec::new();
         for (address, relay) in &mut self.state.relays {
@@ -236,21 +344,63 @@ where
 
         // Reconnect to relays for which enough time has passed:
         for address in spawn_addresses {
-            let relay = self.state.relays.get(&address).unwrap();
-            let access_control_sender = self.spawn_listen(address.clone(), &relay.friends)?;
+            let access_control = self.access_controls.get(&address).unwrap().clone();
+            let connected = self.spawn_listen(address.clone(), access_control)?;
 
             let relay = self.state.relays.get_mut(&address).unwrap();
-            relay.status = RelayStatus::Connected(access_control_sender);
+            relay.status = RelayStatus::Connected(connected);
         }
         Ok(())
     }
 }
 
+/// Merge all the sources of events of the listen pool into one stream.
+///
+/// If a few events are ready at the same time, they are handled by the following order:
+/// 1. The consumer of incoming plain connections is gone (We are about to shut down).
+/// 2. Configuration changes.
+/// 3. Timer ticks.
+/// 4. Closed relays.
+///
+/// This way the order of handling events does not depend on races between the sources.
+/// For example, a configuration change is always applied before a relay closed event that
+/// arrived at the same time.
+fn lp_events<'a, RA, TS>(
+    incoming_config: mpsc::Receiver<LpConfig<RA>>,
+    relay_closed_receiver: mpsc::Receiver<(RA, Generation)>,
+    plain_conn_closed_receiver: mpsc::Receiver<()>,
+    timer_stream: TS,
+) -> PrioSelectStreams<'a, LpEvent<RA>>
+where
+    RA: Send + 'a,
+    TS: Stream + Unpin + Send + 'a,
+{
+    let incoming_plain_conn_closed = plain_conn_closed_receiver.map(|_| LpEvent::PlainConnClosed);
+
+    let incoming_config = incoming_config
+        .map(LpEvent::Config)
+        .chain(stream::once(future::ready(LpEvent::ConfigClosed)));
+
+    let timer_stream = timer_stream
+        .map(|_| LpEvent::<RA>::TimerTick)
+        .chain(stream::once(future::ready(LpEvent::TimerClosed)));
+
+    let incoming_relay_closed = relay_closed_receiver.map(LpEvent::RelayClosed);
+
+    prio_select_streams![
+        incoming_plain_conn_closed,
+        incoming_config,
+        timer_stream,
+        incoming_relay_closed
+    ]
+}
+
 async fn listen_pool_loop<RA, L, TS, S>(
     incoming_config: mpsc::Receiver<LpConfig<RA>>,
-    outgoing_plain_conns: mpsc::Sender<(PublicKey, RawConn)>,
+    outgoing_plain_conns: mpsc::Sender<PlainConn<RA>>,
     listener: L,
     backoff_ticks: usize,
+    channel_len: usize,
     timer_stream: TS,
     spawner: S,
     mut opt_event_sender: Option<mpsc::Sender<()>>,
@@ -266,33 +416,47 @@ where
     TS: Stream + Unpin + Send,
     S: Spawn + Clone + Send + 'static,
 {
-    let (relay_closed_sender, relay_closed_receiver) = mpsc::channel(0);
+    let (relay_closed_sender, relay_closed_receiver) = mpsc::channel(channel_len);
+    let (plain_conn_closed_sender, plain_conn_closed_receiver) = mpsc::channel(channel_len);
 
     let mut listen_pool = ListenPool::<RA, L, S>::new(
         outgoing_plain_conns,
         relay_closed_sender,
+        plain_conn_closed_sender,
         listener,
         backoff_ticks,
         spawner,
     );
 
-    let incoming_relay_closed = relay_closed_receiver.map(LpEvent::RelayClosed);
-
-    let incoming_config = incoming_config
-        .map(LpEvent::Config)
-        .chain(stream::once(future::ready(LpEvent::ConfigClosed)));
-
-    let timer_stream = timer_stream
-        .map(|_| LpEvent::<RA>::TimerTick)
-        .chain(stream::once(future::ready(LpEvent::TimerClosed)));
-
-    let mut incoming_events = select_streams![incoming_relay_closed, incoming_config, timer_stream];
+    let mut incoming_events = lp_events(
+        incoming_config,
+        relay_closed_receiver,
+        plain_conn_closed_receiver,
+        timer_stream,
+    );
 
     while let Some(event) = await!(incoming_events.next()) {
         match event {
-            LpEvent::Config(config) => await!(listen_pool.handle_config(config))?,
+            LpEvent::Config(config) => match await!(listen_pool.handle_config(config)) {
+                Ok(()) => {}
+                Err(HandleConfigError::AccessControlSendFailed(addresses)) => {
+                    // The listeners of these relays missed an access control update.
+                    // We reconnect to these relays later, with an up to date access control:
+                    for address in addresses {
+                        warn!(
+                            "listen_pool_loop(): Failed to update access control of relay {:?}",
+                            address
+                        );
+                        listen_pool.backoff_relay(&address);
+                    }
+                }
+                Err(HandleConfigError::ListenPoolError(e)) => return Err(e),
+            },
             LpEvent::ConfigClosed => break,
-            LpEvent::RelayClosed(address) => listen_pool.handle_relay_closed(address)?,
+            LpEvent::RelayClosed((address, generation)) => {
+                listen_pool.handle_relay_closed(address, generation)?
+            }
+            LpEvent::PlainConnClosed => break,
             LpEvent::TimerTick => listen_pool.handle_timer_tick()?,
             LpEvent::TimerClosed => break,
         };
@@ -310,7 +474,11 @@ pub struct PoolListener<RA, L, ET, S> {
     listener: L,
     encrypt_transform: ET,
     max_concurrent_encrypt: usize,
+    /// Maximum amount of concurrent encryptions of connections arriving from a single relay.
+    opt_max_concurrent_encrypt_per_relay: Option<usize>,
     backoff_ticks: usize,
+    /// Capacity of internal channels:
+    channel_len: usize,
     timer_client: TimerClient,
     spawner: S,
     phantom_b: PhantomData<RA>,
@@ -321,7 +489,9 @@ impl<RA, L, ET, S> PoolListener<RA, L, ET, S> {
         listener: L,
         encrypt_transform: ET,
         max_concurrent_encrypt: usize,
+        opt_max_concurrent_encrypt_per_relay: Option<usize>,
         backoff_ticks: usize,
+        channel_len: usize,
         timer_client: TimerClient,
         spawner: S,
     ) -> Self {
@@ -329,7 +499,9 @@ impl<RA, L, ET, S> PoolListener<RA, L, ET, S> {
             listener,
             encrypt_transform,
             max_concurrent_encrypt,
+            opt_max_concurrent_encrypt_per_relay,
             backoff_ticks,
+            channel_len,
             timer_client,
             spawner,
             phantom_b: PhantomData,
@@ -361,29 +533,38 @@ where
         mut self,
         _arg: Self::Arg,
     ) -> (mpsc::Sender<Self::Config>, mpsc::Receiver<Self::Connection>) {
-        let (config_sender, incoming_config) = mpsc::channel(0);
-        let (outgoing_conns, incoming_conns) = mpsc::channel(0);
+        let (config_sender, incoming_config) = mpsc::channel(self.channel_len);
+        let (outgoing_conns, mut incoming_conns) = mpsc::channel(self.channel_len);
 
         let mut c_timer_client = self.timer_client.clone();
         let c_listener = self.listener.clone();
         let c_encrypt_transform = self.encrypt_transform.clone();
         let c_max_concurrent_encrypt = self.max_concurrent_encrypt;
+        let c_opt_max_concurrent_encrypt_per_relay = self.opt_max_concurrent_encrypt_per_relay;
         let c_backoff_ticks = self.backoff_ticks;
+        let c_channel_len = self.channel_len;
         let mut c_spawner = self.spawner.clone();
 
         // Connections encryptor:
-        let (plain_conn_sender, incoming_plain_conn) = mpsc::channel(0);
-        let enc_loop_fut = transform_pool_loop(
+        let (plain_conn_sender, incoming_plain_conn) = mpsc::channel(c_channel_len);
+        // Connections are keyed by the relay they arrived from, so that a burst of connections
+        // from one relay can not take over the encryption pool:
+        let incoming_plain_conn = incoming_plain_conn
+            .map(|(public_key, address, raw_conn)| (address, (public_key, raw_conn)));
+        let enc_loop_fut = keyed_transform_pool_loop(
             incoming_plain_conn,
             outgoing_conns,
             c_encrypt_transform,
             c_max_concurrent_encrypt,
+            c_opt_max_concurrent_encrypt_per_relay,
             c_spawner.clone(),
         )
         .map_err(|e| error!("transform_pool_loop: {:?}", e))
         .map(|_| ());
 
         if c_spawner.spawn(enc_loop_fut).is_err() {
+            error!("PoolListener::listen(): Failed to spawn the encryption loop!");
+            incoming_conns.close();
             return (config_sender, incoming_conns);
         }
 
@@ -402,6 +583,7 @@ where
                 plain_conn_sender,
                 c_listener,
                 c_backoff_ticks,
+                c_channel_len,
                 timer_stream,
                 c_spawner,
                 None
@@ -412,9 +594,13 @@ where
             }
         };
 
-        // If the spawn didn't work, incoming_conns will be closed (because outgoing_conns is
-        // dropped) and the user of this listener will find out about it.
-        let _ = self.spawner.spawn(loop_fut);
+        if self.spawner.spawn(loop_fut).is_err() {
+            error!("PoolListener::listen(): Failed to spawn listen_pool_loop!");
+            // The encryption loop might still hold outgoing_conns for a while.
+            // We close incoming_conns to let the user of this listener find out immediately
+            // that the listener is dead:
+            incoming_conns.close();
+        }
 
         (config_sender, incoming_conns)
     }
@@ -423,14 +609,21 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::{Arc, Mutex};
+
     use futures::channel::mpsc;
     use futures::executor::ThreadPool;
+    use futures::future::FutureObj;
+    use futures::task::SpawnError;
 
     use crypto::identity::PUBLIC_KEY_LEN;
 
+    use common::conn::FuncFutTransform;
     use common::dummy_listener::DummyListener;
     use timer::{dummy_timer_multi_sender, TimerTick};
 
+    use crate::types::raw_conn_pair;
+
     async fn task_listen_pool_loop_set_local_addresses<S>(mut spawner: S)
     where
         S: Spawn + Clone + Send + 'static,
@@ -455,6 +648,7 @@ mod tests {
             outgoing_plain_conns,
             listener,
             backoff_ticks,
+            0,
             timer_stream,
             spawner.clone(),
             Some(event_sender),
@@ -479,21 +673,26 @@ mod tests {
 
         // Send a few connections:
         for _ in 0..5usize {
-            let (_local_sender, remote_receiver) = mpsc::channel(0);
-            let (remote_sender, _local_receiver) = mpsc::channel(0);
-            await!(listen_req0
-                .conn_sender
-                .send((pk_b.clone(), (remote_sender, remote_receiver))))
-            .unwrap();
+            let (remote_conn, _local_conn) = raw_conn_pair(&pk_b);
+            await!(listen_req0.conn_sender.send(remote_conn)).unwrap();
 
-            let (pk, _conn) = await!(incoming_plain_conns.next()).unwrap();
+            let (pk, address, _conn) = await!(incoming_plain_conns.next()).unwrap();
             assert_eq!(pk, pk_b);
+            assert_eq!(address, *relay_address0);
         }
 
         let mut listen_req1 = await!(listen_req_receiver.next()).unwrap();
         let (ref relay_address1, _) = listen_req1.arg;
         observed_addresses.push(relay_address1.clone());
 
+        // A connection through the second listener should carry the second relay address:
+        let (remote_conn, _local_conn) = raw_conn_pair(&pk_b);
+        await!(listen_req1.conn_sender.send(remote_conn)).unwrap();
+
+        let (pk, address, _conn) = await!(incoming_plain_conns.next()).unwrap();
+        assert_eq!(pk, pk_b);
+        assert_eq!(address, *relay_address1);
+
         observed_addresses.sort();
         assert_eq!(local_addresses, observed_addresses);
 
@@ -544,6 +743,7 @@ mod tests {
             outgoing_plain_conns,
             listener,
             backoff_ticks,
+            0,
             timer_stream,
             spawner.clone(),
             Some(event_sender),
@@ -583,6 +783,66 @@ mod tests {
         thread_pool.run(task_listen_pool_loop_backoff_ticks(thread_pool.clone()));
     }
 
+    // ----------------------------------------------------------------
+    // ----------------------------------------------------------------
+
+    async fn task_listen_pool_loop_plain_conn_closed<S>(mut spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        // Create a mock time service:
+        let (mut tick_sender_receiver, mut timer_client) =
+            dummy_timer_multi_sender(spawner.clone());
+        let backoff_ticks = 2;
+
+        let timer_stream = await!(timer_client.request_timer_stream()).unwrap();
+        let _tick_sender = await!(tick_sender_receiver.next()).unwrap();
+
+        let (mut config_sender, incoming_config) = mpsc::channel(0);
+        let (outgoing_plain_conns, incoming_plain_conns) = mpsc::channel(0);
+
+        let (listen_req_sender, mut listen_req_receiver) = mpsc::channel(0);
+        let listener = DummyListener::new(listen_req_sender, spawner.clone());
+
+        let (event_sender, mut event_receiver) = mpsc::channel(0);
+        let fut_loop = listen_pool_loop::<u32, _, _, _>(
+            incoming_config,
+            outgoing_plain_conns,
+            listener,
+            backoff_ticks,
+            0,
+            timer_stream,
+            spawner.clone(),
+            Some(event_sender),
+        )
+        .map_err(|e| error!("listen_pool_loop() error: {:?}", e))
+        .map(|_| ());
+
+        spawner.spawn(fut_loop).unwrap();
+
+        await!(config_sender.send(LpConfig::SetLocalAddresses(vec![0x0u32]))).unwrap();
+        await!(event_receiver.next()).unwrap();
+
+        let mut listen_req = await!(listen_req_receiver.next()).unwrap();
+
+        // Nobody consumes incoming plain connections anymore:
+        drop(incoming_plain_conns);
+
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let (remote_conn, _local_conn) = raw_conn_pair(&pk_b);
+        let _ = await!(listen_req.conn_sender.send(remote_conn));
+
+        // The whole pool should shut down, instead of backing off and retrying:
+        assert!(await!(event_receiver.next()).is_none());
+        assert!(await!(listen_req.config_receiver.next()).is_none());
+    }
+
+    #[test]
+    fn test_listen_pool_loop_plain_conn_closed() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_listen_pool_loop_plain_conn_closed(thread_pool.clone()));
+    }
+
     // ------------------------------------------------------
     // ------------------------------------------------------
 
@@ -610,6 +870,7 @@ mod tests {
             outgoing_plain_conns,
             listener,
             backoff_ticks,
+            0,
             timer_stream,
             spawner.clone(),
             Some(event_sender),
@@ -637,7 +898,7 @@ mod tests {
 
         let config0 = await!(listen_req0.config_receiver.next()).unwrap();
         match config0 {
-            AccessControlOp::Add(pk) => assert_eq!(pk, pk_b),
+            AccessControlOp::Allow(pk) => assert_eq!(pk, pk_b),
             _ => unreachable!(),
         };
 
@@ -667,7 +928,7 @@ mod tests {
         for listen_req in &mut [&mut listen_req0, &mut listen_req2] {
             let config = await!(listen_req.config_receiver.next()).unwrap();
             match config {
-                AccessControlOp::Add(pk) => assert_eq!(pk, pk_c),
+                AccessControlOp::Allow(pk) => assert_eq!(pk, pk_c),
                 _ => unreachable!(),
             };
         }
@@ -682,7 +943,7 @@ mod tests {
         for listen_req in &mut [&mut listen_req0, &mut listen_req2] {
             let config = await!(listen_req.config_receiver.next()).unwrap();
             match config {
-                AccessControlOp::Remove(pk) => assert_eq!(pk, pk_c),
+                AccessControlOp::Deny(pk) => assert_eq!(pk, pk_c),
                 _ => unreachable!(),
             };
         }
@@ -695,4 +956,402 @@ mod tests {
             thread_pool.clone(),
         ));
     }
+
+    // ------------------------------------------------------
+    // ------------------------------------------------------
+
+    async fn task_listen_pool_handle_config_send_failed<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let (plain_conn_sender, _plain_conn_receiver) = mpsc::channel(0);
+        let (relay_closed_sender, _relay_closed_receiver) = mpsc::channel(0);
+        let (plain_conn_closed_sender, _plain_conn_closed_receiver) = mpsc::channel(0);
+
+        let (listen_req_sender, mut listen_req_receiver) = mpsc::channel(0);
+        let listener = DummyListener::new(listen_req_sender, spawner.clone());
+
+        let backoff_ticks = 2;
+        let mut listen_pool = ListenPool::<u32, _, _>::new(
+            plain_conn_sender,
+            relay_closed_sender,
+            plain_conn_closed_sender,
+            listener,
+            backoff_ticks,
+            spawner,
+        );
+
+        await!(listen_pool.handle_config(LpConfig::SetLocalAddresses(vec![0x0u32, 0x1u32])))
+            .unwrap();
+
+        let mut listen_reqs = HashMap::new();
+        for _ in 0..2 {
+            let listen_req = await!(listen_req_receiver.next()).unwrap();
+            let relay_address = listen_req.arg.0;
+            listen_reqs.insert(relay_address, listen_req);
+        }
+
+        // The listener of relay 0x0u32 stops receiving access control updates:
+        let mut listen_req0 = listen_reqs.remove(&0x0u32).unwrap();
+        listen_req0.config_receiver.close();
+        let mut listen_req1 = listen_reqs.remove(&0x1u32).unwrap();
+
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let fut_config =
+            listen_pool.handle_config(LpConfig::UpdateFriend((pk_b.clone(), vec![0x0u32, 0x1u32])));
+        let fut_recv = listen_req1.config_receiver.next();
+        let (res, opt_config1) = await!(future::join(fut_config, fut_recv));
+
+        // Relay 0x1u32 received the update:
+        match opt_config1.unwrap() {
+            AccessControlOp::Allow(pk) => assert_eq!(pk, pk_b),
+            _ => unreachable!(),
+        };
+
+        // The failure to update relay 0x0u32 is reported:
+        match res {
+            Err(HandleConfigError::AccessControlSendFailed(addresses)) => {
+                assert_eq!(addresses, vec![0x0u32])
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    #[test]
+    fn test_listen_pool_handle_config_send_failed() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_listen_pool_handle_config_send_failed(
+            thread_pool.clone(),
+        ));
+    }
+
+    // ------------------------------------------------------
+    // ------------------------------------------------------
+
+    async fn task_lp_events_priority() {
+        let (mut config_sender, incoming_config) = mpsc::channel(1);
+        let (mut relay_closed_sender, relay_closed_receiver) = mpsc::channel(1);
+        let (_plain_conn_closed_sender, plain_conn_closed_receiver) = mpsc::channel(0);
+        let (_tick_sender, timer_stream) = mpsc::channel::<TimerTick>(0);
+
+        // A relay closed event and a config arrive at the same time:
+        await!(relay_closed_sender.send((0x0u32, 0))).unwrap();
+        await!(config_sender.send(LpConfig::SetLocalAddresses(vec![0x1u32]))).unwrap();
+
+        let mut incoming_events = lp_events(
+            incoming_config,
+            relay_closed_receiver,
+            plain_conn_closed_receiver,
+            timer_stream,
+        );
+
+        // The config is applied first:
+        match await!(incoming_events.next()).unwrap() {
+            LpEvent::Config(LpConfig::SetLocalAddresses(addresses)) => {
+                assert_eq!(addresses, vec![0x1u32])
+            }
+            _ => unreachable!(),
+        };
+        match await!(incoming_events.next()).unwrap() {
+            LpEvent::RelayClosed((address, generation)) => {
+                assert_eq!(address, 0x0u32);
+                assert_eq!(generation, 0);
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    #[test]
+    fn test_lp_events_priority() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_lp_events_priority());
+    }
+
+    // ------------------------------------------------------
+    // ------------------------------------------------------
+
+    async fn task_pool_listener_burst<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        // Create a mock time service:
+        let (_tick_sender_receiver, timer_client) = dummy_timer_multi_sender(spawner.clone());
+        let backoff_ticks = 2;
+        let max_concurrent_encrypt = 16;
+        let channel_len = 16;
+        let burst_len = 8;
+
+        let (listen_req_sender, mut listen_req_receiver) = mpsc::channel(0);
+        let listener = DummyListener::new(listen_req_sender, spawner.clone());
+
+        // A dummy encrypt transform, returning the connection as is:
+        let encrypt_transform = FuncFutTransform::new(|input| Box::pin(future::ready(Some(input))));
+
+        let pool_listener = PoolListener::<u32, _, _, _>::new(
+            listener,
+            encrypt_transform,
+            max_concurrent_encrypt,
+            None,
+            backoff_ticks,
+            channel_len,
+            timer_client,
+            spawner.clone(),
+        );
+
+        let (mut config_sender, mut incoming_conns) = pool_listener.listen(());
+
+        await!(config_sender.send(LpConfig::SetLocalAddresses(vec![0x0u32]))).unwrap();
+        let mut listen_req = await!(listen_req_receiver.next()).unwrap();
+
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+
+        // Send a burst of connections, without reading any of them on the other side.
+        // The internal buffers should be able to hold all of them:
+        let mut conns = Vec::new();
+        for _ in 0..burst_len {
+            let (remote_conn, local_conn) = raw_conn_pair(&pk_b);
+            await!(listen_req.conn_sender.send(remote_conn)).unwrap();
+            conns.push(local_conn);
+        }
+
+        for _ in 0..burst_len {
+            let (pk, _conn) = await!(incoming_conns.next()).unwrap();
+            assert_eq!(pk, pk_b);
+        }
+    }
+
+    #[test]
+    fn test_pool_listener_burst() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_pool_listener_burst(thread_pool.clone()));
+    }
+
+    /// A spawner that spawns the first `num_allowed` futures, and refuses to spawn any further
+    /// futures.
+    #[derive(Clone)]
+    struct RefusingSpawner<S> {
+        spawner: S,
+        num_allowed: Arc<Mutex<usize>>,
+    }
+
+    impl<S> RefusingSpawner<S> {
+        fn new(spawner: S, num_allowed: usize) -> Self {
+            RefusingSpawner {
+                spawner,
+                num_allowed: Arc::new(Mutex::new(num_allowed)),
+            }
+        }
+    }
+
+    impl<S> Spawn for RefusingSpawner<S>
+    where
+        S: Spawn,
+    {
+        fn spawn_obj(&mut self, future: FutureObj<'static, ()>) -> Result<(), SpawnError> {
+            let mut num_allowed = self.num_allowed.lock().unwrap();
+            if *num_allowed == 0 {
+                return Err(SpawnError::shutdown());
+            }
+            *num_allowed -= 1;
+            self.spawner.spawn_obj(future)
+        }
+    }
+
+    async fn task_pool_listener_spawn_failure<S>(spawner: S, num_allowed: usize)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let (_tick_sender_receiver, timer_client) = dummy_timer_multi_sender(spawner.clone());
+
+        let (listen_req_sender, _listen_req_receiver) = mpsc::channel(0);
+        let listener = DummyListener::new(listen_req_sender, spawner.clone());
+
+        let encrypt_transform = FuncFutTransform::new(|input| Box::pin(future::ready(Some(input))));
+
+        let pool_listener = PoolListener::<u32, _, _, _>::new(
+            listener,
+            encrypt_transform,
+            16,   // max_concurrent_encrypt
+            None, // opt_max_concurrent_encrypt_per_relay
+            2,    // backoff_ticks
+            16,   // channel_len
+            timer_client,
+            RefusingSpawner::new(spawner.clone(), num_allowed),
+        );
+
+        let (mut config_sender, mut incoming_conns) = pool_listener.listen(());
+
+        // The listener is observably dead:
+        assert!(await!(incoming_conns.next()).is_none());
+        assert!(await!(config_sender.send(LpConfig::SetLocalAddresses(vec![0x0u32]))).is_err());
+    }
+
+    #[test]
+    fn test_pool_listener_spawn_failure() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        // Spawning the encryption loop fails:
+        thread_pool.run(task_pool_listener_spawn_failure(thread_pool.clone(), 0));
+        // Spawning the encryption loop succeeds, but spawning listen_pool_loop fails:
+        thread_pool.run(task_pool_listener_spawn_failure(thread_pool.clone(), 1));
+    }
+
+    // ----------------------------------------------------------------
+    // ----------------------------------------------------------------
+
+    async fn task_listen_pool_access_control_snapshot<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let (listen_req_sender, mut listen_req_receiver) = mpsc::channel(0);
+        let listener = DummyListener::new(listen_req_sender, spawner.clone());
+
+        let (plain_conn_sender, _plain_conn_receiver) = mpsc::channel(0);
+        let (relay_closed_sender, mut relay_closed_receiver) = mpsc::channel(0);
+        let (plain_conn_closed_sender, _plain_conn_closed_receiver) = mpsc::channel(0);
+        let backoff_ticks = 2;
+
+        let mut listen_pool = ListenPool::<u32, _, _>::new(
+            plain_conn_sender,
+            relay_closed_sender,
+            plain_conn_closed_sender,
+            listener,
+            backoff_ticks,
+            spawner.clone(),
+        );
+
+        await!(listen_pool.handle_config(LpConfig::SetLocalAddresses(vec![0x0u32]))).unwrap();
+        let listen_req = await!(listen_req_receiver.next()).unwrap();
+
+        // Simulate closing of the listener:
+        drop(listen_req);
+        let (address, generation) = await!(relay_closed_receiver.next()).unwrap();
+        listen_pool
+            .handle_relay_closed(address, generation)
+            .unwrap();
+
+        // Add many friends while we are disconnected from the relay:
+        let public_keys = (0..0x100usize)
+            .map(|i| {
+                let mut public_key_bytes = [0u8; PUBLIC_KEY_LEN];
+                public_key_bytes[0] = (i >> 8) as u8;
+                public_key_bytes[1] = i as u8;
+                PublicKey::from(&public_key_bytes)
+            })
+            .collect::<Vec<_>>();
+        for public_key in &public_keys {
+            await!(listen_pool.handle_config(LpConfig::UpdateFriend((public_key.clone(), vec![]))))
+                .unwrap();
+        }
+        await!(listen_pool.handle_config(LpConfig::RemoveFriend(public_keys[0].clone()))).unwrap();
+
+        // Forget the friends of the relay. If the access control was rebuilt from the relay's
+        // friends when reconnecting, it would not allow any friend:
+        listen_pool
+            .state
+            .relays
+            .get_mut(&0x0u32)
+            .unwrap()
+            .friends
+            .clear();
+
+        // Wait until backoff_ticks time passes:
+        for _ in 0..backoff_ticks {
+            listen_pool.handle_timer_tick().unwrap();
+        }
+
+        let listen_req = await!(listen_req_receiver.next()).unwrap();
+        let (ref relay_address, ref access_control) = listen_req.arg;
+        assert_eq!(*relay_address, 0x0u32);
+        assert!(!access_control.is_allowed(&public_keys[0]));
+        for public_key in &public_keys[1..] {
+            assert!(access_control.is_allowed(public_key));
+        }
+    }
+
+    #[test]
+    fn test_listen_pool_access_control_snapshot() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_listen_pool_access_control_snapshot(
+            thread_pool.clone(),
+        ));
+    }
+
+    // ----------------------------------------------------------------
+    // ----------------------------------------------------------------
+
+    async fn task_listen_pool_stale_relay_closed<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let (listen_req_sender, mut listen_req_receiver) = mpsc::channel(0);
+        let listener = DummyListener::new(listen_req_sender, spawner.clone());
+
+        let (plain_conn_sender, _plain_conn_receiver) = mpsc::channel(0);
+        let (relay_closed_sender, mut relay_closed_receiver) = mpsc::channel(0);
+        let (plain_conn_closed_sender, _plain_conn_closed_receiver) = mpsc::channel(0);
+        let backoff_ticks = 2;
+
+        let mut listen_pool = ListenPool::<u32, _, _>::new(
+            plain_conn_sender,
+            relay_closed_sender,
+            plain_conn_closed_sender,
+            listener,
+            backoff_ticks,
+            spawner.clone(),
+        );
+
+        await!(listen_pool.handle_config(LpConfig::SetLocalAddresses(vec![0x0u32]))).unwrap();
+        let listen_req0 = await!(listen_req_receiver.next()).unwrap();
+
+        // The first listener is closed, but we learn about it only later:
+        drop(listen_req0);
+        let (address, stale_generation) = await!(relay_closed_receiver.next()).unwrap();
+        assert_eq!(address, 0x0u32);
+
+        // Meanwhile, we stop using the first listener (For example, because it missed an access
+        // control update), and listen again to the relay after backoff_ticks:
+        listen_pool.backoff_relay(&0x0u32);
+        for _ in 0..backoff_ticks {
+            listen_pool.handle_timer_tick().unwrap();
+        }
+        let mut listen_req1 = await!(listen_req_receiver.next()).unwrap();
+
+        // The closing of the first listener does not affect the second listener:
+        listen_pool
+            .handle_relay_closed(address, stale_generation)
+            .unwrap();
+        match &listen_pool.state.relays.get(&0x0u32).unwrap().status {
+            RelayStatus::Connected((generation, _)) => assert_ne!(*generation, stale_generation),
+            RelayStatus::Waiting(_) => unreachable!(),
+        };
+
+        // The second listener still receives access control updates:
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let fut_config =
+            listen_pool.handle_config(LpConfig::UpdateFriend((pk_b.clone(), vec![0x0u32])));
+        let fut_recv = listen_req1.config_receiver.next();
+        let (res, opt_config1) = await!(future::join(fut_config, fut_recv));
+        res.unwrap();
+        match opt_config1.unwrap() {
+            AccessControlOp::Allow(pk) => assert_eq!(pk, pk_b),
+            _ => unreachable!(),
+        };
+
+        // Closing of the second listener is handled:
+        drop(listen_req1);
+        let (address, generation) = await!(relay_closed_receiver.next()).unwrap();
+        listen_pool
+            .handle_relay_closed(address, generation)
+            .unwrap();
+        match &listen_pool.state.relays.get(&0x0u32).unwrap().status {
+            RelayStatus::Waiting(_) => {}
+            RelayStatus::Connected(_) => unreachable!(),
+        };
+    }
+
+    #[test]
+    fn test_listen_pool_stale_relay_closed() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_listen_pool_stale_relay_closed(thread_pool.clone()));
+    }
 }
@@ -102,11 +102,13 @@ pub async fn spawn_channeler<RA, C, ET, KT, S>(
     backoff_ticks: usize,
     conn_timeout_ticks: usize,
     max_concurrent_encrypt: usize,
+    opt_max_concurrent_encrypt_per_relay: Option<usize>,
+    channel_len: usize,
     enc_relay_connector: C,
     encrypt_transform: ET,
     keepalive_transform: KT,
     from_funder: mpsc::Receiver<FunderToChanneler<RA>>,
-    to_funder: mpsc::Sender<ChannelerToFunder>,
+    to_funder: mpsc::Sender<ChannelerToFunder<RA>>,
     spawner: S,
 ) -> Result<(), ChannelerError>
 where
@@ -132,6 +134,7 @@ where
         client_connector.clone(),
         connect_encrypt_transform,
         backoff_ticks,
+        conn_timeout_ticks,
         spawner.clone(),
     );
 
@@ -149,7 +152,9 @@ where
         client_listener,
         listen_encrypt_transform,
         max_concurrent_encrypt,
+        opt_max_concurrent_encrypt_per_relay,
         backoff_ticks,
+        channel_len,
         timer_client.clone(),
         spawner.clone(),
     );
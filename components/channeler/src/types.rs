@@ -1,3 +1,6 @@
+#[cfg(test)]
+use futures::channel::mpsc;
+
 use common::access_control::{AccessControl, AccessControlOp};
 use common::conn::ConnPair;
 use crypto::identity::PublicKey;
@@ -6,3 +9,53 @@ pub type RawConn = ConnPair<Vec<u8>, Vec<u8>>;
 
 pub type AccessControlPk = AccessControl<PublicKey>;
 pub type AccessControlOpPk = AccessControlOp<PublicKey>;
+
+/// Create two connected in memory connections, for testing.
+/// Data sent through one connection arrives at the other connection.
+///
+/// The first connection is returned together with `public_key`, in the form a listener hands
+/// over an incoming connection from the remote `public_key`. The second connection is the local
+/// end of the same connection.
+#[cfg(test)]
+pub fn raw_conn_pair(public_key: &PublicKey) -> ((PublicKey, RawConn), RawConn) {
+    let (local_sender, remote_receiver) = mpsc::channel(0);
+    let (remote_sender, local_receiver) = mpsc::channel(0);
+    (
+        (public_key.clone(), (remote_sender, remote_receiver)),
+        (local_sender, local_receiver),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::ThreadPool;
+    use futures::{SinkExt, StreamExt};
+
+    use crypto::identity::PUBLIC_KEY_LEN;
+
+    async fn task_raw_conn_pair() {
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let ((public_key, remote_conn), local_conn) = raw_conn_pair(&pk_b);
+        assert_eq!(public_key, pk_b);
+
+        let (mut remote_sender, mut remote_receiver) = remote_conn;
+        let (mut local_sender, mut local_receiver) = local_conn;
+
+        await!(remote_sender.send(vec![1, 2, 3])).unwrap();
+        assert_eq!(await!(local_receiver.next()).unwrap(), vec![1, 2, 3]);
+
+        await!(local_sender.send(vec![3, 2, 1])).unwrap();
+        assert_eq!(await!(remote_receiver.next()).unwrap(), vec![3, 2, 1]);
+
+        // Closing one end is observed by the other end:
+        drop(local_sender);
+        assert!(await!(remote_receiver.next()).is_none());
+    }
+
+    #[test]
+    fn test_raw_conn_pair() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_raw_conn_pair());
+    }
+}
@@ -2,39 +2,71 @@ use std::collections::HashSet;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum AccessControlOp<T> {
-    Add(T),
-    Remove(T),
+    Allow(T),
+    Deny(T),
+}
+
+/// Determines which items are allowed by an AccessControl.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessControlMode {
+    /// Only items that were explicitly allowed are allowed.
+    AllowList,
+    /// All items are allowed, except for items that were explicitly denied.
+    DenyList,
+}
+
+impl Default for AccessControlMode {
+    fn default() -> Self {
+        AccessControlMode::AllowList
+    }
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct AccessControl<T: std::cmp::Eq + std::hash::Hash> {
-    allowed: HashSet<T>,
+    mode: AccessControlMode,
+    /// Allowed items in AllowList mode, denied items in DenyList mode.
+    items: HashSet<T>,
 }
 
 impl<T> AccessControl<T>
 where
     T: std::cmp::Eq + std::hash::Hash,
 {
+    /// Create an empty AccessControl in AllowList mode (Nothing is allowed).
     pub fn new() -> AccessControl<T> {
+        AccessControl::with_mode(AccessControlMode::AllowList)
+    }
+
+    pub fn with_mode(mode: AccessControlMode) -> AccessControl<T> {
         AccessControl {
-            allowed: HashSet::new(),
+            mode,
+            items: HashSet::new(),
         }
     }
 
-    pub fn apply_op(&mut self, allowed_op: AccessControlOp<T>) {
-        match allowed_op {
-            AccessControlOp::Add(item) => {
-                self.allowed.insert(item);
+    pub fn mode(&self) -> AccessControlMode {
+        self.mode
+    }
+
+    pub fn apply_op(&mut self, access_control_op: AccessControlOp<T>) {
+        match (self.mode, access_control_op) {
+            (AccessControlMode::AllowList, AccessControlOp::Allow(item))
+            | (AccessControlMode::DenyList, AccessControlOp::Deny(item)) => {
+                self.items.insert(item);
             }
-            AccessControlOp::Remove(item) => {
-                self.allowed.remove(&item);
+            (AccessControlMode::AllowList, AccessControlOp::Deny(item))
+            | (AccessControlMode::DenyList, AccessControlOp::Allow(item)) => {
+                self.items.remove(&item);
             }
         }
     }
 
     /// Check if a certain public key is allowed.
     pub fn is_allowed(&self, item: &T) -> bool {
-        self.allowed.contains(item)
+        match self.mode {
+            AccessControlMode::AllowList => self.items.contains(item),
+            AccessControlMode::DenyList => !self.items.contains(item),
+        }
     }
 }
 
@@ -51,29 +83,55 @@ mod tests {
         assert!(!ac.is_allowed(&a_public_key));
         assert!(!ac.is_allowed(&b_public_key));
 
-        // Add a:
-        ac.apply_op(AccessControlOp::Add(a_public_key.clone()));
+        // Allow a:
+        ac.apply_op(AccessControlOp::Allow(a_public_key.clone()));
         assert!(ac.is_allowed(&a_public_key));
         assert!(!ac.is_allowed(&b_public_key));
 
-        // Add b:
-        ac.apply_op(AccessControlOp::Add(b_public_key.clone()));
+        // Allow b:
+        ac.apply_op(AccessControlOp::Allow(b_public_key.clone()));
         assert!(ac.is_allowed(&a_public_key));
         assert!(ac.is_allowed(&b_public_key));
 
-        // Remove a:
-        ac.apply_op(AccessControlOp::Remove(a_public_key.clone()));
+        // Deny a:
+        ac.apply_op(AccessControlOp::Deny(a_public_key.clone()));
         assert!(!ac.is_allowed(&a_public_key));
         assert!(ac.is_allowed(&b_public_key));
 
-        // Remove b:
-        ac.apply_op(AccessControlOp::Remove(b_public_key.clone()));
+        // Deny b:
+        ac.apply_op(AccessControlOp::Deny(b_public_key.clone()));
         assert!(!ac.is_allowed(&a_public_key));
         assert!(!ac.is_allowed(&b_public_key));
 
-        // Remove b again:
-        ac.apply_op(AccessControlOp::Remove(b_public_key.clone()));
+        // Deny b again:
+        ac.apply_op(AccessControlOp::Deny(b_public_key.clone()));
         assert!(!ac.is_allowed(&a_public_key));
         assert!(!ac.is_allowed(&b_public_key));
     }
+
+    #[test]
+    fn test_access_control_deny_list() {
+        let a_public_key = 0xaa;
+        let b_public_key = 0xbb;
+
+        let mut ac = AccessControl::with_mode(AccessControlMode::DenyList);
+        assert_eq!(ac.mode(), AccessControlMode::DenyList);
+        assert!(ac.is_allowed(&a_public_key));
+        assert!(ac.is_allowed(&b_public_key));
+
+        // Deny a:
+        ac.apply_op(AccessControlOp::Deny(a_public_key.clone()));
+        assert!(!ac.is_allowed(&a_public_key));
+        assert!(ac.is_allowed(&b_public_key));
+
+        // Allow b (Already allowed):
+        ac.apply_op(AccessControlOp::Allow(b_public_key.clone()));
+        assert!(!ac.is_allowed(&a_public_key));
+        assert!(ac.is_allowed(&b_public_key));
+
+        // Allow a:
+        ac.apply_op(AccessControlOp::Allow(a_public_key.clone()));
+        assert!(ac.is_allowed(&a_public_key));
+        assert!(ac.is_allowed(&b_public_key));
+    }
 }
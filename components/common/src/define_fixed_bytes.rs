@@ -4,7 +4,7 @@ macro_rules! define_fixed_bytes {
         #[derive(
             Default, Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize,
         )]
-        pub struct $name([u8; $len]);
+        pub struct $name(#[serde(with = "::common::fixed_bytes_serde")] [u8; $len]);
 
         impl $name {
             #[allow(unused)]
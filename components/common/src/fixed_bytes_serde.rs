@@ -0,0 +1,78 @@
+use serde::de::{Deserialize, Deserializer, Error};
+use serde::ser::{Serialize, Serializer};
+
+/// Serialization of fixed size byte arrays, used by `define_fixed_bytes!`.
+///
+/// Human readable formats (Like json) get a hex string, which also allows using the array as a
+/// map key. Other formats (Like bincode) get the plain array.
+/// Meant to be used with `#[serde(with = "::common::fixed_bytes_serde")]`.
+pub fn serialize<T, S>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize + AsRef<[u8]>,
+    S: Serializer,
+{
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&to_hex(bytes.as_ref()))
+    } else {
+        bytes.serialize(serializer)
+    }
+}
+
+/// Deserialize a fixed size byte array serialized using `serialize`.
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: Deserialize<'de> + Default + AsMut<[u8]>,
+    D: Deserializer<'de>,
+{
+    if deserializer.is_human_readable() {
+        let hex_str = String::deserialize(deserializer)?;
+        let mut bytes = T::default();
+        if !from_hex(&hex_str, bytes.as_mut()) {
+            return Err(D::Error::custom("invalid hex string"));
+        }
+        Ok(bytes)
+    } else {
+        T::deserialize(deserializer)
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decode `hex_str` into `bytes`.
+/// Returns false if `hex_str` is not a hex string of the exact length of `bytes`.
+fn from_hex(hex_str: &str, bytes: &mut [u8]) -> bool {
+    if !hex_str.chars().all(|c| c.is_ascii_hexdigit()) || hex_str.len() != bytes.len() * 2 {
+        return false;
+    }
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        // Can not fail, as we already checked that all the characters are hex digits:
+        *byte = u8::from_str_radix(&hex_str[2 * i..2 * i + 2], 16).unwrap();
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex() {
+        let bytes = [0x00u8, 0x01, 0xab, 0xff];
+        assert_eq!(to_hex(&bytes), "0001abff");
+
+        let mut decoded = [0u8; 4];
+        assert!(from_hex("0001abff", &mut decoded));
+        assert_eq!(decoded, bytes);
+        assert!(from_hex("0001ABFF", &mut decoded));
+        assert_eq!(decoded, bytes);
+
+        // Wrong length:
+        assert!(!from_hex("0001ab", &mut decoded));
+        assert!(!from_hex("0001abff00", &mut decoded));
+        // Not hex:
+        assert!(!from_hex("0001abfg", &mut decoded));
+        assert!(!from_hex("0001ab+f", &mut decoded));
+    }
+}
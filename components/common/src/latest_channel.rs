@@ -0,0 +1,125 @@
+use futures::task::Waker;
+use futures::{Poll, Stream};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+struct Inner<T> {
+    opt_value: Option<T>,
+    opt_waker: Option<Waker>,
+    sender_closed: bool,
+    receiver_closed: bool,
+}
+
+#[derive(Debug)]
+pub struct LatestReceiverClosed;
+
+/// The sending side of a latest value channel.
+/// Sending never blocks: A value that was not yet received is replaced by the newly sent value.
+pub struct LatestSender<T> {
+    inner: Arc<Mutex<Inner<T>>>,
+}
+
+/// The receiving side of a latest value channel.
+/// Yields only the most recently sent value. Intermediate values might be skipped.
+pub struct LatestReceiver<T> {
+    inner: Arc<Mutex<Inner<T>>>,
+}
+
+/// Create a channel that holds at most one value. Useful for sending full snapshots of a
+/// state to a possibly slow receiver, where only the latest snapshot is relevant.
+pub fn latest_channel<T>() -> (LatestSender<T>, LatestReceiver<T>) {
+    let inner = Arc::new(Mutex::new(Inner {
+        opt_value: None,
+        opt_waker: None,
+        sender_closed: false,
+        receiver_closed: false,
+    }));
+    (
+        LatestSender {
+            inner: inner.clone(),
+        },
+        LatestReceiver { inner },
+    )
+}
+
+impl<T> LatestSender<T> {
+    /// Send a value, replacing any value that was not yet received.
+    pub fn send(&mut self, value: T) -> Result<(), LatestReceiverClosed> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.receiver_closed {
+            return Err(LatestReceiverClosed);
+        }
+        inner.opt_value = Some(value);
+        if let Some(waker) = inner.opt_waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+}
+
+impl<T> Drop for LatestSender<T> {
+    fn drop(&mut self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.sender_closed = true;
+        if let Some(waker) = inner.opt_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for LatestReceiver<T> {
+    fn drop(&mut self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.receiver_closed = true;
+        inner.opt_value = None;
+    }
+}
+
+impl<T> Stream for LatestReceiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, waker: &Waker) -> Poll<Option<Self::Item>> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(value) = inner.opt_value.take() {
+            return Poll::Ready(Some(value));
+        }
+        if inner.sender_closed {
+            return Poll::Ready(None);
+        }
+        inner.opt_waker = Some(waker.clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::ThreadPool;
+    use futures::StreamExt;
+
+    #[test]
+    fn test_latest_channel_keeps_latest() {
+        let (mut sender, mut receiver) = latest_channel::<u32>();
+        // Sending does not block, even if nothing is received:
+        for i in 0..16u32 {
+            sender.send(i).unwrap();
+        }
+
+        let mut thread_pool = ThreadPool::new().unwrap();
+        assert_eq!(thread_pool.run(receiver.next()), Some(15));
+
+        sender.send(16).unwrap();
+        drop(sender);
+        // A value sent before the sender was dropped is still received:
+        assert_eq!(thread_pool.run(receiver.next()), Some(16));
+        assert_eq!(thread_pool.run(receiver.next()), None);
+    }
+
+    #[test]
+    fn test_latest_channel_receiver_closed() {
+        let (mut sender, receiver) = latest_channel::<u32>();
+        sender.send(0).unwrap();
+        drop(receiver);
+        assert!(sender.send(1).is_err());
+    }
+}
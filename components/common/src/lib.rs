@@ -20,6 +20,7 @@ pub mod safe_arithmetic;
 pub mod big_array;
 #[macro_use]
 pub mod define_fixed_bytes;
+pub mod fixed_bytes_serde;
 pub mod async_adapter;
 // pub mod frame_codec;
 pub mod access_control;
@@ -30,8 +31,10 @@ pub mod conn;
 pub mod dummy_connector;
 pub mod dummy_listener;
 pub mod futures_compat;
+pub mod latest_channel;
 pub mod multi_consumer;
 pub mod mutable_state;
+pub mod ordered_serialize;
 pub mod select_streams;
 pub mod state_service;
 pub mod transform_pool;
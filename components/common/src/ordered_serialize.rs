@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+
+use serde::ser::{Serialize, Serializer};
+
+/// Serialize a map, emitting its entries ordered by key.
+///
+/// Unordered maps (Like `HashMap` or `im::HashMap`) iterate over their entries in an arbitrary
+/// order, which means that the same logical map could be serialized into different bytes.
+/// Meant to be used with `#[serde(serialize_with = "serialize_ordered_map")]`. The output is
+/// compatible with the default `Deserialize` implementation of the map.
+pub fn serialize_ordered_map<'a, M, K, V, S>(map: &'a M, serializer: S) -> Result<S::Ok, S::Error>
+where
+    &'a M: IntoIterator<Item = (&'a K, &'a V)>,
+    K: Ord + Serialize + 'a,
+    V: Serialize + 'a,
+    S: Serializer,
+{
+    map.into_iter()
+        .collect::<BTreeMap<_, _>>()
+        .serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn to_json<'a, M>(map: &'a M) -> Vec<u8>
+    where
+        &'a M: IntoIterator<Item = (&'a u32, &'a u32)>,
+    {
+        let mut buff = Vec::new();
+        serialize_ordered_map(map, &mut serde_json::Serializer::new(&mut buff)).unwrap();
+        buff
+    }
+
+    #[test]
+    fn test_serialize_ordered_map() {
+        // Every HashMap is created with different random hash keys, so the iteration order of
+        // the two maps is most likely different:
+        let map1 = (0..16u32).map(|i| (i, i * 2)).collect::<HashMap<_, _>>();
+        let map2 = (0..16u32)
+            .rev()
+            .map(|i| (i, i * 2))
+            .collect::<HashMap<_, _>>();
+
+        let ordered_map = (0..16u32).map(|i| (i, i * 2)).collect::<BTreeMap<_, _>>();
+        let expected = serde_json::to_vec(&ordered_map).unwrap();
+
+        assert_eq!(to_json(&map1), expected);
+        assert_eq!(to_json(&map2), expected);
+
+        // The output can be deserialized by the default implementation:
+        let map3: HashMap<u32, u32> = serde_json::from_slice(&expected).unwrap();
+        assert_eq!(map3, map1);
+    }
+}
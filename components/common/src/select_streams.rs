@@ -74,6 +74,59 @@ macro_rules! select_streams {
     };
 }
 
+/// Select over a few streams, always preferring the earlier streams.
+/// The streams are polled in the order they were given. If a few streams are ready at the same
+/// time, an item is returned from the first ready stream.
+///
+/// Note that a busy stream may starve all the streams that come after it.
+pub struct PrioSelectStreams<'a, T> {
+    opt_streams: Vec<Option<BoxStream<'a, T>>>,
+}
+
+impl<'a, T> Stream for PrioSelectStreams<'a, T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, waker: &Waker) -> Poll<Option<Self::Item>> {
+        for opt_stream in &mut self.opt_streams {
+            let res = match opt_stream {
+                Some(stream) => stream.poll_next_unpin(waker),
+                None => continue,
+            };
+            match res {
+                Poll::Pending => {}
+                Poll::Ready(Some(t)) => return Poll::Ready(Some(t)),
+                Poll::Ready(None) => *opt_stream = None,
+            }
+        }
+
+        if self.opt_streams.iter().all(Option::is_none) {
+            // No more streams to poll:
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+pub fn prio_select_streams<'a, T>(streams: Vec<BoxStream<'a, T>>) -> PrioSelectStreams<'a, T> {
+    PrioSelectStreams {
+        opt_streams: streams.into_iter().map(Some).collect(),
+    }
+}
+
+#[macro_export]
+macro_rules! prio_select_streams {
+    ( $( $x:expr ),* ) => {
+        {
+            let mut streams_vec: Vec<BoxStream<'_,_>> = Vec::new();
+            $(
+                streams_vec.push(Box::pin($x));
+            )*
+            prio_select_streams(streams_vec)
+        }
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -94,5 +147,17 @@ mod tests {
         assert_eq!(result.len(), 4 + 5 + 4);
     }
 
+    #[test]
+    fn test_prio_select_streams_order() {
+        let s1 = stream::iter(vec![1, 2, 3u8]);
+        let s2 = stream::iter(vec![4, 5u8]);
+        let s3 = stream::iter(vec![6, 7, 8u8]);
+
+        // All the streams are always ready, so the earlier streams are drained first:
+        let selected = prio_select_streams![s1, s2, s3];
+        let result = block_on(selected.collect::<Vec<u8>>());
+        assert_eq!(result, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
     // TODO: Add more tests here.
 }
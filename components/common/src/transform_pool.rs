@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
 use futures::channel::mpsc;
 use futures::task::{Spawn, SpawnExt};
 use futures::{future, stream, Sink, SinkExt, Stream, StreamExt};
@@ -9,10 +12,10 @@ pub enum TransformPoolLoopError {
     SpawnError,
 }
 
-enum TransformPoolEvent<I> {
-    Incoming(I),
+enum TransformPoolEvent<K, I> {
+    Incoming((K, I)),
     IncomingClosed,
-    TransformDone,
+    TransformDone(K),
 }
 
 /// Transform a stream of incoming items to outgoing items.
@@ -25,7 +28,7 @@ pub async fn transform_pool_loop<IN, OUT, I, O, T, S>(
     outgoing: O,
     transform: T,
     max_concurrent: usize,
-    mut spawner: S,
+    spawner: S,
 ) -> Result<(), TransformPoolLoopError>
 where
     IN: Send + 'static,
@@ -34,6 +37,40 @@ where
     I: Stream<Item = IN> + Unpin,
     O: Sink<SinkItem = OUT> + Clone + Send + Unpin + 'static,
     S: Spawn,
+{
+    let incoming = incoming.map(|input_value| ((), input_value));
+    await!(keyed_transform_pool_loop(
+        incoming,
+        outgoing,
+        transform,
+        max_concurrent,
+        None,
+        spawner
+    ))
+}
+
+/// Similar to `transform_pool_loop`, but every incoming item is tagged with a key (For example:
+/// the address it arrived from).
+///
+/// If `opt_max_concurrent_per_key` is provided, it limits the amount of concurrent
+/// transformations of items with the same key. This makes sure that a burst of items with one key
+/// can not take over all the available transformations.
+pub async fn keyed_transform_pool_loop<K, IN, OUT, I, O, T, S>(
+    incoming: I,
+    outgoing: O,
+    transform: T,
+    max_concurrent: usize,
+    opt_max_concurrent_per_key: Option<usize>,
+    mut spawner: S,
+) -> Result<(), TransformPoolLoopError>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    IN: Send + 'static,
+    OUT: Send,
+    T: FutTransform<Input = IN, Output = Option<OUT>> + Clone + Send + 'static,
+    I: Stream<Item = (K, IN)> + Unpin,
+    O: Sink<SinkItem = OUT> + Clone + Send + Unpin + 'static,
+    S: Spawn,
 {
     let incoming = incoming
         .map(TransformPoolEvent::Incoming)
@@ -41,21 +78,33 @@ where
             TransformPoolEvent::IncomingClosed,
         )));
 
-    let (close_sender, close_receiver) = mpsc::channel::<()>(0);
-    let close_receiver = close_receiver.map(|()| TransformPoolEvent::TransformDone);
+    let (close_sender, close_receiver) = mpsc::channel::<K>(0);
+    let close_receiver = close_receiver.map(TransformPoolEvent::TransformDone);
 
     let mut incoming_events = incoming.select(close_receiver);
     let mut num_concurrent: usize = 0;
+    let mut num_concurrent_per_key: HashMap<K, usize> = HashMap::new();
     let mut incoming_closed = false;
     while let Some(event) = await!(incoming_events.next()) {
         match event {
-            TransformPoolEvent::Incoming(input_value) => {
+            TransformPoolEvent::Incoming((key, input_value)) => {
                 if num_concurrent >= max_concurrent {
                     warn!("transform_pool_loop: Dropping connection: max_concurrent exceeded");
                     // We drop the input value because we don't have any room to process it.
                     continue;
                 }
+                let key_concurrent = num_concurrent_per_key.get(&key).cloned().unwrap_or(0);
+                if let Some(max_concurrent_per_key) = opt_max_concurrent_per_key {
+                    if key_concurrent >= max_concurrent_per_key {
+                        warn!(
+                            "transform_pool_loop: Dropping connection: \
+                             max_concurrent_per_key exceeded"
+                        );
+                        continue;
+                    }
+                }
                 num_concurrent = num_concurrent.checked_add(1).unwrap();
+                num_concurrent_per_key.insert(key.clone(), key_concurrent.checked_add(1).unwrap());
                 let mut c_outgoing = outgoing.clone();
                 let mut c_transform = transform.clone();
                 let mut c_close_sender = close_sender.clone();
@@ -63,7 +112,7 @@ where
                     if let Some(output_value) = await!(c_transform.transform(input_value)) {
                         let _ = await!(c_outgoing.send(output_value));
                     }
-                    let _ = await!(c_close_sender.send(()));
+                    let _ = await!(c_close_sender.send(key));
                 };
                 spawner
                     .spawn(fut)
@@ -72,8 +121,18 @@ where
             TransformPoolEvent::IncomingClosed => {
                 incoming_closed = true;
             }
-            TransformPoolEvent::TransformDone => {
+            TransformPoolEvent::TransformDone(key) => {
                 num_concurrent = num_concurrent.checked_sub(1).unwrap();
+                let key_concurrent = num_concurrent_per_key
+                    .get(&key)
+                    .unwrap()
+                    .checked_sub(1)
+                    .unwrap();
+                if key_concurrent == 0 {
+                    num_concurrent_per_key.remove(&key);
+                } else {
+                    num_concurrent_per_key.insert(key, key_concurrent);
+                }
             }
         }
         if incoming_closed && num_concurrent == 0 {
@@ -83,7 +142,76 @@ where
     Ok(())
 }
 
-// TODO: Add tests!
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::channel::oneshot;
+    use futures::executor::ThreadPool;
+    use futures::future::BoxFuture;
+    use futures::FutureExt;
+
+    use crate::conn::FuncFutTransform;
+
+    async fn task_keyed_transform_pool_loop_per_key(
+        mut spawner: impl Spawn + Clone + Send + 'static,
+    ) {
+        let (mut input_sender, incoming) = mpsc::channel(0);
+        let (outgoing, mut output_receiver) = mpsc::channel(0);
+
+        // Every transformation waits until it is released:
+        let transform =
+            FuncFutTransform::new(|(relay, release_receiver): (u32, oneshot::Receiver<()>)| {
+                Box::pin(async move {
+                    let _ = await!(release_receiver);
+                    Some(relay)
+                }) as BoxFuture<'static, Option<u32>>
+            });
+
+        let c_spawner = spawner.clone();
+        spawner
+            .spawn(
+                keyed_transform_pool_loop(
+                    incoming,
+                    outgoing,
+                    transform,
+                    3,       // max_concurrent
+                    Some(2), // opt_max_concurrent_per_key
+                    c_spawner.clone(),
+                )
+                .map(|_| ()),
+            )
+            .unwrap();
+
+        // A burst of connections from relay 0:
+        let mut release_senders = Vec::new();
+        for _ in 0..3 {
+            let (release_sender, release_receiver) = oneshot::channel();
+            await!(input_sender.send((0u32, (0u32, release_receiver)))).unwrap();
+            release_senders.push(release_sender);
+        }
+
+        // A connection from relay 1 is still handled:
+        let (release_sender1, release_receiver1) = oneshot::channel();
+        await!(input_sender.send((1u32, (1u32, release_receiver1)))).unwrap();
+        release_sender1.send(()).unwrap();
+        assert_eq!(await!(output_receiver.next()), Some(1));
+
+        // The third connection of relay 0 was dropped:
+        let release_sender = release_senders.pop().unwrap();
+        assert!(release_sender.send(()).is_err());
+
+        for release_sender in release_senders {
+            release_sender.send(()).unwrap();
+            assert_eq!(await!(output_receiver.next()), Some(0));
+        }
+    }
+
+    #[test]
+    fn test_keyed_transform_pool_loop_per_key() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_keyed_transform_pool_loop_per_key(thread_pool.clone()));
+    }
+}
 
 /*
 
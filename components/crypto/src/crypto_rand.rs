@@ -1,7 +1,8 @@
 use std::collections::VecDeque;
 use std::ops::Deref;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
+use rand::{RngCore, SeedableRng, StdRng};
 use ring::error::Unspecified;
 use ring::rand::{SecureRandom, SystemRandom};
 
@@ -54,6 +55,44 @@ pub fn system_random() -> OffstSystemRandom {
     RngContainer::new(SystemRandom::new())
 }
 
+/// A deterministic random generator, constructed from a u64 seed.
+/// Two `SeededRng`-s created from the same seed produce the same sequence of random bytes,
+/// which allows reproducing simulations of the whole stack.
+///
+/// The seed has only 64 bits of entropy, so this generator must never be used outside of
+/// tests and simulations.
+pub struct SeededRng {
+    inner: Mutex<StdRng>,
+}
+
+impl SeededRng {
+    pub fn from_seed(seed: u64) -> Self {
+        let mut rng_seed: [u8; 32] = [0; 32];
+        rng_seed[..8].copy_from_slice(&seed.to_le_bytes());
+        SeededRng {
+            inner: Mutex::new(StdRng::from_seed(rng_seed)),
+        }
+    }
+}
+
+impl SecureRandom for SeededRng {
+    fn fill(&self, dest: &mut [u8]) -> Result<(), Unspecified> {
+        self.inner.lock().unwrap().fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRandom for SeededRng {}
+
+pub type OffstSeededRng = RngContainer<SeededRng>;
+
+/// Returns a deterministic random generator, seeded from `seed`.
+/// Clones of the returned generator share a single random sequence, so that all the
+/// components of a simulation may draw from the same generator.
+pub fn seeded_rng(seed: u64) -> OffstSeededRng {
+    RngContainer::new(SeededRng::from_seed(seed))
+}
+
 impl RandValue {
     pub fn new<R: CryptoRandom>(crypt_rng: &R) -> Self {
         let mut rand_value = RandValue([0; RAND_VALUE_LEN]);
@@ -149,4 +188,29 @@ mod tests {
         assert!(!rand_values_store.contains(&rand_value));
         assert!(!rand_values_store.contains(&rand_value0));
     }
+
+    #[test]
+    fn test_seeded_rng_deterministic() {
+        let rng_a = SeededRng::from_seed(7);
+        let rng_b = SeededRng::from_seed(7);
+        let rng_c = SeededRng::from_seed(8);
+
+        for _ in 0..16 {
+            let rand_value_a = RandValue::new(&rng_a);
+            assert_eq!(rand_value_a, RandValue::new(&rng_b));
+            assert_ne!(rand_value_a, RandValue::new(&rng_c));
+        }
+    }
+
+    #[test]
+    fn test_seeded_rng_shared_sequence() {
+        let rng = seeded_rng(3);
+        let rng_clone = rng.clone();
+        let reference = SeededRng::from_seed(3);
+
+        // Both clones draw from the same sequence:
+        assert_eq!(RandValue::new(&rng), RandValue::new(&reference));
+        assert_eq!(RandValue::new(&rng_clone), RandValue::new(&reference));
+        assert_eq!(RandValue::new(&rng), RandValue::new(&reference));
+    }
 }
@@ -1,3 +1,14 @@
+//! Symmetric encryption of messages (CHACHA20_POLY1305).
+//!
+//! Nonce scheme: Every direction of communication uses its own symmetric key, together with its
+//! own nonce counter. The first message is encrypted using the nonce 0, and the nonce is
+//! incremented by one for every message. The nonce is sent in the clear, in front of the
+//! encrypted message.
+//!
+//! The receiver expects the nonces in exactly the same order. A message with an unexpected nonce
+//! (A reused nonce, or a message that arrived out of order) is rejected. Once all the possible
+//! nonces were used, no more messages can be encrypted or decrypted using the same key.
+
 use std::iter;
 
 use ring;
@@ -18,20 +29,32 @@ pub struct EncryptNonce(pub [u8; ENC_NONCE_LEN]);
 
 pub struct EncryptNonceCounter {
     inner: EncryptNonce,
+    /// Set when the counter wraps around. From this point on, every nonce would be a reused nonce.
+    exhausted: bool,
 }
 
 impl EncryptNonceCounter {
     pub fn new() -> Self {
         EncryptNonceCounter {
             inner: EncryptNonce([0_u8; ENC_NONCE_LEN]),
+            exhausted: false,
         }
     }
 
     /// Get a new nonce.
-    pub fn next_nonce(&mut self) -> EncryptNonce {
+    /// Returns None if all the possible nonces were already used.
+    pub fn next_nonce(&mut self) -> Option<EncryptNonce> {
+        if self.exhausted {
+            return None;
+        }
         let export_nonce = self.inner.clone();
         increase_nonce(&mut self.inner.0);
-        export_nonce
+        self.exhausted = self.inner.0.iter().all(|&byte| byte == 0);
+        Some(export_nonce)
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted
     }
 }
 
@@ -63,10 +86,10 @@ impl Encryptor {
         })
     }
 
-    /// Encrypt a message. The nonce must be unique.
+    /// Encrypt a message. Every message is encrypted using a new nonce.
     pub fn encrypt(&mut self, plain_msg: &[u8]) -> Result<Vec<u8>, CryptoError> {
         // Put the nonce in the beginning of the resulting buffer:
-        let enc_nonce = self.nonce_counter.next_nonce();
+        let enc_nonce = self.nonce_counter.next_nonce().ok_or(CryptoError)?;
         let mut msg_buffer = enc_nonce.0.to_vec();
         msg_buffer.extend(plain_msg);
         // Extend the message with TAG_LEN zeroes. This leaves space for the tag:
@@ -102,10 +125,14 @@ impl Decryptor {
     }
 
     /// Decrypt and authenticate a message.
+    /// The message must use the next expected nonce.
     pub fn decrypt(&mut self, cipher_msg: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        if cipher_msg.len() < ENC_NONCE_LEN || self.nonce_counter.is_exhausted() {
+            return Err(CryptoError);
+        }
         let enc_nonce = &cipher_msg[..ENC_NONCE_LEN];
         if enc_nonce != self.nonce_counter.as_ref() {
-            // Nonce doesn't match!
+            // Nonce doesn't match! (Reused nonce, or message out of order)
             return Err(CryptoError);
         }
 
@@ -125,6 +152,7 @@ impl Decryptor {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
 
     #[test]
     fn increase_nonce_basic() {
@@ -160,4 +188,47 @@ mod tests {
 
         assert_eq!(plain_msg, &decrypted_msg[..]);
     }
+    #[test]
+    fn test_encryptor_decryptor_nonces() {
+        let symmetric_key = SymmetricKey::from(&[1; SYMMETRIC_KEY_LEN]);
+        let mut encryptor = Encryptor::new(&symmetric_key).unwrap();
+        let mut decryptor = Decryptor::new(&symmetric_key).unwrap();
+
+        let mut nonces = HashSet::new();
+        let mut cipher_msgs = Vec::new();
+        for i in 0..0x200u32 {
+            let plain_msg = i.to_le_bytes();
+            let cipher_msg = encryptor.encrypt(&plain_msg).unwrap();
+            // Every message is encrypted using a new nonce:
+            assert!(nonces.insert(cipher_msg[..ENC_NONCE_LEN].to_vec()));
+            cipher_msgs.push(cipher_msg);
+        }
+
+        // Messages that arrive out of order are rejected:
+        assert!(decryptor.decrypt(&cipher_msgs[1]).is_err());
+        for (i, cipher_msg) in cipher_msgs.iter().enumerate() {
+            let decrypted_msg = decryptor.decrypt(cipher_msg).unwrap();
+            assert_eq!(decrypted_msg, (i as u32).to_le_bytes().to_vec());
+        }
+
+        // A reused nonce is rejected:
+        assert!(decryptor.decrypt(&cipher_msgs[0]).is_err());
+        assert!(decryptor.decrypt(&cipher_msgs[0x1ff]).is_err());
+
+        // A message too short to contain a nonce is rejected:
+        assert!(decryptor.decrypt(&[0u8; ENC_NONCE_LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_nonce_counter_exhausted() {
+        let mut nonce_counter = EncryptNonceCounter {
+            inner: EncryptNonce([0xff; ENC_NONCE_LEN]),
+            exhausted: false,
+        };
+        assert!(!nonce_counter.is_exhausted());
+        assert!(nonce_counter.next_nonce().is_some());
+        // The counter wrapped around. The next nonce would repeat the first nonce:
+        assert!(nonce_counter.is_exhausted());
+        assert!(nonce_counter.next_nonce().is_none());
+    }
 }
@@ -52,6 +52,14 @@ where
 
         Ok(())
     }
+
+    /// Wait until all the mutations that were sent to the database before this call are applied
+    /// and persisted. This includes mutations sent through other clones of this client.
+    pub async fn flush(&mut self) -> Result<(), DatabaseClientError> {
+        // Requests are handled in order, hence an empty request is acked only after all the
+        // previous requests were persisted.
+        await!(self.mutate(Vec::new()))
+    }
 }
 
 pub async fn database_loop<AD, S>(
@@ -75,6 +83,13 @@ where
             mutations,
             response_sender,
         } = database_request;
+
+        // A flush request. All previous mutations were already persisted:
+        if mutations.is_empty() {
+            let _ = response_sender.send(());
+            continue;
+        }
+
         let mutate_fut = future::lazy(move |_| {
             atomic_db
                 .mutate_db(&mutations[..])
@@ -193,4 +208,40 @@ mod tests {
         let mut thread_pool = ThreadPool::new().unwrap();
         thread_pool.run(task_database_loop_basic(thread_pool.clone()));
     }
+
+    async fn task_database_loop_flush<S>(mut spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let atomic_db = DummyAtomicDb::new();
+        let (mut request_sender, incoming_requests) = mpsc::channel(0);
+        let loop_fut = database_loop(atomic_db, incoming_requests, spawner.clone());
+        let loop_res_fut = spawner.spawn_with_handle(loop_fut).unwrap();
+
+        let mut db_client = DatabaseClient::new(request_sender.clone());
+
+        // Send mutations without waiting for them to be persisted:
+        let (response_sender, mut request_done) = oneshot::channel();
+        let database_request = DatabaseRequest {
+            mutations: vec![DummyMutation::Inc, DummyMutation::Inc],
+            response_sender,
+        };
+        await!(request_sender.send(database_request)).unwrap();
+
+        // Flush returns only after the previous mutations were persisted:
+        await!(db_client.flush()).unwrap();
+        assert_eq!(request_done.try_recv().unwrap(), Some(()));
+
+        drop(request_sender);
+        drop(db_client);
+
+        let atomic_db = await!(loop_res_fut).unwrap();
+        assert_eq!(atomic_db.dummy_state.x, 2);
+    }
+
+    #[test]
+    fn test_database_loop_flush() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_database_loop_flush(thread_pool.clone()));
+    }
 }
@@ -1,6 +1,6 @@
 use std::io;
 use std::io::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use std::fmt::Debug;
 use std::fs::File;
@@ -8,9 +8,9 @@ use std::fs::File;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
-// use serde_json;
 use atomicwrites;
 use bincode;
+use serde_json;
 
 use crate::atomic_db::AtomicDb;
 use common::mutable_state::MutableState;
@@ -20,15 +20,115 @@ pub enum FileDbError<ME> {
     OpenError(io::Error),
     ReadError(io::Error),
     WriteError(atomicwrites::Error<io::Error>),
+    SyncError(io::Error),
     DeserializeError(bincode::Error),
     SerializeError(bincode::Error),
+    JsonDeserializeError(serde_json::Error),
+    JsonSerializeError(serde_json::Error),
+    /// The file does not start with a database header. Files created by older versions do not
+    /// contain a header, and can not be loaded.
+    UnsupportedFormat,
+    MissingFormat,
+    UnknownFormat(u8),
     MutateError(ME),
     FileAlreadyExists,
 }
 
+/// Magic bytes at the beginning of a database file, followed by a single byte describing the
+/// serialization format of the rest of the file.
+const FILE_DB_MAGIC: &[u8] = b"OFDB";
+
+/// On disk serialization format of the database state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileDbFormat {
+    /// Compact binary format
+    Bincode,
+    /// Human readable format, useful for debugging
+    Json,
+}
+
+impl FileDbFormat {
+    fn to_byte(self) -> u8 {
+        match self {
+            FileDbFormat::Bincode => 0,
+            FileDbFormat::Json => 1,
+        }
+    }
+
+    fn from_byte(format_byte: u8) -> Option<FileDbFormat> {
+        match format_byte {
+            0 => Some(FileDbFormat::Bincode),
+            1 => Some(FileDbFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Serialize a state, prefixed by a header describing the format.
+fn serialize_state<S, ME>(state: &S, format: FileDbFormat) -> Result<Vec<u8>, FileDbError<ME>>
+where
+    S: Serialize,
+{
+    let serialized_state = match format {
+        FileDbFormat::Bincode => bincode::serialize(state).map_err(FileDbError::SerializeError)?,
+        FileDbFormat::Json => serde_json::to_vec(state).map_err(FileDbError::JsonSerializeError)?,
+    };
+
+    let mut serialized_buff = FILE_DB_MAGIC.to_vec();
+    serialized_buff.push(format.to_byte());
+    serialized_buff.extend(serialized_state);
+    Ok(serialized_buff)
+}
+
+/// Deserialize a state, according to the format specified in the header.
+fn deserialize_state<S, ME>(serialized_buff: &[u8]) -> Result<(S, FileDbFormat), FileDbError<ME>>
+where
+    S: DeserializeOwned,
+{
+    if !serialized_buff.starts_with(FILE_DB_MAGIC) {
+        return Err(FileDbError::UnsupportedFormat);
+    }
+
+    let serialized_buff = &serialized_buff[FILE_DB_MAGIC.len()..];
+    let (format_byte, serialized_state) = serialized_buff
+        .split_first()
+        .ok_or(FileDbError::MissingFormat)?;
+    let format =
+        FileDbFormat::from_byte(*format_byte).ok_or(FileDbError::UnknownFormat(*format_byte))?;
+
+    let state = match format {
+        FileDbFormat::Bincode => {
+            bincode::deserialize(serialized_state).map_err(FileDbError::DeserializeError)?
+        }
+        FileDbFormat::Json => {
+            serde_json::from_slice(serialized_state).map_err(FileDbError::JsonDeserializeError)?
+        }
+    };
+    Ok((state, format))
+}
+
+/// Make sure that the database file and the directory containing it were written to disk.
+/// The directory has to be synced too, because the file was atomically renamed into it.
+fn sync_to_disk(path: &Path) -> io::Result<()> {
+    File::open(path)?.sync_all()?;
+
+    // Directories can not be opened for syncing on all platforms:
+    #[cfg(unix)]
+    {
+        let dir_path = match path.parent() {
+            Some(dir_path) if !dir_path.as_os_str().is_empty() => dir_path,
+            _ => Path::new("."),
+        };
+        File::open(dir_path)?.sync_all()?;
+    }
+    Ok(())
+}
+
 pub struct FileDb<S> {
     /// Connection to the database
     path_buf: PathBuf,
+    /// Serialization format used for the database file
+    format: FileDbFormat,
     /// Current state represented by the database:
     state: S,
 }
@@ -39,11 +139,21 @@ where
     S::Mutation: Clone + Serialize + DeserializeOwned,
     S::MutateError: Debug,
 {
-    /// Create a new database file from an initial state
+    /// Create a new database file from an initial state, using the bincode format.
     /// Aborts if destination file already exists
     pub fn create(
         path_buf: PathBuf,
         initial_state: S,
+    ) -> Result<Self, FileDbError<S::MutateError>> {
+        FileDb::create_with_format(path_buf, initial_state, FileDbFormat::Bincode)
+    }
+
+    /// Create a new database file from an initial state, using the given serialization format.
+    /// Aborts if destination file already exists
+    pub fn create_with_format(
+        path_buf: PathBuf,
+        initial_state: S,
+        format: FileDbFormat,
     ) -> Result<Self, FileDbError<S::MutateError>> {
         if path_buf.exists() {
             return Err(FileDbError::FileAlreadyExists);
@@ -51,20 +161,24 @@ where
 
         // There is no file, we create a new file:
         // Serialize the state:
-        let serialized_buff =
-            bincode::serialize(&initial_state).map_err(FileDbError::SerializeError)?;
+        let serialized_buff = serialize_state(&initial_state, format)?;
         // Save the new state to file, atomically:
         let af = atomicwrites::AtomicFile::new(&path_buf, atomicwrites::AllowOverwrite);
         af.write(|fw| fw.write_all(&serialized_buff))
             .map_err(FileDbError::WriteError)?;
+        sync_to_disk(&path_buf).map_err(FileDbError::SyncError)?;
 
-        let state: S =
-            bincode::deserialize(&serialized_buff).map_err(FileDbError::DeserializeError)?;
+        let (state, format) = deserialize_state(&serialized_buff)?;
 
-        Ok(FileDb { path_buf, state })
+        Ok(FileDb {
+            path_buf,
+            format,
+            state,
+        })
     }
 
     /// Load an existing database from file
+    /// The serialization format is read from the file header.
     /// Returns an error if database file does not exist
     pub fn load(path_buf: PathBuf) -> Result<Self, FileDbError<S::MutateError>> {
         let mut f = File::open(&path_buf).map_err(FileDbError::OpenError)?;
@@ -73,10 +187,18 @@ where
         f.read_to_end(&mut serialized_buff)
             .map_err(FileDbError::ReadError)?;
 
-        let state: S =
-            bincode::deserialize(&serialized_buff).map_err(FileDbError::DeserializeError)?;
+        let (state, format) = deserialize_state(&serialized_buff)?;
+
+        Ok(FileDb {
+            path_buf,
+            format,
+            state,
+        })
+    }
 
-        Ok(FileDb { path_buf, state })
+    /// Serialization format used for the database file
+    pub fn format(&self) -> FileDbFormat {
+        self.format
     }
 }
 
@@ -105,13 +227,14 @@ where
         }
 
         // Serialize the state:
-        let serialized_buff =
-            bincode::serialize(&self.state).map_err(FileDbError::SerializeError)?;
+        let serialized_buff = serialize_state(&self.state, self.format)?;
 
         // Save the new state to file, atomically:
         let af = atomicwrites::AtomicFile::new(&self.path_buf, atomicwrites::AllowOverwrite);
         af.write(|fw| fw.write_all(&serialized_buff))
             .map_err(FileDbError::WriteError)?;
+        // Only report success after the new state is durable:
+        sync_to_disk(&self.path_buf).map_err(FileDbError::SyncError)?;
 
         Ok(())
     }
@@ -120,8 +243,14 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use futures::channel::{mpsc, oneshot};
+    use futures::executor::ThreadPool;
+    use futures::task::{Spawn, SpawnExt};
+    use futures::SinkExt;
     use tempfile::tempdir;
 
+    use crate::database::{database_loop, DatabaseClient, DatabaseRequest};
+
     /// A dummy state (used for testing)
     #[derive(Debug, Serialize, Deserialize, Clone)]
     struct DummyState {
@@ -203,4 +332,101 @@ mod tests {
         // Remove temporary directory:
         dir.close().unwrap();
     }
+
+    #[test]
+    fn test_file_db_formats() {
+        let dir = tempdir().unwrap();
+
+        for (i, format) in [FileDbFormat::Bincode, FileDbFormat::Json]
+            .iter()
+            .enumerate()
+        {
+            let file_path = dir.path().join(format!("database_file{}", i));
+
+            let initial_state = DummyState::new(5);
+            let mut file_db =
+                FileDb::<DummyState>::create_with_format(file_path.clone(), initial_state, *format)
+                    .unwrap();
+            file_db
+                .mutate_db(&[DummyMutation::Inc, DummyMutation::Inc])
+                .unwrap();
+            drop(file_db);
+
+            // The format is read from the file header:
+            let mut file_db = FileDb::<DummyState>::load(file_path.clone()).unwrap();
+            assert_eq!(file_db.format(), *format);
+            assert_eq!(file_db.get_state().x, 7);
+
+            // The format is kept after further mutations:
+            file_db.mutate_db(&[DummyMutation::Dec]).unwrap();
+            drop(file_db);
+            let file_db = FileDb::<DummyState>::load(file_path.clone()).unwrap();
+            assert_eq!(file_db.format(), *format);
+            assert_eq!(file_db.get_state().x, 6);
+        }
+
+        // The json format is human readable:
+        let contents = std::fs::read(dir.path().join("database_file1")).unwrap();
+        assert_eq!(&contents[FILE_DB_MAGIC.len() + 1..], br#"{"x":6}"#);
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_file_db_load_without_header() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("database_file");
+
+        // A file that contains only the bincode serialized state, without a header:
+        let serialized_state = bincode::serialize(&DummyState::new(3)).unwrap();
+        std::fs::write(&file_path, &serialized_state).unwrap();
+
+        match FileDb::<DummyState>::load(file_path.clone()) {
+            Err(FileDbError::UnsupportedFormat) => {}
+            _ => unreachable!(),
+        };
+
+        dir.close().unwrap();
+    }
+
+    async fn task_file_db_flush<S>(mut spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("database_file");
+
+        let file_db = FileDb::<DummyState>::create(file_path.clone(), DummyState::new(0)).unwrap();
+        let (mut request_sender, incoming_requests) = mpsc::channel(0);
+        let loop_fut = database_loop(file_db, incoming_requests, spawner.clone());
+        let loop_res_fut = spawner.spawn_with_handle(loop_fut).unwrap();
+
+        let mut db_client = DatabaseClient::new(request_sender.clone());
+
+        // Send mutations without waiting for them to be persisted:
+        let (response_sender, _request_done) = oneshot::channel();
+        let database_request = DatabaseRequest {
+            mutations: vec![DummyMutation::Inc, DummyMutation::Inc],
+            response_sender,
+        };
+        await!(request_sender.send(database_request)).unwrap();
+
+        // After flush returns, the mutations can be found on disk:
+        await!(db_client.flush()).unwrap();
+        let loaded_file_db = FileDb::<DummyState>::load(file_path.clone()).unwrap();
+        assert_eq!(loaded_file_db.get_state().x, 2);
+
+        drop(request_sender);
+        drop(db_client);
+        let file_db = await!(loop_res_fut).unwrap();
+        assert_eq!(file_db.get_state().x, 2);
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_file_db_flush() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_file_db_flush(thread_pool.clone()));
+    }
 }
@@ -0,0 +1,39 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Counters of rejected control messages, keyed by the name of the error that caused the
+/// rejection. Cloning a `ControlStats` returns a handle to the same counters, allowing the
+/// counters to be read while the Funder is running.
+#[derive(Debug, Clone, Default)]
+pub struct ControlStats {
+    counters: Arc<Mutex<HashMap<&'static str, u64>>>,
+}
+
+impl ControlStats {
+    pub fn new() -> Self {
+        ControlStats {
+            counters: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub(crate) fn increment(&self, error_name: &'static str) {
+        let mut counters = self.counters.lock().unwrap();
+        let counter = counters.entry(error_name).or_insert(0);
+        *counter = counter.saturating_add(1);
+    }
+
+    /// Get the amount of control messages rejected due to the error `error_name`.
+    pub fn get(&self, error_name: &str) -> u64 {
+        self.counters
+            .lock()
+            .unwrap()
+            .get(error_name)
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    /// Get a copy of all the counters.
+    pub fn snapshot(&self) -> HashMap<&'static str, u64> {
+        self.counters.lock().unwrap().clone()
+    }
+}
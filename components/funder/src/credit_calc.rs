@@ -15,6 +15,9 @@ use std::convert::TryFrom;
 /// In the example above, num_nodes = 5, node_index = 1 for the node C.
 /// Upon any overflow (u128) this function will return None.
 ///
+/// A payment with dest_payment = 0 is a route probe. The destination receives nothing, but the
+/// intermediate nodes are still paid for forwarding it.
+///
 pub fn credits_on_success(node_index: u32, route_len: u32, dest_payment: u128) -> Option<u128> {
     if node_index == 0 {
         None
@@ -147,4 +150,20 @@ mod tests {
             assert!(freeze_credits >= success_credits);
         }
     }
+
+    #[test]
+    fn test_credits_zero_payment() {
+        let route_len = 5;
+
+        // Route probes still pay the intermediate nodes for forwarding:
+        for node_index in 1..route_len {
+            let dist = u128::from(route_len - node_index - 1);
+            assert_eq!(credits_on_success(node_index, route_len, 0), Some(dist));
+            assert_eq!(credits_to_freeze(node_index, route_len, 0), Some(dist));
+        }
+        // The destination is paid nothing:
+        assert_eq!(credits_on_success(route_len - 1, route_len, 0), Some(0));
+        // The route is still checked:
+        assert_eq!(credits_on_success(route_len, route_len, 0), None);
+    }
 }
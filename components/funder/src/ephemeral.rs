@@ -1,19 +1,129 @@
+use im::hashmap::HashMap as ImHashMap;
+use im::hashset::HashSet as ImHashSet;
+use im::vector::Vector as ImVec;
+
+use crypto::identity::PublicKey;
+use crypto::uid::Uid;
+
+use proto::funder::messages::AckedReceipt;
+use proto::report::messages::RttReport;
+
 use super::liveness::{Liveness, LivenessMutation};
+use crate::handler::FriendSendCommands;
+
+/// Maximum amount of recently completed requests we remember.
+pub const MAX_RECENT_COMPLETED_REQUESTS: usize = 0x400;
+
+/// Amount of timer ticks we wait for a warmed friend to become ready.
+pub const WARM_FRIEND_TIMEOUT_TICKS: u64 = 0x40;
+
+/// Amount of timer ticks we wait for the response to a request used for measuring the round trip
+/// time with a friend. After this amount of ticks, a newer request may be used instead.
+pub const RTT_PROBE_TIMEOUT_TICKS: u64 = 0x100;
+
+/// Add a round trip time measurement to `rtt_report`.
+/// The average is an exponential moving average, giving every new measurement a weight of 1/8.
+pub fn add_rtt_sample(rtt_report: &RttReport, rtt: u64) -> RttReport {
+    let avg_rtt = if rtt_report.num_samples == 0 {
+        rtt
+    } else {
+        (rtt_report.avg_rtt.saturating_mul(7).saturating_add(rtt)) / 8
+    };
+    RttReport {
+        last_rtt: rtt,
+        avg_rtt,
+        num_samples: rtt_report.num_samples.saturating_add(1),
+    }
+}
+
+/// A bounded set of recently completed request ids.
+/// When full, the oldest request id is forgotten.
+#[derive(Clone, Default)]
+pub struct RecentRequests {
+    request_ids: ImHashSet<Uid>,
+    order: ImVec<Uid>,
+}
+
+impl RecentRequests {
+    pub fn new() -> Self {
+        RecentRequests {
+            request_ids: ImHashSet::new(),
+            order: ImVec::new(),
+        }
+    }
+
+    pub fn contains(&self, request_id: &Uid) -> bool {
+        self.request_ids.contains(request_id)
+    }
+
+    fn insert(&mut self, request_id: Uid) {
+        if self.request_ids.contains(&request_id) {
+            return;
+        }
+        if self.order.len() >= MAX_RECENT_COMPLETED_REQUESTS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.request_ids.remove(&oldest);
+            }
+        }
+        self.request_ids.insert(request_id.clone());
+        self.order.push_back(request_id);
+    }
+}
 
 #[derive(Clone, Default)]
 pub struct Ephemeral {
     pub liveness: Liveness,
+    /// Timer tick of the last move token sent to or received from each friend.
+    pub last_move_token_ticks: ImHashMap<PublicKey, u64>,
+    /// Requests that were recently completed. Used to reject replays.
+    pub recent_completed_requests: RecentRequests,
+    /// Friends we are currently warming, together with the timer tick at which we give up.
+    pub warm_friends: ImHashMap<PublicKey, u64>,
+    /// A request sent to each friend, used to measure round trip time, together with the timer
+    /// tick at which it was sent.
+    pub rtt_probes: ImHashMap<PublicKey, (Uid, u64)>,
+    /// Round trip time measurements for every friend.
+    pub friend_rtts: ImHashMap<PublicKey, RttReport>,
+    /// Receipts that were recently acked, oldest first.
+    /// Not persisted, so this list is empty after a restart.
+    pub recent_receipts: ImVec<AckedReceipt>,
+    /// Send commands for friends that were not yet handled, because too many friends had to be
+    /// sent messages while handling a single event.
+    pub deferred_send_commands: ImHashMap<PublicKey, FriendSendCommands>,
+    /// Amount of duplicate move tokens received from each friend.
+    /// Only counted if duplicate move tokens are monitored.
+    pub duplicate_move_tokens: ImHashMap<PublicKey, u64>,
 }
 
 #[derive(Debug)]
 pub enum EphemeralMutation {
     LivenessMutation(LivenessMutation),
+    SetLastMoveTokenTick((PublicKey, u64)),
+    AddCompletedRequest(Uid),
+    AddWarmFriend((PublicKey, u64)),
+    RemoveWarmFriend(PublicKey),
+    SetRttProbe((PublicKey, Uid, u64)),
+    RemoveRttProbe(PublicKey),
+    SetFriendRtt((PublicKey, RttReport)),
+    AddRecentReceipt(AckedReceipt),
+    RemoveOldestRecentReceipt,
+    SetDeferredSendCommands((PublicKey, FriendSendCommands)),
+    RemoveDeferredSendCommands(PublicKey),
+    IncDuplicateMoveTokens(PublicKey),
 }
 
 impl Ephemeral {
     pub fn new() -> Ephemeral {
         Ephemeral {
             liveness: Liveness::new(),
+            last_move_token_ticks: ImHashMap::new(),
+            recent_completed_requests: RecentRequests::new(),
+            warm_friends: ImHashMap::new(),
+            rtt_probes: ImHashMap::new(),
+            friend_rtts: ImHashMap::new(),
+            recent_receipts: ImVec::new(),
+            deferred_send_commands: ImHashMap::new(),
+            duplicate_move_tokens: ImHashMap::new(),
         }
     }
 
@@ -22,6 +132,88 @@ impl Ephemeral {
             EphemeralMutation::LivenessMutation(liveness_mutation) => {
                 self.liveness.mutate(liveness_mutation)
             }
+            EphemeralMutation::SetLastMoveTokenTick((public_key, tick)) => {
+                self.last_move_token_ticks.insert(public_key.clone(), *tick);
+            }
+            EphemeralMutation::AddCompletedRequest(request_id) => {
+                self.recent_completed_requests.insert(request_id.clone());
+            }
+            EphemeralMutation::AddWarmFriend((public_key, deadline_tick)) => {
+                self.warm_friends.insert(public_key.clone(), *deadline_tick);
+            }
+            EphemeralMutation::RemoveWarmFriend(public_key) => {
+                self.warm_friends.remove(public_key);
+            }
+            EphemeralMutation::SetRttProbe((public_key, request_id, sent_tick)) => {
+                self.rtt_probes
+                    .insert(public_key.clone(), (*request_id, *sent_tick));
+            }
+            EphemeralMutation::RemoveRttProbe(public_key) => {
+                self.rtt_probes.remove(public_key);
+            }
+            EphemeralMutation::SetFriendRtt((public_key, rtt_report)) => {
+                self.friend_rtts
+                    .insert(public_key.clone(), rtt_report.clone());
+            }
+            EphemeralMutation::AddRecentReceipt(acked_receipt) => {
+                self.recent_receipts.push_back(acked_receipt.clone());
+            }
+            EphemeralMutation::RemoveOldestRecentReceipt => {
+                let _ = self.recent_receipts.pop_front();
+            }
+            EphemeralMutation::SetDeferredSendCommands((public_key, friend_send_commands)) => {
+                self.deferred_send_commands
+                    .insert(public_key.clone(), friend_send_commands.clone());
+            }
+            EphemeralMutation::RemoveDeferredSendCommands(public_key) => {
+                self.deferred_send_commands.remove(public_key);
+            }
+            EphemeralMutation::IncDuplicateMoveTokens(public_key) => {
+                let counter = self
+                    .duplicate_move_tokens
+                    .entry(public_key.clone())
+                    .or_insert(0);
+                *counter = counter.saturating_add(1);
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crypto::uid::UID_LEN;
+
+    #[test]
+    fn test_recent_requests_bounded() {
+        let mut recent_requests = RecentRequests::new();
+        let first = Uid::from(&[0xff; UID_LEN]);
+        recent_requests.insert(first.clone());
+        assert!(recent_requests.contains(&first));
+
+        for i in 0..MAX_RECENT_COMPLETED_REQUESTS {
+            let mut uid_bytes = [0u8; UID_LEN];
+            uid_bytes[0] = (i >> 8) as u8;
+            uid_bytes[1] = i as u8;
+            recent_requests.insert(Uid::from(&uid_bytes));
+        }
+
+        // The oldest request id was forgotten:
+        assert!(!recent_requests.contains(&first));
+        assert_eq!(recent_requests.order.len(), MAX_RECENT_COMPLETED_REQUESTS);
+    }
+
+    #[test]
+    fn test_add_rtt_sample() {
+        let rtt_report = add_rtt_sample(&RttReport::default(), 16);
+        assert_eq!(rtt_report.last_rtt, 16);
+        assert_eq!(rtt_report.avg_rtt, 16);
+        assert_eq!(rtt_report.num_samples, 1);
+
+        let rtt_report = add_rtt_sample(&rtt_report, 32);
+        assert_eq!(rtt_report.last_rtt, 32);
+        assert_eq!(rtt_report.avg_rtt, 18);
+        assert_eq!(rtt_report.num_samples, 2);
+    }
+}
@@ -8,8 +8,8 @@ use common::safe_arithmetic::SafeUnsignedArithmetic;
 
 use proto::app_server::messages::{NamedRelayAddress, RelayAddress};
 use proto::funder::messages::{
-    FailureSendFunds, FriendStatus, PendingRequest, RequestSendFunds, RequestsStatus, ResetTerms,
-    ResponseSendFunds,
+    FailureSendFunds, FriendStatus, FundsReceived, PendingRequest, RequestSendFunds,
+    RequestsStatus, ResetTerms, ResponseSendFunds,
 };
 
 use crate::token_channel::{TcMutation, TokenChannel};
@@ -18,7 +18,8 @@ use crate::types::MoveTokenHashed;
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum ResponseOp {
     Response(ResponseSendFunds),
-    UnsignedResponse(PendingRequest),
+    /// (pending request, memo of the request)
+    UnsignedResponse((PendingRequest, Vec<u8>)),
     Failure(FailureSendFunds),
     UnsignedFailure(PendingRequest),
 }
@@ -70,6 +71,9 @@ pub enum FriendMutation<B: Clone> {
     SetInconsistent(ChannelInconsistent),
     SetConsistent(TokenChannel<B>),
     SetWantedRemoteMaxDebt(u128),
+    SetConfirmRemoteMaxDebt(bool),
+    SetMinBalance(Option<i128>),
+    SetMaxSinglePayment(Option<u128>),
     SetWantedLocalRequestsStatus(RequestsStatus),
     PushBackPendingRequest(RequestSendFunds),
     PopFrontPendingRequest,
@@ -77,10 +81,26 @@ pub enum FriendMutation<B: Clone> {
     PopFrontPendingResponse,
     PushBackPendingUserRequest(RequestSendFunds),
     PopFrontPendingUserRequest,
+    PushBackUnackedFunds(FundsReceived),
+    ClearUnackedFunds,
     SetStatus(FriendStatus),
     SetRemoteRelays(Vec<RelayAddress<B>>),
     SetName(String),
     SetSentLocalRelays(SentLocalRelays<B>),
+    SetResetStatus(ResetStatus),
+}
+
+/// Progress of the last channel reset with this friend.
+#[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
+pub enum ResetStatus {
+    /// No reset took place.
+    NoReset,
+    /// We reset the channel, but the remote side did not yet send a move token signed over the
+    /// reset terms. Contains our local reset terms at the time of the reset, in case the remote
+    /// side has reset the channel at the same time.
+    Pending(ResetTerms),
+    /// Both sides hold a move token, signed by the remote side, that chains on the reset terms.
+    Confirmed,
 }
 
 #[derive(PartialEq, Eq, Clone, Serialize, Deserialize, Debug)]
@@ -122,6 +142,13 @@ pub struct FriendState<B: Clone> {
     pub name: String,
     pub channel_status: ChannelStatus<B>,
     pub wanted_remote_max_debt: u128,
+    // Should wanted_remote_max_debt be proposed to the remote side, applying only after the
+    // remote side acknowledges it?
+    pub confirm_remote_max_debt: bool,
+    // Minimum balance we are willing to keep with this friend when forwarding requests.
+    pub opt_min_balance: Option<i128>,
+    // Maximum amount of a single payment we are willing to send to this friend.
+    pub opt_max_single_payment: Option<u128>,
     pub wanted_local_requests_status: RequestsStatus,
     pub pending_requests: ImVec<RequestSendFunds>,
     pub pending_responses: ImVec<ResponseOp>,
@@ -130,6 +157,11 @@ pub struct FriendState<B: Clone> {
     pub pending_user_requests: ImVec<RequestSendFunds>,
     // Request that the user has sent to this neighbor,
     // but have not been processed yet. Bounded in size.
+    pub reset_status: ResetStatus,
+    /// Funds we receive by responses sent in our last outgoing move token. They are reported
+    /// only after the friend acknowledges the move token.
+    #[serde(default)]
+    pub unacked_funds: ImVec<FundsReceived>,
 }
 
 impl<B> FriendState<B>
@@ -156,6 +188,9 @@ where
             // The remote_max_debt we want to have. When possible, this will be sent to the remote
             // side.
             wanted_remote_max_debt: 0,
+            confirm_remote_max_debt: false,
+            opt_min_balance: None,
+            opt_max_single_payment: None,
             wanted_local_requests_status: RequestsStatus::Closed,
             // The local_send_price we want to have (Or possibly close requests, by having an empty
             // send price). When possible, this will be updated with the TokenChannel.
@@ -163,6 +198,8 @@ where
             pending_responses: ImVec::new(),
             status: FriendStatus::Disabled,
             pending_user_requests: ImVec::new(),
+            reset_status: ResetStatus::NoReset,
+            unacked_funds: ImVec::new(),
         }
     }
 
@@ -203,6 +240,10 @@ where
             },
             FriendMutation::SetInconsistent(channel_inconsistent) => {
                 self.channel_status = ChannelStatus::Inconsistent(channel_inconsistent.clone());
+                // A previous reset no longer describes the channel:
+                self.reset_status = ResetStatus::NoReset;
+                // Our last outgoing move token will never be acknowledged:
+                self.unacked_funds.clear();
             }
             FriendMutation::SetConsistent(token_channel) => {
                 self.channel_status = ChannelStatus::Consistent(token_channel.clone());
@@ -210,6 +251,15 @@ where
             FriendMutation::SetWantedRemoteMaxDebt(wanted_remote_max_debt) => {
                 self.wanted_remote_max_debt = *wanted_remote_max_debt;
             }
+            FriendMutation::SetConfirmRemoteMaxDebt(confirm_remote_max_debt) => {
+                self.confirm_remote_max_debt = *confirm_remote_max_debt;
+            }
+            FriendMutation::SetMinBalance(opt_min_balance) => {
+                self.opt_min_balance = *opt_min_balance;
+            }
+            FriendMutation::SetMaxSinglePayment(opt_max_single_payment) => {
+                self.opt_max_single_payment = *opt_max_single_payment;
+            }
             FriendMutation::SetWantedLocalRequestsStatus(wanted_local_requests_status) => {
                 self.wanted_local_requests_status = wanted_local_requests_status.clone();
             }
@@ -232,6 +282,12 @@ where
             FriendMutation::PopFrontPendingUserRequest => {
                 let _ = self.pending_user_requests.pop_front();
             }
+            FriendMutation::PushBackUnackedFunds(funds_received) => {
+                self.unacked_funds.push_back(funds_received.clone());
+            }
+            FriendMutation::ClearUnackedFunds => {
+                self.unacked_funds.clear();
+            }
             FriendMutation::SetStatus(friend_status) => {
                 self.status = friend_status.clone();
             }
@@ -244,6 +300,9 @@ where
             FriendMutation::SetSentLocalRelays(sent_local_relays) => {
                 self.sent_local_relays = sent_local_relays.clone();
             }
+            FriendMutation::SetResetStatus(reset_status) => {
+                self.reset_status = reset_status.clone();
+            }
         }
     }
 }
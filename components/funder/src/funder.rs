@@ -1,64 +1,201 @@
 use std::fmt::Debug;
+use std::mem;
 
-use futures::channel::mpsc;
-use futures::{future, stream, SinkExt, StreamExt};
+use futures::channel::{mpsc, oneshot};
+use futures::{future, stream, Poll, SinkExt, Stream, StreamExt};
 
 use common::canonical_serialize::CanonicalSerialize;
+use common::latest_channel::LatestSender;
 
 use crypto::crypto_rand::CryptoRandom;
 use identity::IdentityClient;
+use timer::TimerClient;
 
 // use crate::database::{AtomicDb, DbRunner, DbRunnerError};
 use database::DatabaseClient;
 
 use proto::funder::messages::{FunderIncomingControl, FunderOutgoingControl};
+use proto::net::messages::ValidateAddress;
+use proto::report::messages::FunderReport;
 
+use crate::control_stats::ControlStats;
 use crate::ephemeral::Ephemeral;
 use crate::handler::funder_handle_message;
+use crate::mutual_credit::op_timings::OpTimings;
+use crate::report::create_report;
 use crate::state::{FunderMutation, FunderState};
-use crate::types::{FunderIncoming, FunderIncomingComm, FunderOutgoingComm};
+use crate::types::{FunderConfig, FunderIncoming, FunderIncomingComm, FunderOutgoingComm};
 
 #[derive(Debug)]
 pub enum FunderError {
     IncomingControlClosed,
     IncomingCommClosed,
     IncomingMessagesError,
+    RequestTimerStreamError,
     DbError,
     SendControlError,
     SendCommError,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub enum FunderEvent<B> {
     FunderIncoming(FunderIncoming<B>),
     IncomingControlClosed,
     IncomingCommClosed,
+    TimerTick,
+    Shutdown,
+    /// A request to flush the pending batch. The sender is notified after the database has
+    /// acknowledged the pending mutations.
+    Flush(oneshot::Sender<()>),
+}
+
+/// Maximum amount of handled events whose mutations are written to the database in a single
+/// batch.
+pub const MAX_EVENTS_IN_DB_BATCH: usize = 0x40;
+
+/// Outputs of handled events, waiting for their mutations to be written to the database.
+struct PendingBatch<B> {
+    num_events: usize,
+    funder_mutations: Vec<FunderMutation<B>>,
+    outgoing_comms: Vec<FunderOutgoingComm<B>>,
+    outgoing_control: Vec<FunderOutgoingControl<B>>,
+    funder_events: Vec<FunderEvent<B>>,
+    /// Did any of the handled events change the report?
+    report_changed: bool,
+}
+
+impl<B> PendingBatch<B> {
+    fn new() -> Self {
+        PendingBatch {
+            num_events: 0,
+            funder_mutations: Vec::new(),
+            outgoing_comms: Vec::new(),
+            outgoing_control: Vec::new(),
+            funder_events: Vec::new(),
+            report_changed: false,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.num_events == 0
+    }
 }
 
+/// Write all the pending mutations to the database using a single request.
+/// Outgoing messages are sent only after the database has acknowledged the mutations.
+/// If the report has changed, a full report is sent through `opt_report_sender`. Sending a report
+/// never blocks: A report that was not yet received is replaced by the new one.
+async fn flush_batch<'a, B>(
+    pending_batch: &'a mut PendingBatch<B>,
+    db_client: &'a mut DatabaseClient<FunderMutation<B>>,
+    comm_sender: &'a mut mpsc::Sender<FunderOutgoingComm<B>>,
+    control_sender: &'a mut mpsc::Sender<FunderOutgoingControl<B>>,
+    funder_state: &'a FunderState<B>,
+    ephemeral: &'a Ephemeral,
+    opt_report_sender: &'a mut Option<LatestSender<FunderReport<B>>>,
+    opt_event_sender: &'a mut Option<mpsc::Sender<FunderEvent<B>>>,
+) -> Result<(), FunderError>
+where
+    B: Clone + CanonicalSerialize + Debug,
+{
+    let pending_batch = mem::replace(pending_batch, PendingBatch::new());
+
+    if !pending_batch.funder_mutations.is_empty() {
+        await!(db_client.mutate(pending_batch.funder_mutations))
+            .map_err(|_| FunderError::DbError)?;
+    }
+
+    // Send outgoing communication messages:
+    let mut comm_stream = stream::iter::<_>(pending_batch.outgoing_comms);
+    await!(comm_sender.send_all(&mut comm_stream)).map_err(|_| FunderError::SendCommError)?;
+
+    // Send outgoing control messages:
+    let mut control_stream = stream::iter::<_>(pending_batch.outgoing_control);
+    await!(control_sender.send_all(&mut control_stream))
+        .map_err(|_| FunderError::SendControlError)?;
+
+    if pending_batch.report_changed {
+        if let Some(report_sender) = opt_report_sender {
+            if report_sender
+                .send(create_report(funder_state, ephemeral))
+                .is_err()
+            {
+                // The subscriber is gone, we stop sending reports:
+                *opt_report_sender = None;
+            }
+        }
+    }
+
+    if let Some(ref mut event_sender) = opt_event_sender {
+        for funder_event in pending_batch.funder_events {
+            await!(event_sender.send(funder_event)).unwrap();
+        }
+    }
+    Ok(())
+}
+
+/// Get the next item of `stream` only if it is immediately available.
+async fn next_if_ready<'a, S>(stream: &'a mut S) -> Poll<Option<S::Item>>
+where
+    S: Stream + Unpin,
+{
+    await!(future::poll_fn(|waker| Poll::Ready(
+        stream.poll_next_unpin(waker)
+    )))
+}
+
+/// The main loop of the Funder.
+///
+/// Events that are immediately available are handled together, and their mutations are written to
+/// the database using a single request (At most `MAX_EVENTS_IN_DB_BATCH` events per request).
+/// Outgoing messages are sent only after the corresponding mutations were acknowledged by the
+/// database.
+///
+/// If `opt_shutdown_receiver` is provided, sending a message through its corresponding sender
+/// stops the loop: No further incoming messages are processed, and the loop resolves to `Ok(())`.
+/// Pending mutations are flushed to the database before the loop resolves. Dropping the shutdown
+/// sender without sending a message has no effect.
+///
+/// If `opt_flush_receiver` is provided, every `oneshot::Sender` received through it is notified
+/// once all the mutations of previously handled events were acknowledged by the database. This
+/// allows waiting for durability without waiting for the next batch to be flushed.
+///
+/// If `opt_report_sender` is provided, a full report is sent through it whenever the report
+/// changes. A slow receiver never blocks the loop, but might miss intermediate reports.
+///
+/// Messages are sent to at most `funder_config.max_send_friends` friends for every handled event.
+/// Messages to the rest of the friends are deferred, and handled only after the pending batch was
+/// flushed.
 pub async fn inner_funder_loop<B, R>(
     mut identity_client: IdentityClient,
+    mut timer_client: TimerClient,
     rng: R,
     incoming_control: mpsc::Receiver<FunderIncomingControl<B>>,
     incoming_comm: mpsc::Receiver<FunderIncomingComm<B>>,
-    control_sender: mpsc::Sender<FunderOutgoingControl<B>>,
-    comm_sender: mpsc::Sender<FunderOutgoingComm<B>>,
+    mut control_sender: mpsc::Sender<FunderOutgoingControl<B>>,
+    mut comm_sender: mpsc::Sender<FunderOutgoingComm<B>>,
     mut funder_state: FunderState<B>,
     mut db_client: DatabaseClient<FunderMutation<B>>,
-    max_operations_in_batch: usize,
-    max_node_relays: usize,
-    max_pending_user_requests: usize,
+    funder_config: FunderConfig,
+    control_stats: ControlStats,
+    opt_op_timings: Option<OpTimings>,
+    opt_shutdown_receiver: Option<oneshot::Receiver<()>>,
+    opt_flush_receiver: Option<mpsc::Receiver<oneshot::Sender<()>>>,
+    mut opt_report_sender: Option<LatestSender<FunderReport<B>>>,
     mut opt_event_sender: Option<mpsc::Sender<FunderEvent<B>>>,
 ) -> Result<(), FunderError>
 where
-    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+    B: Clone + PartialEq + Eq + CanonicalSerialize + ValidateAddress + Debug,
     R: CryptoRandom + 'static,
 {
-    // Transform error type:
-    let mut comm_sender = comm_sender.sink_map_err(|_| ());
-    let mut control_sender = control_sender.sink_map_err(|_| ());
-
     // let mut db_runner = DbRunner::new(atomic_db);
     let mut ephemeral = Ephemeral::new();
+    // Amount of timer ticks since the Funder has started:
+    let mut current_tick: u64 = 0;
+
+    let timer_stream = await!(timer_client.request_timer_stream())
+        .map_err(|_| FunderError::RequestTimerStreamError)?
+        .map(|_| FunderEvent::TimerTick);
 
     // Select over all possible events:
     let incoming_control = incoming_control
@@ -73,19 +210,107 @@ where
             FunderEvent::FunderIncoming(FunderIncoming::Comm(incoming_comm_msg))
         })
         .chain(stream::once(future::ready(FunderEvent::IncomingCommClosed)));
+    // A canceled shutdown receiver (The sender was dropped) does not produce any event:
+    let incoming_shutdown = stream::iter(opt_shutdown_receiver)
+        .then(|shutdown_receiver| shutdown_receiver)
+        .filter_map(|res| future::ready(res.ok().map(|()| FunderEvent::Shutdown)));
+    let incoming_flush = stream::iter(opt_flush_receiver)
+        .flatten()
+        .map(FunderEvent::Flush);
     // Chain the Init message first:
     let mut incoming_messages = stream::once(future::ready(FunderEvent::FunderIncoming(
         FunderIncoming::Init,
     )))
-    .chain(incoming_control.select(incoming_comm));
+    .chain(
+        incoming_control
+            .select(incoming_comm)
+            .select(timer_stream)
+            .select(incoming_shutdown)
+            .select(incoming_flush),
+    );
+
+    let mut pending_batch = PendingBatch::new();
+
+    loop {
+        // Keep handling events as long as they are immediately available. Otherwise, flush the
+        // pending batch before waiting for the next event:
+        let mut opt_ready_event = None;
+        if !pending_batch.is_empty() && pending_batch.num_events < MAX_EVENTS_IN_DB_BATCH {
+            if let Poll::Ready(opt_funder_event) = await!(next_if_ready(&mut incoming_messages)) {
+                opt_ready_event = Some(opt_funder_event);
+            }
+        }
+        let opt_funder_event = match opt_ready_event {
+            Some(opt_funder_event) => opt_funder_event,
+            None => {
+                await!(flush_batch(
+                    &mut pending_batch,
+                    &mut db_client,
+                    &mut comm_sender,
+                    &mut control_sender,
+                    &funder_state,
+                    &ephemeral,
+                    &mut opt_report_sender,
+                    &mut opt_event_sender
+                ))?;
+                if ephemeral.deferred_send_commands.is_empty() {
+                    await!(incoming_messages.next())
+                } else {
+                    // The previous messages were sent. We may now continue with the deferred
+                    // messages:
+                    Some(FunderEvent::FunderIncoming(FunderIncoming::SendDeferred))
+                }
+            }
+        };
+        let funder_event = match opt_funder_event {
+            Some(funder_event) => funder_event,
+            None => break,
+        };
 
-    while let Some(funder_event) = await!(incoming_messages.next()) {
         // For testing:
         // Read one message from incoming messages:
-        let funder_incoming = match funder_event.clone() {
+        let funder_incoming = match &funder_event {
             FunderEvent::IncomingControlClosed => return Err(FunderError::IncomingControlClosed),
             FunderEvent::IncomingCommClosed => return Err(FunderError::IncomingCommClosed),
-            FunderEvent::FunderIncoming(funder_incoming) => funder_incoming,
+            FunderEvent::Shutdown => {
+                await!(flush_batch(
+                    &mut pending_batch,
+                    &mut db_client,
+                    &mut comm_sender,
+                    &mut control_sender,
+                    &funder_state,
+                    &ephemeral,
+                    &mut opt_report_sender,
+                    &mut opt_event_sender
+                ))?;
+                return Ok(());
+            }
+            FunderEvent::Flush(_) => {
+                await!(flush_batch(
+                    &mut pending_batch,
+                    &mut db_client,
+                    &mut comm_sender,
+                    &mut control_sender,
+                    &funder_state,
+                    &ephemeral,
+                    &mut opt_report_sender,
+                    &mut opt_event_sender
+                ))?;
+                if let FunderEvent::Flush(flush_done) = funder_event {
+                    // The requester might not wait for the result:
+                    let _ = flush_done.send(());
+                }
+                continue;
+            }
+            FunderEvent::TimerTick => {
+                current_tick = current_tick.wrapping_add(1);
+                // Timer ticks are only relevant for timing out warmed friends:
+                if ephemeral.warm_friends.is_empty() {
+                    continue;
+                }
+                FunderIncoming::TimerTick
+            }
+            FunderEvent::FunderIncoming(funder_incoming) => funder_incoming.clone(),
         };
 
         let res = await!(funder_handle_message(
@@ -93,9 +318,10 @@ where
             &rng,
             funder_state.clone(),
             ephemeral.clone(),
-            max_node_relays,
-            max_operations_in_batch,
-            max_pending_user_requests,
+            &funder_config,
+            current_tick,
+            &control_stats,
+            opt_op_timings.as_ref(),
             funder_incoming
         ));
 
@@ -108,14 +334,10 @@ where
             }
         };
 
-        if !handler_output.funder_mutations.is_empty() {
-            // Mutate our funder_state in memory:
-            for mutation in &handler_output.funder_mutations {
-                funder_state.mutate(mutation);
-            }
-            // If there are any mutations, send them to the database:
-            await!(db_client.mutate(handler_output.funder_mutations))
-                .map_err(|_| FunderError::DbError)?;
+        // Mutate our funder_state in memory. The mutations will be sent to the database
+        // together with the rest of the pending batch:
+        for mutation in &handler_output.funder_mutations {
+            funder_state.mutate(mutation);
         }
 
         // Apply ephemeral mutations to our ephemeral:
@@ -123,42 +345,66 @@ where
             ephemeral.mutate(mutation);
         }
 
-        // Send outgoing communication messages:
-        let mut comm_stream = stream::iter::<_>(handler_output.outgoing_comms);
-        await!(comm_sender.send_all(&mut comm_stream)).map_err(|_| FunderError::SendCommError)?;
-
-        // Send outgoing control messages:
-        let mut control_stream = stream::iter::<_>(handler_output.outgoing_control);
-        await!(control_sender.send_all(&mut control_stream))
-            .map_err(|_| FunderError::SendControlError)?;
-
-        if let Some(ref mut event_sender) = opt_event_sender {
-            await!(event_sender.send(funder_event)).unwrap();
-        }
+        pending_batch.num_events += 1;
+        pending_batch.report_changed |=
+            handler_output
+                .outgoing_control
+                .iter()
+                .any(|outgoing_control| match outgoing_control {
+                    FunderOutgoingControl::ReportMutations(funder_report_mutations) => {
+                        !funder_report_mutations.mutations.is_empty()
+                    }
+                    _ => false,
+                });
+        pending_batch
+            .funder_mutations
+            .extend(handler_output.funder_mutations);
+        pending_batch
+            .outgoing_comms
+            .extend(handler_output.outgoing_comms);
+        pending_batch
+            .outgoing_control
+            .extend(handler_output.outgoing_control);
+        pending_batch.funder_events.push(funder_event);
     }
+    await!(flush_batch(
+        &mut pending_batch,
+        &mut db_client,
+        &mut comm_sender,
+        &mut control_sender,
+        &funder_state,
+        &ephemeral,
+        &mut opt_report_sender,
+        &mut opt_event_sender
+    ))?;
     // TODO: Do we ever really get here?
     Ok(())
 }
 
 pub async fn funder_loop<B, R>(
     identity_client: IdentityClient,
+    timer_client: TimerClient,
     rng: R,
     incoming_control: mpsc::Receiver<FunderIncomingControl<B>>,
     incoming_comm: mpsc::Receiver<FunderIncomingComm<B>>,
     control_sender: mpsc::Sender<FunderOutgoingControl<B>>,
     comm_sender: mpsc::Sender<FunderOutgoingComm<B>>,
-    max_operations_in_batch: usize,
-    max_node_relays: usize,
-    max_pending_user_requests: usize,
     funder_state: FunderState<B>,
     db_client: DatabaseClient<FunderMutation<B>>,
+    funder_config: FunderConfig,
+    control_stats: ControlStats,
+    opt_op_timings: Option<OpTimings>,
+    opt_shutdown_receiver: Option<oneshot::Receiver<()>>,
+    opt_flush_receiver: Option<mpsc::Receiver<oneshot::Sender<()>>>,
+    opt_report_sender: Option<LatestSender<FunderReport<B>>>,
 ) -> Result<(), FunderError>
 where
-    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+    B: Clone + PartialEq + Eq + CanonicalSerialize + ValidateAddress + Debug,
     R: CryptoRandom + 'static,
 {
     await!(inner_funder_loop(
         identity_client,
+        timer_client,
         rng,
         incoming_control,
         incoming_comm,
@@ -166,9 +412,12 @@ where
         comm_sender,
         funder_state,
         db_client,
-        max_operations_in_batch,
-        max_node_relays,
-        max_pending_user_requests,
+        funder_config,
+        control_stats,
+        opt_op_timings,
+        opt_shutdown_receiver,
+        opt_flush_receiver,
+        opt_report_sender,
         None
     ))
 }
@@ -1,34 +1,53 @@
+use std::cmp;
+use std::convert::TryFrom;
 use std::fmt::Debug;
 
 use common::canonical_serialize::CanonicalSerialize;
+use common::int_convert::{usize_to_u32, usize_to_u64};
+use common::safe_arithmetic::SafeSignedArithmetic;
 
+use crypto::hash::sha_512_256;
 use crypto::identity::PublicKey;
+use crypto::invoice_id::InvoiceId;
+use crypto::uid::Uid;
 
-use crate::friend::{ChannelStatus, FriendMutation};
-use crate::state::FunderMutation;
+use crate::credit_calc::CreditCalculator;
+#[cfg(feature = "force-inconsistency")]
+use crate::friend::ChannelInconsistent;
+use crate::friend::{ChannelStatus, FriendMutation, FriendState};
+use crate::state::{FunderMutation, FunderState, IdempotentRequest};
 
 use proto::app_server::messages::{NamedRelayAddress, RelayAddress};
+use proto::consts::MAX_MEMO_LEN;
+#[cfg(feature = "force-inconsistency")]
+use proto::funder::messages::ForceInconsistency;
 use proto::funder::messages::{
-    AddFriend, ChannelerUpdateFriend, FriendStatus, FunderControl, FunderOutgoingControl,
-    ReceiptAck, RemoveFriend, ResetFriendChannel, ResponseReceived, ResponseSendFundsResult,
-    SetFriendName, SetFriendRelays, SetFriendRemoteMaxDebt, SetFriendStatus, SetRequestsStatus,
+    AckedReceipt, ActivateFriend, AddFriend, ChannelerUpdateFriend, FirstHopSuggestion,
+    FriendResetToken, FriendStatus, FunderControl, FunderOutgoingControl, PaymentSimulation,
+    PendingFriendRequest, Rebalance, ReceiptAck, RemoveFriend, RemoveFriendConsequences,
+    RequestsStatus, ResetFriendChannel, ResponseReceived, ResponseSendFundsResult,
+    SetFriendMaxSinglePayment, SetFriendMinBalance, SetFriendName, SetFriendRelays,
+    SetFriendRemoteMaxDebt, SetFriendStatus, SetRequestsStatus, SuggestFirstHop,
     UserRequestSendFunds,
 };
+use proto::net::messages::ValidateAddress;
 
-use crate::ephemeral::Ephemeral;
+use crate::control_stats::ControlStats;
+use crate::ephemeral::{Ephemeral, EphemeralMutation, WARM_FRIEND_TIMEOUT_TICKS};
 use crate::handler::canceler::{
     cancel_local_pending_requests, cancel_pending_requests, cancel_pending_user_requests,
 };
 use crate::handler::handler::{is_friend_ready, MutableEphemeral, MutableFunderState};
 use crate::handler::sender::SendCommands;
 
-use crate::types::ChannelerConfig;
+use crate::types::{create_pending_request, ChannelerConfig, FunderConfig};
 
 #[derive(Debug)]
 pub enum HandleControlError {
     FriendDoesNotExist,
     NotInvitedToReset,
     ResetTokenMismatch,
+    EmptyRoute,
     NotFirstInRoute,
     InvalidRoute,
     RequestAlreadyInProgress,
@@ -38,12 +57,57 @@ pub enum HandleControlError {
     UserRequestInvalid,
     FriendNotReady,
     MaxNodeRelaysReached,
+    DuplicateInvoiceId,
+    InvalidAddress,
+    RequestAlreadyCompleted,
+    FriendNotEnabled,
+    PaymentTooLarge,
+    ChannelNotInconsistent,
+    /// The balance of the remote reset terms can not be mirrored on our side.
+    InvalidResetBalance,
+    TokenNotOwned,
+    /// A retry with an idempotency key does not describe the same payment as the original request.
+    IdempotencyKeyMismatch,
 }
 
+impl HandleControlError {
+    /// A name for the error variant, used as a key for ControlStats counters.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HandleControlError::FriendDoesNotExist => "FriendDoesNotExist",
+            HandleControlError::NotInvitedToReset => "NotInvitedToReset",
+            HandleControlError::ResetTokenMismatch => "ResetTokenMismatch",
+            HandleControlError::EmptyRoute => "EmptyRoute",
+            HandleControlError::NotFirstInRoute => "NotFirstInRoute",
+            HandleControlError::InvalidRoute => "InvalidRoute",
+            HandleControlError::RequestAlreadyInProgress => "RequestAlreadyInProgress",
+            HandleControlError::PendingUserRequestsFull => "PendingUserRequestsFull",
+            HandleControlError::ReceiptDoesNotExist => "ReceiptDoesNotExist",
+            HandleControlError::ReceiptSignatureMismatch => "ReceiptSignatureMismatch",
+            HandleControlError::UserRequestInvalid => "UserRequestInvalid",
+            HandleControlError::FriendNotReady => "FriendNotReady",
+            HandleControlError::MaxNodeRelaysReached => "MaxNodeRelaysReached",
+            HandleControlError::DuplicateInvoiceId => "DuplicateInvoiceId",
+            HandleControlError::InvalidAddress => "InvalidAddress",
+            HandleControlError::RequestAlreadyCompleted => "RequestAlreadyCompleted",
+            HandleControlError::FriendNotEnabled => "FriendNotEnabled",
+            HandleControlError::PaymentTooLarge => "PaymentTooLarge",
+            HandleControlError::ChannelNotInconsistent => "ChannelNotInconsistent",
+            HandleControlError::InvalidResetBalance => "InvalidResetBalance",
+            HandleControlError::TokenNotOwned => "TokenNotOwned",
+            HandleControlError::IdempotencyKeyMismatch => "IdempotencyKeyMismatch",
+        }
+    }
+}
+
+/// Set the wanted remote max debt of a friend.
+/// If `confirm` is true, the new value will be proposed to the remote side, and will only
+/// take effect after the remote side acknowledges it.
 fn control_set_friend_remote_max_debt<B>(
     m_state: &mut MutableFunderState<B>,
     send_commands: &mut SendCommands,
     set_friend_remote_max_debt: SetFriendRemoteMaxDebt,
+    confirm: bool,
 ) -> Result<(), HandleControlError>
 where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
@@ -55,14 +119,25 @@ where
         .get(&set_friend_remote_max_debt.friend_public_key)
         .ok_or(HandleControlError::FriendDoesNotExist)?;
 
-    if friend.wanted_remote_max_debt == set_friend_remote_max_debt.remote_max_debt {
+    if friend.wanted_remote_max_debt == set_friend_remote_max_debt.remote_max_debt
+        && friend.confirm_remote_max_debt == confirm
+    {
         // Wanted remote max debt is already set to this value. Nothing to do here.
         return Ok(());
     }
 
+    if friend.confirm_remote_max_debt != confirm {
+        let friend_mutation = FriendMutation::SetConfirmRemoteMaxDebt(confirm);
+        let m_mutation = FunderMutation::FriendMutation((
+            set_friend_remote_max_debt.friend_public_key.clone(),
+            friend_mutation,
+        ));
+        m_state.mutate(m_mutation);
+    }
+
     // We only set the wanted remote max debt here. The actual remote max debt will be changed
     // only when we manage to send a move token message containing the SetRemoteMaxDebt
-    // operation.
+    // operation (Or when the remote side acknowledges our ProposeRemoteMaxDebt operation).
     let friend_mutation =
         FriendMutation::SetWantedRemoteMaxDebt(set_friend_remote_max_debt.remote_max_debt);
     let m_mutation = FunderMutation::FriendMutation((
@@ -75,6 +150,65 @@ where
     Ok(())
 }
 
+fn control_set_friend_min_balance<B>(
+    m_state: &mut MutableFunderState<B>,
+    set_friend_min_balance: SetFriendMinBalance,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    // Make sure that friend exists:
+    let friend = m_state
+        .state()
+        .friends
+        .get(&set_friend_min_balance.friend_public_key)
+        .ok_or(HandleControlError::FriendDoesNotExist)?;
+
+    if friend.opt_min_balance == set_friend_min_balance.opt_min_balance {
+        // Minimum balance is already set to this value. Nothing to do here.
+        return Ok(());
+    }
+
+    let friend_mutation = FriendMutation::SetMinBalance(set_friend_min_balance.opt_min_balance);
+    let funder_mutation = FunderMutation::FriendMutation((
+        set_friend_min_balance.friend_public_key.clone(),
+        friend_mutation,
+    ));
+    m_state.mutate(funder_mutation);
+
+    Ok(())
+}
+
+fn control_set_friend_max_single_payment<B>(
+    m_state: &mut MutableFunderState<B>,
+    set_friend_max_single_payment: SetFriendMaxSinglePayment,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    // Make sure that friend exists:
+    let friend = m_state
+        .state()
+        .friends
+        .get(&set_friend_max_single_payment.friend_public_key)
+        .ok_or(HandleControlError::FriendDoesNotExist)?;
+
+    if friend.opt_max_single_payment == set_friend_max_single_payment.opt_max_single_payment {
+        // Maximum single payment is already set to this value. Nothing to do here.
+        return Ok(());
+    }
+
+    let friend_mutation =
+        FriendMutation::SetMaxSinglePayment(set_friend_max_single_payment.opt_max_single_payment);
+    let funder_mutation = FunderMutation::FriendMutation((
+        set_friend_max_single_payment.friend_public_key.clone(),
+        friend_mutation,
+    ));
+    m_state.mutate(funder_mutation);
+
+    Ok(())
+}
+
 fn control_reset_friend_channel<B>(
     m_state: &mut MutableFunderState<B>,
     send_commands: &mut SendCommands,
@@ -97,6 +231,11 @@ where
                 Some(remote_reset_terms) => {
                     if remote_reset_terms.reset_token != reset_friend_channel.reset_token {
                         Err(HandleControlError::ResetTokenMismatch)
+                    } else if remote_reset_terms.balance_for_reset.checked_neg().is_none() {
+                        // After the reset our balance is the negation of the remote balance.
+                        // If the negation is not representable, the reset terms can not be
+                        // zero sum:
+                        Err(HandleControlError::InvalidResetBalance)
                     } else {
                         Ok(())
                     }
@@ -132,12 +271,16 @@ fn enable_friend<B>(
     outgoing_channeler_config.push(channeler_config);
 }
 
+/// Disconnect from a friend.
+/// If `purge` is true, the friend is going away permanently, and the Channeler may forget
+/// everything it knows about the friend.
 fn disable_friend<B>(
     m_state: &mut MutableFunderState<B>,
     send_commands: &mut SendCommands,
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
     outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
     friend_public_key: &PublicKey,
+    purge: bool,
 ) where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
 {
@@ -147,7 +290,11 @@ fn disable_friend<B>(
     cancel_pending_user_requests(m_state, outgoing_control, friend_public_key);
 
     // Notify Channeler:
-    let channeler_config = ChannelerConfig::RemoveFriend(friend_public_key.clone());
+    let channeler_config = if purge {
+        ChannelerConfig::PurgeFriend(friend_public_key.clone())
+    } else {
+        ChannelerConfig::RemoveFriend(friend_public_key.clone())
+    };
     outgoing_channeler_config.push(channeler_config);
 }
 
@@ -221,16 +368,39 @@ fn control_remove_relay<B>(
     }
 }
 
-fn control_add_friend<B>(m_state: &mut MutableFunderState<B>, add_friend: AddFriend<B>)
+fn control_add_friend<B>(
+    m_state: &mut MutableFunderState<B>,
+    add_friend: AddFriend<B>,
+) -> Result<(), HandleControlError>
 where
-    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+    B: Clone + PartialEq + Eq + CanonicalSerialize + ValidateAddress + Debug,
 {
+    // Make sure that all the friend's relay addresses are well formed:
+    if !add_friend
+        .relays
+        .iter()
+        .all(|relay_address| relay_address.is_valid_address())
+    {
+        return Err(HandleControlError::InvalidAddress);
+    }
+
     let funder_mutation = FunderMutation::AddFriend(add_friend.clone());
     m_state.mutate(funder_mutation);
+
+    // The initial remote max debt is set in the same batch of mutations as the new friend.
+    // It will be sent to the friend once the friend is enabled:
+    if let Some(remote_max_debt) = add_friend.opt_remote_max_debt {
+        let friend_mutation = FriendMutation::SetWantedRemoteMaxDebt(remote_max_debt);
+        let funder_mutation =
+            FunderMutation::FriendMutation((add_friend.friend_public_key, friend_mutation));
+        m_state.mutate(funder_mutation);
+    }
+    Ok(())
 }
 
 /// This is a violent operation, as it removes all the known state with the remote friend.
 /// An inconsistency will occur if the friend is added again.
+/// RequestRemoveFriend can be used first, to find out what will be lost.
 fn control_remove_friend<B>(
     m_state: &mut MutableFunderState<B>,
     send_commands: &mut SendCommands,
@@ -254,6 +424,7 @@ where
         outgoing_control,
         outgoing_channeler_config,
         &remove_friend.friend_public_key,
+        true,
     );
 
     cancel_local_pending_requests(
@@ -303,7 +474,8 @@ where
     let friend_address = friend.remote_relays.clone();
 
     match set_friend_status.status {
-        FriendStatus::Enabled => enable_friend(
+        // A paused friend keeps its connection:
+        FriendStatus::Enabled | FriendStatus::Paused => enable_friend(
             m_state,
             outgoing_channeler_config,
             friend_public_key,
@@ -315,12 +487,61 @@ where
             outgoing_control,
             outgoing_channeler_config,
             &friend_public_key,
+            false,
         ),
     };
 
     Ok(())
 }
 
+/// Enable a friend, open requests and set the wanted remote max debt.
+/// The only way the steps below can fail is if the friend does not exist. This is checked before
+/// anything is changed, so either all the changes are applied or none of them.
+fn control_activate_friend<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    activate_friend: ActivateFriend,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    // Make sure that friend exists:
+    let friend = m_state
+        .state()
+        .friends
+        .get(&activate_friend.friend_public_key)
+        .ok_or(HandleControlError::FriendDoesNotExist)?;
+
+    // Keep the current confirmation policy for the remote max debt:
+    let confirm = friend.confirm_remote_max_debt;
+
+    let set_friend_status = SetFriendStatus {
+        friend_public_key: activate_friend.friend_public_key.clone(),
+        status: FriendStatus::Enabled,
+    };
+    control_set_friend_status(
+        m_state,
+        send_commands,
+        outgoing_control,
+        outgoing_channeler_config,
+        set_friend_status,
+    )?;
+
+    let set_requests_status = SetRequestsStatus {
+        friend_public_key: activate_friend.friend_public_key.clone(),
+        status: RequestsStatus::Open,
+    };
+    control_set_requests_status(m_state, send_commands, set_requests_status)?;
+
+    let set_friend_remote_max_debt = SetFriendRemoteMaxDebt {
+        friend_public_key: activate_friend.friend_public_key,
+        remote_max_debt: activate_friend.remote_max_debt,
+    };
+    control_set_friend_remote_max_debt(m_state, send_commands, set_friend_remote_max_debt, confirm)
+}
+
 fn control_set_requests_status<B>(
     m_state: &mut MutableFunderState<B>,
     send_commands: &mut SendCommands,
@@ -378,7 +599,7 @@ where
     ));
     m_state.mutate(funder_mutation);
 
-    if let FriendStatus::Enabled = friend_status {
+    if friend_status != FriendStatus::Disabled {
         // Notify Channeler to change the friend's address:
         let update_friend = ChannelerUpdateFriend {
             friend_public_key: set_friend_relays.friend_public_key.clone(),
@@ -425,20 +646,97 @@ fn check_user_request_valid(user_request_send_funds: &UserRequestSendFunds) -> O
     if !user_request_send_funds.route.is_valid() {
         return None;
     }
+    if user_request_send_funds.memo.len() > MAX_MEMO_LEN {
+        return None;
+    }
     Some(())
 }
 
+/// Check if the given invoice_id is used by a request that is still in flight, or by a request
+/// that was completed but its receipt was not yet acked.
+fn is_invoice_id_in_use<B>(state: &FunderState<B>, invoice_id: &InvoiceId) -> bool
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    if state
+        .ready_receipts
+        .values()
+        .any(|receipt| &receipt.invoice_id == invoice_id)
+    {
+        return true;
+    }
+
+    for friend in state.friends.values() {
+        if friend
+            .pending_user_requests
+            .iter()
+            .any(|request| &request.invoice_id == invoice_id)
+        {
+            return true;
+        }
+
+        let token_channel = match &friend.channel_status {
+            ChannelStatus::Inconsistent(_) => continue,
+            ChannelStatus::Consistent(token_channel) => token_channel,
+        };
+
+        if token_channel
+            .get_mutual_credit()
+            .state()
+            .pending_requests
+            .pending_local_requests
+            .values()
+            .any(|pending_request| &pending_request.invoice_id == invoice_id)
+        {
+            return true;
+        }
+    }
+    false
+}
+
+/// Check if a request that originated from the user is still in flight.
+fn is_user_request_in_flight<B>(state: &FunderState<B>, request_id: &Uid) -> bool
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    state.friends.values().any(|friend| {
+        if friend
+            .pending_user_requests
+            .iter()
+            .any(|request| &request.request_id == request_id)
+        {
+            return true;
+        }
+        match &friend.channel_status {
+            ChannelStatus::Inconsistent(_) => false,
+            ChannelStatus::Consistent(token_channel) => token_channel
+                .get_mutual_credit()
+                .state()
+                .pending_requests
+                .pending_local_requests
+                .contains_key(request_id),
+        }
+    })
+}
+
 fn control_request_send_funds_inner<B>(
     m_state: &mut MutableFunderState<B>,
     ephemeral: &Ephemeral,
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
     send_commands: &mut SendCommands,
     max_pending_user_requests: usize,
+    reject_duplicate_invoice_id: bool,
     user_request_send_funds: UserRequestSendFunds,
 ) -> Result<(), HandleControlError>
 where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
 {
+    // Reject an empty route early, before anything is derived from it:
+    user_request_send_funds
+        .route
+        .validate_nonempty()
+        .map_err(|_| HandleControlError::EmptyRoute)?;
+
     check_user_request_valid(&user_request_send_funds)
         .ok_or(HandleControlError::UserRequestInvalid)?;
 
@@ -458,6 +756,73 @@ where
         return Ok(());
     }
 
+    // Reject replays of requests that were already completed:
+    if ephemeral
+        .recent_completed_requests
+        .contains(&user_request_send_funds.request_id)
+    {
+        return Err(HandleControlError::RequestAlreadyCompleted);
+    }
+
+    // Retries of the same payment share an idempotency key. A retry returns the result of the
+    // prior request instead of causing a second payment:
+    if let Some(idempotency_key) = &user_request_send_funds.opt_idempotency_key {
+        let opt_prior_request = m_state
+            .state()
+            .idempotency_keys
+            .get(idempotency_key)
+            .cloned();
+        if let Some(prior_request) = opt_prior_request {
+            if prior_request.route != user_request_send_funds.route
+                || prior_request.dest_payment != user_request_send_funds.dest_payment
+            {
+                return Err(HandleControlError::IdempotencyKeyMismatch);
+            }
+
+            let opt_receipt = m_state
+                .state()
+                .ready_receipts
+                .get(&prior_request.request_id)
+                .cloned()
+                .or_else(|| prior_request.opt_acked_receipt.clone());
+
+            if let Some(receipt) = opt_receipt {
+                // The receipt is handed over to the new request, so that it can be acked using
+                // the new request id:
+                let request_id = user_request_send_funds.request_id;
+                m_state.mutate(FunderMutation::RemoveReceipt(prior_request.request_id));
+                m_state.mutate(FunderMutation::AddReceipt((request_id, receipt.clone())));
+                let idempotent_request = IdempotentRequest {
+                    request_id,
+                    ..prior_request
+                };
+                m_state.mutate(FunderMutation::SetIdempotencyKey((
+                    *idempotency_key,
+                    idempotent_request,
+                )));
+
+                let response_received = ResponseReceived {
+                    request_id,
+                    result: ResponseSendFundsResult::Success(receipt),
+                };
+                outgoing_control.push(FunderOutgoingControl::ResponseReceived(response_received));
+                return Ok(());
+            }
+
+            if is_user_request_in_flight(m_state.state(), &prior_request.request_id) {
+                return Err(HandleControlError::RequestAlreadyInProgress);
+            }
+            // The prior request failed, and no payment was made. The retry is a new request.
+        }
+    }
+
+    // Optionally make sure that the invoice_id is not used by another request:
+    if reject_duplicate_invoice_id
+        && is_invoice_id_in_use(m_state.state(), &user_request_send_funds.invoice_id)
+    {
+        return Err(HandleControlError::DuplicateInvoiceId);
+    }
+
     let route = &user_request_send_funds.route;
 
     // We have to be the first on the route:
@@ -482,6 +847,19 @@ where
         return Err(HandleControlError::FriendNotReady);
     }
 
+    if let Some(max_single_payment) = friend.opt_max_single_payment {
+        // The cap applies to the total amount we send to the friend, including the fees of the
+        // nodes along the route:
+        let route_len = usize_to_u32(user_request_send_funds.route.len())
+            .ok_or(HandleControlError::PaymentTooLarge)?;
+        let freeze_credits = CreditCalculator::new(route_len, user_request_send_funds.dest_payment)
+            .credits_to_freeze(1)
+            .ok_or(HandleControlError::PaymentTooLarge)?;
+        if freeze_credits > max_single_payment {
+            return Err(HandleControlError::PaymentTooLarge);
+        }
+    }
+
     // If request is already in progress, we do nothing:
     // Check if there is already a pending user request with the same request_id:
     for user_request in &friend.pending_user_requests {
@@ -511,6 +889,18 @@ where
         return Err(HandleControlError::PendingUserRequestsFull);
     }
 
+    if let Some(idempotency_key) = &user_request_send_funds.opt_idempotency_key {
+        let idempotent_request = IdempotentRequest {
+            request_id: user_request_send_funds.request_id,
+            route: user_request_send_funds.route.clone(),
+            dest_payment: user_request_send_funds.dest_payment,
+            opt_acked_receipt: None,
+        };
+        let funder_mutation =
+            FunderMutation::SetIdempotencyKey((*idempotency_key, idempotent_request));
+        m_state.mutate(funder_mutation);
+    }
+
     let request_send_funds = user_request_send_funds.into_request();
     let friend_mutation = FriendMutation::PushBackPendingUserRequest(request_send_funds);
     let funder_mutation =
@@ -527,6 +917,7 @@ fn control_request_send_funds<B>(
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
     send_commands: &mut SendCommands,
     max_pending_user_requests: usize,
+    reject_duplicate_invoice_id: bool,
     user_request_send_funds: UserRequestSendFunds,
 ) -> Result<(), HandleControlError>
 where
@@ -540,6 +931,7 @@ where
         outgoing_control,
         send_commands,
         max_pending_user_requests,
+        reject_duplicate_invoice_id,
         user_request_send_funds.clone(),
     ) {
         error!("control_request_send_funds_inner() failed: {:?}", e);
@@ -556,9 +948,67 @@ where
     Ok(())
 }
 
+/// Move credits between two of our friends, by sending a payment to ourselves along a cycle:
+/// We -- A -- ... -- B -- We
+/// The result is reported back like the result of any other payment request.
+fn control_rebalance<B>(
+    m_state: &mut MutableFunderState<B>,
+    ephemeral: &Ephemeral,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    send_commands: &mut SendCommands,
+    max_pending_user_requests: usize,
+    reject_duplicate_invoice_id: bool,
+    rebalance: Rebalance,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let local_public_key = m_state.state().local_public_key.clone();
+    let public_keys = &rebalance.route.public_keys;
+
+    // The cycle must pass through at least two friends, otherwise no credits are moved:
+    if public_keys.len() < 4
+        || public_keys.first() != Some(&local_public_key)
+        || public_keys.last() != Some(&local_public_key)
+    {
+        // Every payment request must have a matching response:
+        let response_received = ResponseReceived {
+            request_id: rebalance.request_id,
+            result: ResponseSendFundsResult::Failure(local_public_key),
+        };
+        outgoing_control.push(FunderOutgoingControl::ResponseReceived(response_received));
+        return Err(HandleControlError::InvalidRoute);
+    }
+
+    // We pay ourselves, so there is no real invoice.
+    // We derive a unique invoice id from the request id:
+    let invoice_id = InvoiceId::try_from(&sha_512_256(&rebalance.request_id)[..]).unwrap();
+
+    let user_request_send_funds = UserRequestSendFunds {
+        request_id: rebalance.request_id,
+        route: rebalance.route,
+        invoice_id,
+        dest_payment: rebalance.dest_payment,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
+    };
+
+    control_request_send_funds(
+        m_state,
+        ephemeral,
+        outgoing_control,
+        send_commands,
+        max_pending_user_requests,
+        reject_duplicate_invoice_id,
+        user_request_send_funds,
+    )
+}
+
 /// Handle an incoming receipt ack message
 fn control_receipt_ack<B>(
     m_state: &mut MutableFunderState<B>,
+    m_ephemeral: &mut MutableEphemeral,
+    max_recent_receipts: usize,
     receipt_ack: ReceiptAck,
 ) -> Result<(), HandleControlError>
 where
@@ -576,10 +1026,476 @@ where
     if receipt_ack.receipt_signature != receipt.signature {
         return Err(HandleControlError::ReceiptSignatureMismatch);
     }
+    let receipt = receipt.clone();
+
+    let funder_mutation = FunderMutation::RemoveReceipt(receipt_ack.request_id.clone());
+    m_state.mutate(funder_mutation);
+
+    // Keep the receipt for retries that share the idempotency key of the request:
+    let opt_idempotent = m_state
+        .state()
+        .idempotency_keys
+        .iter()
+        .find(|(_idempotency_key, idempotent_request)| {
+            idempotent_request.request_id == receipt_ack.request_id
+        })
+        .map(|(idempotency_key, idempotent_request)| {
+            (*idempotency_key, idempotent_request.clone())
+        });
+    if let Some((idempotency_key, idempotent_request)) = opt_idempotent {
+        let idempotent_request = IdempotentRequest {
+            opt_acked_receipt: Some(receipt.clone()),
+            ..idempotent_request
+        };
+        m_state.mutate(FunderMutation::SetIdempotencyKey((
+            idempotency_key,
+            idempotent_request,
+        )));
+    }
+
+    // Keep the acked receipt, so that it can be retrieved again later:
+    if max_recent_receipts > 0 {
+        while m_ephemeral.ephemeral().recent_receipts.len() >= max_recent_receipts {
+            m_ephemeral.mutate(EphemeralMutation::RemoveOldestRecentReceipt);
+        }
+        m_ephemeral.mutate(EphemeralMutation::AddRecentReceipt(AckedReceipt {
+            request_id: receipt_ack.request_id.clone(),
+            receipt,
+        }));
+    }
+
+    // Remember the completed request, so that a replay of this request will be rejected:
+    m_ephemeral.mutate(EphemeralMutation::AddCompletedRequest(
+        receipt_ack.request_id,
+    ));
+
+    Ok(())
+}
+
+/// Calculate the amount of credits we can currently send through a friend.
+/// Returns None if the friend is not ready.
+fn friend_send_capacity<B>(
+    state: &FunderState<B>,
+    ephemeral: &Ephemeral,
+    friend_public_key: &PublicKey,
+) -> Option<u128>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    if !is_friend_ready(state, ephemeral, friend_public_key) {
+        return None;
+    }
+
+    let friend = state.friends.get(friend_public_key)?;
+    let token_channel = match &friend.channel_status {
+        ChannelStatus::Inconsistent(_) => return None,
+        ChannelStatus::Consistent(token_channel) => token_channel,
+    };
+
+    // We may send credits as long as balance - local_pending_debt >= -local_max_debt:
+    let balance = &token_channel.get_mutual_credit().state().balance;
+    let capacity = balance
+        .balance
+        .checked_sub_unsigned(balance.local_pending_debt)?
+        .checked_add_unsigned(balance.local_max_debt)?;
+
+    if capacity < 0 {
+        Some(0)
+    } else {
+        Some(capacity as u128)
+    }
+}
+
+/// Suggest a first hop friend for a payment. If the destination is itself a ready friend that
+/// can receive dest_payment, it is suggested, as paying it directly involves no intermediate
+/// nodes. Otherwise the ready friend with the most available capacity is suggested.
+/// Note that this is only a local estimate: The first hop might have to freeze more credits than
+/// dest_payment, depending on the length of the route.
+fn control_suggest_first_hop<B>(
+    m_state: &MutableFunderState<B>,
+    ephemeral: &Ephemeral,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    suggest_first_hop: SuggestFirstHop,
+) where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let dest_public_key = &suggest_first_hop.dest_public_key;
+    let dest_payment = suggest_first_hop.dest_payment;
+    let opt_dest_capacity = friend_send_capacity(m_state.state(), ephemeral, dest_public_key);
+    let opt_friend_public_key = match opt_dest_capacity {
+        Some(dest_capacity) if dest_capacity >= dest_payment => Some(dest_public_key.clone()),
+        _ => best_first_hop(m_state.state(), ephemeral, dest_payment),
+    };
+
+    let first_hop_suggestion = FirstHopSuggestion {
+        request_id: suggest_first_hop.request_id,
+        opt_friend_public_key,
+    };
+    outgoing_control.push(FunderOutgoingControl::FirstHopSuggestion(
+        first_hop_suggestion,
+    ));
+}
+
+/// Find the ready friend with the most available capacity, if it can forward dest_payment.
+fn best_first_hop<B>(
+    state: &FunderState<B>,
+    ephemeral: &Ephemeral,
+    dest_payment: u128,
+) -> Option<PublicKey>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    state
+        .friends
+        .keys()
+        .filter_map(|friend_public_key| {
+            let capacity = friend_send_capacity(state, ephemeral, friend_public_key)?;
+            if capacity < dest_payment {
+                return None;
+            }
+            Some((capacity, friend_public_key))
+        })
+        .max_by_key(|(capacity, _friend_public_key)| *capacity)
+        .map(|(_capacity, friend_public_key)| friend_public_key.clone())
+}
+
+/// Calculate the largest dest_payment we can currently send through a first hop friend, along a
+/// route of `route_len` nodes. Takes into account the balance, local_max_debt and
+/// local_pending_debt with the friend, the credits we freeze for the intermediate nodes on the
+/// route and the max single payment set for the friend.
+/// Returns None if the friend is not ready or the route is too short.
+fn max_first_hop_payment<B>(
+    state: &FunderState<B>,
+    ephemeral: &Ephemeral,
+    friend_public_key: &PublicKey,
+    route_len: u32,
+) -> Option<u128>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let capacity = friend_send_capacity(state, ephemeral, friend_public_key)?;
+
+    // On top of dest_payment, the first hop freezes the credits paid to the intermediate nodes:
+    let route_credits = CreditCalculator::new(route_len, 0).credits_to_freeze(1)?;
+    let max_dest_payment = capacity.saturating_sub(route_credits);
+
+    let friend = state.friends.get(friend_public_key)?;
+    Some(match friend.opt_max_single_payment {
+        Some(max_single_payment) => cmp::min(max_dest_payment, max_single_payment),
+        None => max_dest_payment,
+    })
+}
+
+/// Check if the first hop friend would currently accept a payment of `dest_payment` along a
+/// route of `route_len` nodes.
+fn is_first_hop_feasible<B>(
+    state: &FunderState<B>,
+    ephemeral: &Ephemeral,
+    max_pending_user_requests: usize,
+    friend_public_key: &PublicKey,
+    route_len: u32,
+    dest_payment: u128,
+) -> bool
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let friend = match state.friends.get(friend_public_key) {
+        Some(friend) => friend,
+        None => return false,
+    };
+
+    if friend.pending_user_requests.len() >= max_pending_user_requests {
+        return false;
+    }
+
+    match max_first_hop_payment(state, ephemeral, friend_public_key, route_len) {
+        Some(max_dest_payment) => dest_payment <= max_dest_payment,
+        None => false,
+    }
+}
+
+/// Calculate the cost and feasibility of a payment, without changing any state.
+/// Only the first hop can be checked locally: A feasible payment might still fail further along
+/// the route.
+fn control_simulate_payment<B>(
+    m_state: &MutableFunderState<B>,
+    ephemeral: &Ephemeral,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    max_pending_user_requests: usize,
+    user_request_send_funds: UserRequestSendFunds,
+) where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let state = m_state.state();
+    let route = &user_request_send_funds.route;
+    let dest_payment = user_request_send_funds.dest_payment;
+
+    let mut payment_simulation = PaymentSimulation {
+        request_id: user_request_send_funds.request_id,
+        feasible: false,
+        total_cost: 0,
+        opt_limiting_hop: None,
+        max_dest_payment: 0,
+    };
+
+    // The first hop freezes the total amount of credits the payment costs us:
+    let opt_route_len_cost = usize_to_u32(route.len()).and_then(|route_len| {
+        let total_cost = CreditCalculator::new(route_len, dest_payment).credits_to_freeze(1)?;
+        Some((route_len, total_cost))
+    });
+
+    let (route_len, total_cost) = match opt_route_len_cost {
+        Some(route_len_cost)
+            if check_user_request_valid(&user_request_send_funds).is_some()
+                && route.public_keys.first() == Some(&state.local_public_key) =>
+        {
+            route_len_cost
+        }
+        _ => {
+            // Invalid route:
+            outgoing_control.push(FunderOutgoingControl::PaymentSimulation(payment_simulation));
+            return;
+        }
+    };
+    payment_simulation.total_cost = total_cost;
+
+    let friend_public_key = &route.public_keys[1];
+    payment_simulation.max_dest_payment =
+        max_first_hop_payment(state, ephemeral, friend_public_key, route_len).unwrap_or(0);
+    payment_simulation.feasible = is_first_hop_feasible(
+        state,
+        ephemeral,
+        max_pending_user_requests,
+        friend_public_key,
+        route_len,
+        dest_payment,
+    );
+    if !payment_simulation.feasible {
+        payment_simulation.opt_limiting_hop = Some(friend_public_key.clone());
+    }
+
+    outgoing_control.push(FunderOutgoingControl::PaymentSimulation(payment_simulation));
+}
+
+/// Send the local reset terms of an inconsistent channel with a friend.
+pub fn control_get_reset_token<B>(
+    m_state: &MutableFunderState<B>,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    friend_public_key: PublicKey,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let friend = m_state
+        .state()
+        .friends
+        .get(&friend_public_key)
+        .ok_or(HandleControlError::FriendDoesNotExist)?;
+
+    let local_reset_terms = match &friend.channel_status {
+        ChannelStatus::Consistent(_) => return Err(HandleControlError::ChannelNotInconsistent),
+        ChannelStatus::Inconsistent(channel_inconsistent) => {
+            &channel_inconsistent.local_reset_terms
+        }
+    };
+
+    outgoing_control.push(FunderOutgoingControl::ResetToken(FriendResetToken {
+        friend_public_key,
+        reset_token: local_reset_terms.reset_token.clone(),
+        balance_for_reset: local_reset_terms.balance_for_reset,
+    }));
+    Ok(())
+}
+
+/// Report all the requests that are currently pending with a friend: Requests that wait in the
+/// user requests queue, and requests that were already sent through the token channel.
+pub fn control_get_pending_requests<B>(
+    m_state: &MutableFunderState<B>,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    friend_public_key: PublicKey,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let friend = m_state
+        .state()
+        .friends
+        .get(&friend_public_key)
+        .ok_or(HandleControlError::FriendDoesNotExist)?;
+
+    outgoing_control.push(FunderOutgoingControl::PendingRequests(
+        friend_pending_requests(friend),
+    ));
+    Ok(())
+}
+
+/// Collect the requests that are currently pending with a friend.
+fn friend_pending_requests<B>(friend: &FriendState<B>) -> Vec<PendingFriendRequest>
+where
+    B: Clone + CanonicalSerialize,
+{
+    let mut pending_friend_requests: Vec<_> = friend
+        .pending_user_requests
+        .iter()
+        .map(|request_send_funds| PendingFriendRequest {
+            pending_request: create_pending_request(request_send_funds),
+            is_queued: true,
+        })
+        .collect();
+
+    if let ChannelStatus::Consistent(token_channel) = &friend.channel_status {
+        pending_friend_requests.extend(
+            token_channel
+                .get_mutual_credit()
+                .state()
+                .pending_requests
+                .pending_local_requests
+                .values()
+                .map(|pending_request| PendingFriendRequest {
+                    pending_request: pending_request.clone(),
+                    is_queued: false,
+                }),
+        );
+    }
+
+    pending_friend_requests
+}
+
+/// Report what would be lost by removing a friend, without removing it.
+/// The friend can then be removed using RemoveFriend.
+pub fn control_request_remove_friend<B>(
+    m_state: &MutableFunderState<B>,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    friend_public_key: PublicKey,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let friend = m_state
+        .state()
+        .friends
+        .get(&friend_public_key)
+        .ok_or(HandleControlError::FriendDoesNotExist)?;
+
+    let balance = match &friend.channel_status {
+        ChannelStatus::Consistent(token_channel) => {
+            token_channel.get_mutual_credit().state().balance.balance
+        }
+        ChannelStatus::Inconsistent(channel_inconsistent) => {
+            channel_inconsistent.local_reset_terms.balance_for_reset
+        }
+    };
+
+    outgoing_control.push(FunderOutgoingControl::RemoveFriendConsequences(
+        RemoveFriendConsequences {
+            friend_public_key,
+            balance,
+            pending_requests: friend_pending_requests(friend),
+            num_pending_responses: usize_to_u64(friend.pending_responses.len()).unwrap(),
+        },
+    ));
+    Ok(())
+}
+
+/// Connect to a friend and exchange an empty move token, so that the friend becomes ready.
+/// A FriendWarmed message is sent once the friend is ready, or when the timeout elapses.
+fn control_warm_friend<B>(
+    m_state: &mut MutableFunderState<B>,
+    m_ephemeral: &mut MutableEphemeral,
+    send_commands: &mut SendCommands,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    current_tick: u64,
+    friend_public_key: PublicKey,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    // Make sure that friend exists:
+    let friend = m_state
+        .state()
+        .friends
+        .get(&friend_public_key)
+        .ok_or(HandleControlError::FriendDoesNotExist)?;
+
+    if friend.status != FriendStatus::Enabled {
+        return Err(HandleControlError::FriendNotEnabled);
+    }
 
-    let funder_mutation = FunderMutation::RemoveReceipt(receipt_ack.request_id);
+    // Ask the Channeler to connect to the friend.
+    // An existing connection to the friend is kept by the Channeler.
+    let channeler_update_friend = ChannelerUpdateFriend {
+        friend_public_key: friend_public_key.clone(),
+        friend_relays: friend.remote_relays.clone(),
+        local_relays: friend.sent_local_relays.to_vec(),
+    };
+    outgoing_channeler_config.push(ChannelerConfig::UpdateFriend(channeler_update_friend));
+
+    // Send a (possibly empty) move token to the friend:
+    send_commands.set_resend_outgoing(&friend_public_key);
+    send_commands.set_try_send(&friend_public_key);
+
+    let deadline_tick = current_tick.saturating_add(WARM_FRIEND_TIMEOUT_TICKS);
+    m_ephemeral.mutate(EphemeralMutation::AddWarmFriend((
+        friend_public_key,
+        deadline_tick,
+    )));
+    Ok(())
+}
+
+/// Deliberately drive the channel with a friend into an inconsistent state, using the given
+/// local reset terms.
+/// The remote side is notified exactly as if we received an invalid move token from it.
+#[cfg(feature = "force-inconsistency")]
+fn control_force_inconsistency<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    force_inconsistency: ForceInconsistency,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let ForceInconsistency {
+        friend_public_key,
+        reset_terms: local_reset_terms,
+    } = force_inconsistency;
+
+    let friend = m_state
+        .state()
+        .friends
+        .get(&friend_public_key)
+        .ok_or(HandleControlError::FriendDoesNotExist)?;
+
+    let token_channel = match &friend.channel_status {
+        ChannelStatus::Consistent(token_channel) => token_channel,
+        // Already inconsistent. Nothing to do here.
+        ChannelStatus::Inconsistent(_) => return Ok(()),
+    };
+
+    // The remote side will only accept an InconsistencyError message while it is waiting for
+    // the token:
+    if token_channel.is_outgoing() {
+        return Err(HandleControlError::TokenNotOwned);
+    }
+
+    let opt_last_incoming_move_token = token_channel.get_last_incoming_move_token_hashed().cloned();
+
+    cancel_local_pending_requests(m_state, send_commands, outgoing_control, &friend_public_key);
+    cancel_pending_requests(m_state, send_commands, outgoing_control, &friend_public_key);
+    cancel_pending_user_requests(m_state, outgoing_control, &friend_public_key);
+
+    let channel_inconsistent = ChannelInconsistent {
+        opt_last_incoming_move_token,
+        local_reset_terms,
+        opt_remote_reset_terms: None,
+    };
+    let friend_mutation = FriendMutation::SetInconsistent(channel_inconsistent);
+    let funder_mutation =
+        FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
     m_state.mutate(funder_mutation);
 
+    // The InconsistencyError message will be sent by the sender:
+    send_commands.set_try_send(&friend_public_key);
     Ok(())
 }
 
@@ -589,16 +1505,38 @@ pub fn handle_control_message<B>(
     send_commands: &mut SendCommands,
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
     outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
-    max_node_relays: usize,
-    max_pending_user_requests: usize,
+    funder_config: &FunderConfig,
+    current_tick: u64,
+    control_stats: &ControlStats,
     incoming_control: FunderControl<B>,
 ) -> Result<(), HandleControlError>
 where
-    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+    B: Clone + PartialEq + Eq + CanonicalSerialize + ValidateAddress + Debug,
 {
-    match incoming_control {
+    let res = match incoming_control {
         FunderControl::SetFriendRemoteMaxDebt(set_friend_remote_max_debt) => {
-            control_set_friend_remote_max_debt(m_state, send_commands, set_friend_remote_max_debt)
+            control_set_friend_remote_max_debt(
+                m_state,
+                send_commands,
+                set_friend_remote_max_debt,
+                false,
+            )
+        }
+
+        FunderControl::ProposeFriendRemoteMaxDebt(set_friend_remote_max_debt) => {
+            control_set_friend_remote_max_debt(
+                m_state,
+                send_commands,
+                set_friend_remote_max_debt,
+                true,
+            )
+        }
+
+        FunderControl::SetFriendMinBalance(set_friend_min_balance) => {
+            control_set_friend_min_balance(m_state, set_friend_min_balance)
+        }
+        FunderControl::SetFriendMaxSinglePayment(set_friend_max_single_payment) => {
+            control_set_friend_max_single_payment(m_state, set_friend_max_single_payment)
         }
 
         FunderControl::ResetFriendChannel(reset_friend_channel) => {
@@ -609,7 +1547,7 @@ where
             m_state,
             send_commands,
             outgoing_channeler_config,
-            max_node_relays,
+            funder_config.max_node_relays,
             named_relay_address,
         ),
 
@@ -623,10 +1561,7 @@ where
             Ok(())
         }
 
-        FunderControl::AddFriend(add_friend) => {
-            control_add_friend(m_state, add_friend);
-            Ok(())
-        }
+        FunderControl::AddFriend(add_friend) => control_add_friend(m_state, add_friend),
 
         FunderControl::RemoveFriend(remove_friend) => control_remove_friend(
             m_state,
@@ -644,6 +1579,14 @@ where
             set_friend_status,
         ),
 
+        FunderControl::ActivateFriend(activate_friend) => control_activate_friend(
+            m_state,
+            send_commands,
+            outgoing_control,
+            outgoing_channeler_config,
+            activate_friend,
+        ),
+
         FunderControl::SetRequestsStatus(set_requests_status) => {
             control_set_requests_status(m_state, send_commands, set_requests_status)
         }
@@ -661,10 +1604,93 @@ where
             m_ephemeral.ephemeral(),
             outgoing_control,
             send_commands,
-            max_pending_user_requests,
+            funder_config.max_pending_user_requests,
+            funder_config.reject_duplicate_invoice_id,
             user_request_send_funds,
         ),
 
-        FunderControl::ReceiptAck(receipt_ack) => control_receipt_ack(m_state, receipt_ack),
+        FunderControl::SimulatePayment(user_request_send_funds) => {
+            control_simulate_payment(
+                m_state,
+                m_ephemeral.ephemeral(),
+                outgoing_control,
+                funder_config.max_pending_user_requests,
+                user_request_send_funds,
+            );
+            Ok(())
+        }
+
+        FunderControl::ReceiptAck(receipt_ack) => control_receipt_ack(
+            m_state,
+            m_ephemeral,
+            funder_config.max_recent_receipts,
+            receipt_ack,
+        ),
+
+        FunderControl::GetRecentReceipts => {
+            let recent_receipts = m_ephemeral
+                .ephemeral()
+                .recent_receipts
+                .iter()
+                .cloned()
+                .collect();
+            outgoing_control.push(FunderOutgoingControl::RecentReceipts(recent_receipts));
+            Ok(())
+        }
+
+        FunderControl::SuggestFirstHop(suggest_first_hop) => {
+            control_suggest_first_hop(
+                m_state,
+                m_ephemeral.ephemeral(),
+                outgoing_control,
+                suggest_first_hop,
+            );
+            Ok(())
+        }
+
+        FunderControl::GetPendingRequests(friend_public_key) => {
+            control_get_pending_requests(m_state, outgoing_control, friend_public_key)
+        }
+
+        FunderControl::GetResetToken(friend_public_key) => {
+            control_get_reset_token(m_state, outgoing_control, friend_public_key)
+        }
+
+        FunderControl::RequestRemoveFriend(friend_public_key) => {
+            control_request_remove_friend(m_state, outgoing_control, friend_public_key)
+        }
+
+        FunderControl::Rebalance(rebalance) => control_rebalance(
+            m_state,
+            m_ephemeral.ephemeral(),
+            outgoing_control,
+            send_commands,
+            funder_config.max_pending_user_requests,
+            funder_config.reject_duplicate_invoice_id,
+            rebalance,
+        ),
+
+        FunderControl::WarmFriend(friend_public_key) => control_warm_friend(
+            m_state,
+            m_ephemeral,
+            send_commands,
+            outgoing_channeler_config,
+            current_tick,
+            friend_public_key,
+        ),
+
+        #[cfg(feature = "force-inconsistency")]
+        FunderControl::ForceInconsistency(force_inconsistency) => control_force_inconsistency(
+            m_state,
+            send_commands,
+            outgoing_control,
+            force_inconsistency,
+        ),
+    };
+
+    // Count rejected control messages:
+    if let Err(e) = &res {
+        control_stats.increment(e.name());
     }
+    res
 }
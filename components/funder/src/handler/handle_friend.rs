@@ -1,30 +1,37 @@
 use common::canonical_serialize::CanonicalSerialize;
+use common::int_convert::usize_to_u32;
+use common::safe_arithmetic::SafeSignedArithmetic;
+use std::cmp::{self, Ordering};
 use std::fmt::Debug;
 
 use crypto::crypto_rand::CryptoRandom;
-use crypto::identity::{PublicKey, Signature, SIGNATURE_LEN};
+use crypto::identity::{compare_public_key, PublicKey, Signature, SIGNATURE_LEN};
 
 use proto::app_server::messages::RelayAddress;
+use proto::consts::MAX_HOP_BUDGET;
 use proto::funder::messages::{
-    ChannelerUpdateFriend, FailureSendFunds, FriendMessage, FunderOutgoingControl,
-    MoveTokenRequest, PendingRequest, RequestSendFunds, ResetTerms, ResponseReceived,
+    ChannelerUpdateFriend, FailureSendFunds, FriendMessage, FriendStatus, FunderOutgoingControl,
+    MoveToken, MoveTokenRequest, PendingRequest, RequestSendFunds, ResetTerms, ResponseReceived,
     ResponseSendFunds, ResponseSendFundsResult,
 };
 use proto::funder::signature_buff::{prepare_receipt, verify_move_token};
 
+use crate::credit_calc::CreditCalculator;
 use crate::mutual_credit::incoming::{
     IncomingFailureSendFunds, IncomingMessage, IncomingResponseSendFunds,
 };
-use crate::token_channel::{MoveTokenReceived, ReceiveMoveTokenOutput, TokenChannel};
+use crate::mutual_credit::op_timings::OpTimings;
+use crate::mutual_credit::types::McBalance;
+use crate::token_channel::{MoveTokenReceived, ReceiveMoveTokenOutput, TcDirection, TokenChannel};
 
 use crate::types::{create_pending_request, ChannelerConfig};
 
 use crate::friend::{
-    ChannelInconsistent, ChannelStatus, FriendMutation, ResponseOp, SentLocalRelays,
+    ChannelInconsistent, ChannelStatus, FriendMutation, ResetStatus, ResponseOp, SentLocalRelays,
 };
-use crate::state::FunderMutation;
+use crate::state::{FunderMutation, FunderState};
 
-use crate::ephemeral::Ephemeral;
+use crate::ephemeral::{Ephemeral, EphemeralMutation};
 
 use crate::handler::canceler::{
     cancel_local_pending_requests, cancel_pending_requests, cancel_pending_user_requests,
@@ -39,6 +46,7 @@ use crate::handler::sender::SendCommands;
 pub enum HandleFriendError {
     FriendDoesNotExist,
     InconsistencyWhenTokenOwned,
+    MoveTokenCorrupted,
 }
 
 /// Generate a random token to be used for resetting the channel.
@@ -69,6 +77,27 @@ where
     }
 }
 
+/// Check if an incoming move token is a valid attempt of the remote side to reset the channel,
+/// using our local reset terms.
+fn is_remote_reset<B>(
+    move_token: &MoveToken<B>,
+    friend_public_key: &PublicKey,
+    local_reset_terms: &ResetTerms,
+) -> bool
+where
+    B: Clone + CanonicalSerialize,
+{
+    move_token.old_token == local_reset_terms.reset_token
+        && move_token.operations.is_empty()
+        && move_token.opt_local_relays.is_none()
+        && move_token.inconsistency_counter == local_reset_terms.inconsistency_counter
+        && move_token.move_token_counter == 0
+        && move_token.balance == local_reset_terms.balance_for_reset.checked_neg().unwrap()
+        && move_token.local_pending_debt == 0
+        && move_token.remote_pending_debt == 0
+        && verify_move_token(move_token, friend_public_key)
+}
+
 /// Check if channel reset is required (Remove side used the RESET token)
 /// If so, reset the channel.
 pub fn try_reset_channel<B>(
@@ -83,16 +112,7 @@ pub fn try_reset_channel<B>(
     let move_token = &move_token_request.friend_move_token;
 
     // Check if incoming message is a valid attempt to reset the channel:
-    if move_token.old_token != local_reset_terms.reset_token
-        || !move_token.operations.is_empty()
-        || move_token.opt_local_relays.is_some()
-        || move_token.inconsistency_counter != local_reset_terms.inconsistency_counter
-        || move_token.move_token_counter != 0
-        || move_token.balance != local_reset_terms.balance_for_reset.checked_neg().unwrap()
-        || move_token.local_pending_debt != 0
-        || move_token.remote_pending_debt != 0
-        || !verify_move_token(move_token, friend_public_key)
-    {
+    if !is_remote_reset(move_token, friend_public_key, local_reset_terms) {
         send_commands.set_resend_outgoing(friend_public_key);
         return;
     }
@@ -110,6 +130,15 @@ pub fn try_reset_channel<B>(
         FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
     m_state.mutate(funder_mutation);
 
+    // The reset move token was signed by the remote side over our reset terms, hence the remote
+    // side has reset the channel to the same terms:
+    let friend_mutation = FriendMutation::SetResetStatus(ResetStatus::Confirmed);
+    let funder_mutation =
+        FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+    m_state.mutate(funder_mutation);
+
+    // Let the remote side know that we have accepted its reset:
+    send_commands.set_reset_ack(friend_public_key);
     send_commands.set_try_send(friend_public_key);
     if move_token_request.token_wanted {
         send_commands.set_remote_wants_token(friend_public_key);
@@ -124,12 +153,11 @@ fn forward_request<B>(
 ) where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
 {
-    let index = request_send_funds
+    let (_upstream, downstream) = request_send_funds
         .route
-        .pk_to_index(&m_state.state().local_public_key)
+        .split_at_node(&m_state.state().local_public_key)
         .unwrap();
-    let next_index = index.checked_add(1).unwrap();
-    let next_pk = request_send_funds.route.index_to_pk(next_index).unwrap();
+    let next_pk = downstream.index_to_pk(1).unwrap();
 
     // Queue message to the relevant friend. Later this message will be queued to a specific
     // available token channel:
@@ -139,15 +167,117 @@ fn forward_request<B>(
     send_commands.set_try_send(&next_pk);
 }
 
+/// Calculate our balance with the next node on the route, assuming that the given request is
+/// forwarded to it and all pending requests are fulfilled.
+fn balance_after_forward(
+    balance: &McBalance,
+    request_send_funds: &RequestSendFunds,
+    next_index: usize,
+) -> Option<i128> {
+    let route_len = usize_to_u32(request_send_funds.route.len())?;
+    let credit_calc = CreditCalculator::new(route_len, request_send_funds.dest_payment);
+    let freeze_credits = credit_calc.credits_to_freeze(usize_to_u32(next_index)?)?;
+    balance
+        .balance
+        .checked_sub_unsigned(balance.local_pending_debt)?
+        .checked_sub_unsigned(freeze_credits)
+}
+
+/// Check if forwarding a request to the next node on the route would push our balance with
+/// the next node below the minimum balance configured for it.
+fn forward_breaches_min_balance<B>(
+    state: &FunderState<B>,
+    request_send_funds: &RequestSendFunds,
+    next_index: usize,
+) -> bool
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let next_public_key = request_send_funds.route.index_to_pk(next_index).unwrap();
+    let friend = state.friends.get(next_public_key).unwrap();
+    let min_balance = match friend.opt_min_balance {
+        Some(min_balance) => min_balance,
+        None => return false,
+    };
+
+    let token_channel = match &friend.channel_status {
+        ChannelStatus::Consistent(token_channel) => token_channel,
+        ChannelStatus::Inconsistent(_) => unreachable!(),
+    };
+    let balance = &token_channel.get_mutual_credit().state().balance;
+
+    match balance_after_forward(balance, request_send_funds, next_index) {
+        Some(balance_after) => balance_after < min_balance,
+        // Overflow: We can not tell, so we refuse to forward.
+        None => true,
+    }
+}
+
+/// Check if the credits we freeze when forwarding the request to the next node on the route
+/// exceed the maximum single payment we are willing to send to it.
+fn forward_exceeds_max_single_payment<B>(
+    state: &FunderState<B>,
+    request_send_funds: &RequestSendFunds,
+    next_index: usize,
+) -> bool
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let next_public_key = request_send_funds.route.index_to_pk(next_index).unwrap();
+    let friend = state.friends.get(next_public_key).unwrap();
+    let max_single_payment = match friend.opt_max_single_payment {
+        Some(max_single_payment) => max_single_payment,
+        None => return false,
+    };
+    let freeze_credits = usize_to_u32(request_send_funds.route.len()).and_then(|route_len| {
+        CreditCalculator::new(route_len, request_send_funds.dest_payment)
+            .credits_to_freeze(usize_to_u32(next_index)?)
+    });
+    match freeze_credits {
+        Some(freeze_credits) => freeze_credits > max_single_payment,
+        None => true,
+    }
+}
+
+/// Check if the queue of requests waiting to be sent to the next node on the route is full.
+/// This bounds the memory a single friend can make us use, for example by never letting us
+/// send our pending requests.
+fn forward_exceeds_max_pending_requests<B>(
+    state: &FunderState<B>,
+    request_send_funds: &RequestSendFunds,
+    next_index: usize,
+    max_pending_requests: usize,
+) -> bool
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let next_public_key = request_send_funds.route.index_to_pk(next_index).unwrap();
+    let friend = state.friends.get(next_public_key).unwrap();
+    friend.pending_requests.len() >= max_pending_requests
+}
+
 fn handle_request_send_funds<B>(
     m_state: &mut MutableFunderState<B>,
     ephemeral: &Ephemeral,
     send_commands: &mut SendCommands,
+    max_pending_requests: usize,
     remote_public_key: &PublicKey,
-    request_send_funds: RequestSendFunds,
+    mut request_send_funds: RequestSendFunds,
 ) where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
 {
+    // We don't accept new requests from a paused friend:
+    let remote_friend = m_state.state().friends.get(remote_public_key).unwrap();
+    if remote_friend.status == FriendStatus::Paused {
+        reply_with_failure(
+            m_state,
+            send_commands,
+            remote_public_key,
+            &request_send_funds,
+        );
+        return;
+    }
+
     // Find ourselves on the route. If we are not there, abort.
     let remote_index = request_send_funds
         .route
@@ -158,8 +288,10 @@ fn handle_request_send_funds<B>(
     let next_index = local_index.checked_add(1).unwrap();
     if next_index >= request_send_funds.route.len() {
         // We are the destination of this request. We return a response:
+        // The funds are received only after the response is sent (See sender.rs):
         let pending_request = create_pending_request(&request_send_funds);
-        let u_response_op = ResponseOp::UnsignedResponse(pending_request);
+        let u_response_op =
+            ResponseOp::UnsignedResponse((pending_request, request_send_funds.memo));
         let friend_mutation = FriendMutation::PushBackPendingResponse(u_response_op);
         let funder_mutation =
             FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation));
@@ -181,7 +313,23 @@ fn handle_request_send_funds<B>(
         false
     };
 
-    if !friend_ready {
+    // The request may not be forwarded any further if its hop budget was exhausted.
+    // This bounds the amount of work a single request can cause along a long route.
+    // We never allow more than MAX_HOP_BUDGET forwards, whatever budget the origin has chosen:
+    let opt_next_hop_budget =
+        cmp::min(request_send_funds.hop_budget, MAX_HOP_BUDGET).checked_sub(1);
+
+    if !friend_ready
+        || opt_next_hop_budget.is_none()
+        || forward_breaches_min_balance(m_state.state(), &request_send_funds, next_index)
+        || forward_exceeds_max_single_payment(m_state.state(), &request_send_funds, next_index)
+        || forward_exceeds_max_pending_requests(
+            m_state.state(),
+            &request_send_funds,
+            next_index,
+            max_pending_requests,
+        )
+    {
         reply_with_failure(
             m_state,
             send_commands,
@@ -192,6 +340,7 @@ fn handle_request_send_funds<B>(
     }
 
     // Queue message to the next node.
+    request_send_funds.hop_budget = opt_next_hop_budget.unwrap();
     forward_request(m_state, send_commands, request_send_funds);
 }
 
@@ -273,6 +422,7 @@ fn handle_move_token_output<B>(
     m_ephemeral: &mut MutableEphemeral,
     send_commands: &mut SendCommands,
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    max_pending_requests: usize,
     remote_public_key: &PublicKey,
     incoming_messages: Vec<IncomingMessage>,
 ) where
@@ -285,6 +435,7 @@ fn handle_move_token_output<B>(
                     m_state,
                     m_ephemeral.ephemeral(),
                     send_commands,
+                    max_pending_requests,
                     remote_public_key,
                     request_send_funds,
                 );
@@ -363,6 +514,8 @@ fn handle_move_token_success<B>(
     send_commands: &mut SendCommands,
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
     outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    max_pending_requests: usize,
+    monitor_duplicate_move_tokens: bool,
     remote_public_key: &PublicKey,
     receive_move_token_output: ReceiveMoveTokenOutput<B>,
     token_wanted: bool,
@@ -370,7 +523,31 @@ fn handle_move_token_success<B>(
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
 {
     match receive_move_token_output {
-        ReceiveMoveTokenOutput::Duplicate => {}
+        ReceiveMoveTokenOutput::Duplicate => {
+            // Duplicates are expected occasionally, for example when the remote side resends
+            // its last move token. Monitoring them helps detecting replays or message loops:
+            if monitor_duplicate_move_tokens {
+                warn!(
+                    "Received a duplicate move token from {:?}",
+                    remote_public_key
+                );
+                let ephemeral_mutation =
+                    EphemeralMutation::IncDuplicateMoveTokens(remote_public_key.clone());
+                m_ephemeral.mutate(ephemeral_mutation);
+            }
+
+            // The remote side resends its reset move token if our acknowledgement was lost.
+            // We acknowledge it again:
+            let friend = m_state.state().friends.get(remote_public_key).unwrap();
+            if let ChannelStatus::Consistent(token_channel) = &friend.channel_status {
+                if friend.reset_status == ResetStatus::Confirmed
+                    && !token_channel.is_outgoing()
+                    && token_channel.get_move_token_counter() == 0
+                {
+                    send_commands.set_reset_ack(remote_public_key);
+                }
+            }
+        }
         ReceiveMoveTokenOutput::RetransmitOutgoing(_outgoing_move_token) => {
             // Retransmit last sent token channel message:
             send_commands.set_resend_outgoing(remote_public_key);
@@ -412,6 +589,31 @@ fn handle_move_token_success<B>(
                 m_state.mutate(funder_mutation);
             }
 
+            // The remote side has signed its move token on top of our last outgoing move token.
+            // The responses we have sent in our move token are now acknowledged, and the funds
+            // are received:
+            let friend = m_state.state().friends.get(remote_public_key).unwrap();
+            if !friend.unacked_funds.is_empty() {
+                let unacked_funds = friend.unacked_funds.clone();
+                let friend_mutation = FriendMutation::ClearUnackedFunds;
+                let funder_mutation =
+                    FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation));
+                m_state.mutate(funder_mutation);
+                for funds_received in unacked_funds {
+                    outgoing_control.push(FunderOutgoingControl::FundsReceived(funds_received));
+                }
+            }
+
+            // If we have reset the channel, a move token signed on top of our reset move token
+            // confirms the reset, even if the acknowledgement of the remote side was lost:
+            let friend = m_state.state().friends.get(remote_public_key).unwrap();
+            if let ResetStatus::Pending(_) = friend.reset_status {
+                let friend_mutation = FriendMutation::SetResetStatus(ResetStatus::Confirmed);
+                let funder_mutation =
+                    FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation));
+                m_state.mutate(funder_mutation);
+            }
+
             // If address update was pending, we can clear it, as this is a proof that the
             // remote side has received our update:
             let friend = m_state.state().friends.get(remote_public_key).unwrap();
@@ -465,6 +667,7 @@ fn handle_move_token_success<B>(
                 m_ephemeral,
                 send_commands,
                 outgoing_control,
+                max_pending_requests,
                 remote_public_key,
                 incoming_messages,
             );
@@ -482,6 +685,9 @@ fn handle_move_token_request<B, R>(
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
     outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
     rng: &R,
+    max_pending_requests: usize,
+    monitor_duplicate_move_tokens: bool,
+    opt_op_timings: Option<&OpTimings>,
     remote_public_key: &PublicKey,
     friend_move_token_request: MoveTokenRequest<B>,
 ) -> Result<(), HandleFriendError>
@@ -495,6 +701,13 @@ where
         None => Err(HandleFriendError::FriendDoesNotExist),
     }?;
 
+    // Make sure that the move token was not corrupted on its way to us.
+    // This is checked before any signature is verified, to tell apart transport problems from
+    // invalid signatures:
+    if !friend_move_token_request.verify_checksum() {
+        return Err(HandleFriendError::MoveTokenCorrupted);
+    }
+
     let token_channel = match &friend.channel_status {
         ChannelStatus::Consistent(token_channel) => token_channel,
         ChannelStatus::Inconsistent(channel_inconsistent) => {
@@ -510,8 +723,10 @@ where
     };
 
     // We will only consider move token messages if we are in a consistent state:
-    let receive_move_token_res =
-        token_channel.simulate_receive_move_token(friend_move_token_request.friend_move_token);
+    let receive_move_token_res = token_channel.simulate_receive_move_token(
+        friend_move_token_request.friend_move_token.clone(),
+        opt_op_timings,
+    );
     let token_wanted = friend_move_token_request.token_wanted;
 
     match receive_move_token_res {
@@ -522,12 +737,33 @@ where
                 send_commands,
                 outgoing_control,
                 outgoing_channeler_config,
+                max_pending_requests,
+                monitor_duplicate_move_tokens,
                 remote_public_key,
                 receive_move_token_output,
                 token_wanted,
             );
         }
         Err(_receive_move_token_error) => {
+            // Both sides might have reset the channel at the same time. In that case each side
+            // holds a reset move token that the other side can not chain on:
+            if let ResetStatus::Pending(local_reset_terms) = &friend.reset_status {
+                if is_remote_reset(
+                    &friend_move_token_request.friend_move_token,
+                    remote_public_key,
+                    local_reset_terms,
+                ) {
+                    let local_reset_terms = local_reset_terms.clone();
+                    handle_simultaneous_reset(
+                        m_state,
+                        send_commands,
+                        remote_public_key,
+                        &local_reset_terms,
+                        &friend_move_token_request,
+                    );
+                    return Ok(());
+                }
+            }
             handle_move_token_error(
                 m_state,
                 send_commands,
@@ -540,6 +776,71 @@ where
     Ok(())
 }
 
+/// Both sides have reset the channel at the same time.
+/// We resolve the conflict deterministically using the same ordering that decides the first
+/// sender of a new token channel: The reset of the first sender is kept.
+fn handle_simultaneous_reset<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    remote_public_key: &PublicKey,
+    local_reset_terms: &ResetTerms,
+    friend_move_token_request: &MoveTokenRequest<B>,
+) where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let local_public_key = &m_state.state().local_public_key;
+    if compare_public_key(local_public_key, remote_public_key) == Ordering::Less {
+        // We are the first sender. We keep our reset move token, and the remote side will
+        // accept it.
+        return;
+    }
+
+    // We are the second sender. We accept the reset move token of the remote side:
+    try_reset_channel(
+        m_state,
+        send_commands,
+        remote_public_key,
+        local_reset_terms,
+        friend_move_token_request,
+    );
+}
+
+/// The remote side acknowledges our reset move token.
+fn handle_reset_ack<B>(
+    m_state: &mut MutableFunderState<B>,
+    remote_public_key: &PublicKey,
+    reset_token: &Signature,
+) where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let friend = m_state.state().friends.get(remote_public_key).unwrap();
+    match friend.reset_status {
+        ResetStatus::Pending(_) => {}
+        // We are not waiting for an acknowledgement:
+        ResetStatus::NoReset | ResetStatus::Confirmed => return,
+    }
+
+    let token_channel = match &friend.channel_status {
+        ChannelStatus::Consistent(token_channel) => token_channel,
+        ChannelStatus::Inconsistent(_) => return,
+    };
+
+    // Make sure that the acknowledgement matches our reset move token:
+    match token_channel.get_direction() {
+        TcDirection::Outgoing(tc_outgoing) => {
+            if &tc_outgoing.move_token_out.new_token != reset_token {
+                return;
+            }
+        }
+        TcDirection::Incoming(_) => return,
+    }
+
+    let friend_mutation = FriendMutation::SetResetStatus(ResetStatus::Confirmed);
+    let funder_mutation =
+        FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation));
+    m_state.mutate(funder_mutation);
+}
+
 fn handle_inconsistency_error<B, R>(
     m_state: &mut MutableFunderState<B>,
     send_commands: &mut SendCommands,
@@ -611,6 +912,9 @@ pub fn handle_friend_message<B, R>(
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
     outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
     rng: &R,
+    max_pending_requests: usize,
+    monitor_duplicate_move_tokens: bool,
+    opt_op_timings: Option<&OpTimings>,
     remote_public_key: &PublicKey,
     friend_message: FriendMessage<B>,
 ) -> Result<(), HandleFriendError>
@@ -632,6 +936,9 @@ where
             outgoing_control,
             outgoing_channeler_config,
             rng,
+            max_pending_requests,
+            monitor_duplicate_move_tokens,
+            opt_op_timings,
             remote_public_key,
             friend_move_token_request,
         ),
@@ -644,5 +951,241 @@ where
             remote_public_key,
             remote_reset_terms,
         ),
+
+        FriendMessage::ResetAck(reset_token) => {
+            handle_reset_ack(m_state, remote_public_key, &reset_token);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crypto::identity::PUBLIC_KEY_LEN;
+    use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
+    use crypto::uid::{Uid, UID_LEN};
+    use proto::funder::messages::{AddFriend, FriendStatus, FriendsRoute, RequestsStatus};
+
+    use crate::liveness::LivenessMutation;
+    use crate::mutual_credit::types::McMutation;
+    use crate::token_channel::TcMutation;
+
+    use crate::tests::utils::{dummy_named_relay_address, dummy_relay_address};
+
+    /// Create the state of a node that sits between prev_pk and next_pk on a route.
+    /// next_pk is ready to receive forwarded requests.
+    fn create_forwarding_state(
+        local_pk: &PublicKey,
+        prev_pk: &PublicKey,
+        next_pk: &PublicKey,
+    ) -> (FunderState<u32>, Ephemeral) {
+        let relays = vec![dummy_named_relay_address(0)];
+        let mut state = FunderState::<u32>::new(local_pk.clone(), relays);
+        let mut ephemeral = Ephemeral::new();
+
+        for (index, friend_pk) in [prev_pk, next_pk].iter().enumerate() {
+            let add_friend = AddFriend {
+                friend_public_key: (*friend_pk).clone(),
+                relays: vec![dummy_relay_address(index as u8)],
+                name: format!("friend{}", index),
+                balance: 0i128,
+                opt_remote_max_debt: None,
+            };
+            state.mutate(&FunderMutation::AddFriend(add_friend));
+
+            let friend_mutation = FriendMutation::SetStatus(FriendStatus::Enabled);
+            let funder_mutation =
+                FunderMutation::FriendMutation(((*friend_pk).clone(), friend_mutation));
+            state.mutate(&funder_mutation);
+
+            let liveness_mutation = LivenessMutation::SetOnline((*friend_pk).clone());
+            ephemeral.mutate(&EphemeralMutation::LivenessMutation(liveness_mutation));
+        }
+
+        // The next node on the route has its requests open:
+        let mc_mutation = McMutation::SetRemoteRequestsStatus(RequestsStatus::Open);
+        let friend_mutation = FriendMutation::TcMutation(TcMutation::McMutation(mc_mutation));
+        let funder_mutation = FunderMutation::FriendMutation((next_pk.clone(), friend_mutation));
+        state.mutate(&funder_mutation);
+
+        (state, ephemeral)
+    }
+
+    #[test]
+    fn test_handle_request_send_funds_hop_budget() {
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let pk_c = PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]);
+        let pk_d = PublicKey::from(&[0xdd; PUBLIC_KEY_LEN]);
+        let pk_e = PublicKey::from(&[0xee; PUBLIC_KEY_LEN]);
+
+        // The route requires 3 forwards (B, C, D), but the hop budget only allows one:
+        let request_send_funds = RequestSendFunds {
+            request_id: Uid::from(&[1; UID_LEN]),
+            route: FriendsRoute {
+                public_keys: vec![
+                    pk_a.clone(),
+                    pk_b.clone(),
+                    pk_c.clone(),
+                    pk_d.clone(),
+                    pk_e.clone(),
+                ],
+            },
+            dest_payment: 10,
+            invoice_id: InvoiceId::from(&[2; INVOICE_ID_LEN]),
+            hop_budget: 1,
+            memo: Vec::new(),
+        };
+
+        // B forwards the request to C, consuming the last hop:
+        let (state_b, ephemeral_b) = create_forwarding_state(&pk_b, &pk_a, &pk_c);
+        let mut m_state_b = MutableFunderState::new(state_b);
+        let mut send_commands = SendCommands::new();
+        handle_request_send_funds(
+            &mut m_state_b,
+            &ephemeral_b,
+            &mut send_commands,
+            16,
+            &pk_a,
+            request_send_funds,
+        );
+
+        let (_initial_state, _mutations, state_b) = m_state_b.done();
+        let friend_c = state_b.friends.get(&pk_c).unwrap();
+        assert_eq!(friend_c.pending_requests.len(), 1);
+        let forwarded_request = friend_c.pending_requests[0].clone();
+        assert_eq!(forwarded_request.hop_budget, 0);
+        assert!(state_b
+            .friends
+            .get(&pk_a)
+            .unwrap()
+            .pending_responses
+            .is_empty());
+
+        // C can not forward the request any further, and replies with a failure:
+        let (state_c, ephemeral_c) = create_forwarding_state(&pk_c, &pk_b, &pk_d);
+        let mut m_state_c = MutableFunderState::new(state_c);
+        let mut send_commands = SendCommands::new();
+        handle_request_send_funds(
+            &mut m_state_c,
+            &ephemeral_c,
+            &mut send_commands,
+            16,
+            &pk_b,
+            forwarded_request,
+        );
+
+        let (_initial_state, _mutations, state_c) = m_state_c.done();
+        assert!(state_c
+            .friends
+            .get(&pk_d)
+            .unwrap()
+            .pending_requests
+            .is_empty());
+        let friend_b = state_c.friends.get(&pk_b).unwrap();
+        assert_eq!(friend_b.pending_responses.len(), 1);
+        match &friend_b.pending_responses[0] {
+            ResponseOp::UnsignedFailure(pending_request) => {
+                assert_eq!(pending_request.request_id, Uid::from(&[1; UID_LEN]));
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    #[test]
+    fn test_handle_request_send_funds_hop_budget_bounded() {
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let pk_c = PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]);
+        let pk_d = PublicKey::from(&[0xdd; PUBLIC_KEY_LEN]);
+
+        // The origin sets a hop budget above the maximum:
+        let request_send_funds = RequestSendFunds {
+            request_id: Uid::from(&[1; UID_LEN]),
+            route: FriendsRoute {
+                public_keys: vec![pk_a.clone(), pk_b.clone(), pk_c.clone(), pk_d.clone()],
+            },
+            dest_payment: 10,
+            invoice_id: InvoiceId::from(&[2; INVOICE_ID_LEN]),
+            hop_budget: u32::max_value(),
+            memo: Vec::new(),
+        };
+
+        let (state_b, ephemeral_b) = create_forwarding_state(&pk_b, &pk_a, &pk_c);
+        let mut m_state_b = MutableFunderState::new(state_b);
+        let mut send_commands = SendCommands::new();
+        handle_request_send_funds(
+            &mut m_state_b,
+            &ephemeral_b,
+            &mut send_commands,
+            16,
+            &pk_a,
+            request_send_funds,
+        );
+
+        // B forwards the request, but never with more than the maximum hop budget:
+        let (_initial_state, _mutations, state_b) = m_state_b.done();
+        let friend_c = state_b.friends.get(&pk_c).unwrap();
+        assert_eq!(friend_c.pending_requests.len(), 1);
+        assert_eq!(friend_c.pending_requests[0].hop_budget, MAX_HOP_BUDGET - 1);
+    }
+
+    #[test]
+    fn test_handle_request_send_funds_max_pending_requests() {
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let pk_c = PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]);
+
+        let max_pending_requests = 2;
+
+        // B forwards requests from A to C:
+        let (state_b, ephemeral_b) = create_forwarding_state(&pk_b, &pk_a, &pk_c);
+        let mut m_state_b = MutableFunderState::new(state_b);
+        let mut send_commands = SendCommands::new();
+
+        for i in 0..=max_pending_requests as u8 {
+            let request_send_funds = RequestSendFunds {
+                request_id: Uid::from(&[i; UID_LEN]),
+                route: FriendsRoute {
+                    public_keys: vec![pk_a.clone(), pk_b.clone(), pk_c.clone()],
+                },
+                dest_payment: 10,
+                invoice_id: InvoiceId::from(&[i; INVOICE_ID_LEN]),
+                hop_budget: 1,
+                memo: Vec::new(),
+            };
+            handle_request_send_funds(
+                &mut m_state_b,
+                &ephemeral_b,
+                &mut send_commands,
+                max_pending_requests,
+                &pk_a,
+                request_send_funds,
+            );
+        }
+
+        let (_initial_state, _mutations, state_b) = m_state_b.done();
+
+        // The requests that were queued before the queue was full are kept intact:
+        let friend_c = state_b.friends.get(&pk_c).unwrap();
+        assert_eq!(friend_c.pending_requests.len(), max_pending_requests);
+        for (i, pending_request) in friend_c.pending_requests.iter().enumerate() {
+            assert_eq!(pending_request.request_id, Uid::from(&[i as u8; UID_LEN]));
+        }
+
+        // The last request was not queued, and a failure is returned to A:
+        let friend_a = state_b.friends.get(&pk_a).unwrap();
+        assert_eq!(friend_a.pending_responses.len(), 1);
+        match &friend_a.pending_responses[0] {
+            ResponseOp::UnsignedFailure(pending_request) => {
+                assert_eq!(
+                    pending_request.request_id,
+                    Uid::from(&[max_pending_requests as u8; UID_LEN])
+                );
+            }
+            _ => unreachable!(),
+        };
     }
 }
@@ -16,7 +16,7 @@ pub fn handle_init<B>(
     let mut enabled_friends = Vec::new();
     for (_friend_public_key, friend) in &m_state.state().friends {
         match friend.status {
-            FriendStatus::Enabled => {
+            FriendStatus::Enabled | FriendStatus::Paused => {
                 let channeler_add_friend = ChannelerUpdateFriend {
                     friend_public_key: friend.remote_public_key.clone(),
                     friend_relays: friend.remote_relays.clone(),
@@ -79,6 +79,7 @@ mod tests {
             relays: vec![dummy_relay_address(3)],
             name: "pk_b".into(),
             balance: 0i128,
+            opt_remote_max_debt: None,
         };
         let f_mutation = FunderMutation::AddFriend(add_friend);
         state.mutate(&f_mutation);
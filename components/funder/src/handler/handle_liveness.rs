@@ -1,9 +1,12 @@
 use common::canonical_serialize::CanonicalSerialize;
 use std::fmt::Debug;
 
-use proto::funder::messages::{FriendStatus, FunderOutgoingControl};
+use proto::app_server::messages::RelayAddress;
+use proto::funder::messages::{
+    ChannelerRelaysChanged, ChannelerUpdateFriend, FriendStatus, FunderOutgoingControl,
+};
 
-use crate::types::IncomingLivenessMessage;
+use crate::types::{ChannelerConfig, IncomingLivenessMessage};
 
 use crate::ephemeral::EphemeralMutation;
 use crate::liveness::LivenessMutation;
@@ -37,7 +40,7 @@ where
                 None => Err(HandleLivenessError::FriendDoesNotExist),
             }?;
             match friend.status {
-                FriendStatus::Enabled => Ok(()),
+                FriendStatus::Enabled | FriendStatus::Paused => Ok(()),
                 FriendStatus::Disabled => Err(HandleLivenessError::FriendIsDisabled),
             }?;
 
@@ -79,6 +82,48 @@ where
     Ok(())
 }
 
+/// Handle a notification from the Channeler about a change of the relays used to reach a friend.
+/// The Channeler should be using either the relays of the friend or the local relays we sent to
+/// the friend. If it uses other relays (For example, because it handled an older update), we
+/// send it the current relays of the friend again.
+pub fn handle_relays_changed<B>(
+    m_state: &MutableFunderState<B>,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+    relays_changed: ChannelerRelaysChanged<RelayAddress<B>>,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let friend_public_key = &relays_changed.friend_public_key;
+    info!(
+        "Relays changed for friend {:?}: {:?} -> {:?}",
+        friend_public_key, relays_changed.old_relays, relays_changed.new_relays
+    );
+
+    // The friend might have been removed or disabled in the meanwhile. In that case the Channeler
+    // was already told to disconnect from it:
+    let friend = match m_state.state().friends.get(friend_public_key) {
+        Some(friend) => friend,
+        None => return,
+    };
+    if let FriendStatus::Disabled = friend.status {
+        return;
+    }
+
+    let local_relays = friend.sent_local_relays.to_vec();
+    if relays_changed.new_relays == friend.remote_relays
+        || relays_changed.new_relays == local_relays
+    {
+        return;
+    }
+
+    let update_friend = ChannelerUpdateFriend {
+        friend_public_key: friend_public_key.clone(),
+        friend_relays: friend.remote_relays.clone(),
+        local_relays,
+    };
+    outgoing_channeler_config.push(ChannelerConfig::UpdateFriend(update_friend));
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -86,7 +131,8 @@ mod tests {
     use std::cmp::Ordering;
 
     use crypto::identity::{
-        compare_public_key, generate_pkcs8_key_pair, Identity, SoftwareEd25519Identity,
+        compare_public_key, generate_pkcs8_key_pair, Identity, PublicKey, SoftwareEd25519Identity,
+        PUBLIC_KEY_LEN,
     };
     use crypto::test_utils::DummyRandom;
     use proto::funder::messages::{AddFriend, FriendStatus};
@@ -127,6 +173,7 @@ mod tests {
             relays: vec![dummy_relay_address(1)],
             name: "remote_pk".into(),
             balance: 0i128,
+            opt_remote_max_debt: None,
         };
         let funder_mutation = FunderMutation::AddFriend(add_friend);
         state.mutate(&funder_mutation);
@@ -174,4 +221,63 @@ mod tests {
         let friend_send_commands = send_commands.send_commands.get(&remote_pk).unwrap();
         assert!(friend_send_commands.resend_outgoing);
     }
+
+    #[test]
+    fn test_handle_relays_changed() {
+        let local_pk = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let remote_pk = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+
+        let relays = vec![dummy_named_relay_address(0)];
+        let mut state = FunderState::<u32>::new(local_pk, relays);
+        let add_friend = AddFriend {
+            friend_public_key: remote_pk.clone(),
+            relays: vec![dummy_relay_address(1)],
+            name: "remote_pk".into(),
+            balance: 0i128,
+            opt_remote_max_debt: None,
+        };
+        state.mutate(&FunderMutation::AddFriend(add_friend));
+        let friend_mutation = FriendMutation::SetStatus(FriendStatus::Enabled);
+        state.mutate(&FunderMutation::FriendMutation((
+            remote_pk.clone(),
+            friend_mutation,
+        )));
+
+        let m_state = MutableFunderState::new(state);
+        let mut outgoing_channeler_config = Vec::new();
+
+        // The Channeler uses the relays of the friend. Nothing to do:
+        let relays_changed = ChannelerRelaysChanged {
+            friend_public_key: remote_pk.clone(),
+            old_relays: vec![dummy_relay_address(2)],
+            new_relays: vec![dummy_relay_address(1)],
+        };
+        handle_relays_changed(&m_state, &mut outgoing_channeler_config, relays_changed);
+        assert!(outgoing_channeler_config.is_empty());
+
+        // The Channeler uses outdated relays. We send it the current ones:
+        let relays_changed = ChannelerRelaysChanged {
+            friend_public_key: remote_pk.clone(),
+            old_relays: vec![dummy_relay_address(1)],
+            new_relays: vec![dummy_relay_address(2)],
+        };
+        handle_relays_changed(&m_state, &mut outgoing_channeler_config, relays_changed);
+        assert_eq!(outgoing_channeler_config.len(), 1);
+        match outgoing_channeler_config.pop().unwrap() {
+            ChannelerConfig::UpdateFriend(update_friend) => {
+                assert_eq!(update_friend.friend_public_key, remote_pk);
+                assert_eq!(update_friend.friend_relays, vec![dummy_relay_address(1)]);
+            }
+            _ => unreachable!(),
+        };
+
+        // Notifications about unknown friends are ignored:
+        let relays_changed = ChannelerRelaysChanged {
+            friend_public_key: PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]),
+            old_relays: vec![dummy_relay_address(1)],
+            new_relays: vec![dummy_relay_address(2)],
+        };
+        handle_relays_changed(&m_state, &mut outgoing_channeler_config, relays_changed);
+        assert!(outgoing_channeler_config.is_empty());
+    }
 }
@@ -7,23 +7,32 @@ use crypto::identity::PublicKey;
 use crypto::uid::Uid;
 
 use proto::app_server::messages::RelayAddress;
-use proto::funder::messages::FunderOutgoingControl;
+use proto::funder::messages::{
+    FriendMessage, FriendStatus, FriendTcOp, FriendWarmed, FunderOutgoingControl,
+};
+use proto::net::messages::ValidateAddress;
 use proto::report::messages::{FunderReportMutation, FunderReportMutations};
 
 use identity::IdentityClient;
 
 use crate::state::{FunderMutation, FunderState};
 
+use crate::control_stats::ControlStats;
 use crate::handler::handle_control::handle_control_message;
 use crate::handler::handle_friend::{handle_friend_message, HandleFriendError};
 use crate::handler::handle_init::handle_init;
-use crate::handler::handle_liveness::{handle_liveness_message, HandleLivenessError};
+use crate::handler::handle_liveness::{
+    handle_liveness_message, handle_relays_changed, HandleLivenessError,
+};
 use crate::handler::sender::{create_friend_messages, SendCommands};
+use crate::mutual_credit::op_timings::OpTimings;
 
-use crate::ephemeral::{Ephemeral, EphemeralMutation};
+use crate::ephemeral::{add_rtt_sample, Ephemeral, EphemeralMutation, RTT_PROBE_TIMEOUT_TICKS};
 use crate::friend::ChannelStatus;
 use crate::report::{ephemeral_mutation_to_report_mutations, funder_mutation_to_report_mutations};
-use crate::types::{ChannelerConfig, FunderIncoming, FunderIncomingComm, FunderOutgoingComm};
+use crate::types::{
+    ChannelerConfig, FunderConfig, FunderIncoming, FunderIncomingComm, FunderOutgoingComm,
+};
 
 pub struct MutableFunderState<B: Clone> {
     initial_state: FunderState<B>,
@@ -141,6 +150,11 @@ where
     B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
 {
     let friend = state.friends.get(friend_public_key).unwrap();
+    // A paused friend keeps its connection, but doesn't take part in new requests:
+    if friend.status == FriendStatus::Paused {
+        return false;
+    }
+
     if !ephemeral.liveness.is_online(friend_public_key) {
         return false;
     }
@@ -160,6 +174,49 @@ where
         .is_open()
 }
 
+/// Report warmed friends that became ready, or that did not become ready in time.
+fn check_warm_friends<B>(
+    m_state: &MutableFunderState<B>,
+    m_ephemeral: &mut MutableEphemeral,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    current_tick: u64,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let warm_friends = m_ephemeral
+        .ephemeral()
+        .warm_friends
+        .iter()
+        .map(|(friend_public_key, deadline_tick)| (friend_public_key.clone(), *deadline_tick))
+        .collect::<Vec<_>>();
+
+    for (friend_public_key, deadline_tick) in warm_friends {
+        let is_ready = match m_state.state().friends.get(&friend_public_key) {
+            // The friend was removed, disabled or paused while being warmed:
+            None => false,
+            Some(friend) if friend.status != FriendStatus::Enabled => false,
+            Some(_) => {
+                if is_friend_ready(m_state.state(), m_ephemeral.ephemeral(), &friend_public_key) {
+                    true
+                } else if current_tick >= deadline_tick {
+                    false
+                } else {
+                    // Keep waiting:
+                    continue;
+                }
+            }
+        };
+
+        m_ephemeral.mutate(EphemeralMutation::RemoveWarmFriend(
+            friend_public_key.clone(),
+        ));
+        outgoing_control.push(FunderOutgoingControl::FriendWarmed(FriendWarmed {
+            friend_public_key,
+            is_ready,
+        }));
+    }
+}
+
 type FunderHandleIncomingOutput<B> = (
     SendCommands,
     Vec<FunderOutgoingControl<B>>,
@@ -170,12 +227,14 @@ pub fn funder_handle_incoming<B, R>(
     mut m_state: &mut MutableFunderState<B>,
     mut m_ephemeral: &mut MutableEphemeral,
     rng: &R,
-    max_node_relays: usize,
-    max_pending_user_requests: usize,
+    funder_config: &FunderConfig,
+    current_tick: u64,
+    control_stats: &ControlStats,
+    opt_op_timings: Option<&OpTimings>,
     funder_incoming: FunderIncoming<B>,
 ) -> Result<FunderHandleIncomingOutput<B>, FunderHandlerError>
 where
-    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+    B: Clone + CanonicalSerialize + PartialEq + Eq + ValidateAddress + Debug,
     R: CryptoRandom,
 {
     let mut send_commands = SendCommands::new();
@@ -197,8 +256,9 @@ where
                 &mut send_commands,
                 &mut outgoing_control,
                 &mut outgoing_channeler_config,
-                max_node_relays,
-                max_pending_user_requests,
+                funder_config,
+                current_tick,
+                control_stats,
                 funder_incoming_control.funder_control,
             ) {
                 error!("handle_control_error(): {:?}", e);
@@ -225,16 +285,36 @@ where
                         &mut outgoing_control,
                         &mut outgoing_channeler_config,
                         rng,
+                        funder_config.max_pending_requests,
+                        funder_config.monitor_duplicate_move_tokens,
+                        opt_op_timings,
                         &origin_public_key,
                         friend_message,
                     )
                     .map_err(FunderHandlerError::HandleFriendError)?
                 }
+
+                FunderIncomingComm::RelaysChanged(relays_changed) => {
+                    handle_relays_changed(&m_state, &mut outgoing_channeler_config, relays_changed)
+                }
             };
             None
         }
+
+        // Only used to time out warmed friends, see below:
+        FunderIncoming::TimerTick => None,
+
+        // Deferred send commands are handled together with the send commands of every event:
+        FunderIncoming::SendDeferred => None,
     };
 
+    check_warm_friends(
+        &m_state,
+        &mut m_ephemeral,
+        &mut outgoing_control,
+        current_tick,
+    );
+
     Ok((
         send_commands,
         outgoing_control,
@@ -274,56 +354,208 @@ where
     report_mutations
 }
 
+/// Record the current timer tick as the time of the last move token exchanged with a friend.
+fn set_last_move_token_tick(
+    m_ephemeral: &mut MutableEphemeral,
+    friend_public_key: &PublicKey,
+    current_tick: u64,
+) {
+    let last_move_token_ticks = &m_ephemeral.ephemeral().last_move_token_ticks;
+    if last_move_token_ticks.get(friend_public_key) == Some(&current_tick) {
+        // Nothing has changed:
+        return;
+    }
+    m_ephemeral.mutate(EphemeralMutation::SetLastMoveTokenTick((
+        friend_public_key.clone(),
+        current_tick,
+    )));
+}
+
+/// Use a request sent to a friend for measuring the round trip time with this friend.
+/// Only one request is measured at a time for every friend.
+fn start_rtt_probe(
+    m_ephemeral: &mut MutableEphemeral,
+    friend_public_key: &PublicKey,
+    operations: &[FriendTcOp],
+    current_tick: u64,
+) {
+    if let Some((_request_id, sent_tick)) =
+        m_ephemeral.ephemeral().rtt_probes.get(friend_public_key)
+    {
+        if current_tick.saturating_sub(*sent_tick) < RTT_PROBE_TIMEOUT_TICKS {
+            // We are still waiting for the response to the current probe:
+            return;
+        }
+    }
+
+    let opt_request_id = operations.iter().find_map(|operation| match operation {
+        FriendTcOp::RequestSendFunds(request_send_funds) => Some(request_send_funds.request_id),
+        _ => None,
+    });
+
+    if let Some(request_id) = opt_request_id {
+        m_ephemeral.mutate(EphemeralMutation::SetRttProbe((
+            friend_public_key.clone(),
+            request_id,
+            current_tick,
+        )));
+    }
+}
+
+/// Complete the round trip time measurement with a friend, if the friend has sent us the
+/// response (Or failure) to the probe request.
+fn complete_rtt_probe(
+    m_ephemeral: &mut MutableEphemeral,
+    friend_public_key: &PublicKey,
+    operations: &[FriendTcOp],
+    current_tick: u64,
+) {
+    let (probe_request_id, sent_tick) =
+        match m_ephemeral.ephemeral().rtt_probes.get(friend_public_key) {
+            Some(rtt_probe) => *rtt_probe,
+            None => return,
+        };
+
+    let probe_completed = operations.iter().any(|operation| match operation {
+        FriendTcOp::ResponseSendFunds(response_send_funds) => {
+            response_send_funds.request_id == probe_request_id
+        }
+        FriendTcOp::FailureSendFunds(failure_send_funds) => {
+            failure_send_funds.request_id == probe_request_id
+        }
+        _ => false,
+    });
+    if !probe_completed {
+        return;
+    }
+
+    let rtt_report = add_rtt_sample(
+        &m_ephemeral
+            .ephemeral()
+            .friend_rtts
+            .get(friend_public_key)
+            .cloned()
+            .unwrap_or_default(),
+        current_tick.saturating_sub(sent_tick),
+    );
+    m_ephemeral.mutate(EphemeralMutation::RemoveRttProbe(friend_public_key.clone()));
+    m_ephemeral.mutate(EphemeralMutation::SetFriendRtt((
+        friend_public_key.clone(),
+        rtt_report,
+    )));
+}
+
 pub async fn funder_handle_message<'a, B, R>(
     identity_client: &'a mut IdentityClient,
     rng: &'a R,
     funder_state: FunderState<B>,
     funder_ephemeral: Ephemeral,
-    max_node_relays: usize,
-    max_operations_in_batch: usize,
-    max_pending_user_requests: usize,
+    funder_config: &'a FunderConfig,
+    current_tick: u64,
+    control_stats: &'a ControlStats,
+    opt_op_timings: Option<&'a OpTimings>,
     funder_incoming: FunderIncoming<B>,
 ) -> Result<FunderHandlerOutput<B>, FunderHandlerError>
 where
-    B: 'a + Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+    B: 'a + Clone + PartialEq + Eq + CanonicalSerialize + ValidateAddress + Debug,
     R: CryptoRandom + 'a,
 {
     let mut m_state = MutableFunderState::new(funder_state);
     let mut m_ephemeral = MutableEphemeral::new(funder_ephemeral);
     let mut outgoing_comms = Vec::new();
 
-    let (send_commands, handle_outgoing_control, outgoing_channeler_config, opt_app_request_id) =
+    let opt_move_token_sender = match &funder_incoming {
+        FunderIncoming::Comm(FunderIncomingComm::Friend((
+            public_key,
+            FriendMessage::MoveTokenRequest(move_token_request),
+        ))) => Some((
+            public_key.clone(),
+            move_token_request.friend_move_token.operations.clone(),
+        )),
+        _ => None,
+    };
+
+    let (mut send_commands, handle_outgoing_control, outgoing_channeler_config, opt_app_request_id) =
         funder_handle_incoming(
             &mut m_state,
             &mut m_ephemeral,
             rng,
-            max_node_relays,
-            max_pending_user_requests,
+            funder_config,
+            current_tick,
+            control_stats,
+            opt_op_timings,
             funder_incoming,
         )?;
 
+    if let Some((move_token_sender, operations)) = opt_move_token_sender {
+        if m_state.state().friends.contains_key(&move_token_sender) {
+            set_last_move_token_tick(&mut m_ephemeral, &move_token_sender, current_tick);
+            complete_rtt_probe(
+                &mut m_ephemeral,
+                &move_token_sender,
+                &operations,
+                current_tick,
+            );
+        }
+    }
+
     for channeler_config in outgoing_channeler_config {
         outgoing_comms.push(FunderOutgoingComm::ChannelerConfig(channeler_config));
     }
 
+    // Continue handling send commands that were deferred while handling previous events:
+    let prev_deferred_send_commands = m_ephemeral.ephemeral().deferred_send_commands.clone();
+    for (friend_public_key, friend_send_commands) in prev_deferred_send_commands.iter() {
+        m_ephemeral.mutate(EphemeralMutation::RemoveDeferredSendCommands(
+            friend_public_key.clone(),
+        ));
+        // The friend might have been removed in the meanwhile:
+        if m_state.state().friends.contains_key(friend_public_key) {
+            send_commands.merge_friend(friend_public_key, friend_send_commands);
+        }
+    }
+
     // Send all possible messages according to SendCommands
     // TODO: Maybe we should output outgoing_comms instead of friend_messages and
     // outgoing_channeler_config. When we merge the two, we might be out of order!
-    let (sender_outgoing_control, friend_messages, outgoing_channeler_config) =
-        await!(create_friend_messages(
-            &mut m_state,
-            m_ephemeral.ephemeral(),
-            &send_commands,
-            max_operations_in_batch,
-            identity_client,
-            rng
-        ));
+    let (
+        sender_outgoing_control,
+        friend_messages,
+        outgoing_channeler_config,
+        deferred_send_commands,
+    ) = await!(create_friend_messages(
+        &mut m_state,
+        m_ephemeral.ephemeral(),
+        &send_commands,
+        funder_config.max_operations_in_batch,
+        funder_config.max_send_friends,
+        identity_client,
+        rng
+    ));
+
+    for (friend_public_key, friend_send_commands) in deferred_send_commands.send_commands {
+        m_ephemeral.mutate(EphemeralMutation::SetDeferredSendCommands((
+            friend_public_key,
+            friend_send_commands,
+        )));
+    }
 
     for channeler_config in outgoing_channeler_config {
         outgoing_comms.push(FunderOutgoingComm::ChannelerConfig(channeler_config));
     }
 
     for friend_message in friend_messages {
+        if let (friend_public_key, FriendMessage::MoveTokenRequest(move_token_request)) =
+            &friend_message
+        {
+            set_last_move_token_tick(&mut m_ephemeral, friend_public_key, current_tick);
+            start_rtt_probe(
+                &mut m_ephemeral,
+                friend_public_key,
+                &move_token_request.friend_move_token.operations,
+                current_tick,
+            );
+        }
         outgoing_comms.push(FunderOutgoingComm::FriendMessage(friend_message));
     }
 
@@ -9,4 +9,9 @@ mod sender;
 #[cfg(test)]
 mod tests;
 
-pub use self::handler::{funder_handle_message, FunderHandlerError};
+pub use self::handle_control::{
+    control_get_pending_requests, control_get_reset_token, control_request_remove_friend,
+    HandleControlError,
+};
+pub use self::handler::{funder_handle_message, FunderHandlerError, MutableFunderState};
+pub(crate) use self::sender::FriendSendCommands;
@@ -8,8 +8,8 @@ use crypto::identity::PublicKey;
 
 use proto::app_server::messages::RelayAddress;
 use proto::funder::messages::{
-    ChannelerUpdateFriend, FriendMessage, FriendTcOp, FunderOutgoingControl, MoveTokenRequest,
-    RequestsStatus, ResponseReceived, ResponseSendFundsResult,
+    ChannelerUpdateFriend, FriendMessage, FriendTcOp, FunderOutgoingControl, FundsReceived,
+    MoveTokenRequest, RequestsStatus, ResponseReceived, ResponseSendFundsResult,
 };
 
 use identity::IdentityClient;
@@ -21,7 +21,7 @@ use crate::types::{
 };
 
 use crate::friend::{
-    ChannelInconsistent, ChannelStatus, FriendMutation, ResponseOp, SentLocalRelays,
+    ChannelInconsistent, ChannelStatus, FriendMutation, ResetStatus, ResponseOp, SentLocalRelays,
 };
 use crate::token_channel::{SetDirection, TcDirection, TcMutation, TokenChannel};
 
@@ -39,6 +39,8 @@ pub struct FriendSendCommands {
     pub remote_wants_token: bool,
     /// We want to perform a local reset
     pub local_reset: bool,
+    /// Acknowledge the reset move token received from the remote side
+    pub reset_ack: bool,
 }
 
 impl FriendSendCommands {
@@ -48,8 +50,18 @@ impl FriendSendCommands {
             resend_outgoing: false,
             remote_wants_token: false,
             local_reset: false,
+            reset_ack: false,
         }
     }
+
+    /// Add all the commands set in `other`.
+    fn merge(&mut self, other: &FriendSendCommands) {
+        self.try_send |= other.try_send;
+        self.resend_outgoing |= other.resend_outgoing;
+        self.remote_wants_token |= other.remote_wants_token;
+        self.local_reset |= other.local_reset;
+        self.reset_ack |= other.reset_ack;
+    }
 }
 
 pub type OutgoingMessage<B> = (PublicKey, FriendMessage<B>);
@@ -97,6 +109,26 @@ impl SendCommands {
             .or_insert_with(FriendSendCommands::new);
         friend_send_commands.local_reset = true;
     }
+
+    pub fn set_reset_ack(&mut self, friend_public_key: &PublicKey) {
+        let friend_send_commands = self
+            .send_commands
+            .entry(friend_public_key.clone())
+            .or_insert_with(FriendSendCommands::new);
+        friend_send_commands.reset_ack = true;
+    }
+
+    /// Add commands for a friend, keeping the commands that were already set for this friend.
+    pub fn merge_friend(
+        &mut self,
+        friend_public_key: &PublicKey,
+        friend_send_commands: &FriendSendCommands,
+    ) {
+        self.send_commands
+            .entry(friend_public_key.clone())
+            .or_insert_with(FriendSendCommands::new)
+            .merge(friend_send_commands);
+    }
 }
 
 #[derive(Debug)]
@@ -207,10 +239,7 @@ fn transmit_outgoing<B>(
         TcDirection::Incoming(_) => unreachable!(),
     };
 
-    let move_token_request = MoveTokenRequest {
-        friend_move_token: move_token,
-        token_wanted,
-    };
+    let move_token_request = MoveTokenRequest::new(move_token, token_wanted);
 
     outgoing_messages.push((
         friend_public_key.clone(),
@@ -266,6 +295,14 @@ pub async fn apply_local_reset<'a, B, R>(
     let funder_mutation =
         FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
     m_state.mutate(funder_mutation);
+
+    // The reset is confirmed once the remote side acknowledges our reset move token:
+    let friend_mutation = FriendMutation::SetResetStatus(ResetStatus::Pending(
+        channel_inconsistent.local_reset_terms.clone(),
+    ));
+    let funder_mutation =
+        FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+    m_state.mutate(funder_mutation);
 }
 
 async fn send_friend_iter1<'a, B, R>(
@@ -288,6 +325,7 @@ async fn send_friend_iter1<'a, B, R>(
         && !friend_send_commands.resend_outgoing
         && !friend_send_commands.remote_wants_token
         && !friend_send_commands.local_reset
+        && !friend_send_commands.reset_ack
     {
         return;
     }
@@ -325,6 +363,16 @@ async fn send_friend_iter1<'a, B, R>(
         }
     };
 
+    if friend_send_commands.reset_ack {
+        // The last incoming move token is the reset move token of the remote side:
+        if let Some(move_token_in) = token_channel.get_last_incoming_move_token_hashed() {
+            outgoing_messages.push((
+                friend_public_key.clone(),
+                FriendMessage::ResetAck(move_token_in.new_token.clone()),
+            ));
+        }
+    }
+
     let tc_incoming = match &token_channel.get_direction() {
         TcDirection::Outgoing(tc_outgoing) => {
             if estimate_should_send(m_state.state(), friend_public_key) {
@@ -335,8 +383,11 @@ async fn send_friend_iter1<'a, B, R>(
                     is_token_wanted,
                     &mut outgoing_messages,
                 );
-            } else if friend_send_commands.resend_outgoing {
-                let is_token_wanted = tc_outgoing.move_token_out.opt_local_relays.is_some();
+            } else if friend_send_commands.local_reset || friend_send_commands.resend_outgoing {
+                // A reset move token is sent right away, so that the remote side can acknowledge it.
+                // We want the token back if we have sent our relays or received funds:
+                let is_token_wanted = tc_outgoing.move_token_out.opt_local_relays.is_some()
+                    || !friend.unacked_funds.is_empty();
                 transmit_outgoing(
                     m_state,
                     &friend_public_key,
@@ -401,7 +452,17 @@ where
     // Check if update to remote_max_debt is required:
     match &friend.channel_status {
         ChannelStatus::Consistent(token_channel) => {
+            let balance = &token_channel.get_mutual_credit().state().balance;
             if friend.wanted_remote_max_debt != token_channel.get_remote_max_debt() {
+                // When waiting for an acknowledgement of a proposal, there is nothing to send:
+                if !friend.confirm_remote_max_debt || balance.opt_pending_remote_max_debt.is_none()
+                {
+                    return true;
+                }
+            }
+
+            // A max debt proposal from the remote side has to be acknowledged:
+            if balance.opt_pending_local_max_debt.is_some() {
                 return true;
             }
 
@@ -527,6 +588,26 @@ where
 }
 */
 
+/// Sending a response for a request where we are the destination completes the payment, once
+/// the friend acknowledges the move token carrying the response.
+/// Returns the funds we receive by sending `response_op`, if any.
+fn response_op_to_funds_received(response_op: &ResponseOp) -> Option<FundsReceived> {
+    match response_op {
+        // A zero payment is only a route probe. No funds are received:
+        ResponseOp::UnsignedResponse((pending_request, memo))
+            if pending_request.dest_payment > 0 =>
+        {
+            Some(FundsReceived {
+                request_id: pending_request.request_id,
+                invoice_id: pending_request.invoice_id.clone(),
+                dest_payment: pending_request.dest_payment,
+                memo: memo.clone(),
+            })
+        }
+        _ => None,
+    }
+}
+
 async fn response_op_to_friend_tc_op<'a, B, R>(
     m_state: &'a mut MutableFunderState<B>,
     response_op: ResponseOp,
@@ -539,7 +620,7 @@ where
 {
     match response_op {
         ResponseOp::Response(response) => FriendTcOp::ResponseSendFunds(response),
-        ResponseOp::UnsignedResponse(pending_request) => {
+        ResponseOp::UnsignedResponse((pending_request, _memo)) => {
             let rand_nonce = RandValue::new(rng);
             FriendTcOp::ResponseSendFunds(await!(create_response_send_funds(
                 &pending_request,
@@ -647,8 +728,43 @@ where
     }
     .get_remote_max_debt();
 
+    let (opt_pending_remote_max_debt, opt_pending_local_max_debt) = match &friend.channel_status {
+        ChannelStatus::Consistent(token_channel) => {
+            let balance = &token_channel.get_mutual_credit().state().balance;
+            (
+                balance.opt_pending_remote_max_debt,
+                balance.opt_pending_local_max_debt,
+            )
+        }
+        ChannelStatus::Inconsistent(_) => unreachable!(),
+    };
+
     if friend.wanted_remote_max_debt != remote_max_debt {
-        let operation = FriendTcOp::SetRemoteMaxDebt(friend.wanted_remote_max_debt);
+        let opt_operation = if !friend.confirm_remote_max_debt {
+            Some(FriendTcOp::SetRemoteMaxDebt(friend.wanted_remote_max_debt))
+        } else if opt_pending_remote_max_debt.is_none() {
+            // Only one proposal may be in flight. A newer wanted value will be proposed after
+            // the current proposal is acknowledged.
+            Some(FriendTcOp::ProposeRemoteMaxDebt(
+                friend.wanted_remote_max_debt,
+            ))
+        } else {
+            None
+        };
+        if let Some(operation) = opt_operation {
+            await!(queue_operation_or_failure(
+                m_state,
+                pending_move_token,
+                failure_public_keys,
+                outgoing_control,
+                &operation
+            ))?;
+        }
+    }
+
+    // Acknowledge a max debt proposal from the remote side:
+    if let Some(pending_local_max_debt) = opt_pending_local_max_debt {
+        let operation = FriendTcOp::AckRemoteMaxDebt(pending_local_max_debt);
         await!(queue_operation_or_failure(
             m_state,
             pending_move_token,
@@ -691,6 +807,7 @@ where
     // TODO: Possibly replace this clone with something more efficient later:
     let mut pending_responses = friend.pending_responses.clone();
     while let Some(pending_response) = pending_responses.pop_front() {
+        let opt_funds_received = response_op_to_funds_received(&pending_response);
         let pending_op = await!(response_op_to_friend_tc_op(
             m_state,
             pending_response,
@@ -709,6 +826,15 @@ where
         let funder_mutation =
             FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
         m_state.mutate(funder_mutation);
+
+        if let Some(funds_received) = opt_funds_received {
+            let friend_mutation = FriendMutation::PushBackUnackedFunds(funds_received);
+            let funder_mutation =
+                FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+            m_state.mutate(funder_mutation);
+            // The friend acknowledges the response by sending the token back:
+            pending_move_token.token_wanted = true;
+        }
     }
 
     let friend = m_state.state().friends.get(friend_public_key).unwrap();
@@ -769,6 +895,7 @@ where
     // TODO: Possibly replace this clone with something more efficient later:
     let mut pending_responses = friend.pending_responses.clone();
     while let Some(pending_response) = pending_responses.pop_front() {
+        let opt_funds_received = response_op_to_funds_received(&pending_response);
         let pending_op = await!(response_op_to_friend_tc_op(
             m_state,
             pending_response,
@@ -790,6 +917,15 @@ where
         let funder_mutation =
             FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
         m_state.mutate(funder_mutation);
+
+        if let Some(funds_received) = opt_funds_received {
+            let friend_mutation = FriendMutation::PushBackUnackedFunds(funds_received);
+            let funder_mutation =
+                FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+            m_state.mutate(funder_mutation);
+            // The friend acknowledges the response by sending the token back:
+            pending_move_token.token_wanted = true;
+        }
     }
     Ok(())
 }
@@ -857,10 +993,7 @@ async fn send_move_token<'a, B, R>(
     };
 
     let friend_move_token = tc_outgoing.create_outgoing_move_token();
-    let move_token_request = MoveTokenRequest {
-        friend_move_token,
-        token_wanted,
-    };
+    let move_token_request = MoveTokenRequest::new(friend_move_token, token_wanted);
 
     outgoing_messages.push((
         friend_public_key.clone(),
@@ -915,17 +1048,23 @@ fn init_failure_pending_move_token<B>(
 }
 
 /// Send all possible messages according to SendCommands
+/// Create messages for friends according to `send_commands`.
+///
+/// Messages are created for at most `max_send_friends` friends. The commands for the rest of the
+/// friends are returned as deferred send commands, to be handled later.
 pub async fn create_friend_messages<'a, B, R>(
     m_state: &'a mut MutableFunderState<B>,
     ephemeral: &'a Ephemeral,
     send_commands: &'a SendCommands,
     max_operations_in_batch: usize,
+    max_send_friends: usize,
     identity_client: &'a mut IdentityClient,
     rng: &'a R,
 ) -> (
     Vec<FunderOutgoingControl<B>>,
     Vec<OutgoingMessage<B>>,
     Vec<ChannelerConfig<RelayAddress<B>>>,
+    SendCommands,
 )
 where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
@@ -935,6 +1074,8 @@ where
     let mut outgoing_messages = Vec::new();
     let mut outgoing_channeler_config = Vec::new();
     let mut pending_move_tokens: HashMap<PublicKey, PendingMoveToken<B>> = HashMap::new();
+    let mut deferred_send_commands = SendCommands::new();
+    let mut send_friends = HashSet::new();
 
     // First iteration:
     let mut failure_public_keys = HashSet::new();
@@ -942,6 +1083,11 @@ where
         if !ephemeral.liveness.is_online(friend_public_key) {
             continue;
         }
+        if send_friends.len() >= max_send_friends {
+            deferred_send_commands.merge_friend(friend_public_key, friend_send_commands);
+            continue;
+        }
+        send_friends.insert(friend_public_key.clone());
         await!(send_friend_iter1(
             m_state,
             friend_public_key,
@@ -957,6 +1103,24 @@ where
         ));
     }
 
+    // Failures queued for friends beyond `max_send_friends` will be sent later:
+    let failure_public_keys = failure_public_keys
+        .into_iter()
+        .filter(|friend_public_key| {
+            if !ephemeral.liveness.is_online(friend_public_key)
+                || send_friends.contains(friend_public_key)
+            {
+                return true;
+            }
+            if send_friends.len() >= max_send_friends {
+                deferred_send_commands.set_try_send(friend_public_key);
+                return false;
+            }
+            send_friends.insert(friend_public_key.clone());
+            true
+        })
+        .collect::<HashSet<_>>();
+
     // Create PendingMoveToken-s for all the friends that were queued
     // new pending messages during `send_friend_iter1`:
     init_failure_pending_move_token(
@@ -996,5 +1160,6 @@ where
         outgoing_control,
         outgoing_messages,
         outgoing_channeler_config,
+        deferred_send_commands,
     )
 }
@@ -0,0 +1,142 @@
+use super::utils::{apply_funder_incoming, init_node, spawn_identity_client};
+
+use futures::executor::ThreadPool;
+
+use identity::IdentityClient;
+
+use crypto::crypto_rand::RngContainer;
+use crypto::identity::{PublicKey, PUBLIC_KEY_LEN};
+use crypto::test_utils::DummyRandom;
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::funder::messages::{
+    ActivateFriend, AddFriend, FriendStatus, FunderControl, FunderIncomingControl, RequestsStatus,
+    SetFriendRemoteMaxDebt,
+};
+
+use crate::types::{ChannelerConfig, FunderIncoming, FunderOutgoingComm};
+
+use crate::tests::utils::dummy_relay_address;
+
+async fn task_handler_activate_friend(mut identity_client: IdentityClient) {
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+    let (mut state, mut ephemeral) = await!(init_node::<u32, _>(
+        Vec::new(),
+        &mut rng,
+        &mut identity_client
+    ));
+
+    let friend_pk = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+
+    let add_friend = AddFriend {
+        friend_public_key: friend_pk.clone(),
+        relays: vec![dummy_relay_address(1)],
+        name: "friend".into(),
+        balance: 0i128,
+        opt_remote_max_debt: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[11; UID_LEN]),
+        FunderControl::AddFriend(add_friend),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    // The friend has to confirm changes to its max debt:
+    let propose_remote_max_debt = SetFriendRemoteMaxDebt {
+        friend_public_key: friend_pk.clone(),
+        remote_max_debt: 50,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[12; UID_LEN]),
+        FunderControl::ProposeFriendRemoteMaxDebt(propose_remote_max_debt),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    // Activating a friend that does not exist changes nothing:
+    let state_before = bincode::serialize(&state).unwrap();
+    let activate_friend = ActivateFriend {
+        friend_public_key: PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]),
+        remote_max_debt: 100,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[13; UID_LEN]),
+        FunderControl::ActivateFriend(activate_friend),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+    assert!(outgoing_comms.is_empty());
+    assert_eq!(bincode::serialize(&state).unwrap(), state_before);
+
+    let friend = state.friends.get(&friend_pk).unwrap();
+    assert_eq!(friend.status, FriendStatus::Disabled);
+    assert_eq!(friend.wanted_local_requests_status, RequestsStatus::Closed);
+    assert_eq!(friend.wanted_remote_max_debt, 50);
+    assert!(friend.confirm_remote_max_debt);
+
+    // Activate the friend:
+    let activate_friend = ActivateFriend {
+        friend_public_key: friend_pk.clone(),
+        remote_max_debt: 100,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[14; UID_LEN]),
+        FunderControl::ActivateFriend(activate_friend),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    // All the changes were applied:
+    let friend = state.friends.get(&friend_pk).unwrap();
+    assert_eq!(friend.status, FriendStatus::Enabled);
+    assert_eq!(friend.wanted_local_requests_status, RequestsStatus::Open);
+    assert_eq!(friend.wanted_remote_max_debt, 100);
+    // The new max debt still has to be confirmed by the friend:
+    assert!(friend.confirm_remote_max_debt);
+
+    // The Channeler was told to connect to the friend:
+    assert_eq!(outgoing_comms.len(), 1);
+    match &outgoing_comms[0] {
+        FunderOutgoingComm::ChannelerConfig(ChannelerConfig::UpdateFriend(update_friend)) => {
+            assert_eq!(update_friend.friend_public_key, friend_pk);
+            assert_eq!(update_friend.friend_relays, vec![dummy_relay_address(1)]);
+        }
+        _ => unreachable!(),
+    };
+}
+
+#[test]
+fn test_handler_activate_friend() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let identity_client = spawn_identity_client(&mut thread_pool, 1);
+    thread_pool.run(task_handler_activate_friend(identity_client));
+}
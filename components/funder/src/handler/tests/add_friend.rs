@@ -0,0 +1,146 @@
+use super::utils::{apply_funder_incoming, init_node, spawn_identity_client};
+
+use std::convert::TryFrom;
+
+use futures::executor::ThreadPool;
+
+use identity::IdentityClient;
+
+use crypto::crypto_rand::RngContainer;
+use crypto::identity::{PublicKey, PUBLIC_KEY_LEN};
+use crypto::test_utils::DummyRandom;
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::app_server::messages::{NamedRelayAddress, RelayAddress};
+use proto::funder::messages::{AddFriend, FunderControl, FunderIncomingControl};
+use proto::net::messages::NetAddress;
+
+use crate::types::FunderIncoming;
+
+fn net_relay_address(index: u8, address: &str) -> RelayAddress<NetAddress> {
+    RelayAddress {
+        public_key: PublicKey::from(&[index; PUBLIC_KEY_LEN]),
+        address: NetAddress::try_from(address.to_owned()).unwrap(),
+    }
+}
+
+async fn task_handler_add_friend_invalid_address(mut identity_client: IdentityClient) {
+    let relays = vec![NamedRelayAddress {
+        public_key: PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+        address: NetAddress::try_from("relay.example:1337".to_owned()).unwrap(),
+        name: "relay".to_owned(),
+    }];
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+    let (mut state, mut ephemeral) = await!(init_node::<NetAddress, _>(
+        relays,
+        &mut rng,
+        &mut identity_client
+    ));
+
+    let friend_pk = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+
+    // Add a friend with a malformed relay address (The port is missing):
+    let add_friend = AddFriend {
+        friend_public_key: friend_pk.clone(),
+        relays: vec![
+            net_relay_address(1, "relay1.example:1337"),
+            net_relay_address(2, "relay2.example"),
+        ],
+        name: "friend".into(),
+        balance: 0i128,
+        opt_remote_max_debt: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[11; UID_LEN]),
+        FunderControl::AddFriend(add_friend),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    // The friend should not be added:
+    assert!(state.friends.get(&friend_pk).is_none());
+
+    // Add the same friend with well formed relay addresses:
+    let add_friend = AddFriend {
+        friend_public_key: friend_pk.clone(),
+        relays: vec![
+            net_relay_address(1, "relay1.example:1337"),
+            net_relay_address(2, "relay2.example:1338"),
+        ],
+        name: "friend".into(),
+        balance: 0i128,
+        opt_remote_max_debt: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[12; UID_LEN]),
+        FunderControl::AddFriend(add_friend),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    assert!(state.friends.get(&friend_pk).is_some());
+}
+
+#[test]
+fn test_handler_add_friend_invalid_address() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let identity_client = spawn_identity_client(&mut thread_pool, 1);
+    thread_pool.run(task_handler_add_friend_invalid_address(identity_client));
+}
+
+async fn task_handler_add_friend_remote_max_debt(mut identity_client: IdentityClient) {
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+    let (mut state, mut ephemeral) = await!(init_node::<NetAddress, _>(
+        Vec::new(),
+        &mut rng,
+        &mut identity_client
+    ));
+
+    // Add a friend together with an initial remote max debt:
+    let friend_pk = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+    let add_friend = AddFriend {
+        friend_public_key: friend_pk.clone(),
+        relays: vec![net_relay_address(1, "relay1.example:1337")],
+        name: "friend".into(),
+        balance: 0i128,
+        opt_remote_max_debt: Some(100),
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[11; UID_LEN]),
+        FunderControl::AddFriend(add_friend),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    // No separate SetFriendRemoteMaxDebt is required:
+    let friend = state.friends.get(&friend_pk).unwrap();
+    assert_eq!(friend.wanted_remote_max_debt, 100);
+}
+
+#[test]
+fn test_handler_add_friend_remote_max_debt() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let identity_client = spawn_identity_client(&mut thread_pool, 1);
+    thread_pool.run(task_handler_add_friend_remote_max_debt(identity_client));
+}
@@ -9,12 +9,15 @@ use futures::{future, FutureExt};
 use identity::{create_identity, IdentityClient};
 
 use crypto::crypto_rand::RngContainer;
-use crypto::identity::{compare_public_key, generate_pkcs8_key_pair, SoftwareEd25519Identity};
+use crypto::identity::{
+    compare_public_key, generate_pkcs8_key_pair, PublicKey, SoftwareEd25519Identity, PUBLIC_KEY_LEN,
+};
 use crypto::test_utils::DummyRandom;
 use crypto::uid::{Uid, UID_LEN};
 
 use proto::funder::messages::{
-    AddFriend, FriendMessage, FriendStatus, FunderControl, FunderIncomingControl, SetFriendStatus,
+    AddFriend, FriendMessage, FriendStatus, FunderControl, FunderIncomingControl, SetFriendRelays,
+    SetFriendStatus,
 };
 
 use crate::ephemeral::Ephemeral;
@@ -80,6 +83,7 @@ async fn task_handler_change_address(
         relays: vec![dummy_relay_address(2)],
         name: String::from("pk2"),
         balance: 0i128,
+        opt_remote_max_debt: None,
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[11; UID_LEN]),
@@ -120,6 +124,7 @@ async fn task_handler_change_address(
         relays: vec![dummy_relay_address(1)],
         name: String::from("pk1"),
         balance: 0i128,
+        opt_remote_max_debt: None,
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[13; UID_LEN]),
@@ -465,3 +470,116 @@ fn test_handler_change_address() {
         identity_client2,
     ));
 }
+
+async fn task_handler_set_friend_relays(mut identity_client: IdentityClient) {
+    let local_pk = await!(identity_client.request_public_key()).unwrap();
+
+    let relays = vec![dummy_named_relay_address(0)];
+    let mut state = FunderState::<u32>::new(local_pk, relays);
+    let mut ephemeral = Ephemeral::new();
+
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+
+    // Initialize:
+    await!(Box::pin(apply_funder_incoming(
+        FunderIncoming::Init,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    let friend_pk = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+
+    // Add an enabled friend:
+    let add_friend = AddFriend {
+        friend_public_key: friend_pk.clone(),
+        relays: vec![dummy_relay_address(1)],
+        name: "friend".into(),
+        balance: 0i128,
+        opt_remote_max_debt: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[11; UID_LEN]),
+        FunderControl::AddFriend(add_friend),
+    );
+    await!(Box::pin(apply_funder_incoming(
+        FunderIncoming::Control(incoming_control_message),
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    let set_friend_status = SetFriendStatus {
+        friend_public_key: friend_pk.clone(),
+        status: FriendStatus::Enabled,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[12; UID_LEN]),
+        FunderControl::SetFriendStatus(set_friend_status),
+    );
+    await!(Box::pin(apply_funder_incoming(
+        FunderIncoming::Control(incoming_control_message),
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    // Replace the relays of the friend:
+    let set_friend_relays = SetFriendRelays {
+        friend_public_key: friend_pk.clone(),
+        relays: vec![dummy_relay_address(2), dummy_relay_address(3)],
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[13; UID_LEN]),
+        FunderControl::SetFriendRelays(set_friend_relays),
+    );
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        FunderIncoming::Control(incoming_control_message),
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    // The Channeler is asked to swap the relays in a single update.
+    // The friend is never removed from the Channeler:
+    assert_eq!(outgoing_comms.len(), 1);
+    match &outgoing_comms[0] {
+        FunderOutgoingComm::ChannelerConfig(ChannelerConfig::UpdateFriend(update_friend)) => {
+            assert_eq!(update_friend.friend_public_key, friend_pk);
+            assert_eq!(
+                update_friend.friend_relays,
+                vec![dummy_relay_address(2), dummy_relay_address(3)]
+            );
+        }
+        _ => unreachable!(),
+    };
+
+    assert_eq!(
+        state.friends.get(&friend_pk).unwrap().remote_relays,
+        vec![dummy_relay_address(2), dummy_relay_address(3)]
+    );
+}
+
+#[test]
+fn test_handler_set_friend_relays() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+
+    let rng = DummyRandom::new(&[1u8]);
+    let pkcs8 = generate_pkcs8_key_pair(&rng);
+    let identity = SoftwareEd25519Identity::from_pkcs8(&pkcs8).unwrap();
+    let (requests_sender, identity_server) = create_identity(identity);
+    let identity_client = IdentityClient::new(requests_sender);
+    thread_pool
+        .spawn(identity_server.then(|_| future::ready(())))
+        .unwrap();
+
+    thread_pool.run(task_handler_set_friend_relays(identity_client));
+}
@@ -0,0 +1,83 @@
+use super::utils::{apply_funder_incoming_with_stats, init_node, spawn_identity_client};
+
+use std::convert::TryFrom;
+
+use futures::executor::ThreadPool;
+
+use identity::IdentityClient;
+
+use crypto::crypto_rand::RngContainer;
+use crypto::identity::{PublicKey, PUBLIC_KEY_LEN};
+use crypto::test_utils::DummyRandom;
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::app_server::messages::RelayAddress;
+use proto::funder::messages::{
+    AddFriend, FunderControl, FunderIncomingControl, RemoveFriend, SetFriendName,
+};
+use proto::net::messages::NetAddress;
+
+use crate::control_stats::ControlStats;
+use crate::types::FunderIncoming;
+
+async fn task_handler_control_stats(mut identity_client: IdentityClient) {
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+    let control_stats = ControlStats::new();
+    let (mut state, mut ephemeral) = await!(init_node::<NetAddress, _>(
+        Vec::new(),
+        &mut rng,
+        &mut identity_client
+    ));
+
+    let friend_pk = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+
+    // Control messages that refer to a nonexistent friend:
+    let funder_controls = vec![
+        FunderControl::SetFriendName(SetFriendName {
+            friend_public_key: friend_pk.clone(),
+            name: "friend".into(),
+        }),
+        FunderControl::RemoveFriend(RemoveFriend {
+            friend_public_key: friend_pk.clone(),
+        }),
+        // Adding a friend with a malformed relay address (The port is missing):
+        FunderControl::AddFriend(AddFriend {
+            friend_public_key: friend_pk.clone(),
+            relays: vec![RelayAddress {
+                public_key: PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]),
+                address: NetAddress::try_from("relay.example".to_owned()).unwrap(),
+            }],
+            name: "friend".into(),
+            balance: 0i128,
+            opt_remote_max_debt: None,
+        }),
+    ];
+
+    for (i, funder_control) in funder_controls.into_iter().enumerate() {
+        let incoming_control_message =
+            FunderIncomingControl::new(Uid::from(&[i as u8; UID_LEN]), funder_control);
+        // Rejected control messages are not considered handler errors:
+        await!(Box::pin(apply_funder_incoming_with_stats(
+            FunderIncoming::Control(incoming_control_message),
+            &mut state,
+            &mut ephemeral,
+            &mut rng,
+            &mut identity_client,
+            0,
+            &control_stats
+        )))
+        .unwrap();
+    }
+
+    assert_eq!(control_stats.get("FriendDoesNotExist"), 2);
+    assert_eq!(control_stats.get("InvalidAddress"), 1);
+    assert_eq!(control_stats.get("FriendNotReady"), 0);
+    assert_eq!(control_stats.snapshot().len(), 2);
+}
+
+#[test]
+fn test_handler_control_stats() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let identity_client = spawn_identity_client(&mut thread_pool, 1);
+    thread_pool.run(task_handler_control_stats(identity_client));
+}
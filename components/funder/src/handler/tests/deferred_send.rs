@@ -0,0 +1,166 @@
+use super::utils::{
+    apply_funder_incoming, init_node, spawn_identity_client, TEST_MAX_SEND_FRIENDS,
+};
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use futures::executor::ThreadPool;
+
+use identity::IdentityClient;
+
+use crypto::crypto_rand::RngContainer;
+use crypto::identity::{compare_public_key, PublicKey, PUBLIC_KEY_LEN};
+use crypto::test_utils::DummyRandom;
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::funder::messages::{
+    AddFriend, FriendStatus, FunderControl, FunderIncomingControl, SetFriendStatus,
+};
+
+use crate::types::{
+    FunderIncoming, FunderIncomingComm, FunderOutgoingComm, IncomingLivenessMessage,
+};
+
+use crate::tests::utils::{dummy_named_relay_address, dummy_relay_address};
+
+/// Collect the public keys of the friends we send messages to.
+fn friend_messages_destinations(outgoing_comms: &[FunderOutgoingComm<u32>]) -> Vec<PublicKey> {
+    outgoing_comms
+        .iter()
+        .filter_map(|outgoing_comm| match outgoing_comm {
+            FunderOutgoingComm::FriendMessage((public_key, _)) => Some(public_key.clone()),
+            FunderOutgoingComm::ChannelerConfig(_) => None,
+        })
+        .collect()
+}
+
+async fn task_handler_deferred_send(mut identity_client: IdentityClient) {
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+    let (mut state, mut ephemeral) = await!(init_node::<u32, _>(
+        Vec::new(),
+        &mut rng,
+        &mut identity_client
+    ));
+    let local_pk = state.local_public_key.clone();
+
+    // We pick friends for which we are the first sender, so that every friend has an outgoing
+    // move token that can be resent:
+    let num_friends = 2 * TEST_MAX_SEND_FRIENDS + 2;
+    let friend_pks = (0..=255u8)
+        .map(|i| PublicKey::from(&[i; PUBLIC_KEY_LEN]))
+        .filter(|friend_pk| compare_public_key(&local_pk, friend_pk) == Ordering::Less)
+        .take(num_friends)
+        .collect::<Vec<_>>();
+    assert_eq!(friend_pks.len(), num_friends);
+
+    for (i, friend_pk) in friend_pks.iter().enumerate() {
+        let add_friend = AddFriend {
+            friend_public_key: friend_pk.clone(),
+            relays: vec![dummy_relay_address(i as u8)],
+            name: format!("friend{}", i),
+            balance: 0i128,
+            opt_remote_max_debt: None,
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[2 * i as u8; UID_LEN]),
+            FunderControl::AddFriend(add_friend),
+        );
+        let funder_incoming = FunderIncoming::Control(incoming_control_message);
+        await!(Box::pin(apply_funder_incoming(
+            funder_incoming,
+            &mut state,
+            &mut ephemeral,
+            &mut rng,
+            &mut identity_client
+        )))
+        .unwrap();
+
+        let set_friend_status = SetFriendStatus {
+            friend_public_key: friend_pk.clone(),
+            status: FriendStatus::Enabled,
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[2 * i as u8 + 1; UID_LEN]),
+            FunderControl::SetFriendStatus(set_friend_status),
+        );
+        let funder_incoming = FunderIncoming::Control(incoming_control_message);
+        await!(Box::pin(apply_funder_incoming(
+            funder_incoming,
+            &mut state,
+            &mut ephemeral,
+            &mut rng,
+            &mut identity_client
+        )))
+        .unwrap();
+
+        // The outgoing move token is resent to the friend once it is online:
+        let incoming_liveness_message = IncomingLivenessMessage::Online(friend_pk.clone());
+        let funder_incoming =
+            FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+        let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+            funder_incoming,
+            &mut state,
+            &mut ephemeral,
+            &mut rng,
+            &mut identity_client
+        )))
+        .unwrap();
+        assert_eq!(
+            friend_messages_destinations(&outgoing_comms),
+            vec![friend_pk.clone()]
+        );
+    }
+
+    // Adding a relay requires notifying all the friends:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[0xff; UID_LEN]),
+        FunderControl::AddRelay(dummy_named_relay_address(0xff)),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    // Only some of the friends are sent messages, the rest are deferred:
+    let mut destinations = friend_messages_destinations(&outgoing_comms);
+    assert_eq!(destinations.len(), TEST_MAX_SEND_FRIENDS);
+    assert_eq!(
+        ephemeral.deferred_send_commands.len(),
+        num_friends - TEST_MAX_SEND_FRIENDS
+    );
+
+    // The deferred messages are sent on the following iterations:
+    for &expected_len in &[TEST_MAX_SEND_FRIENDS, 2] {
+        let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+            FunderIncoming::SendDeferred,
+            &mut state,
+            &mut ephemeral,
+            &mut rng,
+            &mut identity_client
+        )))
+        .unwrap();
+        let iter_destinations = friend_messages_destinations(&outgoing_comms);
+        assert_eq!(iter_destinations.len(), expected_len);
+        destinations.extend(iter_destinations);
+    }
+    assert!(ephemeral.deferred_send_commands.is_empty());
+
+    // Every friend was sent exactly one message:
+    assert_eq!(destinations.len(), num_friends);
+    let destinations = destinations.into_iter().collect::<HashSet<_>>();
+    let friend_pks = friend_pks.into_iter().collect::<HashSet<_>>();
+    assert_eq!(destinations, friend_pks);
+}
+
+#[test]
+fn test_handler_deferred_send() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let identity_client = spawn_identity_client(&mut thread_pool, 1);
+    thread_pool.run(task_handler_deferred_send(identity_client));
+}
@@ -0,0 +1,189 @@
+use super::utils::{apply_funder_incoming, init_node, spawn_identity_client};
+
+use std::cmp::Ordering;
+
+use futures::executor::ThreadPool;
+
+use identity::IdentityClient;
+
+use crypto::crypto_rand::RngContainer;
+use crypto::identity::compare_public_key;
+use crypto::test_utils::DummyRandom;
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::funder::messages::{
+    AddFriend, FriendMessage, FriendStatus, FunderControl, FunderIncomingControl, SetFriendStatus,
+};
+
+use crate::types::{
+    FunderIncoming, FunderIncomingComm, FunderOutgoingComm, IncomingLivenessMessage,
+};
+
+use crate::tests::utils::{dummy_named_relay_address, dummy_relay_address};
+
+/// Find the first outgoing MoveTokenRequest message
+fn find_move_token_request(outgoing_comms: &[FunderOutgoingComm<u32>]) -> FriendMessage<u32> {
+    outgoing_comms
+        .iter()
+        .filter_map(|outgoing_comm| match outgoing_comm {
+            FunderOutgoingComm::FriendMessage((_pk, friend_message)) => match friend_message {
+                FriendMessage::MoveTokenRequest(_) => Some(friend_message.clone()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .next()
+        .unwrap()
+}
+
+async fn task_handler_duplicate_move_token<'a>(
+    identity_client1: &'a mut IdentityClient,
+    identity_client2: &'a mut IdentityClient,
+) {
+    // Sort the identities. identity_client1 will be the first sender:
+    let pk1 = await!(identity_client1.request_public_key()).unwrap();
+    let pk2 = await!(identity_client2.request_public_key()).unwrap();
+    let (identity_client1, pk1, identity_client2, pk2) =
+        if compare_public_key(&pk1, &pk2) == Ordering::Less {
+            (identity_client1, pk1, identity_client2, pk2)
+        } else {
+            (identity_client2, pk2, identity_client1, pk1)
+        };
+
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+
+    // Initialize both nodes:
+    let relays1 = vec![dummy_named_relay_address(1)];
+    let (mut state1, mut ephemeral1) =
+        await!(init_node::<u32, _>(relays1, &mut rng, identity_client1));
+    let relays2 = vec![dummy_named_relay_address(2)];
+    let (mut state2, mut ephemeral2) =
+        await!(init_node::<u32, _>(relays2, &mut rng, identity_client2));
+
+    // Add and enable friends:
+    let nodes = vec![
+        (
+            &mut state1,
+            &mut ephemeral1,
+            &mut *identity_client1,
+            pk2.clone(),
+            2u8,
+        ),
+        (
+            &mut state2,
+            &mut ephemeral2,
+            &mut *identity_client2,
+            pk1.clone(),
+            1u8,
+        ),
+    ];
+    for (state, ephemeral, identity_client, friend_pk, friend_index) in nodes {
+        let add_friend = AddFriend {
+            friend_public_key: friend_pk.clone(),
+            relays: vec![dummy_relay_address(friend_index)],
+            name: format!("pk{}", friend_index),
+            balance: 0i128,
+            opt_remote_max_debt: None,
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[11; UID_LEN]),
+            FunderControl::AddFriend(add_friend),
+        );
+        await!(Box::pin(apply_funder_incoming(
+            FunderIncoming::Control(incoming_control_message),
+            state,
+            ephemeral,
+            &mut rng,
+            identity_client
+        )))
+        .unwrap();
+
+        let set_friend_status = SetFriendStatus {
+            friend_public_key: friend_pk.clone(),
+            status: FriendStatus::Enabled,
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[12; UID_LEN]),
+            FunderControl::SetFriendStatus(set_friend_status),
+        );
+        await!(Box::pin(apply_funder_incoming(
+            FunderIncoming::Control(incoming_control_message),
+            state,
+            ephemeral,
+            &mut rng,
+            identity_client
+        )))
+        .unwrap();
+    }
+
+    // Node1: Notify that Node2 is alive. Node1 sends its initial move token:
+    let incoming_liveness_message = IncomingLivenessMessage::Online(pk2.clone());
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1
+    )))
+    .unwrap();
+    let friend_message = find_move_token_request(&outgoing_comms);
+
+    // Node2: Notify that Node1 is alive. Node2 already has the initial move token, and sends the
+    // next one:
+    let incoming_liveness_message = IncomingLivenessMessage::Online(pk1.clone());
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2
+    )))
+    .unwrap();
+    assert!(ephemeral2.duplicate_move_tokens.get(&pk1).is_none());
+
+    // Node2 receives the initial move token of Node1 twice:
+    let state2_before = bincode::serialize(&state2).unwrap();
+    for i in 1..=2u64 {
+        let funder_incoming = FunderIncoming::Comm(FunderIncomingComm::Friend((
+            pk1.clone(),
+            friend_message.clone(),
+        )));
+        let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+            funder_incoming,
+            &mut state2,
+            &mut ephemeral2,
+            &mut rng,
+            identity_client2
+        )))
+        .unwrap();
+
+        // The duplicate was counted, and did not change Node2's state:
+        assert_eq!(ephemeral2.duplicate_move_tokens.get(&pk1), Some(&i));
+        assert_eq!(bincode::serialize(&state2).unwrap(), state2_before);
+
+        // Node2 resends its last move token:
+        assert_eq!(outgoing_comms.len(), 1);
+        match find_move_token_request(&outgoing_comms) {
+            FriendMessage::MoveTokenRequest(move_token_request) => {
+                let friend_move_token = &move_token_request.friend_move_token;
+                assert_eq!(friend_move_token.move_token_counter, 1);
+            }
+            _ => unreachable!(),
+        };
+    }
+}
+
+#[test]
+fn test_handler_duplicate_move_token() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let mut identity_client1 = spawn_identity_client(&mut thread_pool, 1);
+    let mut identity_client2 = spawn_identity_client(&mut thread_pool, 2);
+    thread_pool.run(task_handler_duplicate_move_token(
+        &mut identity_client1,
+        &mut identity_client2,
+    ));
+}
@@ -0,0 +1,95 @@
+use super::utils::{apply_funder_incoming, init_node, spawn_identity_client};
+
+use std::cmp::Ordering;
+
+use futures::executor::ThreadPool;
+
+use identity::IdentityClient;
+
+use crypto::crypto_rand::RngContainer;
+use crypto::identity::{compare_public_key, PublicKey, Signature, PUBLIC_KEY_LEN, SIGNATURE_LEN};
+use crypto::test_utils::DummyRandom;
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::funder::messages::{
+    AddFriend, ForceInconsistency, FunderControl, FunderIncomingControl, ResetTerms,
+};
+
+use crate::friend::ChannelStatus;
+use crate::types::FunderIncoming;
+
+use crate::tests::utils::dummy_relay_address;
+
+async fn task_handler_force_inconsistency(mut identity_client: IdentityClient) {
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+    let (mut state, mut ephemeral) = await!(init_node::<u32, _>(
+        Vec::new(),
+        &mut rng,
+        &mut identity_client
+    ));
+    let local_pk = state.local_public_key.clone();
+
+    // We pick a friend for which the friend is the first sender. The token is then held by us:
+    let friend_pk = (0..=255u8)
+        .map(|i| PublicKey::from(&[i; PUBLIC_KEY_LEN]))
+        .find(|friend_pk| compare_public_key(&local_pk, friend_pk) == Ordering::Greater)
+        .unwrap();
+
+    let add_friend = AddFriend {
+        friend_public_key: friend_pk.clone(),
+        relays: vec![dummy_relay_address(1)],
+        name: "friend".into(),
+        balance: 8i128,
+        opt_remote_max_debt: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[11; UID_LEN]),
+        FunderControl::AddFriend(add_friend),
+    );
+    await!(Box::pin(apply_funder_incoming(
+        FunderIncoming::Control(incoming_control_message),
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    // The reset terms are chosen by the caller:
+    let reset_terms = ResetTerms {
+        reset_token: Signature::from(&[7; SIGNATURE_LEN]),
+        inconsistency_counter: 1,
+        balance_for_reset: 8i128,
+    };
+    let force_inconsistency = ForceInconsistency {
+        friend_public_key: friend_pk.clone(),
+        reset_terms: reset_terms.clone(),
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[12; UID_LEN]),
+        FunderControl::ForceInconsistency(force_inconsistency),
+    );
+    await!(Box::pin(apply_funder_incoming(
+        FunderIncoming::Control(incoming_control_message),
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    match &state.friends.get(&friend_pk).unwrap().channel_status {
+        ChannelStatus::Consistent(_) => unreachable!(),
+        ChannelStatus::Inconsistent(channel_inconsistent) => {
+            assert_eq!(channel_inconsistent.local_reset_terms, reset_terms);
+            assert!(channel_inconsistent.opt_remote_reset_terms.is_none());
+        }
+    };
+}
+
+#[test]
+fn test_handler_force_inconsistency() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let identity_client = spawn_identity_client(&mut thread_pool, 1);
+    thread_pool.run(task_handler_force_inconsistency(identity_client));
+}
@@ -0,0 +1,273 @@
+use super::utils::{apply_funder_incoming, init_node, spawn_identity_client};
+
+use std::cmp::Ordering;
+
+use futures::executor::ThreadPool;
+
+use identity::IdentityClient;
+
+use crypto::crypto_rand::RngContainer;
+use crypto::identity::compare_public_key;
+use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
+use crypto::test_utils::DummyRandom;
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::funder::messages::{
+    AddFriend, FriendMessage, FriendStatus, FriendTcOp, FriendsRoute, FunderControl,
+    FunderIncomingControl, FunderOutgoingControl, FundsReceived, PendingRequest, SetFriendStatus,
+};
+
+use crate::friend::{FriendMutation, ResponseOp};
+use crate::mutual_credit::types::McMutation;
+use crate::state::FunderMutation;
+use crate::token_channel::TcMutation;
+use crate::types::{
+    FunderIncoming, FunderIncomingComm, FunderOutgoingComm, IncomingLivenessMessage,
+};
+
+use crate::tests::utils::{dummy_named_relay_address, dummy_relay_address};
+
+/// Find the received funds in the outgoing control messages.
+fn find_funds_received(outgoing_control: &[FunderOutgoingControl<u32>]) -> Option<&FundsReceived> {
+    outgoing_control
+        .iter()
+        .filter_map(|outgoing| match outgoing {
+            FunderOutgoingControl::FundsReceived(funds_received) => Some(funds_received),
+            _ => None,
+        })
+        .next()
+}
+
+/// Find the first outgoing MoveTokenRequest message
+fn find_move_token_request(outgoing_comms: &[FunderOutgoingComm<u32>]) -> FriendMessage<u32> {
+    outgoing_comms
+        .iter()
+        .filter_map(|outgoing_comm| match outgoing_comm {
+            FunderOutgoingComm::FriendMessage((_pk, friend_message)) => match friend_message {
+                FriendMessage::MoveTokenRequest(_) => Some(friend_message.clone()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .next()
+        .unwrap()
+}
+
+async fn task_handler_funds_received<'a>(
+    identity_client1: &'a mut IdentityClient,
+    identity_client2: &'a mut IdentityClient,
+) {
+    // Sort the identities. identity_client1 will be the first sender, hence Node2 holds the token,
+    // and sends its pending responses as soon as Node1 is online:
+    let pk1 = await!(identity_client1.request_public_key()).unwrap();
+    let pk2 = await!(identity_client2.request_public_key()).unwrap();
+    let (identity_client1, pk1, identity_client2, pk2) =
+        if compare_public_key(&pk1, &pk2) == Ordering::Less {
+            (identity_client1, pk1, identity_client2, pk2)
+        } else {
+            (identity_client2, pk2, identity_client1, pk1)
+        };
+
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+
+    // Initialize both nodes:
+    let relays1 = vec![dummy_named_relay_address(1)];
+    let (mut state1, mut ephemeral1) =
+        await!(init_node::<u32, _>(relays1, &mut rng, identity_client1));
+    let relays2 = vec![dummy_named_relay_address(2)];
+    let (mut state2, mut ephemeral2) =
+        await!(init_node::<u32, _>(relays2, &mut rng, identity_client2));
+
+    // Add and enable friends:
+    let nodes = vec![
+        (
+            &mut state1,
+            &mut ephemeral1,
+            &mut *identity_client1,
+            pk2.clone(),
+            2u8,
+        ),
+        (
+            &mut state2,
+            &mut ephemeral2,
+            &mut *identity_client2,
+            pk1.clone(),
+            1u8,
+        ),
+    ];
+    for (state, ephemeral, identity_client, friend_pk, friend_index) in nodes {
+        let add_friend = AddFriend {
+            friend_public_key: friend_pk.clone(),
+            relays: vec![dummy_relay_address(friend_index)],
+            name: format!("pk{}", friend_index),
+            balance: 0i128,
+            opt_remote_max_debt: None,
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[11; UID_LEN]),
+            FunderControl::AddFriend(add_friend),
+        );
+        await!(Box::pin(apply_funder_incoming(
+            FunderIncoming::Control(incoming_control_message),
+            state,
+            ephemeral,
+            &mut rng,
+            identity_client
+        )))
+        .unwrap();
+
+        let set_friend_status = SetFriendStatus {
+            friend_public_key: friend_pk.clone(),
+            status: FriendStatus::Enabled,
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[12; UID_LEN]),
+            FunderControl::SetFriendStatus(set_friend_status),
+        );
+        let (_outgoing_comms, outgoing_control) = await!(Box::pin(apply_funder_incoming(
+            FunderIncoming::Control(incoming_control_message),
+            state,
+            ephemeral,
+            &mut rng,
+            identity_client
+        )))
+        .unwrap();
+        assert!(find_funds_received(&outgoing_control).is_none());
+    }
+
+    // Node1 sent Node2 a request for which Node2 is the destination. Node2's response was queued,
+    // but not sent yet, because Node1 went offline:
+    let pending_request = PendingRequest {
+        request_id: Uid::from(&[1; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: vec![pk1.clone(), pk2.clone()],
+        },
+        dest_payment: 20,
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+    };
+    let memo = b"memo".to_vec();
+
+    let mc_mutations1 = vec![
+        McMutation::InsertLocalPendingRequest(pending_request.clone()),
+        McMutation::SetLocalPendingDebt(20),
+    ];
+    for mc_mutation in mc_mutations1 {
+        let friend_mutation = FriendMutation::TcMutation(TcMutation::McMutation(mc_mutation));
+        state1.mutate(&FunderMutation::FriendMutation((
+            pk2.clone(),
+            friend_mutation,
+        )));
+    }
+
+    let mc_mutations2 = vec![
+        McMutation::InsertRemotePendingRequest(pending_request.clone()),
+        McMutation::SetRemotePendingDebt(20),
+    ];
+    for mc_mutation in mc_mutations2 {
+        let friend_mutation = FriendMutation::TcMutation(TcMutation::McMutation(mc_mutation));
+        state2.mutate(&FunderMutation::FriendMutation((
+            pk1.clone(),
+            friend_mutation,
+        )));
+    }
+    let friend_mutation = FriendMutation::PushBackPendingResponse(ResponseOp::UnsignedResponse((
+        pending_request.clone(),
+        memo.clone(),
+    )));
+    state2.mutate(&FunderMutation::FriendMutation((
+        pk1.clone(),
+        friend_mutation,
+    )));
+
+    // Node1: Notify that Node2 is alive:
+    let incoming_liveness_message = IncomingLivenessMessage::Online(pk2.clone());
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1
+    )))
+    .unwrap();
+
+    // Node2: Notify that Node1 is alive. Node2 sends its response:
+    let incoming_liveness_message = IncomingLivenessMessage::Online(pk1.clone());
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+    let (outgoing_comms, outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2
+    )))
+    .unwrap();
+
+    let friend_message = find_move_token_request(&outgoing_comms);
+    match &friend_message {
+        FriendMessage::MoveTokenRequest(move_token_request) => {
+            let operations = &move_token_request.friend_move_token.operations;
+            assert!(operations.iter().any(|operation| match operation {
+                FriendTcOp::ResponseSendFunds(response_send_funds) => {
+                    response_send_funds.request_id == pending_request.request_id
+                }
+                _ => false,
+            }));
+        }
+        _ => unreachable!(),
+    };
+
+    // The funds are not received until Node1 acknowledges the move token:
+    assert!(find_funds_received(&outgoing_control).is_none());
+    let friend2 = state2.friends.get(&pk1).unwrap();
+    assert!(friend2.pending_responses.is_empty());
+    assert_eq!(friend2.unacked_funds.len(), 1);
+
+    // Node1: Receive the response from Node2. Node1 sends its relays in the next move token:
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk2.clone(), friend_message)));
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1
+    )))
+    .unwrap();
+    let friend_message = find_move_token_request(&outgoing_comms);
+
+    // Node2: Receive the move token of Node1, which acknowledges the response. Only then the funds
+    // are received:
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk1.clone(), friend_message)));
+    let (_outgoing_comms, outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2
+    )))
+    .unwrap();
+
+    let funds_received = find_funds_received(&outgoing_control).unwrap();
+    assert_eq!(funds_received.request_id, pending_request.request_id);
+    assert_eq!(funds_received.invoice_id, pending_request.invoice_id);
+    assert_eq!(funds_received.dest_payment, 20);
+    assert_eq!(funds_received.memo, memo);
+
+    let friend2 = state2.friends.get(&pk1).unwrap();
+    assert!(friend2.unacked_funds.is_empty());
+}
+
+#[test]
+fn test_handler_funds_received() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let mut identity_client1 = spawn_identity_client(&mut thread_pool, 1);
+    let mut identity_client2 = spawn_identity_client(&mut thread_pool, 2);
+    thread_pool.run(task_handler_funds_received(
+        &mut identity_client1,
+        &mut identity_client2,
+    ));
+}
@@ -0,0 +1,303 @@
+use super::utils::{apply_funder_incoming, init_node, spawn_identity_client};
+
+use std::cmp::Ordering;
+
+use futures::executor::ThreadPool;
+
+use identity::IdentityClient;
+
+use crypto::crypto_rand::RngContainer;
+use crypto::hash::{HashResult, HASH_RESULT_LEN};
+use crypto::identity::{
+    compare_public_key, PublicKey, Signature, SoftwareEd25519Identity, PUBLIC_KEY_LEN,
+    SIGNATURE_LEN,
+};
+use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
+use crypto::test_utils::DummyRandom;
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::funder::messages::{
+    AddFriend, FriendStatus, FriendsRoute, FunderControl, FunderIncomingControl,
+    FunderOutgoingControl, Receipt, ReceiptAck, RequestsStatus, ResponseReceived,
+    ResponseSendFundsResult, SetFriendStatus, UserRequestSendFunds,
+};
+
+use crate::friend::FriendMutation;
+use crate::mutual_credit::types::McMutation;
+use crate::state::FunderMutation;
+use crate::token_channel::TcMutation;
+use crate::types::{FunderIncoming, FunderIncomingComm, IncomingLivenessMessage};
+
+use crate::tests::utils::dummy_relay_address;
+
+/// Find the response for the given request id in the outgoing control messages.
+fn find_response_received<'a>(
+    outgoing_control: &'a [FunderOutgoingControl<u32>],
+    request_id: &Uid,
+) -> Option<&'a ResponseReceived> {
+    outgoing_control
+        .iter()
+        .filter_map(|outgoing| match outgoing {
+            FunderOutgoingControl::ResponseReceived(response_received) => Some(response_received),
+            _ => None,
+        })
+        .find(|response_received| &response_received.request_id == request_id)
+}
+
+async fn task_handler_idempotency_key(mut identity_client: IdentityClient) {
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+    let (mut state, mut ephemeral) = await!(init_node::<u32, _>(
+        Vec::new(),
+        &mut rng,
+        &mut identity_client
+    ));
+    let local_pk = state.local_public_key.clone();
+
+    // We pick a friend for which we are the first sender. The token is then held by the friend,
+    // and our requests wait until the token is received:
+    let friend_pk = (0..=255u8)
+        .map(|i| PublicKey::from(&[i; PUBLIC_KEY_LEN]))
+        .find(|friend_pk| compare_public_key(&local_pk, friend_pk) == Ordering::Less)
+        .unwrap();
+
+    let add_friend = AddFriend {
+        friend_public_key: friend_pk.clone(),
+        relays: vec![dummy_relay_address(1)],
+        name: "friend".into(),
+        balance: 100i128,
+        opt_remote_max_debt: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[11; UID_LEN]),
+        FunderControl::AddFriend(add_friend),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    let set_friend_status = SetFriendStatus {
+        friend_public_key: friend_pk.clone(),
+        status: FriendStatus::Enabled,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[12; UID_LEN]),
+        FunderControl::SetFriendStatus(set_friend_status),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    let incoming_liveness_message = IncomingLivenessMessage::Online(friend_pk.clone());
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    // The friend has its requests open:
+    let mc_mutation = McMutation::SetRemoteRequestsStatus(RequestsStatus::Open);
+    let friend_mutation = FriendMutation::TcMutation(TcMutation::McMutation(mc_mutation));
+    state.mutate(&FunderMutation::FriendMutation((
+        friend_pk.clone(),
+        friend_mutation,
+    )));
+
+    let idempotency_key = Uid::from(&[0xaa; UID_LEN]);
+    let create_request = |index: u8| UserRequestSendFunds {
+        request_id: Uid::from(&[index; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: vec![local_pk.clone(), friend_pk.clone()],
+        },
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        dest_payment: 20,
+        memo: Vec::new(),
+        opt_idempotency_key: Some(idempotency_key),
+    };
+
+    // Send the first request:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[13; UID_LEN]),
+        FunderControl::RequestSendFunds(create_request(1)),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    let (_outgoing_comms, outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+    assert!(find_response_received(&outgoing_control, &Uid::from(&[1; UID_LEN])).is_none());
+
+    // A retry with the same idempotency key but a different request id, while the first request
+    // is still in flight:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[14; UID_LEN]),
+        FunderControl::RequestSendFunds(create_request(2)),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    let (_outgoing_comms, outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+    let response_received =
+        find_response_received(&outgoing_control, &Uid::from(&[2; UID_LEN])).unwrap();
+    match response_received.result {
+        ResponseSendFundsResult::Failure(_) => {}
+        ResponseSendFundsResult::Success(_) => unreachable!(),
+    };
+
+    // Only a single payment was queued:
+    let friend = state.friends.get(&friend_pk).unwrap();
+    assert_eq!(friend.pending_user_requests.len(), 1);
+    assert_eq!(
+        friend.pending_user_requests[0].request_id,
+        Uid::from(&[1; UID_LEN])
+    );
+
+    // Simulate the completion of the first request:
+    let receipt = Receipt {
+        response_hash: HashResult::from(&[0; HASH_RESULT_LEN]),
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        dest_payment: 20,
+        signature: Signature::from(&[0; SIGNATURE_LEN]),
+    };
+    state.mutate(&FunderMutation::FriendMutation((
+        friend_pk.clone(),
+        FriendMutation::PopFrontPendingUserRequest,
+    )));
+    state.mutate(&FunderMutation::AddReceipt((
+        Uid::from(&[1; UID_LEN]),
+        receipt.clone(),
+    )));
+
+    // Another retry returns the receipt of the first request:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[15; UID_LEN]),
+        FunderControl::RequestSendFunds(create_request(3)),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    let (_outgoing_comms, outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+    let response_received =
+        find_response_received(&outgoing_control, &Uid::from(&[3; UID_LEN])).unwrap();
+    match &response_received.result {
+        ResponseSendFundsResult::Success(response_receipt) => {
+            assert_eq!(response_receipt, &receipt)
+        }
+        ResponseSendFundsResult::Failure(_) => unreachable!(),
+    };
+
+    // No new payment was made, and the receipt can now be acked using the new request id:
+    let friend = state.friends.get(&friend_pk).unwrap();
+    assert!(friend.pending_user_requests.is_empty());
+    assert!(!state.ready_receipts.contains_key(&Uid::from(&[1; UID_LEN])));
+    assert!(state.ready_receipts.contains_key(&Uid::from(&[3; UID_LEN])));
+
+    // Ack the receipt:
+    let receipt_ack = ReceiptAck {
+        request_id: Uid::from(&[3; UID_LEN]),
+        receipt_signature: receipt.signature.clone(),
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[16; UID_LEN]),
+        FunderControl::ReceiptAck(receipt_ack),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+    assert!(state.ready_receipts.is_empty());
+
+    // The idempotency key is still remembered after the ack. A retry returns the receipt again:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[17; UID_LEN]),
+        FunderControl::RequestSendFunds(create_request(4)),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    let (_outgoing_comms, outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+    let response_received =
+        find_response_received(&outgoing_control, &Uid::from(&[4; UID_LEN])).unwrap();
+    match &response_received.result {
+        ResponseSendFundsResult::Success(response_receipt) => {
+            assert_eq!(response_receipt, &receipt)
+        }
+        ResponseSendFundsResult::Failure(_) => unreachable!(),
+    };
+    let friend = state.friends.get(&friend_pk).unwrap();
+    assert!(friend.pending_user_requests.is_empty());
+    assert!(state.ready_receipts.contains_key(&Uid::from(&[4; UID_LEN])));
+
+    // A retry that does not describe the same payment is rejected:
+    let mut user_request_send_funds = create_request(5);
+    user_request_send_funds.dest_payment = 21;
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[18; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    let (_outgoing_comms, outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+    let response_received =
+        find_response_received(&outgoing_control, &Uid::from(&[5; UID_LEN])).unwrap();
+    match response_received.result {
+        ResponseSendFundsResult::Failure(_) => {}
+        ResponseSendFundsResult::Success(_) => unreachable!(),
+    };
+    let friend = state.friends.get(&friend_pk).unwrap();
+    assert!(friend.pending_user_requests.is_empty());
+    assert!(!state.ready_receipts.contains_key(&Uid::from(&[5; UID_LEN])));
+}
+
+#[test]
+fn test_handler_idempotency_key() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let identity_client = spawn_identity_client(&mut thread_pool, 1);
+    thread_pool.run(task_handler_idempotency_key(identity_client));
+}
@@ -1,4 +1,21 @@
+mod activate_friend;
+mod add_friend;
 mod change_address;
+mod control_stats;
+mod deferred_send;
+mod duplicate_move_token;
+#[cfg(feature = "force-inconsistency")]
+mod force_inconsistency;
+mod funds_received;
+mod idempotency;
+mod move_token_corruption;
+mod move_token_tick;
 mod pair_basic;
 mod pair_inconsistency;
+mod pending_requests;
+mod remove_friend;
+mod reset_balance;
+mod reset_status;
+mod rtt;
+mod simultaneous_reset;
 mod utils;
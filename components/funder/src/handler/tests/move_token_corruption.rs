@@ -0,0 +1,203 @@
+use super::utils::{apply_funder_incoming, init_node, spawn_identity_client};
+
+use std::cmp::Ordering;
+
+use futures::executor::ThreadPool;
+
+use identity::IdentityClient;
+
+use crypto::crypto_rand::{RandValue, RngContainer, RAND_VALUE_LEN};
+use crypto::identity::compare_public_key;
+use crypto::test_utils::DummyRandom;
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::funder::messages::{
+    AddFriend, FriendMessage, FriendStatus, FunderControl, FunderIncomingControl, SetFriendStatus,
+};
+
+use crate::friend::ChannelStatus;
+use crate::handler::handle_friend::HandleFriendError;
+use crate::handler::handler::FunderHandlerError;
+use crate::types::{
+    FunderIncoming, FunderIncomingComm, FunderOutgoingComm, IncomingLivenessMessage,
+};
+
+use crate::tests::utils::{dummy_named_relay_address, dummy_relay_address};
+
+async fn task_handler_move_token_corruption<'a>(
+    identity_client1: &'a mut IdentityClient,
+    identity_client2: &'a mut IdentityClient,
+) {
+    // Sort the identities. identity_client1 will be the first sender:
+    let pk1 = await!(identity_client1.request_public_key()).unwrap();
+    let pk2 = await!(identity_client2.request_public_key()).unwrap();
+    let (identity_client1, pk1, identity_client2, pk2) =
+        if compare_public_key(&pk1, &pk2) == Ordering::Less {
+            (identity_client1, pk1, identity_client2, pk2)
+        } else {
+            (identity_client2, pk2, identity_client1, pk1)
+        };
+
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+
+    // Initialize both nodes:
+    let relays1 = vec![dummy_named_relay_address(1)];
+    let (mut state1, mut ephemeral1) =
+        await!(init_node::<u32, _>(relays1, &mut rng, identity_client1));
+    let relays2 = vec![dummy_named_relay_address(2)];
+    let (mut state2, mut ephemeral2) =
+        await!(init_node::<u32, _>(relays2, &mut rng, identity_client2));
+
+    // Add and enable friends:
+    let nodes = vec![
+        (
+            &mut state1,
+            &mut ephemeral1,
+            &mut *identity_client1,
+            pk2.clone(),
+            2u8,
+        ),
+        (
+            &mut state2,
+            &mut ephemeral2,
+            &mut *identity_client2,
+            pk1.clone(),
+            1u8,
+        ),
+    ];
+    for (state, ephemeral, identity_client, friend_pk, friend_index) in nodes {
+        let add_friend = AddFriend {
+            friend_public_key: friend_pk.clone(),
+            relays: vec![dummy_relay_address(friend_index)],
+            name: format!("pk{}", friend_index),
+            balance: 0i128,
+            opt_remote_max_debt: None,
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[11; UID_LEN]),
+            FunderControl::AddFriend(add_friend),
+        );
+        await!(Box::pin(apply_funder_incoming(
+            FunderIncoming::Control(incoming_control_message),
+            state,
+            ephemeral,
+            &mut rng,
+            identity_client
+        )))
+        .unwrap();
+
+        let set_friend_status = SetFriendStatus {
+            friend_public_key: friend_pk.clone(),
+            status: FriendStatus::Enabled,
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[12; UID_LEN]),
+            FunderControl::SetFriendStatus(set_friend_status),
+        );
+        await!(Box::pin(apply_funder_incoming(
+            FunderIncoming::Control(incoming_control_message),
+            state,
+            ephemeral,
+            &mut rng,
+            identity_client
+        )))
+        .unwrap();
+    }
+
+    // Node1: Notify that Node2 is alive.
+    // Node1 sends a move token to Node2:
+    let incoming_liveness_message = IncomingLivenessMessage::Online(pk2.clone());
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1
+    )))
+    .unwrap();
+    assert_eq!(outgoing_comms.len(), 1);
+    let friend_message = match &outgoing_comms[0] {
+        FunderOutgoingComm::FriendMessage((pk, friend_message)) => {
+            assert_eq!(pk, &pk2);
+            friend_message.clone()
+        }
+        _ => unreachable!(),
+    };
+
+    // Node2: Notify that Node1 is alive:
+    let incoming_liveness_message = IncomingLivenessMessage::Online(pk1.clone());
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2
+    )))
+    .unwrap();
+
+    // Corrupt a single byte in the middle of the move token:
+    let mut corrupt_message = friend_message.clone();
+    if let FriendMessage::MoveTokenRequest(move_token_request) = &mut corrupt_message {
+        let friend_move_token = &mut move_token_request.friend_move_token;
+        let mut rand_nonce_bytes = [0u8; RAND_VALUE_LEN];
+        rand_nonce_bytes.copy_from_slice(&friend_move_token.rand_nonce);
+        rand_nonce_bytes[RAND_VALUE_LEN / 2] ^= 0x01;
+        friend_move_token.rand_nonce = RandValue::from(&rand_nonce_bytes);
+    } else {
+        unreachable!();
+    }
+
+    // Node2: Receive the corrupt move token.
+    // We expect a corruption error, and not a signature error:
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk1.clone(), corrupt_message)));
+    let res = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2
+    )));
+    match res {
+        Err(FunderHandlerError::HandleFriendError(HandleFriendError::MoveTokenCorrupted)) => {}
+        _ => unreachable!(),
+    };
+
+    // The corrupt message did not cause an inconsistency:
+    match &state2.friends.get(&pk1).unwrap().channel_status {
+        ChannelStatus::Consistent(_) => {}
+        ChannelStatus::Inconsistent(_) => unreachable!(),
+    };
+
+    // Node2: Receive the original move token. This time it is accepted:
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk1.clone(), friend_message)));
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2
+    )))
+    .unwrap();
+
+    match &state2.friends.get(&pk1).unwrap().channel_status {
+        ChannelStatus::Consistent(_) => {}
+        ChannelStatus::Inconsistent(_) => unreachable!(),
+    };
+}
+
+#[test]
+fn test_handler_move_token_corruption() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let mut identity_client1 = spawn_identity_client(&mut thread_pool, 1);
+    let mut identity_client2 = spawn_identity_client(&mut thread_pool, 2);
+    thread_pool.run(task_handler_move_token_corruption(
+        &mut identity_client1,
+        &mut identity_client2,
+    ));
+}
@@ -0,0 +1,199 @@
+use super::utils::{apply_funder_incoming_at_tick, init_node, spawn_identity_client};
+
+use std::cmp::Ordering;
+
+use futures::executor::ThreadPool;
+
+use identity::IdentityClient;
+
+use crypto::crypto_rand::RngContainer;
+use crypto::identity::compare_public_key;
+use crypto::test_utils::DummyRandom;
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::funder::messages::{
+    AddFriend, FriendMessage, FriendStatus, FunderControl, FunderIncomingControl, SetFriendStatus,
+};
+
+use crate::report::create_report;
+use crate::types::{
+    FunderIncoming, FunderIncomingComm, FunderOutgoingComm, IncomingLivenessMessage,
+};
+
+use crate::tests::utils::{dummy_named_relay_address, dummy_relay_address};
+
+/// Find the first outgoing MoveTokenRequest message
+fn find_move_token_request(outgoing_comms: &[FunderOutgoingComm<u32>]) -> FriendMessage<u32> {
+    outgoing_comms
+        .iter()
+        .filter_map(|outgoing_comm| match outgoing_comm {
+            FunderOutgoingComm::FriendMessage((_pk, friend_message)) => match friend_message {
+                FriendMessage::MoveTokenRequest(_) => Some(friend_message.clone()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .next()
+        .unwrap()
+}
+
+async fn task_handler_move_token_tick<'a>(
+    identity_client1: &'a mut IdentityClient,
+    identity_client2: &'a mut IdentityClient,
+) {
+    // Sort the identities. identity_client1 will be the first sender:
+    let pk1 = await!(identity_client1.request_public_key()).unwrap();
+    let pk2 = await!(identity_client2.request_public_key()).unwrap();
+    let (identity_client1, pk1, identity_client2, pk2) =
+        if compare_public_key(&pk1, &pk2) == Ordering::Less {
+            (identity_client1, pk1, identity_client2, pk2)
+        } else {
+            (identity_client2, pk2, identity_client1, pk1)
+        };
+
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+
+    // Initialize both nodes:
+    let relays1 = vec![dummy_named_relay_address(1)];
+    let (mut state1, mut ephemeral1) =
+        await!(init_node::<u32, _>(relays1, &mut rng, identity_client1));
+    let relays2 = vec![dummy_named_relay_address(2)];
+    let (mut state2, mut ephemeral2) =
+        await!(init_node::<u32, _>(relays2, &mut rng, identity_client2));
+
+    // Add and enable friends:
+    let nodes = vec![
+        (
+            &mut state1,
+            &mut ephemeral1,
+            &mut *identity_client1,
+            pk2.clone(),
+            2u8,
+        ),
+        (
+            &mut state2,
+            &mut ephemeral2,
+            &mut *identity_client2,
+            pk1.clone(),
+            1u8,
+        ),
+    ];
+    for (state, ephemeral, identity_client, friend_pk, friend_index) in nodes {
+        let add_friend = AddFriend {
+            friend_public_key: friend_pk.clone(),
+            relays: vec![dummy_relay_address(friend_index)],
+            name: format!("pk{}", friend_index),
+            balance: 0i128,
+            opt_remote_max_debt: None,
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[11; UID_LEN]),
+            FunderControl::AddFriend(add_friend),
+        );
+        await!(Box::pin(apply_funder_incoming_at_tick(
+            FunderIncoming::Control(incoming_control_message),
+            state,
+            ephemeral,
+            &mut rng,
+            identity_client,
+            0
+        )))
+        .unwrap();
+
+        let set_friend_status = SetFriendStatus {
+            friend_public_key: friend_pk.clone(),
+            status: FriendStatus::Enabled,
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[12; UID_LEN]),
+            FunderControl::SetFriendStatus(set_friend_status),
+        );
+        await!(Box::pin(apply_funder_incoming_at_tick(
+            FunderIncoming::Control(incoming_control_message),
+            state,
+            ephemeral,
+            &mut rng,
+            identity_client,
+            0
+        )))
+        .unwrap();
+
+        // No move tokens were exchanged yet:
+        assert!(ephemeral.last_move_token_ticks.get(&friend_pk).is_none());
+    }
+
+    // Node1: Notify that Node2 is alive (tick 3).
+    // Node1 sends a move token to Node2:
+    let incoming_liveness_message = IncomingLivenessMessage::Online(pk2.clone());
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming_at_tick(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1,
+        3
+    )))
+    .unwrap();
+    let friend_message = find_move_token_request(&outgoing_comms);
+    assert_eq!(ephemeral1.last_move_token_ticks.get(&pk2), Some(&3));
+
+    // Node2: Notify that Node1 is alive (tick 4):
+    let incoming_liveness_message = IncomingLivenessMessage::Online(pk1.clone());
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+    await!(Box::pin(apply_funder_incoming_at_tick(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2,
+        4
+    )))
+    .unwrap();
+
+    // Node2: Receive the move token from Node1 (tick 5):
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk1.clone(), friend_message)));
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming_at_tick(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2,
+        5
+    )))
+    .unwrap();
+    let friend_message = find_move_token_request(&outgoing_comms);
+    assert_eq!(ephemeral2.last_move_token_ticks.get(&pk1), Some(&5));
+
+    // Node1: Receive the move token from Node2 (tick 9):
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk2.clone(), friend_message)));
+    await!(Box::pin(apply_funder_incoming_at_tick(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1,
+        9
+    )))
+    .unwrap();
+
+    // Last activity of Node1 with Node2 has advanced:
+    assert_eq!(ephemeral1.last_move_token_ticks.get(&pk2), Some(&9));
+    let report1 = create_report(&state1, &ephemeral1);
+    assert_eq!(report1.friends.get(&pk2).unwrap().last_move_token_tick, 9);
+}
+
+#[test]
+fn test_handler_move_token_tick() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let mut identity_client1 = spawn_identity_client(&mut thread_pool, 1);
+    let mut identity_client2 = spawn_identity_client(&mut thread_pool, 2);
+    thread_pool.run(task_handler_move_token_tick(
+        &mut identity_client1,
+        &mut identity_client2,
+    ));
+}
@@ -85,6 +85,7 @@ async fn task_handler_pair_basic<'a>(
         relays: vec![dummy_relay_address(2)],
         name: String::from("pk2"),
         balance: 0i128,
+        opt_remote_max_debt: None,
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[11; UID_LEN]),
@@ -125,6 +126,7 @@ async fn task_handler_pair_basic<'a>(
         relays: vec![dummy_relay_address(1)],
         name: String::from("pk1"),
         balance: 0i128,
+        opt_remote_max_debt: None,
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[13; UID_LEN]),
@@ -423,6 +425,8 @@ async fn task_handler_pair_basic<'a>(
         },
         invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
         dest_payment: 20,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[16; UID_LEN]),
@@ -565,6 +569,8 @@ async fn task_handler_pair_basic<'a>(
         },
         invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
         dest_payment: 20,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[18; UID_LEN]),
@@ -19,7 +19,7 @@ use proto::funder::messages::{
 };
 
 use crate::ephemeral::Ephemeral;
-use crate::friend::ChannelStatus;
+use crate::friend::{ChannelStatus, ResetStatus};
 use crate::state::FunderState;
 use crate::types::{
     FunderIncoming, FunderIncomingComm, FunderOutgoingComm, IncomingLivenessMessage,
@@ -82,6 +82,7 @@ async fn task_handler_pair_inconsistency<'a>(
         relays: vec![dummy_relay_address(2)],
         name: String::from("pk2"),
         balance: 20i128,
+        opt_remote_max_debt: None,
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[11; UID_LEN]),
@@ -124,6 +125,7 @@ async fn task_handler_pair_inconsistency<'a>(
         relays: vec![dummy_relay_address(1)],
         name: String::from("pk1"),
         balance: -10i128,
+        opt_remote_max_debt: None,
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[13; UID_LEN]),
@@ -353,7 +355,7 @@ async fn task_handler_pair_inconsistency<'a>(
 
     // Node1 should send a MoveToken message that resolves the inconsistency:
     assert_eq!(outgoing_comms.len(), 1);
-    let friend_message = match &outgoing_comms[0] {
+    let (friend_message, reset_move_token_new_token) = match &outgoing_comms[0] {
         FunderOutgoingComm::FriendMessage((pk, friend_message)) => {
             if let FriendMessage::MoveTokenRequest(move_token_request) = friend_message {
                 assert_eq!(pk, &pk2);
@@ -366,14 +368,21 @@ async fn task_handler_pair_inconsistency<'a>(
                 assert_eq!(friend_move_token.inconsistency_counter, 1);
                 assert_eq!(friend_move_token.balance, 10i128);
                 assert!(friend_move_token.opt_local_relays.is_none());
+                (friend_message.clone(), friend_move_token.new_token.clone())
             } else {
                 unreachable!();
             }
-            friend_message.clone()
         }
         _ => unreachable!(),
     };
 
+    // Node1 waits for Node2 to acknowledge the reset:
+    let friend2 = state1.friends.get(&pk2).unwrap();
+    match &friend2.reset_status {
+        ResetStatus::Pending(_) => {}
+        _ => unreachable!(),
+    };
+
     // Node2: Receive MoveToken (that resolves inconsistency) from Node1:
     let funder_incoming =
         FunderIncoming::Comm(FunderIncomingComm::Friend((pk1.clone(), friend_message)));
@@ -386,9 +395,21 @@ async fn task_handler_pair_inconsistency<'a>(
     )))
     .unwrap();
 
-    // Node2 should send back an empty move token:
-    assert_eq!(outgoing_comms.len(), 1);
-    let friend_message = match &outgoing_comms[0] {
+    // Node2 should acknowledge the reset, and send back an empty move token:
+    assert_eq!(outgoing_comms.len(), 2);
+    let reset_ack_message = match &outgoing_comms[0] {
+        FunderOutgoingComm::FriendMessage((pk, friend_message)) => {
+            if let FriendMessage::ResetAck(reset_token) = friend_message {
+                assert_eq!(pk, &pk1);
+                assert_eq!(reset_token, &reset_move_token_new_token);
+            } else {
+                unreachable!();
+            }
+            friend_message.clone()
+        }
+        _ => unreachable!(),
+    };
+    let friend_message = match &outgoing_comms[1] {
         FunderOutgoingComm::FriendMessage((pk, friend_message)) => {
             if let FriendMessage::MoveTokenRequest(move_token_request) = friend_message {
                 assert_eq!(pk, &pk1);
@@ -409,6 +430,22 @@ async fn task_handler_pair_inconsistency<'a>(
         _ => unreachable!(),
     };
 
+    // Node1: Receive the reset acknowledgement from Node2:
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk2.clone(), reset_ack_message)));
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1
+    )))
+    .unwrap();
+    assert!(outgoing_comms.is_empty());
+
+    let friend2 = state1.friends.get(&pk2).unwrap();
+    assert_eq!(friend2.reset_status, ResetStatus::Confirmed);
+
     // Node1: Receive MoveToken from Node2:
     let funder_incoming =
         FunderIncoming::Comm(FunderIncomingComm::Friend((pk2.clone(), friend_message)));
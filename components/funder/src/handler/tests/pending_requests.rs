@@ -0,0 +1,209 @@
+use super::utils::{apply_funder_incoming, init_node, spawn_identity_client};
+
+use std::cmp::Ordering;
+
+use futures::executor::ThreadPool;
+
+use identity::IdentityClient;
+
+use crypto::crypto_rand::RngContainer;
+use crypto::identity::{compare_public_key, PublicKey, PUBLIC_KEY_LEN};
+use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
+use crypto::test_utils::DummyRandom;
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::funder::messages::{
+    AddFriend, FriendStatus, FriendsRoute, FunderControl, FunderIncomingControl,
+    FunderOutgoingControl, PendingFriendRequest, PendingRequest, RequestsStatus, SetFriendStatus,
+    UserRequestSendFunds,
+};
+
+use crate::friend::FriendMutation;
+use crate::mutual_credit::types::McMutation;
+use crate::state::FunderMutation;
+use crate::token_channel::TcMutation;
+use crate::types::{FunderIncoming, FunderIncomingComm, IncomingLivenessMessage};
+
+use crate::tests::utils::dummy_relay_address;
+
+/// Find the pending requests in the outgoing control messages.
+fn find_pending_requests(
+    outgoing_control: &[FunderOutgoingControl<u32>],
+) -> Option<&Vec<PendingFriendRequest>> {
+    outgoing_control
+        .iter()
+        .filter_map(|outgoing| match outgoing {
+            FunderOutgoingControl::PendingRequests(pending_requests) => Some(pending_requests),
+            _ => None,
+        })
+        .next()
+}
+
+async fn task_handler_pending_requests(mut identity_client: IdentityClient) {
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+    let (mut state, mut ephemeral) = await!(init_node::<u32, _>(
+        Vec::new(),
+        &mut rng,
+        &mut identity_client
+    ));
+    let local_pk = state.local_public_key.clone();
+
+    // We pick a friend for which we are the first sender. The token is then held by the friend,
+    // and our requests wait until the token is received:
+    let friend_pk = (0..=255u8)
+        .map(|i| PublicKey::from(&[i; PUBLIC_KEY_LEN]))
+        .find(|friend_pk| compare_public_key(&local_pk, friend_pk) == Ordering::Less)
+        .unwrap();
+
+    let add_friend = AddFriend {
+        friend_public_key: friend_pk.clone(),
+        relays: vec![dummy_relay_address(1)],
+        name: "friend".into(),
+        balance: 100i128,
+        opt_remote_max_debt: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[11; UID_LEN]),
+        FunderControl::AddFriend(add_friend),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    let set_friend_status = SetFriendStatus {
+        friend_public_key: friend_pk.clone(),
+        status: FriendStatus::Enabled,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[12; UID_LEN]),
+        FunderControl::SetFriendStatus(set_friend_status),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    let incoming_liveness_message = IncomingLivenessMessage::Online(friend_pk.clone());
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    // The friend has its requests open, and a request we sent earlier still awaits a response:
+    let route = FriendsRoute {
+        public_keys: vec![local_pk.clone(), friend_pk.clone()],
+    };
+    let sent_request = PendingRequest {
+        request_id: Uid::from(&[2; UID_LEN]),
+        route: route.clone(),
+        dest_payment: 30,
+        invoice_id: InvoiceId::from(&[2; INVOICE_ID_LEN]),
+    };
+    let mc_mutations = vec![
+        McMutation::SetRemoteRequestsStatus(RequestsStatus::Open),
+        McMutation::InsertLocalPendingRequest(sent_request.clone()),
+    ];
+    for mc_mutation in mc_mutations {
+        let friend_mutation = FriendMutation::TcMutation(TcMutation::McMutation(mc_mutation));
+        state.mutate(&FunderMutation::FriendMutation((
+            friend_pk.clone(),
+            friend_mutation,
+        )));
+    }
+
+    // Send a request. It is queued until we receive the token:
+    let user_request_send_funds = UserRequestSendFunds {
+        request_id: Uid::from(&[1; UID_LEN]),
+        route: route.clone(),
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        dest_payment: 20,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[13; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    // Ask for the requests currently pending with the friend:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[14; UID_LEN]),
+        FunderControl::GetPendingRequests(friend_pk.clone()),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    let (outgoing_comms, outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+    assert!(outgoing_comms.is_empty());
+
+    // Queued requests come first, followed by the requests that were already sent:
+    let pending_requests = find_pending_requests(&outgoing_control).unwrap();
+    assert_eq!(pending_requests.len(), 2);
+
+    assert!(pending_requests[0].is_queued);
+    let queued_request = &pending_requests[0].pending_request;
+    assert_eq!(queued_request.request_id, Uid::from(&[1; UID_LEN]));
+    assert_eq!(queued_request.route, route);
+    assert_eq!(queued_request.dest_payment, 20);
+    assert_eq!(
+        queued_request.invoice_id,
+        InvoiceId::from(&[1; INVOICE_ID_LEN])
+    );
+
+    assert!(!pending_requests[1].is_queued);
+    assert_eq!(pending_requests[1].pending_request, sent_request);
+
+    // Asking about a friend that does not exist produces no pending requests:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[15; UID_LEN]),
+        FunderControl::GetPendingRequests(PublicKey::from(&[0xcc; PUBLIC_KEY_LEN])),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    let (_outgoing_comms, outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+    assert!(find_pending_requests(&outgoing_control).is_none());
+}
+
+#[test]
+fn test_handler_pending_requests() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let identity_client = spawn_identity_client(&mut thread_pool, 1);
+    thread_pool.run(task_handler_pending_requests(identity_client));
+}
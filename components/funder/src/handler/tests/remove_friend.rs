@@ -0,0 +1,229 @@
+use super::utils::{apply_funder_incoming, init_node, spawn_identity_client};
+
+use futures::executor::ThreadPool;
+
+use identity::IdentityClient;
+
+use crypto::crypto_rand::RngContainer;
+use crypto::identity::{PublicKey, PUBLIC_KEY_LEN};
+use crypto::test_utils::DummyRandom;
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::funder::messages::{
+    AddFriend, FriendStatus, FunderControl, FunderIncomingControl, FunderOutgoingControl,
+    RemoveFriend, SetFriendStatus,
+};
+
+use crate::types::{ChannelerConfig, FunderIncoming, FunderOutgoingComm};
+
+use crate::tests::utils::dummy_relay_address;
+
+async fn task_handler_request_remove_friend(mut identity_client: IdentityClient) {
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+    let (mut state, mut ephemeral) = await!(init_node::<u32, _>(
+        Vec::new(),
+        &mut rng,
+        &mut identity_client
+    ));
+
+    let friend_pk = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+    let add_friend = AddFriend {
+        friend_public_key: friend_pk.clone(),
+        relays: vec![dummy_relay_address(1)],
+        name: "friend".into(),
+        balance: 7i128,
+        opt_remote_max_debt: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[11; UID_LEN]),
+        FunderControl::AddFriend(add_friend),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    // Request to remove the friend:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[12; UID_LEN]),
+        FunderControl::RequestRemoveFriend(friend_pk.clone()),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    let (_outgoing_comms, outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    let remove_friend_consequences = outgoing_control
+        .iter()
+        .filter_map(|outgoing| match outgoing {
+            FunderOutgoingControl::RemoveFriendConsequences(remove_friend_consequences) => {
+                Some(remove_friend_consequences)
+            }
+            _ => None,
+        })
+        .next()
+        .unwrap();
+    assert_eq!(remove_friend_consequences.friend_public_key, friend_pk);
+    assert_eq!(remove_friend_consequences.balance, 7i128);
+    assert!(remove_friend_consequences.pending_requests.is_empty());
+    assert_eq!(remove_friend_consequences.num_pending_responses, 0);
+
+    // The friend is left intact until the removal is confirmed:
+    assert!(state.friends.get(&friend_pk).is_some());
+
+    // Confirm the removal:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[13; UID_LEN]),
+        FunderControl::RemoveFriend(RemoveFriend {
+            friend_public_key: friend_pk.clone(),
+        }),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    assert!(state.friends.get(&friend_pk).is_none());
+}
+
+#[test]
+fn test_handler_request_remove_friend() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let identity_client = spawn_identity_client(&mut thread_pool, 1);
+    thread_pool.run(task_handler_request_remove_friend(identity_client));
+}
+
+async fn task_handler_remove_friend_purge(mut identity_client: IdentityClient) {
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+    let (mut state, mut ephemeral) = await!(init_node::<u32, _>(
+        Vec::new(),
+        &mut rng,
+        &mut identity_client
+    ));
+
+    let friend_pk = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+    let add_friend = AddFriend {
+        friend_public_key: friend_pk.clone(),
+        relays: vec![dummy_relay_address(1)],
+        name: "friend".into(),
+        balance: 0i128,
+        opt_remote_max_debt: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[11; UID_LEN]),
+        FunderControl::AddFriend(add_friend),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    let set_friend_status = SetFriendStatus {
+        friend_public_key: friend_pk.clone(),
+        status: FriendStatus::Enabled,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[12; UID_LEN]),
+        FunderControl::SetFriendStatus(set_friend_status),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    // Disabling the friend only removes the friend from the Channeler:
+    let set_friend_status = SetFriendStatus {
+        friend_public_key: friend_pk.clone(),
+        status: FriendStatus::Disabled,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[13; UID_LEN]),
+        FunderControl::SetFriendStatus(set_friend_status),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+    assert!(outgoing_comms
+        .iter()
+        .any(|outgoing_comm| match outgoing_comm {
+            FunderOutgoingComm::ChannelerConfig(ChannelerConfig::RemoveFriend(public_key)) => {
+                public_key == &friend_pk
+            }
+            _ => false,
+        }));
+    assert!(!outgoing_comms
+        .iter()
+        .any(|outgoing_comm| match outgoing_comm {
+            FunderOutgoingComm::ChannelerConfig(ChannelerConfig::PurgeFriend(_)) => true,
+            _ => false,
+        }));
+
+    // Removing the friend purges the friend from the Channeler:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[14; UID_LEN]),
+        FunderControl::RemoveFriend(RemoveFriend {
+            friend_public_key: friend_pk.clone(),
+        }),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+    assert!(outgoing_comms
+        .iter()
+        .any(|outgoing_comm| match outgoing_comm {
+            FunderOutgoingComm::ChannelerConfig(ChannelerConfig::PurgeFriend(public_key)) => {
+                public_key == &friend_pk
+            }
+            _ => false,
+        }));
+    assert!(!outgoing_comms
+        .iter()
+        .any(|outgoing_comm| match outgoing_comm {
+            FunderOutgoingComm::ChannelerConfig(ChannelerConfig::RemoveFriend(_)) => true,
+            _ => false,
+        }));
+}
+
+#[test]
+fn test_handler_remove_friend_purge() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let identity_client = spawn_identity_client(&mut thread_pool, 1);
+    thread_pool.run(task_handler_remove_friend_purge(identity_client));
+}
@@ -0,0 +1,104 @@
+use super::utils::{apply_funder_incoming_with_stats, spawn_identity_client};
+
+use futures::executor::ThreadPool;
+
+use identity::IdentityClient;
+
+use crypto::crypto_rand::RngContainer;
+use crypto::identity::{PublicKey, Signature, PUBLIC_KEY_LEN, SIGNATURE_LEN};
+use crypto::test_utils::DummyRandom;
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::funder::messages::{
+    AddFriend, FunderControl, FunderIncomingControl, ResetFriendChannel, ResetTerms,
+};
+use proto::net::messages::NetAddress;
+
+use crate::control_stats::ControlStats;
+use crate::ephemeral::Ephemeral;
+use crate::friend::{ChannelInconsistent, ChannelStatus, FriendMutation};
+use crate::state::{FunderMutation, FunderState};
+use crate::types::FunderIncoming;
+
+async fn task_handler_reset_balance(mut identity_client: IdentityClient) {
+    let local_pk = await!(identity_client.request_public_key()).unwrap();
+
+    let mut state = FunderState::<NetAddress>::new(local_pk, Vec::new());
+    let mut ephemeral = Ephemeral::new();
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+    let control_stats = ControlStats::new();
+
+    // Add an inconsistent friend, whose reset terms can not be mirrored on our side:
+    let friend_pk = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+    state.mutate(&FunderMutation::AddFriend(AddFriend {
+        friend_public_key: friend_pk.clone(),
+        relays: Vec::new(),
+        name: "friend".into(),
+        balance: 0i128,
+        opt_remote_max_debt: None,
+    }));
+    let reset_token = Signature::from(&[0x11; SIGNATURE_LEN]);
+    let channel_inconsistent = ChannelInconsistent {
+        opt_last_incoming_move_token: None,
+        local_reset_terms: ResetTerms {
+            reset_token: Signature::from(&[0x22; SIGNATURE_LEN]),
+            inconsistency_counter: 1,
+            balance_for_reset: 0,
+        },
+        opt_remote_reset_terms: Some(ResetTerms {
+            reset_token: reset_token.clone(),
+            inconsistency_counter: 1,
+            balance_for_reset: i128::min_value(),
+        }),
+    };
+    state.mutate(&FunderMutation::FriendMutation((
+        friend_pk.clone(),
+        FriendMutation::SetInconsistent(channel_inconsistent),
+    )));
+
+    await!(Box::pin(apply_funder_incoming_with_stats(
+        FunderIncoming::Init,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client,
+        0,
+        &control_stats
+    )))
+    .unwrap();
+
+    let reset_friend_channel = ResetFriendChannel {
+        friend_public_key: friend_pk.clone(),
+        reset_token,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[0; UID_LEN]),
+        FunderControl::ResetFriendChannel(reset_friend_channel),
+    );
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming_with_stats(
+        FunderIncoming::Control(incoming_control_message),
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client,
+        0,
+        &control_stats
+    )))
+    .unwrap();
+
+    // The reset was rejected, and the channel is still inconsistent:
+    assert_eq!(control_stats.get("InvalidResetBalance"), 1);
+    assert!(outgoing_comms.is_empty());
+    let friend = state.friends.get(&friend_pk).unwrap();
+    match &friend.channel_status {
+        ChannelStatus::Inconsistent(_) => {}
+        ChannelStatus::Consistent(_) => unreachable!(),
+    };
+}
+
+#[test]
+fn test_handler_reset_balance() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let identity_client = spawn_identity_client(&mut thread_pool, 1);
+    thread_pool.run(task_handler_reset_balance(identity_client));
+}
@@ -0,0 +1,133 @@
+use super::utils::{apply_funder_incoming, init_node, spawn_identity_client};
+
+use std::cmp::Ordering;
+
+use futures::executor::ThreadPool;
+
+use identity::IdentityClient;
+
+use crypto::crypto_rand::RngContainer;
+use crypto::identity::{compare_public_key, PublicKey, Signature, PUBLIC_KEY_LEN, SIGNATURE_LEN};
+use crypto::test_utils::DummyRandom;
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::funder::messages::{
+    AddFriend, FriendMessage, FriendStatus, FunderControl, FunderIncomingControl, ResetTerms,
+    SetFriendStatus,
+};
+
+use crate::friend::{ChannelStatus, FriendMutation, ResetStatus};
+use crate::state::FunderMutation;
+use crate::types::{FunderIncoming, FunderIncomingComm, IncomingLivenessMessage};
+
+use crate::tests::utils::dummy_relay_address;
+
+async fn task_handler_reset_status_cleared(mut identity_client: IdentityClient) {
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+    let (mut state, mut ephemeral) = await!(init_node::<u32, _>(
+        Vec::new(),
+        &mut rng,
+        &mut identity_client
+    ));
+    let local_pk = state.local_public_key.clone();
+
+    // We pick a friend for which we are the first sender. The token is then held by the friend,
+    // and the friend may send us an InconsistencyError message:
+    let friend_pk = (0..=255u8)
+        .map(|i| PublicKey::from(&[i; PUBLIC_KEY_LEN]))
+        .find(|friend_pk| compare_public_key(&local_pk, friend_pk) == Ordering::Less)
+        .unwrap();
+
+    let add_friend = AddFriend {
+        friend_public_key: friend_pk.clone(),
+        relays: vec![dummy_relay_address(1)],
+        name: "friend".into(),
+        balance: 0i128,
+        opt_remote_max_debt: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[11; UID_LEN]),
+        FunderControl::AddFriend(add_friend),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    let set_friend_status = SetFriendStatus {
+        friend_public_key: friend_pk.clone(),
+        status: FriendStatus::Enabled,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[12; UID_LEN]),
+        FunderControl::SetFriendStatus(set_friend_status),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    let incoming_liveness_message = IncomingLivenessMessage::Online(friend_pk.clone());
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    // A previous reset of the channel was confirmed:
+    let friend_mutation = FriendMutation::SetResetStatus(ResetStatus::Confirmed);
+    state.mutate(&FunderMutation::FriendMutation((
+        friend_pk.clone(),
+        friend_mutation,
+    )));
+
+    // The friend reports an inconsistency:
+    let remote_reset_terms = ResetTerms {
+        reset_token: Signature::from(&[0xaa; SIGNATURE_LEN]),
+        inconsistency_counter: 1,
+        balance_for_reset: 0,
+    };
+    let friend_message = FriendMessage::InconsistencyError(remote_reset_terms);
+    let funder_incoming = FunderIncoming::Comm(FunderIncomingComm::Friend((
+        friend_pk.clone(),
+        friend_message,
+    )));
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state,
+        &mut ephemeral,
+        &mut rng,
+        &mut identity_client
+    )))
+    .unwrap();
+
+    // The confirmed reset does not describe the inconsistent channel:
+    let friend = state.friends.get(&friend_pk).unwrap();
+    match &friend.channel_status {
+        ChannelStatus::Consistent(_) => unreachable!(),
+        ChannelStatus::Inconsistent(_) => {}
+    };
+    assert_eq!(friend.reset_status, ResetStatus::NoReset);
+}
+
+#[test]
+fn test_handler_reset_status_cleared() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let identity_client = spawn_identity_client(&mut thread_pool, 1);
+    thread_pool.run(task_handler_reset_status_cleared(identity_client));
+}
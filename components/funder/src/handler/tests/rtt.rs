@@ -0,0 +1,352 @@
+use super::utils::{apply_funder_incoming_at_tick, init_node, spawn_identity_client};
+
+use std::cmp::Ordering;
+
+use futures::executor::ThreadPool;
+
+use identity::IdentityClient;
+
+use crypto::crypto_rand::{CryptoRandom, RngContainer};
+use crypto::identity::compare_public_key;
+use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
+use crypto::test_utils::DummyRandom;
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::funder::messages::{
+    AddFriend, FriendMessage, FriendStatus, FriendTcOp, FriendsRoute, FunderControl,
+    FunderIncomingControl, RequestsStatus, SetFriendStatus, SetRequestsStatus,
+    UserRequestSendFunds,
+};
+
+use crate::ephemeral::Ephemeral;
+use crate::report::create_report;
+use crate::state::FunderState;
+use crate::types::{
+    FunderIncoming, FunderIncomingComm, FunderOutgoingComm, IncomingLivenessMessage,
+};
+
+use crate::tests::utils::{dummy_named_relay_address, dummy_relay_address};
+
+/// Find the first outgoing MoveTokenRequest message
+fn find_move_token_request(
+    outgoing_comms: &[FunderOutgoingComm<u32>],
+) -> Option<FriendMessage<u32>> {
+    outgoing_comms
+        .iter()
+        .filter_map(|outgoing_comm| match outgoing_comm {
+            FunderOutgoingComm::FriendMessage((_pk, friend_message)) => match friend_message {
+                FriendMessage::MoveTokenRequest(_) => Some(friend_message.clone()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .next()
+}
+
+/// Deliver a friend message to one of the nodes, and keep passing the messages sent in response
+/// between the two nodes, until no more messages are sent.
+async fn exchange_friend_messages<'a, R>(
+    state1: &'a mut FunderState<u32>,
+    ephemeral1: &'a mut Ephemeral,
+    identity_client1: &'a mut IdentityClient,
+    state2: &'a mut FunderState<u32>,
+    ephemeral2: &'a mut Ephemeral,
+    identity_client2: &'a mut IdentityClient,
+    rng: &'a mut R,
+    friend_message: FriendMessage<u32>,
+    mut to_node1: bool,
+) where
+    R: CryptoRandom + 'a,
+{
+    let pk1 = state1.local_public_key.clone();
+    let pk2 = state2.local_public_key.clone();
+
+    let mut opt_friend_message = Some(friend_message);
+    while let Some(friend_message) = opt_friend_message.take() {
+        let (outgoing_comms, _outgoing_control) = if to_node1 {
+            let funder_incoming =
+                FunderIncoming::Comm(FunderIncomingComm::Friend((pk2.clone(), friend_message)));
+            await!(Box::pin(apply_funder_incoming_at_tick(
+                funder_incoming,
+                &mut *state1,
+                &mut *ephemeral1,
+                &mut *rng,
+                &mut *identity_client1,
+                0
+            )))
+            .unwrap()
+        } else {
+            let funder_incoming =
+                FunderIncoming::Comm(FunderIncomingComm::Friend((pk1.clone(), friend_message)));
+            await!(Box::pin(apply_funder_incoming_at_tick(
+                funder_incoming,
+                &mut *state2,
+                &mut *ephemeral2,
+                &mut *rng,
+                &mut *identity_client2,
+                0
+            )))
+            .unwrap()
+        };
+        opt_friend_message = find_move_token_request(&outgoing_comms);
+        to_node1 = !to_node1;
+    }
+}
+
+async fn task_handler_rtt<'a>(
+    identity_client1: &'a mut IdentityClient,
+    identity_client2: &'a mut IdentityClient,
+) {
+    // Sort the identities. identity_client1 will be the first sender:
+    let pk1 = await!(identity_client1.request_public_key()).unwrap();
+    let pk2 = await!(identity_client2.request_public_key()).unwrap();
+    let (identity_client1, pk1, identity_client2, pk2) =
+        if compare_public_key(&pk1, &pk2) == Ordering::Less {
+            (identity_client1, pk1, identity_client2, pk2)
+        } else {
+            (identity_client2, pk2, identity_client1, pk1)
+        };
+
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+
+    // Initialize both nodes:
+    let relays1 = vec![dummy_named_relay_address(1)];
+    let (mut state1, mut ephemeral1) =
+        await!(init_node::<u32, _>(relays1, &mut rng, identity_client1));
+    let relays2 = vec![dummy_named_relay_address(2)];
+    let (mut state2, mut ephemeral2) =
+        await!(init_node::<u32, _>(relays2, &mut rng, identity_client2));
+
+    // Add and enable friends. Node1 lets Node2 owe it up to 100 credits:
+    let nodes = vec![
+        (
+            &mut state1,
+            &mut ephemeral1,
+            &mut *identity_client1,
+            pk2.clone(),
+            2u8,
+            Some(100),
+        ),
+        (
+            &mut state2,
+            &mut ephemeral2,
+            &mut *identity_client2,
+            pk1.clone(),
+            1u8,
+            None,
+        ),
+    ];
+    for (state, ephemeral, identity_client, friend_pk, friend_index, opt_remote_max_debt) in nodes {
+        let add_friend = AddFriend {
+            friend_public_key: friend_pk.clone(),
+            relays: vec![dummy_relay_address(friend_index)],
+            name: format!("pk{}", friend_index),
+            balance: 0i128,
+            opt_remote_max_debt,
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[11; UID_LEN]),
+            FunderControl::AddFriend(add_friend),
+        );
+        await!(Box::pin(apply_funder_incoming_at_tick(
+            FunderIncoming::Control(incoming_control_message),
+            state,
+            ephemeral,
+            &mut rng,
+            identity_client,
+            0
+        )))
+        .unwrap();
+
+        let set_friend_status = SetFriendStatus {
+            friend_public_key: friend_pk.clone(),
+            status: FriendStatus::Enabled,
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[12; UID_LEN]),
+            FunderControl::SetFriendStatus(set_friend_status),
+        );
+        await!(Box::pin(apply_funder_incoming_at_tick(
+            FunderIncoming::Control(incoming_control_message),
+            state,
+            ephemeral,
+            &mut rng,
+            identity_client,
+            0
+        )))
+        .unwrap();
+    }
+
+    // Node1: Notify that Node2 is alive. Node1 resends its initial move token, which Node2
+    // already has, so we don't deliver it:
+    let incoming_liveness_message = IncomingLivenessMessage::Online(pk2.clone());
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+    await!(Box::pin(apply_funder_incoming_at_tick(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1,
+        0
+    )))
+    .unwrap();
+
+    // Node2: Notify that Node1 is alive, and let the nodes exchange their configuration:
+    let incoming_liveness_message = IncomingLivenessMessage::Online(pk1.clone());
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming_at_tick(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2,
+        0
+    )))
+    .unwrap();
+    let friend_message = find_move_token_request(&outgoing_comms).unwrap();
+    await!(Box::pin(exchange_friend_messages(
+        &mut state1,
+        &mut ephemeral1,
+        identity_client1,
+        &mut state2,
+        &mut ephemeral2,
+        identity_client2,
+        &mut rng,
+        friend_message,
+        true
+    )));
+
+    // Node1 opens its requests. The last move token is sent by Node1, leaving the token with
+    // Node2:
+    let set_requests_status = SetRequestsStatus {
+        friend_public_key: pk2.clone(),
+        status: RequestsStatus::Open,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[13; UID_LEN]),
+        FunderControl::SetRequestsStatus(set_requests_status),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming_at_tick(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1,
+        0
+    )))
+    .unwrap();
+    let friend_message = find_move_token_request(&outgoing_comms).unwrap();
+    await!(Box::pin(exchange_friend_messages(
+        &mut state1,
+        &mut ephemeral1,
+        identity_client1,
+        &mut state2,
+        &mut ephemeral2,
+        identity_client2,
+        &mut rng,
+        friend_message,
+        false
+    )));
+
+    // No round trip time was measured yet:
+    let report2 = create_report(&state2, &ephemeral2);
+    assert_eq!(report2.friends.get(&pk1).unwrap().rtt.num_samples, 0);
+
+    // Node2 sends funds to Node1 at tick 10:
+    let user_request_send_funds = UserRequestSendFunds {
+        request_id: Uid::from(&[3; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: vec![pk2.clone(), pk1.clone()],
+        },
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        dest_payment: 20,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[14; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming_at_tick(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2,
+        10
+    )))
+    .unwrap();
+
+    // The request is sent right away, and is used as a probe:
+    let friend_message = find_move_token_request(&outgoing_comms).unwrap();
+    match &friend_message {
+        FriendMessage::MoveTokenRequest(move_token_request) => {
+            let operations = &move_token_request.friend_move_token.operations;
+            assert!(operations.iter().any(|operation| match operation {
+                FriendTcOp::RequestSendFunds(request_send_funds) => {
+                    request_send_funds.request_id == Uid::from(&[3; UID_LEN])
+                }
+                _ => false,
+            }));
+        }
+        _ => unreachable!(),
+    };
+    assert_eq!(
+        ephemeral2.rtt_probes.get(&pk1),
+        Some(&(Uid::from(&[3; UID_LEN]), 10))
+    );
+
+    // Node1 receives the request at tick 12, and sends back a response:
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk2.clone(), friend_message)));
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming_at_tick(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1,
+        12
+    )))
+    .unwrap();
+    let friend_message = find_move_token_request(&outgoing_comms).unwrap();
+
+    // Node2 receives the response at tick 14:
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk1.clone(), friend_message)));
+    await!(Box::pin(apply_funder_incoming_at_tick(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2,
+        14
+    )))
+    .unwrap();
+
+    // The round trip time of the request appears in the report:
+    let report2 = create_report(&state2, &ephemeral2);
+    let rtt_report = &report2.friends.get(&pk1).unwrap().rtt;
+    assert_eq!(rtt_report.last_rtt, 4);
+    assert_eq!(rtt_report.avg_rtt, 4);
+    assert_eq!(rtt_report.num_samples, 1);
+    assert!(ephemeral2.rtt_probes.get(&pk1).is_none());
+
+    // Node1 did not send any request, so it has no measurement:
+    let report1 = create_report(&state1, &ephemeral1);
+    assert_eq!(report1.friends.get(&pk2).unwrap().rtt.num_samples, 0);
+}
+
+#[test]
+fn test_handler_rtt() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let mut identity_client1 = spawn_identity_client(&mut thread_pool, 1);
+    let mut identity_client2 = spawn_identity_client(&mut thread_pool, 2);
+    thread_pool.run(task_handler_rtt(
+        &mut identity_client1,
+        &mut identity_client2,
+    ));
+}
@@ -0,0 +1,476 @@
+use super::utils::{apply_funder_incoming, init_node, spawn_identity_client};
+
+use std::cmp::Ordering;
+
+use futures::executor::ThreadPool;
+
+use identity::IdentityClient;
+
+use crypto::crypto_rand::RngContainer;
+use crypto::identity::compare_public_key;
+use crypto::test_utils::DummyRandom;
+use crypto::uid::{Uid, UID_LEN};
+
+use proto::funder::messages::{
+    AddFriend, FriendMessage, FriendStatus, FunderControl, FunderIncomingControl,
+    ResetFriendChannel, SetFriendStatus,
+};
+
+use crate::friend::{ChannelStatus, ResetStatus};
+use crate::types::{
+    FunderIncoming, FunderIncomingComm, FunderOutgoingComm, IncomingLivenessMessage,
+};
+
+use crate::tests::utils::{dummy_named_relay_address, dummy_relay_address};
+
+async fn task_handler_simultaneous_reset<'a>(
+    identity_client1: &'a mut IdentityClient,
+    identity_client2: &'a mut IdentityClient,
+) {
+    // NOTE: We use Box::pin() in order to make sure we don't get a too large Future which will
+    // cause a stack overflow.
+    // See:  https://github.com/rust-lang-nursery/futures-rs/issues/1330
+
+    // Sort the identities. identity_client1 will be the first sender:
+    let pk1 = await!(identity_client1.request_public_key()).unwrap();
+    let pk2 = await!(identity_client2.request_public_key()).unwrap();
+    let (identity_client1, pk1, identity_client2, pk2) =
+        if compare_public_key(&pk1, &pk2) == Ordering::Less {
+            (identity_client1, pk1, identity_client2, pk2)
+        } else {
+            (identity_client2, pk2, identity_client1, pk1)
+        };
+
+    let mut rng = RngContainer::new(DummyRandom::new(&[3u8]));
+
+    // Initialize both nodes:
+    let relays1 = vec![dummy_named_relay_address(1)];
+    let (mut state1, mut ephemeral1) =
+        await!(init_node::<u32, _>(relays1, &mut rng, identity_client1));
+    let relays2 = vec![dummy_named_relay_address(2)];
+    let (mut state2, mut ephemeral2) =
+        await!(init_node::<u32, _>(relays2, &mut rng, identity_client2));
+
+    // Node1: Add friend 2:
+    let add_friend = AddFriend {
+        friend_public_key: pk2.clone(),
+        relays: vec![dummy_relay_address(2)],
+        name: String::from("pk2"),
+        balance: 20i128,
+        opt_remote_max_debt: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[11; UID_LEN]),
+        FunderControl::AddFriend(add_friend),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1
+    )))
+    .unwrap();
+
+    // Node1: Enable friend 2:
+    let set_friend_status = SetFriendStatus {
+        friend_public_key: pk2.clone(),
+        status: FriendStatus::Enabled,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[12; UID_LEN]),
+        FunderControl::SetFriendStatus(set_friend_status),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1
+    )))
+    .unwrap();
+
+    // Node2: Add friend 1:
+    // Note that this friend's initial balance should have been
+    // -20i128, but we assign -10i128 to cause an inconsistency.
+    let add_friend = AddFriend {
+        friend_public_key: pk1.clone(),
+        relays: vec![dummy_relay_address(1)],
+        name: String::from("pk1"),
+        balance: -10i128,
+        opt_remote_max_debt: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[13; UID_LEN]),
+        FunderControl::AddFriend(add_friend),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2
+    )))
+    .unwrap();
+
+    // Node2: enable friend 1:
+    let set_friend_status = SetFriendStatus {
+        friend_public_key: pk1.clone(),
+        status: FriendStatus::Enabled,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[14; UID_LEN]),
+        FunderControl::SetFriendStatus(set_friend_status),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2
+    )))
+    .unwrap();
+
+    // Node1: Notify that Node2 is alive
+    // We expect that Node1 will resend his outgoing message when he is notified that Node1 is online.
+    let incoming_liveness_message = IncomingLivenessMessage::Online(pk2.clone());
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1
+    )))
+    .unwrap();
+
+    assert_eq!(outgoing_comms.len(), 1);
+    let friend_message = match &outgoing_comms[0] {
+        FunderOutgoingComm::FriendMessage((pk, friend_message)) => {
+            if let FriendMessage::MoveTokenRequest(move_token_request) = friend_message {
+                assert_eq!(pk, &pk2);
+                // Token is wanted because Node1 wants to send his configured address later.
+                assert_eq!(move_token_request.token_wanted, true);
+
+                let friend_move_token = &move_token_request.friend_move_token;
+                assert_eq!(friend_move_token.move_token_counter, 0);
+                assert_eq!(friend_move_token.inconsistency_counter, 0);
+                assert_eq!(friend_move_token.balance, 20i128);
+                assert!(friend_move_token.opt_local_relays.is_none());
+            } else {
+                unreachable!();
+            }
+            friend_message.clone()
+        }
+        _ => unreachable!(),
+    };
+
+    // Node2: Notify that Node1 is alive
+    let incoming_liveness_message = IncomingLivenessMessage::Online(pk1.clone());
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+    // TODO: Check outgoing_comms here:
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2
+    )))
+    .unwrap();
+
+    // Node2 sends information about his address to Node1:
+    assert_eq!(outgoing_comms.len(), 2);
+
+    // Node2: Receive MoveToken from Node1:
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk1.clone(), friend_message)));
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2
+    )))
+    .unwrap();
+
+    // Node2 should retransmit his outgoing message:
+    // Note that Node2 haven't yet detected the inconsistency.
+    assert_eq!(outgoing_comms.len(), 1);
+
+    let friend_message = match &outgoing_comms[0] {
+        FunderOutgoingComm::FriendMessage((pk, friend_message)) => {
+            if let FriendMessage::MoveTokenRequest(move_token_request) = friend_message {
+                assert_eq!(pk, &pk1);
+                assert_eq!(move_token_request.token_wanted, true);
+
+                let friend_move_token = &move_token_request.friend_move_token;
+                assert_eq!(friend_move_token.move_token_counter, 1);
+                assert_eq!(friend_move_token.inconsistency_counter, 0);
+                assert_eq!(friend_move_token.balance, -10i128);
+                assert!(friend_move_token.opt_local_relays.is_some());
+            } else {
+                unreachable!();
+            }
+            friend_message.clone()
+        }
+        _ => unreachable!(),
+    };
+
+    // Node1: Receive MoveToken from Node2 with invalid balance.
+    // At this point Node1 should detect inconsistency
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk2.clone(), friend_message)));
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1
+    )))
+    .unwrap();
+
+    // Node1 should send an inconsistency error:
+    assert_eq!(outgoing_comms.len(), 1);
+
+    let (friend_message, reset_token1) = match &outgoing_comms[0] {
+        FunderOutgoingComm::FriendMessage((pk, friend_message)) => {
+            if let FriendMessage::InconsistencyError(reset_terms) = friend_message {
+                assert_eq!(reset_terms.inconsistency_counter, 1);
+                assert_eq!(reset_terms.balance_for_reset, 20i128);
+                assert_eq!(pk, &pk2);
+                (friend_message.clone(), reset_terms.reset_token.clone())
+            } else {
+                unreachable!();
+            }
+        }
+        _ => unreachable!(),
+    };
+
+    // Node2: Receive InconsistencyError from Node1:
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk1.clone(), friend_message)));
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2
+    )))
+    .unwrap();
+
+    // Node2 should send his reset terms:
+    assert_eq!(outgoing_comms.len(), 1);
+
+    let (friend_message, reset_token2) = match &outgoing_comms[0] {
+        FunderOutgoingComm::FriendMessage((pk, friend_message)) => {
+            if let FriendMessage::InconsistencyError(reset_terms) = friend_message {
+                assert_eq!(reset_terms.inconsistency_counter, 1);
+                assert_eq!(reset_terms.balance_for_reset, -10i128);
+                assert_eq!(pk, &pk1);
+                (friend_message.clone(), reset_terms.reset_token.clone())
+            } else {
+                unreachable!();
+            }
+        }
+        _ => unreachable!(),
+    };
+
+    // Node1: Receive InconsistencyError from Node2:
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk2.clone(), friend_message)));
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1
+    )))
+    .unwrap();
+
+    assert!(outgoing_comms.is_empty());
+
+    // Simultaneous reset
+    // ------------------
+
+    // Node1: Reset channel, agreeing to Node2's conditions:
+    let reset_friend_channel = ResetFriendChannel {
+        friend_public_key: pk2.clone(),
+        reset_token: reset_token2.clone(),
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[15; UID_LEN]),
+        FunderControl::ResetFriendChannel(reset_friend_channel),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1
+    )))
+    .unwrap();
+
+    assert_eq!(outgoing_comms.len(), 1);
+    let reset_message1 = match &outgoing_comms[0] {
+        FunderOutgoingComm::FriendMessage((pk, friend_message)) => {
+            assert_eq!(pk, &pk2);
+            if let FriendMessage::MoveTokenRequest(move_token_request) = friend_message {
+                assert_eq!(move_token_request.friend_move_token.old_token, reset_token2);
+            } else {
+                unreachable!();
+            }
+            friend_message.clone()
+        }
+        _ => unreachable!(),
+    };
+
+    // Node2: Reset channel at the same time, agreeing to Node1's conditions:
+    let reset_friend_channel = ResetFriendChannel {
+        friend_public_key: pk1.clone(),
+        reset_token: reset_token1.clone(),
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[16; UID_LEN]),
+        FunderControl::ResetFriendChannel(reset_friend_channel),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2
+    )))
+    .unwrap();
+
+    assert_eq!(outgoing_comms.len(), 1);
+    let reset_message2 = match &outgoing_comms[0] {
+        FunderOutgoingComm::FriendMessage((pk, friend_message)) => {
+            assert_eq!(pk, &pk1);
+            if let FriendMessage::MoveTokenRequest(move_token_request) = friend_message {
+                assert_eq!(move_token_request.friend_move_token.old_token, reset_token1);
+            } else {
+                unreachable!();
+            }
+            friend_message.clone()
+        }
+        _ => unreachable!(),
+    };
+
+    // Node1: Receive the reset MoveToken of Node2.
+    // Node1 is the first sender, so it keeps its own reset:
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk2.clone(), reset_message2)));
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1
+    )))
+    .unwrap();
+    assert!(outgoing_comms.is_empty());
+
+    let friend2 = state1.friends.get(&pk2).unwrap();
+    match &friend2.channel_status {
+        ChannelStatus::Consistent(token_channel) => {
+            assert_eq!(
+                token_channel.get_mutual_credit().state().balance.balance,
+                10i128
+            );
+        }
+        _ => unreachable!(),
+    };
+
+    // Node2: Receive the reset MoveToken of Node1.
+    // Node2 is the second sender, so it accepts the reset of Node1:
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk1.clone(), reset_message1)));
+    let (outgoing_comms, _outgoing_control) = await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        identity_client2
+    )))
+    .unwrap();
+
+    let friend1 = state2.friends.get(&pk1).unwrap();
+    match &friend1.channel_status {
+        ChannelStatus::Consistent(token_channel) => {
+            assert_eq!(
+                token_channel.get_mutual_credit().state().balance.balance,
+                -10i128
+            );
+        }
+        _ => unreachable!(),
+    };
+    assert_eq!(friend1.reset_status, ResetStatus::Confirmed);
+
+    // Node2 acknowledges the reset of Node1, and sends a MoveToken on top of the reset MoveToken
+    // of Node1:
+    assert_eq!(outgoing_comms.len(), 2);
+    match &outgoing_comms[0] {
+        FunderOutgoingComm::FriendMessage((pk, FriendMessage::ResetAck(_))) => {
+            assert_eq!(pk, &pk1);
+        }
+        _ => unreachable!(),
+    };
+    let friend_message = match &outgoing_comms[1] {
+        FunderOutgoingComm::FriendMessage((pk, friend_message)) => {
+            if let FriendMessage::MoveTokenRequest(move_token_request) = friend_message {
+                assert_eq!(pk, &pk1);
+                let friend_move_token = &move_token_request.friend_move_token;
+                assert_eq!(friend_move_token.move_token_counter, 1);
+                assert_eq!(friend_move_token.inconsistency_counter, 1);
+                assert_eq!(friend_move_token.balance, -10i128);
+            } else {
+                unreachable!();
+            }
+            friend_message.clone()
+        }
+        _ => unreachable!(),
+    };
+
+    // Node1: Receive MoveToken from Node2. Both sides have converged:
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk2.clone(), friend_message)));
+    await!(Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        identity_client1
+    )))
+    .unwrap();
+
+    let friend2 = state1.friends.get(&pk2).unwrap();
+    match &friend2.channel_status {
+        ChannelStatus::Consistent(token_channel) => {
+            assert_eq!(
+                token_channel.get_mutual_credit().state().balance.balance,
+                10i128
+            );
+        }
+        _ => unreachable!(),
+    };
+    assert_eq!(friend2.reset_status, ResetStatus::Confirmed);
+}
+
+#[test]
+fn test_handler_simultaneous_reset() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    let mut identity_client1 = spawn_identity_client(&mut thread_pool, 1);
+    let mut identity_client2 = spawn_identity_client(&mut thread_pool, 2);
+    thread_pool.run(task_handler_simultaneous_reset(
+        &mut identity_client1,
+        &mut identity_client2,
+    ));
+}
@@ -1,19 +1,79 @@
-use identity::IdentityClient;
 use std::fmt::Debug;
 
+use futures::task::{Spawn, SpawnExt};
+use futures::{future, FutureExt};
+
+use identity::{create_identity, IdentityClient};
+
 use common::canonical_serialize::CanonicalSerialize;
 use crypto::crypto_rand::CryptoRandom;
+use crypto::identity::{generate_pkcs8_key_pair, SoftwareEd25519Identity};
+use crypto::test_utils::DummyRandom;
 
+use proto::app_server::messages::NamedRelayAddress;
 use proto::funder::messages::FunderOutgoingControl;
+use proto::net::messages::ValidateAddress;
 
+use crate::control_stats::ControlStats;
 use crate::ephemeral::Ephemeral;
 use crate::handler::handler::{funder_handle_message, FunderHandlerError, FunderHandlerOutput};
 use crate::state::FunderState;
-use crate::types::{FunderIncoming, FunderOutgoingComm};
+use crate::types::{FunderConfig, FunderIncoming, FunderOutgoingComm};
+
+pub const TEST_MAX_SEND_FRIENDS: usize = 4;
+
+const TEST_FUNDER_CONFIG: FunderConfig = FunderConfig {
+    max_operations_in_batch: 16,
+    max_node_relays: 16,
+    max_pending_user_requests: 16,
+    max_pending_requests: 16,
+    monitor_duplicate_move_tokens: true,
+    reject_duplicate_invoice_id: false,
+    max_recent_receipts: 16,
+    max_send_friends: TEST_MAX_SEND_FRIENDS,
+};
+
+/// Create a new identity (derived from `seed`), spawn an identity server for it and return a
+/// client connected to the server.
+pub fn spawn_identity_client<S>(spawner: &mut S, seed: u8) -> IdentityClient
+where
+    S: Spawn,
+{
+    let rng = DummyRandom::new(&[seed]);
+    let pkcs8 = generate_pkcs8_key_pair(&rng);
+    let identity = SoftwareEd25519Identity::from_pkcs8(&pkcs8).unwrap();
+    let (requests_sender, identity_server) = create_identity(identity);
+    spawner
+        .spawn(identity_server.then(|_| future::ready(())))
+        .unwrap();
+    IdentityClient::new(requests_sender)
+}
+
+/// Create a FunderState and an Ephemeral for the node of `identity_client`, and initialize them.
+pub async fn init_node<'a, B, R>(
+    relays: Vec<NamedRelayAddress<B>>,
+    rng: &'a mut R,
+    identity_client: &'a mut IdentityClient,
+) -> (FunderState<B>, Ephemeral)
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + ValidateAddress + Debug + 'a,
+    R: CryptoRandom + 'a,
+{
+    let local_pk = await!(identity_client.request_public_key()).unwrap();
+    let mut state = FunderState::<B>::new(local_pk, relays);
+    let mut ephemeral = Ephemeral::new();
 
-const TEST_MAX_NODE_RELAYS: usize = 16;
-const TEST_MAX_OPERATIONS_IN_BATCH: usize = 16;
-const TEST_MAX_PENDING_USER_REQUESTS: usize = 16;
+    await!(Box::pin(apply_funder_incoming(
+        FunderIncoming::Init,
+        &mut state,
+        &mut ephemeral,
+        rng,
+        identity_client
+    )))
+    .unwrap();
+
+    (state, ephemeral)
+}
 
 /// A helper function. Applies an incoming funder message, updating state and ephemeral
 /// accordingly:
@@ -25,7 +85,57 @@ pub async fn apply_funder_incoming<'a, B, R>(
     identity_client: &'a mut IdentityClient,
 ) -> Result<(Vec<FunderOutgoingComm<B>>, Vec<FunderOutgoingControl<B>>), FunderHandlerError>
 where
-    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug + 'a,
+    B: Clone + PartialEq + Eq + CanonicalSerialize + ValidateAddress + Debug + 'a,
+    R: CryptoRandom + 'a,
+{
+    await!(apply_funder_incoming_at_tick(
+        funder_incoming,
+        state,
+        ephemeral,
+        rng,
+        identity_client,
+        0
+    ))
+}
+
+/// Same as apply_funder_incoming(), but allows to specify the current timer tick.
+pub async fn apply_funder_incoming_at_tick<'a, B, R>(
+    funder_incoming: FunderIncoming<B>,
+    state: &'a mut FunderState<B>,
+    ephemeral: &'a mut Ephemeral,
+    rng: &'a mut R,
+    identity_client: &'a mut IdentityClient,
+    current_tick: u64,
+) -> Result<(Vec<FunderOutgoingComm<B>>, Vec<FunderOutgoingControl<B>>), FunderHandlerError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + ValidateAddress + Debug + 'a,
+    R: CryptoRandom + 'a,
+{
+    let control_stats = ControlStats::new();
+    await!(apply_funder_incoming_with_stats(
+        funder_incoming,
+        state,
+        ephemeral,
+        rng,
+        identity_client,
+        current_tick,
+        &control_stats
+    ))
+}
+
+/// Same as apply_funder_incoming_at_tick(), but counts rejected control messages into
+/// `control_stats`.
+pub async fn apply_funder_incoming_with_stats<'a, B, R>(
+    funder_incoming: FunderIncoming<B>,
+    state: &'a mut FunderState<B>,
+    ephemeral: &'a mut Ephemeral,
+    rng: &'a mut R,
+    identity_client: &'a mut IdentityClient,
+    current_tick: u64,
+    control_stats: &'a ControlStats,
+) -> Result<(Vec<FunderOutgoingComm<B>>, Vec<FunderOutgoingControl<B>>), FunderHandlerError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + ValidateAddress + Debug + 'a,
     R: CryptoRandom + 'a,
 {
     let funder_handler_output = await!(funder_handle_message(
@@ -33,9 +143,10 @@ where
         rng,
         state.clone(),
         ephemeral.clone(),
-        TEST_MAX_NODE_RELAYS,
-        TEST_MAX_OPERATIONS_IN_BATCH,
-        TEST_MAX_PENDING_USER_REQUESTS,
+        &TEST_FUNDER_CONFIG,
+        current_tick,
+        control_stats,
+        None,
         funder_incoming
     ))?;
 
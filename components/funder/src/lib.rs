@@ -18,6 +18,7 @@ extern crate log;
 #[macro_use]
 extern crate serde_derive;
 
+mod control_stats;
 mod credit_calc;
 mod ephemeral;
 mod friend;
@@ -25,6 +26,7 @@ mod funder;
 mod handler;
 mod liveness;
 mod mutual_credit;
+mod replica;
 pub mod report;
 mod state;
 #[cfg(test)]
@@ -32,5 +34,8 @@ mod tests;
 mod token_channel;
 pub mod types;
 
+pub use self::control_stats::ControlStats;
 pub use self::funder::{funder_loop, FunderError};
+pub use self::mutual_credit::op_timings::{OpTiming, OpTimings};
+pub use self::replica::{replicate_db_requests, FunderReplica, ReplicaError};
 pub use self::state::{FunderMutation, FunderState};
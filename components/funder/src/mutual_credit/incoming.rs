@@ -1,8 +1,11 @@
+use std::time::Instant;
+
 use crypto::identity::verify_signature;
 
 use common::int_convert::usize_to_u32;
 use common::safe_arithmetic::SafeSignedArithmetic;
 
+use proto::consts::MAX_MEMO_LEN;
 use proto::funder::messages::{
     FailureSendFunds, FriendTcOp, PendingRequest, RequestSendFunds, RequestsStatus,
     ResponseSendFunds,
@@ -13,6 +16,7 @@ use crate::types::create_pending_request;
 
 use crate::credit_calc::CreditCalculator;
 
+use super::op_timings::{friend_tc_op_name, OpTimings};
 use super::types::{McMutation, MutualCredit, MAX_FUNDER_DEBT};
 
 /*
@@ -65,6 +69,10 @@ pub enum ProcessOperationError {
     InvalidReportingNode,
     InvalidFailureSignature,
     LocalRequestsClosed,
+    /// The memo attached to a request is longer than MAX_MEMO_LEN.
+    MemoTooLong,
+    /// Received an acknowledgement for a remote_max_debt we did not propose.
+    MaxDebtProposalMismatch,
 }
 
 #[derive(Debug)]
@@ -73,9 +81,12 @@ pub struct ProcessTransListError {
     process_trans_error: ProcessOperationError,
 }
 
+/// Process a list of incoming operations.
+/// If `opt_op_timings` is provided, the processing time of every operation is recorded into it.
 pub fn process_operations_list(
     mutual_credit: &mut MutualCredit,
     operations: Vec<FriendTcOp>,
+    opt_op_timings: Option<&OpTimings>,
 ) -> Result<Vec<ProcessOperationOutput>, ProcessTransListError> {
     let mut outputs = Vec::new();
 
@@ -85,7 +96,17 @@ pub fn process_operations_list(
     // (specifically, HashMaps).
 
     for (index, funds) in operations.into_iter().enumerate() {
-        match process_operation(mutual_credit, funds) {
+        let res = match opt_op_timings {
+            None => process_operation(mutual_credit, funds),
+            Some(op_timings) => {
+                let op_name = friend_tc_op_name(&funds);
+                let start = Instant::now();
+                let res = process_operation(mutual_credit, funds);
+                op_timings.record(op_name, start.elapsed());
+                res
+            }
+        };
+        match res {
             Err(e) => {
                 return Err(ProcessTransListError {
                     index,
@@ -117,6 +138,12 @@ pub fn process_operation(
         FriendTcOp::FailureSendFunds(failure_send_funds) => {
             process_failure_send_funds(mutual_credit, failure_send_funds)
         }
+        FriendTcOp::ProposeRemoteMaxDebt(proposed_max_debt) => {
+            process_propose_remote_max_debt(mutual_credit, proposed_max_debt)
+        }
+        FriendTcOp::AckRemoteMaxDebt(acked_max_debt) => {
+            process_ack_remote_max_debt(mutual_credit, acked_max_debt)
+        }
     }
 }
 
@@ -170,10 +197,70 @@ fn process_set_remote_max_debt(
         let tc_mutation = McMutation::SetLocalMaxDebt(proposed_max_debt);
         mutual_credit.mutate(&tc_mutation);
         op_output.mc_mutations.push(tc_mutation);
+
+        // The new max debt replaces any proposal the remote side has sent earlier.
+        // We may not acknowledge the replaced proposal anymore:
+        if mutual_credit
+            .state()
+            .balance
+            .opt_pending_local_max_debt
+            .is_some()
+        {
+            let tc_mutation = McMutation::SetPendingLocalMaxDebt(None);
+            mutual_credit.mutate(&tc_mutation);
+            op_output.mc_mutations.push(tc_mutation);
+        }
         Ok(op_output)
     }
 }
 
+/// Process an incoming max debt proposal. The new local_max_debt only applies after we send
+/// back an acknowledgement.
+fn process_propose_remote_max_debt(
+    mutual_credit: &mut MutualCredit,
+    proposed_max_debt: u128,
+) -> Result<ProcessOperationOutput, ProcessOperationError> {
+    let mut op_output = ProcessOperationOutput {
+        incoming_message: None,
+        mc_mutations: Vec::new(),
+    };
+
+    if proposed_max_debt > MAX_FUNDER_DEBT {
+        return Err(ProcessOperationError::RemoteMaxDebtTooLarge(
+            proposed_max_debt,
+        ));
+    }
+
+    let tc_mutation = McMutation::SetPendingLocalMaxDebt(Some(proposed_max_debt));
+    mutual_credit.mutate(&tc_mutation);
+    op_output.mc_mutations.push(tc_mutation);
+    Ok(op_output)
+}
+
+/// Process an acknowledgement for a max debt we have previously proposed.
+fn process_ack_remote_max_debt(
+    mutual_credit: &mut MutualCredit,
+    acked_max_debt: u128,
+) -> Result<ProcessOperationOutput, ProcessOperationError> {
+    let mut op_output = ProcessOperationOutput {
+        incoming_message: None,
+        mc_mutations: Vec::new(),
+    };
+
+    if mutual_credit.state().balance.opt_pending_remote_max_debt != Some(acked_max_debt) {
+        return Err(ProcessOperationError::MaxDebtProposalMismatch);
+    }
+
+    let tc_mutation = McMutation::SetRemoteMaxDebt(acked_max_debt);
+    mutual_credit.mutate(&tc_mutation);
+    op_output.mc_mutations.push(tc_mutation);
+
+    let tc_mutation = McMutation::SetPendingRemoteMaxDebt(None);
+    mutual_credit.mutate(&tc_mutation);
+    op_output.mc_mutations.push(tc_mutation);
+    Ok(op_output)
+}
+
 /// Process an incoming RequestSendFunds
 fn process_request_send_funds(
     mutual_credit: &mut MutualCredit,
@@ -183,6 +270,10 @@ fn process_request_send_funds(
         return Err(ProcessOperationError::InvalidRoute);
     }
 
+    if request_send_funds.memo.len() > MAX_MEMO_LEN {
+        return Err(ProcessOperationError::MemoTooLong);
+    }
+
     // Find ourselves on the route. If we are not there, abort.
     let remote_index = request_send_funds
         .route
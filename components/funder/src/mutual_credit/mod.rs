@@ -1,6 +1,7 @@
 #![warn(unused)]
 
 pub mod incoming;
+pub mod op_timings;
 pub mod outgoing;
 #[cfg(test)]
 mod tests;
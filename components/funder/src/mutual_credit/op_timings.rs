@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use proto::funder::messages::FriendTcOp;
+
+/// Accumulated processing time of a single kind of operation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpTiming {
+    /// Amount of processed operations
+    pub count: u64,
+    /// Total time spent processing the operations
+    pub total: Duration,
+}
+
+/// Processing times of incoming operations, keyed by the name of the operation.
+/// Cloning an `OpTimings` returns a handle to the same timings, allowing the timings to be read
+/// while operations are being processed.
+#[derive(Debug, Clone, Default)]
+pub struct OpTimings {
+    timings: Arc<Mutex<HashMap<&'static str, OpTiming>>>,
+}
+
+/// Get the name used for recording the processing time of an operation.
+pub fn friend_tc_op_name(friend_tc_op: &FriendTcOp) -> &'static str {
+    match friend_tc_op {
+        FriendTcOp::EnableRequests => "EnableRequests",
+        FriendTcOp::DisableRequests => "DisableRequests",
+        FriendTcOp::SetRemoteMaxDebt(_) => "SetRemoteMaxDebt",
+        FriendTcOp::RequestSendFunds(_) => "RequestSendFunds",
+        FriendTcOp::ResponseSendFunds(_) => "ResponseSendFunds",
+        FriendTcOp::FailureSendFunds(_) => "FailureSendFunds",
+        FriendTcOp::ProposeRemoteMaxDebt(_) => "ProposeRemoteMaxDebt",
+        FriendTcOp::AckRemoteMaxDebt(_) => "AckRemoteMaxDebt",
+    }
+}
+
+impl OpTimings {
+    pub fn new() -> Self {
+        OpTimings {
+            timings: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub(crate) fn record(&self, op_name: &'static str, elapsed: Duration) {
+        let mut timings = self.timings.lock().unwrap();
+        let op_timing = timings.entry(op_name).or_insert_with(OpTiming::default);
+        op_timing.count = op_timing.count.saturating_add(1);
+        op_timing.total += elapsed;
+    }
+
+    /// Get the accumulated processing time of the operation `op_name`.
+    pub fn get(&self, op_name: &str) -> OpTiming {
+        self.timings
+            .lock()
+            .unwrap()
+            .get(op_name)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Get a copy of all the timings.
+    pub fn snapshot(&self) -> HashMap<&'static str, OpTiming> {
+        self.timings.lock().unwrap().clone()
+    }
+}
@@ -35,6 +35,7 @@ pub enum QueueOperationError {
     InvalidFailureSignature,
     FailureSentFromDest,
     RemoteRequestsClosed,
+    MaxDebtProposalMismatch,
 }
 
 /// A wrapper over a token channel, accumulating funds to be sent as one transaction.
@@ -65,6 +66,12 @@ impl OutgoingMc {
             FriendTcOp::FailureSendFunds(failure_send_funds) => {
                 self.queue_failure_send_funds(failure_send_funds)
             }
+            FriendTcOp::ProposeRemoteMaxDebt(proposed_max_debt) => {
+                self.queue_propose_remote_max_debt(proposed_max_debt)
+            }
+            FriendTcOp::AckRemoteMaxDebt(acked_max_debt) => {
+                self.queue_ack_remote_max_debt(acked_max_debt)
+            }
         }
     }
 
@@ -99,6 +106,61 @@ impl OutgoingMc {
         let tc_mutation = McMutation::SetRemoteMaxDebt(proposed_max_debt);
         self.mutual_credit.mutate(&tc_mutation);
         tc_mutations.push(tc_mutation);
+
+        // The new max debt replaces any proposal we have sent earlier:
+        if self
+            .mutual_credit
+            .state()
+            .balance
+            .opt_pending_remote_max_debt
+            .is_some()
+        {
+            let tc_mutation = McMutation::SetPendingRemoteMaxDebt(None);
+            self.mutual_credit.mutate(&tc_mutation);
+            tc_mutations.push(tc_mutation);
+        }
+        Ok(tc_mutations)
+    }
+
+    fn queue_propose_remote_max_debt(
+        &mut self,
+        proposed_max_debt: u128,
+    ) -> Result<Vec<McMutation>, QueueOperationError> {
+        if proposed_max_debt > MAX_FUNDER_DEBT {
+            return Err(QueueOperationError::RemoteMaxDebtTooLarge);
+        }
+
+        // remote_max_debt is only changed after the remote side acknowledges the proposal:
+        let mut tc_mutations = Vec::new();
+        let tc_mutation = McMutation::SetPendingRemoteMaxDebt(Some(proposed_max_debt));
+        self.mutual_credit.mutate(&tc_mutation);
+        tc_mutations.push(tc_mutation);
+        Ok(tc_mutations)
+    }
+
+    fn queue_ack_remote_max_debt(
+        &mut self,
+        acked_max_debt: u128,
+    ) -> Result<Vec<McMutation>, QueueOperationError> {
+        // We can only acknowledge the last proposal received from the remote side:
+        if self
+            .mutual_credit
+            .state()
+            .balance
+            .opt_pending_local_max_debt
+            != Some(acked_max_debt)
+        {
+            return Err(QueueOperationError::MaxDebtProposalMismatch);
+        }
+
+        let mut tc_mutations = Vec::new();
+        let tc_mutation = McMutation::SetLocalMaxDebt(acked_max_debt);
+        self.mutual_credit.mutate(&tc_mutation);
+        tc_mutations.push(tc_mutation);
+
+        let tc_mutation = McMutation::SetPendingLocalMaxDebt(None);
+        self.mutual_credit.mutate(&tc_mutation);
+        tc_mutations.push(tc_mutation);
         Ok(tc_mutations)
     }
 
@@ -19,8 +19,9 @@ use crate::mutual_credit::types::MutualCredit;
 use crate::types::create_pending_request;
 
 use crate::mutual_credit::incoming::{
-    process_operation, ProcessOperationError, ProcessOperationOutput,
+    process_operation, process_operations_list, ProcessOperationError, ProcessOperationOutput,
 };
+use crate::mutual_credit::op_timings::OpTimings;
 use crate::mutual_credit::outgoing::{OutgoingMc, QueueOperationError};
 
 /// Helper function for applying an outgoing operation over a token channel.
@@ -94,6 +95,112 @@ fn test_outgoing_set_remote_max_debt() {
     assert_eq!(mutual_credit.state().balance.remote_max_debt, 20);
 }
 
+#[test]
+fn test_propose_ack_remote_max_debt() {
+    let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+    let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+    let mut mutual_credit_a = MutualCredit::new(&pk_a, &pk_b, 0);
+    let mut mutual_credit_b = MutualCredit::new(&pk_b, &pk_a, 0);
+
+    // A proposes to increase the max debt of B:
+    let propose_op = FriendTcOp::ProposeRemoteMaxDebt(50);
+    apply_outgoing(&mut mutual_credit_a, &propose_op).unwrap();
+    apply_incoming(&mut mutual_credit_b, propose_op).unwrap();
+
+    // The proposal does not take effect yet:
+    assert_eq!(mutual_credit_a.state().balance.remote_max_debt, 0);
+    assert_eq!(mutual_credit_b.state().balance.local_max_debt, 0);
+    assert_eq!(
+        mutual_credit_a.state().balance.opt_pending_remote_max_debt,
+        Some(50)
+    );
+    assert_eq!(
+        mutual_credit_b.state().balance.opt_pending_local_max_debt,
+        Some(50)
+    );
+
+    // B can not acknowledge a value that was not proposed:
+    assert!(apply_outgoing(&mut mutual_credit_b, &FriendTcOp::AckRemoteMaxDebt(60)).is_err());
+
+    // B acknowledges the proposal:
+    let ack_op = FriendTcOp::AckRemoteMaxDebt(50);
+    apply_outgoing(&mut mutual_credit_b, &ack_op).unwrap();
+    assert_eq!(mutual_credit_b.state().balance.local_max_debt, 50);
+    assert_eq!(
+        mutual_credit_b.state().balance.opt_pending_local_max_debt,
+        None
+    );
+
+    // The new max debt applies on A only after the acknowledgement arrives:
+    assert_eq!(mutual_credit_a.state().balance.remote_max_debt, 0);
+    apply_incoming(&mut mutual_credit_a, ack_op).unwrap();
+    assert_eq!(mutual_credit_a.state().balance.remote_max_debt, 50);
+    assert_eq!(
+        mutual_credit_a.state().balance.opt_pending_remote_max_debt,
+        None
+    );
+
+    // A duplicate acknowledgement is rejected:
+    assert!(apply_incoming(&mut mutual_credit_a, FriendTcOp::AckRemoteMaxDebt(50)).is_err());
+}
+
+#[test]
+fn test_set_remote_max_debt_replaces_proposal() {
+    let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+    let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+    let mut mutual_credit_a = MutualCredit::new(&pk_a, &pk_b, 0);
+    let mut mutual_credit_b = MutualCredit::new(&pk_b, &pk_a, 0);
+
+    // A proposes to increase the max debt of B, and then sets a different max debt directly:
+    for op in vec![
+        FriendTcOp::ProposeRemoteMaxDebt(50),
+        FriendTcOp::SetRemoteMaxDebt(30),
+    ] {
+        apply_outgoing(&mut mutual_credit_a, &op).unwrap();
+        apply_incoming(&mut mutual_credit_b, op).unwrap();
+    }
+
+    // The proposal was dropped on both sides:
+    assert_eq!(mutual_credit_a.state().balance.remote_max_debt, 30);
+    assert_eq!(
+        mutual_credit_a.state().balance.opt_pending_remote_max_debt,
+        None
+    );
+    assert_eq!(mutual_credit_b.state().balance.local_max_debt, 30);
+    assert_eq!(
+        mutual_credit_b.state().balance.opt_pending_local_max_debt,
+        None
+    );
+
+    // The replaced proposal can not be acknowledged:
+    assert!(apply_outgoing(&mut mutual_credit_b, &FriendTcOp::AckRemoteMaxDebt(50)).is_err());
+    assert!(apply_incoming(&mut mutual_credit_a, FriendTcOp::AckRemoteMaxDebt(50)).is_err());
+    assert_eq!(mutual_credit_a.state().balance.remote_max_debt, 30);
+}
+
+#[test]
+fn test_process_operations_list_timings() {
+    let local_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+    let remote_public_key = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+    let mut mutual_credit = MutualCredit::new(&local_public_key, &remote_public_key, 0);
+
+    let op_timings = OpTimings::new();
+    let operations = vec![
+        FriendTcOp::SetRemoteMaxDebt(100),
+        FriendTcOp::EnableRequests,
+        FriendTcOp::SetRemoteMaxDebt(50),
+    ];
+    let outputs =
+        process_operations_list(&mut mutual_credit, operations, Some(&op_timings)).unwrap();
+    assert_eq!(outputs.len(), 3);
+
+    // Every processed operation is recorded:
+    assert_eq!(op_timings.get("SetRemoteMaxDebt").count, 2);
+    assert_eq!(op_timings.get("EnableRequests").count, 1);
+    assert_eq!(op_timings.get("RequestSendFunds").count, 0);
+    assert_eq!(op_timings.snapshot().len(), 2);
+}
+
 #[test]
 fn test_request_response_send_funds() {
     let local_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
@@ -127,6 +234,8 @@ fn test_request_response_send_funds() {
         route,
         dest_payment: 10,
         invoice_id,
+        hop_budget: 1,
+        memo: Vec::new(),
     };
 
     let pending_request = create_pending_request(&request_send_funds);
@@ -202,6 +311,8 @@ fn test_request_failure_send_funds() {
         route,
         dest_payment: 10,
         invoice_id,
+        hop_budget: 1,
+        memo: Vec::new(),
     };
 
     let pending_request = create_pending_request(&request_send_funds);
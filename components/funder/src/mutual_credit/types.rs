@@ -1,5 +1,6 @@
 use im::hashmap::HashMap as ImHashMap;
 
+use common::ordered_serialize::serialize_ordered_map;
 use common::safe_arithmetic::SafeSignedArithmetic;
 use crypto::identity::PublicKey;
 use crypto::uid::Uid;
@@ -33,6 +34,10 @@ pub struct McBalance {
     pub local_pending_debt: u128,
     /// Frozen credits by the remote side
     pub remote_pending_debt: u128,
+    /// A remote_max_debt we proposed, waiting for the remote side to acknowledge
+    pub opt_pending_remote_max_debt: Option<u128>,
+    /// A local_max_debt proposed by the remote side, waiting for us to acknowledge
+    pub opt_pending_local_max_debt: Option<u128>,
 }
 
 impl McBalance {
@@ -46,6 +51,8 @@ impl McBalance {
             remote_max_debt: 0,
             local_pending_debt: 0,
             remote_pending_debt: 0,
+            opt_pending_remote_max_debt: None,
+            opt_pending_local_max_debt: None,
         }
     }
 }
@@ -55,8 +62,10 @@ impl McBalance {
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct McPendingRequests {
     /// Pending requests that were opened locally and not yet completed
+    #[serde(serialize_with = "serialize_ordered_map")]
     pub pending_local_requests: ImHashMap<Uid, PendingRequest>,
     /// Pending requests that were opened remotely and not yet completed
+    #[serde(serialize_with = "serialize_ordered_map")]
     pub pending_remote_requests: ImHashMap<Uid, PendingRequest>,
 }
 
@@ -112,6 +121,8 @@ pub enum McMutation {
     RemoveRemotePendingRequest(Uid),
     SetLocalPendingDebt(u128),
     SetRemotePendingDebt(u128),
+    SetPendingRemoteMaxDebt(Option<u128>),
+    SetPendingLocalMaxDebt(Option<u128>),
 }
 
 impl MutualCredit {
@@ -184,6 +195,12 @@ impl MutualCredit {
             McMutation::SetRemotePendingDebt(remote_pending_debt) => {
                 self.set_remote_pending_debt(*remote_pending_debt)
             }
+            McMutation::SetPendingRemoteMaxDebt(opt_max_debt) => {
+                self.set_pending_remote_max_debt(*opt_max_debt)
+            }
+            McMutation::SetPendingLocalMaxDebt(opt_max_debt) => {
+                self.set_pending_local_max_debt(*opt_max_debt)
+            }
         }
     }
 
@@ -244,4 +261,12 @@ impl MutualCredit {
     fn set_local_pending_debt(&mut self, local_pending_debt: u128) {
         self.state.balance.local_pending_debt = local_pending_debt;
     }
+
+    fn set_pending_remote_max_debt(&mut self, opt_max_debt: Option<u128>) {
+        self.state.balance.opt_pending_remote_max_debt = opt_max_debt;
+    }
+
+    fn set_pending_local_max_debt(&mut self, opt_max_debt: Option<u128>) {
+        self.state.balance.opt_pending_local_max_debt = opt_max_debt;
+    }
 }
@@ -0,0 +1,304 @@
+use std::fmt::Debug;
+
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+
+use common::canonical_serialize::CanonicalSerialize;
+
+use database::{DatabaseClient, DatabaseRequest};
+
+use proto::funder::messages::{FunderControl, FunderOutgoingControl};
+use proto::report::messages::FunderReport;
+
+use crate::handler::{
+    control_get_pending_requests, control_get_reset_token, control_request_remove_friend,
+    HandleControlError, MutableFunderState,
+};
+use crate::report::create_initial_report;
+use crate::state::{FunderMutation, FunderState};
+
+#[derive(Debug)]
+pub enum ReplicaError {
+    /// The control message might change the state, which is not allowed for a replica.
+    ReadOnly,
+    /// The control message is not mutating, but can not be answered by a replica, because it
+    /// depends on the ephemeral state of the primary (For example: liveness of friends).
+    Unsupported,
+    HandleControlError(HandleControlError),
+}
+
+/// A read only copy of the state of a funder.
+/// The replica is kept up to date by applying the mutations of the primary funder, as they are
+/// written to the database, and answers queries without touching the primary.
+pub struct FunderReplica<B: Clone> {
+    state: FunderState<B>,
+}
+
+impl<B> FunderReplica<B>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    pub fn new(state: FunderState<B>) -> Self {
+        FunderReplica { state }
+    }
+
+    /// Apply a batch of mutations received from the primary funder.
+    pub fn apply_mutations(&mut self, mutations: &[FunderMutation<B>]) {
+        for mutation in mutations {
+            self.state.mutate(mutation);
+        }
+    }
+
+    pub fn state(&self) -> &FunderState<B> {
+        &self.state
+    }
+
+    /// Create a report of the replicated state.
+    /// The replica has no liveness information, hence all friends are reported as offline.
+    pub fn report(&self) -> FunderReport<B> {
+        create_initial_report(&self.state)
+    }
+
+    /// Answer a query control message.
+    /// Any control message that might change the state is rejected with `ReplicaError::ReadOnly`.
+    pub fn handle_control(
+        &self,
+        funder_control: FunderControl<B>,
+    ) -> Result<Vec<FunderOutgoingControl<B>>, ReplicaError> {
+        let m_state = MutableFunderState::new(self.state.clone());
+        let mut outgoing_control = Vec::new();
+
+        let res = match funder_control {
+            FunderControl::GetPendingRequests(friend_public_key) => {
+                control_get_pending_requests(&m_state, &mut outgoing_control, friend_public_key)
+            }
+            FunderControl::GetResetToken(friend_public_key) => {
+                control_get_reset_token(&m_state, &mut outgoing_control, friend_public_key)
+            }
+            FunderControl::RequestRemoveFriend(friend_public_key) => {
+                control_request_remove_friend(&m_state, &mut outgoing_control, friend_public_key)
+            }
+            FunderControl::SimulatePayment(_)
+            | FunderControl::SuggestFirstHop(_)
+            | FunderControl::GetRecentReceipts => return Err(ReplicaError::Unsupported),
+            FunderControl::AddRelay(_)
+            | FunderControl::RemoveRelay(_)
+            | FunderControl::AddFriend(_)
+            | FunderControl::RemoveFriend(_)
+            | FunderControl::SetRequestsStatus(_)
+            | FunderControl::SetFriendStatus(_)
+            | FunderControl::ActivateFriend(_)
+            | FunderControl::SetFriendRemoteMaxDebt(_)
+            | FunderControl::ProposeFriendRemoteMaxDebt(_)
+            | FunderControl::SetFriendMinBalance(_)
+            | FunderControl::SetFriendMaxSinglePayment(_)
+            | FunderControl::SetFriendRelays(_)
+            | FunderControl::SetFriendName(_)
+            | FunderControl::ResetFriendChannel(_)
+            | FunderControl::RequestSendFunds(_)
+            | FunderControl::ReceiptAck(_)
+            | FunderControl::Rebalance(_)
+            | FunderControl::WarmFriend(_) => return Err(ReplicaError::ReadOnly),
+            #[cfg(feature = "force-inconsistency")]
+            FunderControl::ForceInconsistency(_) => return Err(ReplicaError::ReadOnly),
+        };
+        res.map_err(ReplicaError::HandleControlError)?;
+
+        Ok(outgoing_control)
+    }
+}
+
+/// Forward the database requests of a primary funder to its database.
+/// Every batch of mutations is also sent through `mutations_sender` after it was persisted,
+/// allowing a replica to follow the state of the primary funder. If the replica goes away, the
+/// requests are still forwarded to the database.
+pub async fn replicate_db_requests<B>(
+    mut incoming_requests: mpsc::Receiver<DatabaseRequest<FunderMutation<B>>>,
+    mut db_client: DatabaseClient<FunderMutation<B>>,
+    mutations_sender: mpsc::Sender<Vec<FunderMutation<B>>>,
+) where
+    B: Clone + Debug,
+{
+    let mut opt_mutations_sender = Some(mutations_sender);
+    while let Some(request) = await!(incoming_requests.next()) {
+        let DatabaseRequest {
+            mutations,
+            response_sender,
+        } = request;
+
+        if let Err(e) = await!(db_client.mutate(mutations.clone())) {
+            error!("replicate_db_requests(): database error: {:?}", e);
+            return;
+        }
+        if response_sender.send(()).is_err() {
+            error!("replicate_db_requests(): Can not ack the primary funder");
+            return;
+        }
+
+        if mutations.is_empty() {
+            continue;
+        }
+        if let Some(mutations_sender) = &mut opt_mutations_sender {
+            if await!(mutations_sender.send(mutations)).is_err() {
+                warn!("replicate_db_requests(): The replica is gone");
+                opt_mutations_sender = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::ThreadPool;
+    use futures::task::{Spawn, SpawnExt};
+
+    use crypto::identity::{PublicKey, PUBLIC_KEY_LEN};
+
+    use proto::funder::messages::AddFriend;
+    use proto::report::messages::ChannelStatusReport;
+
+    use crate::friend::FriendMutation;
+
+    fn add_friend(index: u8, balance: i128) -> AddFriend<u32> {
+        AddFriend {
+            friend_public_key: PublicKey::from(&[index; PUBLIC_KEY_LEN]),
+            relays: Vec::new(),
+            name: format!("friend{}", index),
+            balance,
+            opt_remote_max_debt: None,
+        }
+    }
+
+    fn report_balance(report: &FunderReport<u32>, friend_public_key: &PublicKey) -> i128 {
+        match &report
+            .friends
+            .get(friend_public_key)
+            .unwrap()
+            .channel_status
+        {
+            ChannelStatusReport::Consistent(tc_report) => tc_report.balance.balance,
+            ChannelStatusReport::Inconsistent(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_funder_replica() {
+        let local_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let mut primary = FunderState::<u32>::new(local_public_key.clone(), Vec::new());
+        let mut replica = FunderReplica::new(primary.clone());
+
+        // Mutations of the primary, as they are written to the database:
+        let mutations = vec![
+            FunderMutation::AddFriend(add_friend(1, 5)),
+            FunderMutation::AddFriend(add_friend(2, -3)),
+            FunderMutation::FriendMutation((
+                PublicKey::from(&[2; PUBLIC_KEY_LEN]),
+                FriendMutation::SetName("friend-two".to_owned()),
+            )),
+        ];
+        for mutation in &mutations {
+            primary.mutate(mutation);
+        }
+        replica.apply_mutations(&mutations);
+
+        let report = replica.report();
+        let primary_report = create_initial_report(&primary);
+        assert_eq!(report, primary_report);
+        for index in 1..=2u8 {
+            let friend_public_key = PublicKey::from(&[index; PUBLIC_KEY_LEN]);
+            assert_eq!(
+                report_balance(&report, &friend_public_key),
+                report_balance(&primary_report, &friend_public_key)
+            );
+        }
+        assert_eq!(
+            report_balance(&report, &PublicKey::from(&[1; PUBLIC_KEY_LEN])),
+            5
+        );
+
+        // Queries are answered:
+        let outgoing_control = replica
+            .handle_control(FunderControl::GetPendingRequests(PublicKey::from(
+                &[1; PUBLIC_KEY_LEN],
+            )))
+            .unwrap();
+        match &outgoing_control[..] {
+            [FunderOutgoingControl::PendingRequests(pending_requests)] => {
+                assert!(pending_requests.is_empty())
+            }
+            _ => unreachable!(),
+        };
+
+        // Mutating controls are refused, and the state is left unchanged:
+        let res = replica.handle_control(FunderControl::AddFriend(add_friend(3, 0)));
+        match res {
+            Err(ReplicaError::ReadOnly) => {}
+            _ => unreachable!(),
+        };
+        assert!(!replica
+            .state()
+            .friends
+            .contains_key(&PublicKey::from(&[3; PUBLIC_KEY_LEN])));
+    }
+
+    async fn task_replicate_db_requests(mut spawner: impl Spawn) {
+        let local_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let mut replica = FunderReplica::new(FunderState::<u32>::new(local_public_key, Vec::new()));
+
+        // The database requests of the primary go through the replication adapter:
+        let (request_sender, incoming_requests) = mpsc::channel(0);
+        let mut primary_db_client = DatabaseClient::new(request_sender);
+        let (db_request_sender, mut incoming_db_requests) = mpsc::channel(0);
+        let db_client = DatabaseClient::new(db_request_sender);
+        let (mutations_sender, mut incoming_mutations) = mpsc::channel(0);
+        spawner
+            .spawn(replicate_db_requests(
+                incoming_requests,
+                db_client,
+                mutations_sender,
+            ))
+            .unwrap();
+
+        // A mock database:
+        spawner
+            .spawn(async move {
+                while let Some(db_request) = await!(incoming_db_requests.next()) {
+                    db_request.response_sender.send(()).unwrap();
+                }
+            })
+            .unwrap();
+
+        let mutations = vec![
+            FunderMutation::AddFriend(add_friend(1, 5)),
+            FunderMutation::AddFriend(add_friend(2, -3)),
+        ];
+        await!(primary_db_client.mutate(mutations)).unwrap();
+
+        // The replica follows the mutations of the primary:
+        let mutations = await!(incoming_mutations.next()).unwrap();
+        replica.apply_mutations(&mutations);
+        let report = replica.report();
+        assert_eq!(
+            report_balance(&report, &PublicKey::from(&[1; PUBLIC_KEY_LEN])),
+            5
+        );
+        assert_eq!(
+            report_balance(&report, &PublicKey::from(&[2; PUBLIC_KEY_LEN])),
+            -3
+        );
+
+        // The primary keeps working after the replica is gone:
+        drop(incoming_mutations);
+        let mutations = vec![FunderMutation::AddFriend(add_friend(3, 0))];
+        await!(primary_db_client.mutate(mutations)).unwrap();
+        await!(primary_db_client.mutate(Vec::new())).unwrap();
+    }
+
+    #[test]
+    fn test_replicate_db_requests() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_replicate_db_requests(thread_pool.clone()));
+    }
+}
@@ -4,16 +4,17 @@ use common::canonical_serialize::CanonicalSerialize;
 use common::int_convert::usize_to_u64;
 
 use proto::report::messages::{
-    AddFriendReport, ChannelInconsistentReport, ChannelStatusReport, DirectionReport,
-    FriendLivenessReport, FriendReport, FriendReportMutation, FriendStatusReport, FunderReport,
-    FunderReportMutation, McBalanceReport, McRequestsStatusReport, MoveTokenHashedReport,
-    RequestsStatusReport, ResetTermsReport, SentLocalRelaysReport, TcReport,
+    calc_credit_totals, AddFriendReport, ChannelInconsistentReport, ChannelStatusReport,
+    DirectionReport, FriendLivenessReport, FriendReport, FriendReportMutation, FriendStatusReport,
+    FunderReport, FunderReportMutation, McBalanceReport, McRequestsStatusReport,
+    MoveTokenHashedReport, RequestsStatusReport, ResetTermsReport, RttReport,
+    SentLocalRelaysReport, TcReport,
 };
 
 use crate::types::MoveTokenHashed;
 
 use crate::ephemeral::{Ephemeral, EphemeralMutation};
-use crate::friend::{ChannelStatus, FriendMutation, FriendState, SentLocalRelays};
+use crate::friend::{ChannelStatus, FriendMutation, FriendState, ResetStatus, SentLocalRelays};
 use crate::liveness::LivenessMutation;
 use crate::mutual_credit::types::{McBalance, McRequestsStatus};
 use crate::state::{FunderMutation, FunderState};
@@ -134,6 +135,8 @@ where
 fn create_friend_report<B>(
     friend_state: &FriendState<B>,
     friend_liveness: &FriendLivenessReport,
+    last_move_token_tick: u64,
+    rtt: RttReport,
 ) -> FriendReport<B>
 where
     B: Clone + CanonicalSerialize,
@@ -158,6 +161,9 @@ where
         num_pending_responses: usize_to_u64(friend_state.pending_responses.len()).unwrap(),
         status: FriendStatusReport::from(&friend_state.status),
         num_pending_user_requests: usize_to_u64(friend_state.pending_user_requests.len()).unwrap(),
+        last_move_token_tick,
+        rtt,
+        reset_confirmed: friend_state.reset_status == ResetStatus::Confirmed,
     }
 }
 
@@ -172,15 +178,30 @@ where
         } else {
             FriendLivenessReport::Offline
         };
-        let friend_report = create_friend_report(&friend_state, &friend_liveness);
+        let last_move_token_tick = ephemeral
+            .last_move_token_ticks
+            .get(friend_public_key)
+            .cloned()
+            .unwrap_or(0);
+        let rtt = ephemeral
+            .friend_rtts
+            .get(friend_public_key)
+            .cloned()
+            .unwrap_or_default();
+        let friend_report =
+            create_friend_report(&friend_state, &friend_liveness, last_move_token_tick, rtt);
         friends.insert(friend_public_key.clone(), friend_report);
     }
 
+    let (total_credit_extended, total_credit_received) = calc_credit_totals(&friends);
+
     FunderReport {
         local_public_key: funder_state.local_public_key.clone(),
         relays: funder_state.relays.clone(),
         friends,
         num_ready_receipts: usize_to_u64(funder_state.ready_receipts.len()).unwrap(),
+        total_credit_extended,
+        total_credit_received,
     }
 }
 
@@ -223,6 +244,12 @@ where
                 *wanted_remote_max_debt,
             )]
         }
+        // The confirmation mode of remote max debt is not part of the report:
+        FriendMutation::SetConfirmRemoteMaxDebt(_) => Vec::new(),
+        // The minimum balance is not part of the report:
+        FriendMutation::SetMinBalance(_) => Vec::new(),
+        // The maximum single payment is not part of the report:
+        FriendMutation::SetMaxSinglePayment(_) => Vec::new(),
         FriendMutation::SetWantedLocalRequestsStatus(requests_status) => {
             vec![FriendReportMutation::SetWantedLocalRequestsStatus(
                 RequestsStatusReport::from(requests_status),
@@ -258,6 +285,8 @@ where
                 usize_to_u64(friend_after.pending_user_requests.len()).unwrap(),
             )]
         }
+        // Unacknowledged funds are not reported:
+        FriendMutation::PushBackUnackedFunds(_) | FriendMutation::ClearUnackedFunds => Vec::new(),
         FriendMutation::SetStatus(friend_status) => vec![FriendReportMutation::SetStatus(
             FriendStatusReport::from(friend_status),
         )],
@@ -270,6 +299,11 @@ where
                 sent_local_relays.into(),
             )]
         }
+        FriendMutation::SetResetStatus(reset_status) => {
+            vec![FriendReportMutation::SetResetConfirmed(
+                *reset_status == ResetStatus::Confirmed,
+            )]
+        }
         FriendMutation::SetInconsistent(_) | FriendMutation::SetConsistent(_) => {
             let channel_status_report = ChannelStatusReport::from(&friend_after.channel_status);
             let set_channel_status = FriendReportMutation::SetChannelStatus(channel_status_report);
@@ -279,7 +313,15 @@ where
                 .map(|move_token_hashed| MoveTokenHashedReport::from(&move_token_hashed));
             let set_last_incoming_move_token =
                 FriendReportMutation::SetOptLastIncomingMoveToken(opt_move_token_hashed_report);
-            vec![set_channel_status, set_last_incoming_move_token]
+            // SetInconsistent also clears the reset status:
+            let set_reset_confirmed = FriendReportMutation::SetResetConfirmed(
+                friend_after.reset_status == ResetStatus::Confirmed,
+            );
+            vec![
+                set_channel_status,
+                set_last_incoming_move_token,
+                set_reset_confirmed,
+            ]
         }
     }
 }
@@ -360,6 +402,7 @@ where
                 Vec::new()
             }
         }
+        FunderMutation::SetIdempotencyKey(_) => Vec::new(),
     }
 }
 
@@ -408,5 +451,40 @@ where
                 ))]
             }
         },
+        EphemeralMutation::SetLastMoveTokenTick((public_key, tick)) => {
+            if !funder_state.friends.contains_key(public_key) {
+                // We ignore the mutation if friend does not exist.
+                return Vec::new();
+            }
+            let friend_report_mutation = FriendReportMutation::SetLastMoveTokenTick(*tick);
+            vec![FunderReportMutation::FriendReportMutation((
+                public_key.clone(),
+                friend_report_mutation,
+            ))]
+        }
+        // Recently completed requests are not part of the report:
+        EphemeralMutation::AddCompletedRequest(_) => Vec::new(),
+        // Warm friends are not part of the report:
+        EphemeralMutation::AddWarmFriend(_) | EphemeralMutation::RemoveWarmFriend(_) => Vec::new(),
+        // RTT probes are not part of the report:
+        EphemeralMutation::SetRttProbe(_) | EphemeralMutation::RemoveRttProbe(_) => Vec::new(),
+        // Recently acked receipts are not part of the report:
+        EphemeralMutation::AddRecentReceipt(_) | EphemeralMutation::RemoveOldestRecentReceipt => {
+            Vec::new()
+        }
+        // Deferred send commands are not part of the report:
+        EphemeralMutation::SetDeferredSendCommands(_)
+        | EphemeralMutation::RemoveDeferredSendCommands(_) => Vec::new(),
+        EphemeralMutation::SetFriendRtt((public_key, rtt_report)) => {
+            if !funder_state.friends.contains_key(public_key) {
+                // We ignore the mutation if friend does not exist.
+                return Vec::new();
+            }
+            let friend_report_mutation = FriendReportMutation::SetRtt(rtt_report.clone());
+            vec![FunderReportMutation::FriendReportMutation((
+                public_key.clone(),
+                friend_report_mutation,
+            ))]
+        }
     }
 }
@@ -2,22 +2,47 @@ use im::hashmap::HashMap as ImHashMap;
 use im::vector::Vector as ImVec;
 
 use common::canonical_serialize::CanonicalSerialize;
+use common::ordered_serialize::serialize_ordered_map;
 use crypto::identity::PublicKey;
 use crypto::uid::Uid;
 
 use proto::app_server::messages::NamedRelayAddress;
-use proto::funder::messages::{AddFriend, Receipt};
+use proto::funder::messages::{AddFriend, FriendsRoute, Receipt};
 
 use crate::friend::{FriendMutation, FriendState};
 
+/// Maximum amount of idempotency keys we remember.
+/// When full, the oldest idempotency key is forgotten.
+pub const MAX_IDEMPOTENCY_KEYS: usize = 0x400;
+
+/// A user request that was sent with an idempotency key.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct IdempotentRequest {
+    /// Request id of the last request sent with the idempotency key.
+    pub request_id: Uid,
+    pub route: FriendsRoute,
+    pub dest_payment: u128,
+    /// The receipt of the payment, kept after the receipt was acked.
+    pub opt_acked_receipt: Option<Receipt>,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct FunderState<B: Clone> {
     pub local_public_key: PublicKey,
     /// Address of relay we are going to connect to.
     /// None means that no address was configured.
     pub relays: ImVec<NamedRelayAddress<B>>,
+    #[serde(serialize_with = "serialize_ordered_map")]
     pub friends: ImHashMap<PublicKey, FriendState<B>>,
+    #[serde(serialize_with = "serialize_ordered_map")]
     pub ready_receipts: ImHashMap<Uid, Receipt>,
+    /// Recent idempotency keys of user requests, mapped to the request that was sent with this
+    /// key.
+    #[serde(default, serialize_with = "serialize_ordered_map")]
+    pub idempotency_keys: ImHashMap<Uid, IdempotentRequest>,
+    /// The keys of `idempotency_keys`, oldest first.
+    #[serde(default)]
+    pub idempotency_keys_order: ImVec<Uid>,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -30,6 +55,7 @@ pub enum FunderMutation<B: Clone> {
     RemoveFriend(PublicKey),
     AddReceipt((Uid, Receipt)), //(request_id, receipt)
     RemoveReceipt(Uid),
+    SetIdempotencyKey((Uid, IdempotentRequest)), // (idempotency_key, idempotent_request)
 }
 
 impl<B> FunderState<B>
@@ -45,6 +71,8 @@ where
             relays,
             friends: ImHashMap::new(),
             ready_receipts: ImHashMap::new(),
+            idempotency_keys: ImHashMap::new(),
+            idempotency_keys_order: ImVec::new(),
         }
     }
     // TODO: Add code for initialization from database?
@@ -94,6 +122,135 @@ where
             FunderMutation::RemoveReceipt(uid) => {
                 let _ = self.ready_receipts.remove(uid);
             }
+            FunderMutation::SetIdempotencyKey((idempotency_key, idempotent_request)) => {
+                let opt_prev = self
+                    .idempotency_keys
+                    .insert(*idempotency_key, idempotent_request.clone());
+                if opt_prev.is_none() {
+                    if self.idempotency_keys_order.len() >= MAX_IDEMPOTENCY_KEYS {
+                        if let Some(oldest) = self.idempotency_keys_order.pop_front() {
+                            let _ = self.idempotency_keys.remove(&oldest);
+                        }
+                    }
+                    self.idempotency_keys_order.push_back(*idempotency_key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crypto::identity::PUBLIC_KEY_LEN;
+    use crypto::uid::UID_LEN;
+
+    fn add_friend_mutation(index: u8) -> FunderMutation<u32> {
+        FunderMutation::AddFriend(AddFriend {
+            friend_public_key: PublicKey::from(&[index; PUBLIC_KEY_LEN]),
+            relays: Vec::new(),
+            name: format!("friend{}", index),
+            balance: i128::from(index),
+            opt_remote_max_debt: None,
+        })
+    }
+
+    fn idempotent_request(index: u8) -> IdempotentRequest {
+        IdempotentRequest {
+            request_id: Uid::from(&[index; UID_LEN]),
+            route: FriendsRoute {
+                public_keys: vec![
+                    PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+                    PublicKey::from(&[index; PUBLIC_KEY_LEN]),
+                ],
+            },
+            dest_payment: u128::from(index),
+            opt_acked_receipt: None,
+        }
+    }
+
+    #[test]
+    fn test_funder_state_serialize_deterministic() {
+        let local_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let mut state1 = FunderState::<u32>::new(local_public_key.clone(), Vec::new());
+        let mut state2 = FunderState::<u32>::new(local_public_key, Vec::new());
+
+        for index in 0..16u8 {
+            state1.mutate(&add_friend_mutation(index));
+        }
+        for index in (0..16u8).rev() {
+            state2.mutate(&add_friend_mutation(index));
+        }
+
+        assert_eq!(
+            bincode::serialize(&state1).unwrap(),
+            bincode::serialize(&state2).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_funder_state_json_round_trip() {
+        let local_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let mut state = FunderState::<u32>::new(local_public_key, Vec::new());
+        for index in 0..4u8 {
+            state.mutate(&add_friend_mutation(index));
         }
+        state.mutate(&FunderMutation::SetIdempotencyKey((
+            Uid::from(&[1; UID_LEN]),
+            idempotent_request(2),
+        )));
+
+        // Maps keyed by public keys and uids can be stored in a human readable format:
+        let ser_state = serde_json::to_vec(&state).unwrap();
+        let state2: FunderState<u32> = serde_json::from_slice(&ser_state).unwrap();
+        assert_eq!(state2.friends.len(), 4);
+        assert_eq!(
+            bincode::serialize(&state).unwrap(),
+            bincode::serialize(&state2).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_idempotency_keys_bounded() {
+        let local_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let mut state = FunderState::<u32>::new(local_public_key, Vec::new());
+
+        let idempotency_key = |index: usize| {
+            let mut key_bytes = [0u8; UID_LEN];
+            key_bytes[0] = (index >> 8) as u8;
+            key_bytes[1] = index as u8;
+            Uid::from(&key_bytes)
+        };
+
+        for index in 0..MAX_IDEMPOTENCY_KEYS {
+            state.mutate(&FunderMutation::SetIdempotencyKey((
+                idempotency_key(index),
+                idempotent_request(1),
+            )));
+        }
+        // Updating a remembered key does not forget any key:
+        state.mutate(&FunderMutation::SetIdempotencyKey((
+            idempotency_key(0),
+            idempotent_request(2),
+        )));
+        assert_eq!(state.idempotency_keys.len(), MAX_IDEMPOTENCY_KEYS);
+        assert_eq!(
+            state.idempotency_keys.get(&idempotency_key(0)),
+            Some(&idempotent_request(2))
+        );
+
+        // A new key replaces the oldest key:
+        state.mutate(&FunderMutation::SetIdempotencyKey((
+            idempotency_key(MAX_IDEMPOTENCY_KEYS),
+            idempotent_request(1),
+        )));
+        assert_eq!(state.idempotency_keys.len(), MAX_IDEMPOTENCY_KEYS);
+        assert_eq!(state.idempotency_keys_order.len(), MAX_IDEMPOTENCY_KEYS);
+        assert!(!state.idempotency_keys.contains_key(&idempotency_key(0)));
+        assert!(state.idempotency_keys.contains_key(&idempotency_key(1)));
+        assert!(state
+            .idempotency_keys
+            .contains_key(&idempotency_key(MAX_IDEMPOTENCY_KEYS)));
     }
 }
@@ -1,17 +1,40 @@
+use futures::channel::{mpsc, oneshot};
 use futures::executor::ThreadPool;
-use futures::task::Spawn;
+use futures::task::{Spawn, SpawnExt};
+use futures::{future, FutureExt, SinkExt, StreamExt};
 
-use crypto::identity::PublicKey;
+use crypto::identity::{
+    generate_pkcs8_key_pair, PublicKey, SoftwareEd25519Identity, PUBLIC_KEY_LEN,
+};
 use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
+use crypto::test_utils::DummyRandom;
 use crypto::uid::{Uid, UID_LEN};
 
+use common::latest_channel::latest_channel;
+use database::DatabaseClient;
+use identity::{create_identity, IdentityClient};
+use timer::{dummy_timer_multi_sender, TimerTick};
+
 use proto::funder::messages::{
-    FriendStatus, FriendsRoute, FunderControl, FunderIncomingControl, ReceiptAck, RequestsStatus,
-    ResetFriendChannel, ResponseSendFundsResult, UserRequestSendFunds,
+    AddFriend, FriendStatus, FriendsRoute, FunderControl, FunderIncomingControl,
+    FunderOutgoingControl, Rebalance, ReceiptAck, RequestsStatus, ResetFriendChannel,
+    ResponseSendFundsResult, SetFriendMaxSinglePayment, SetFriendMinBalance, SetFriendStatus,
+    SetRequestsStatus, SuggestFirstHop, UserRequestSendFunds,
 };
-use proto::report::messages::{ChannelStatusReport, FunderReport};
+use proto::report::messages::{
+    ChannelStatusReport, FriendLivenessReport, FunderReport, RequestsStatusReport,
+};
+
+use crate::control_stats::ControlStats;
+use crate::ephemeral::WARM_FRIEND_TIMEOUT_TICKS;
+use crate::friend::FriendMutation;
+use crate::funder::inner_funder_loop;
+use crate::state::{FunderMutation, FunderState};
 
-use super::utils::{create_node_controls, dummy_named_relay_address, dummy_relay_address};
+use super::utils::{
+    create_node_controls, dummy_named_relay_address, dummy_relay_address, NodeRecv,
+    TEST_FUNDER_CONFIG,
+};
 
 async fn task_funder_basic(spawner: impl Spawn + Clone + Send + 'static) {
     let num_nodes = 2;
@@ -55,6 +78,8 @@ async fn task_funder_basic(spawner: impl Spawn + Clone + Send + 'static) {
         },
         invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
         dest_payment: 5,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
     };
     let incoming_control_message = FunderIncomingControl::new(
         Uid::from(&[40; UID_LEN]),
@@ -110,210 +135,230 @@ fn test_funder_basic() {
     thread_pool.run(task_funder_basic(thread_pool.clone()));
 }
 
-async fn task_funder_forward_payment(spawner: impl Spawn + Clone + Send + 'static) {
-    /*
-     * 0 -- 1 -- 2
-     */
-    let num_nodes = 3;
+async fn task_funder_requests_status_effective(spawner: impl Spawn + Clone + Send + 'static) {
+    let num_nodes = 2;
     let mut node_controls = await!(create_node_controls(num_nodes, spawner));
 
-    // Create topology:
-    // ----------------
     let public_keys = node_controls
         .iter()
         .map(|nc| nc.public_key.clone())
         .collect::<Vec<PublicKey>>();
 
-    // Add friends:
     let relays0 = vec![dummy_relay_address(0)];
     let relays1 = vec![dummy_relay_address(1)];
-    let relays2 = vec![dummy_relay_address(2)];
     await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", 8));
-    await!(node_controls[1].add_friend(&public_keys[0], relays0.clone(), "node0", -8));
-    await!(node_controls[1].add_friend(&public_keys[2], relays2, "node2", 6));
-    await!(node_controls[2].add_friend(&public_keys[1], relays0, "node0", -6));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", -8));
 
-    // Enable friends:
+    // Only node0 enables the friend, hence no move tokens are exchanged:
     await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+
+    let set_requests_status = SetRequestsStatus {
+        friend_public_key: public_keys[1].clone(),
+        status: RequestsStatus::Open,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[42; UID_LEN]),
+        FunderControl::SetRequestsStatus(set_requests_status),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[1]).unwrap();
+        friend.wanted_local_requests_status == RequestsStatusReport::Open
+    };
+    await!(node_controls[0].recv_until(pred));
+
+    // The wanted status was not yet sent to the remote side:
+    let friend = node_controls[0]
+        .report
+        .friends
+        .get(&public_keys[1])
+        .unwrap();
+    assert_eq!(
+        friend.effective_local_requests_status(),
+        Some(&RequestsStatusReport::Closed)
+    );
+    assert!(friend.is_local_requests_status_pending());
+
+    // Once node1 enables the friend, the new status is sent inside a move token:
     await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
-    await!(node_controls[1].set_friend_status(&public_keys[2], FriendStatus::Enabled));
-    await!(node_controls[2].set_friend_status(&public_keys[1], FriendStatus::Enabled));
 
-    // Set remote max debt:
-    await!(node_controls[0].set_remote_max_debt(&public_keys[1], 200));
-    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 100));
-    await!(node_controls[1].set_remote_max_debt(&public_keys[2], 300));
-    await!(node_controls[2].set_remote_max_debt(&public_keys[1], 400));
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[1]).unwrap();
+        friend.effective_local_requests_status() == Some(&RequestsStatusReport::Open)
+    };
+    await!(node_controls[0].recv_until(pred));
+    let friend = node_controls[0]
+        .report
+        .friends
+        .get(&public_keys[1])
+        .unwrap();
+    assert!(!friend.is_local_requests_status_pending());
+}
 
-    // Open requests, allowing this route: 0 --> 1 --> 2
-    await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
-    await!(node_controls[2].set_requests_status(&public_keys[1], RequestsStatus::Open));
+#[test]
+fn test_funder_requests_status_effective() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_requests_status_effective(thread_pool.clone()));
+}
 
-    // Wait until route is ready (Online + Consistent + open requests)
-    // Note: We don't need the other direction to be ready, because the request is sent
-    // along the following route: 0 --> 1 --> 2
-    await!(node_controls[0].wait_until_ready(&public_keys[1]));
-    await!(node_controls[1].wait_until_ready(&public_keys[2]));
+async fn task_funder_payment_memo(spawner: impl Spawn + Clone + Send + 'static) {
+    let num_nodes = 3;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
 
-    // Send credits 0 --> 2
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // Create a chain of friends: 0 -- 1 -- 2
+    for i in 0..num_nodes - 1 {
+        let relays = vec![dummy_relay_address(i as u8 + 1)];
+        await!(node_controls[i].add_friend(&public_keys[i + 1], relays, "next", 0));
+        let relays = vec![dummy_relay_address(i as u8)];
+        await!(node_controls[i + 1].add_friend(&public_keys[i], relays, "prev", 0));
+
+        await!(node_controls[i].set_friend_status(&public_keys[i + 1], FriendStatus::Enabled));
+        await!(node_controls[i + 1].set_friend_status(&public_keys[i], FriendStatus::Enabled));
+
+        await!(node_controls[i].set_remote_max_debt(&public_keys[i + 1], 100));
+        await!(node_controls[i + 1].set_remote_max_debt(&public_keys[i], 100));
+
+        await!(node_controls[i].set_requests_status(&public_keys[i + 1], RequestsStatus::Open));
+        await!(node_controls[i + 1].set_requests_status(&public_keys[i], RequestsStatus::Open));
+
+        await!(node_controls[i].wait_until_ready(&public_keys[i + 1]));
+        await!(node_controls[i + 1].wait_until_ready(&public_keys[i]));
+    }
+
+    // Send credits 0 --> 2, attaching a memo:
+    let memo = b"Payment for order 1337".to_vec();
     let user_request_send_funds = UserRequestSendFunds {
         request_id: Uid::from(&[3; UID_LEN]),
         route: FriendsRoute {
-            public_keys: vec![
-                public_keys[0].clone(),
-                public_keys[1].clone(),
-                public_keys[2].clone(),
-            ],
+            public_keys: public_keys.clone(),
         },
         invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
-        dest_payment: 20,
+        dest_payment: 5,
+        memo: memo.clone(),
+        opt_idempotency_key: None,
     };
     let incoming_control_message = FunderIncomingControl::new(
-        Uid::from(&[42; UID_LEN]),
+        Uid::from(&[40; UID_LEN]),
         FunderControl::RequestSendFunds(user_request_send_funds),
     );
     await!(node_controls[0].send(incoming_control_message)).unwrap();
-    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
-    assert_eq!(response_received.request_id, Uid::from(&[3; UID_LEN]));
-    let receipt = match response_received.result {
-        ResponseSendFundsResult::Failure(_) => unreachable!(),
-        ResponseSendFundsResult::Success(send_funds_receipt) => send_funds_receipt,
-    };
 
-    // Send ReceiptAck:
-    let receipt_ack = ReceiptAck {
-        request_id: Uid::from(&[3; UID_LEN]),
-        receipt_signature: receipt.signature.clone(),
-    };
-    let incoming_control_message = FunderIncomingControl::new(
-        Uid::from(&[43; UID_LEN]),
-        FunderControl::ReceiptAck(receipt_ack),
+    // The memo arrives intact at the destination:
+    let funds_received = await!(node_controls[2].recv_until_funds_received()).unwrap();
+    assert_eq!(funds_received.request_id, Uid::from(&[3; UID_LEN]));
+    assert_eq!(
+        funds_received.invoice_id,
+        InvoiceId::from(&[1; INVOICE_ID_LEN])
     );
-    await!(node_controls[0].send(incoming_control_message)).unwrap();
-
-    let pred = |report: &FunderReport<_>| report.num_ready_receipts == 0;
-    await!(node_controls[0].recv_until(pred));
+    assert_eq!(funds_received.dest_payment, 5);
+    assert_eq!(funds_received.memo, memo);
 
-    // Make sure that node2 got the credits:
-    let pred = |report: &FunderReport<_>| {
-        let friend = match report.friends.get(&public_keys[1]) {
-            None => return false,
-            Some(friend) => friend,
-        };
-        let tc_report = match &friend.channel_status {
-            ChannelStatusReport::Consistent(tc_report) => tc_report,
-            _ => return false,
-        };
-        tc_report.balance.balance == -6 + 20
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+    assert_eq!(response_received.request_id, Uid::from(&[3; UID_LEN]));
+    match response_received.result {
+        ResponseSendFundsResult::Success(_) => {}
+        ResponseSendFundsResult::Failure(_) => unreachable!(),
     };
-    await!(node_controls[2].recv_until(pred));
 }
 
 #[test]
-fn test_funder_forward_payment() {
+fn test_funder_payment_memo() {
     let mut thread_pool = ThreadPool::new().unwrap();
-    thread_pool.run(task_funder_forward_payment(thread_pool.clone()));
+    thread_pool.run(task_funder_payment_memo(thread_pool.clone()));
 }
 
-async fn task_funder_payment_failure(spawner: impl Spawn + Clone + Send + 'static) {
-    /*
-     * 0 -- 1 -- 2
-     * We will try to send payment from 0 along the route 0 -- 1 -- 2 -- 3,
-     * where 3 does not exist. We expect that node 2 will return a failure response.
-     */
-    let num_nodes = 4;
+async fn task_funder_recent_receipts(spawner: impl Spawn + Clone + Send + 'static) {
+    let num_nodes = 2;
     let mut node_controls = await!(create_node_controls(num_nodes, spawner));
 
-    // Create topology:
-    // ----------------
     let public_keys = node_controls
         .iter()
         .map(|nc| nc.public_key.clone())
         .collect::<Vec<PublicKey>>();
 
-    // Add friends:
     let relays0 = vec![dummy_relay_address(0)];
     let relays1 = vec![dummy_relay_address(1)];
-    let relays2 = vec![dummy_relay_address(2)];
-    await!(node_controls[0].add_friend(&public_keys[1], relays1.clone(), "node1", 8));
-    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", -8));
-    await!(node_controls[1].add_friend(&public_keys[2], relays2, "node2", 6));
-    await!(node_controls[2].add_friend(&public_keys[1], relays1, "node0", -6));
+    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", 0));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", 0));
 
-    // Enable friends:
     await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
     await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
-    await!(node_controls[1].set_friend_status(&public_keys[2], FriendStatus::Enabled));
-    await!(node_controls[2].set_friend_status(&public_keys[1], FriendStatus::Enabled));
 
-    // Set remote max debt:
-    await!(node_controls[0].set_remote_max_debt(&public_keys[1], 200));
+    await!(node_controls[0].set_remote_max_debt(&public_keys[1], 100));
     await!(node_controls[1].set_remote_max_debt(&public_keys[0], 100));
-    await!(node_controls[1].set_remote_max_debt(&public_keys[2], 300));
-    await!(node_controls[2].set_remote_max_debt(&public_keys[1], 400));
 
-    // Open requests, allowing this route: 0 --> 1 --> 2
+    await!(node_controls[0].set_requests_status(&public_keys[1], RequestsStatus::Open));
     await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
-    await!(node_controls[2].set_requests_status(&public_keys[1], RequestsStatus::Open));
 
-    // Wait until route is ready (Online + Consistent + open requests)
-    // Note: We don't need the other direction to be ready, because the request is sent
-    // along the following route: 0 --> 1 --> 2
     await!(node_controls[0].wait_until_ready(&public_keys[1]));
-    await!(node_controls[1].wait_until_ready(&public_keys[2]));
+    await!(node_controls[1].wait_until_ready(&public_keys[0]));
 
-    // Send credits 0 --> 2
+    // No receipts were acked yet:
+    let incoming_control_message =
+        FunderIncomingControl::new(Uid::from(&[40; UID_LEN]), FunderControl::GetRecentReceipts);
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let recent_receipts = await!(node_controls[0].recv_until_recent_receipts()).unwrap();
+    assert!(recent_receipts.is_empty());
+
+    // Send credits 0 --> 1
     let user_request_send_funds = UserRequestSendFunds {
         request_id: Uid::from(&[3; UID_LEN]),
         route: FriendsRoute {
-            public_keys: vec![
-                public_keys[0].clone(),
-                public_keys[1].clone(),
-                public_keys[2].clone(),
-                public_keys[3].clone(),
-            ],
+            public_keys: public_keys.clone(),
         },
         invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
-        dest_payment: 20,
+        dest_payment: 5,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
     };
     let incoming_control_message = FunderIncomingControl::new(
-        Uid::from(&[44; UID_LEN]),
+        Uid::from(&[41; UID_LEN]),
         FunderControl::RequestSendFunds(user_request_send_funds),
     );
     await!(node_controls[0].send(incoming_control_message)).unwrap();
     let response_received = await!(node_controls[0].recv_until_response()).unwrap();
-    assert_eq!(response_received.request_id, Uid::from(&[3; UID_LEN]));
-    let reporting_public_key = match response_received.result {
-        ResponseSendFundsResult::Failure(reporting_public_key) => reporting_public_key,
-        ResponseSendFundsResult::Success(_) => unreachable!(),
+    let receipt = match response_received.result {
+        ResponseSendFundsResult::Failure(_) => unreachable!(),
+        ResponseSendFundsResult::Success(send_funds_receipt) => send_funds_receipt,
     };
 
-    assert_eq!(reporting_public_key, public_keys[2]);
-
-    let friend = node_controls[2]
-        .report
-        .friends
-        .get(&public_keys[1])
-        .unwrap();
-    let tc_report = match &friend.channel_status {
-        ChannelStatusReport::Consistent(tc_report) => tc_report,
-        _ => unreachable!(),
+    let receipt_ack = ReceiptAck {
+        request_id: Uid::from(&[3; UID_LEN]),
+        receipt_signature: receipt.signature.clone(),
     };
-    assert_eq!(tc_report.balance.balance, -6);
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[42; UID_LEN]),
+        FunderControl::ReceiptAck(receipt_ack),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+
+    let pred = |report: &FunderReport<_>| report.num_ready_receipts == 0;
+    await!(node_controls[0].recv_until(pred));
+
+    // The acked receipt can still be retrieved:
+    let incoming_control_message =
+        FunderIncomingControl::new(Uid::from(&[43; UID_LEN]), FunderControl::GetRecentReceipts);
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let recent_receipts = await!(node_controls[0].recv_until_recent_receipts()).unwrap();
+    assert_eq!(recent_receipts.len(), 1);
+    assert_eq!(recent_receipts[0].request_id, Uid::from(&[3; UID_LEN]));
+    assert_eq!(recent_receipts[0].receipt, receipt);
 }
 
 #[test]
-fn test_funder_payment_failure() {
+fn test_funder_recent_receipts() {
     let mut thread_pool = ThreadPool::new().unwrap();
-    thread_pool.run(task_funder_payment_failure(thread_pool.clone()));
+    thread_pool.run(task_funder_recent_receipts(thread_pool.clone()));
 }
 
-/// Test a basic inconsistency between two adjacent nodes
-async fn task_funder_inconsistency_basic<S>(spawner: S)
-where
-    S: Spawn + Clone + Send + 'static,
-{
-    let num_nodes = 2;
+async fn task_funder_simulate_payment(spawner: impl Spawn + Clone + Send + 'static) {
+    let num_nodes = 3;
     let mut node_controls = await!(create_node_controls(num_nodes, spawner));
 
     let public_keys = node_controls
@@ -321,97 +366,1741 @@ where
         .map(|nc| nc.public_key.clone())
         .collect::<Vec<PublicKey>>();
 
-    // We set incompatible initial balances (non zero sum) to cause an inconsistency:
-    let relays0 = vec![dummy_relay_address(0)];
-    let relays1 = vec![dummy_relay_address(1)];
-    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", 20));
-    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", -8));
+    // Create a chain of friends: 0 -- 1 -- 2
+    for i in 0..num_nodes - 1 {
+        let relays = vec![dummy_relay_address(i as u8 + 1)];
+        await!(node_controls[i].add_friend(&public_keys[i + 1], relays, "next", 0));
+        let relays = vec![dummy_relay_address(i as u8)];
+        await!(node_controls[i + 1].add_friend(&public_keys[i], relays, "prev", 0));
 
-    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
-    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+        await!(node_controls[i].set_friend_status(&public_keys[i + 1], FriendStatus::Enabled));
+        await!(node_controls[i + 1].set_friend_status(&public_keys[i], FriendStatus::Enabled));
 
-    // Expect inconsistency, together with reset terms:
-    let pred = |report: &FunderReport<_>| {
-        let friend = report.friends.get(&public_keys[1]).unwrap();
-        let channel_inconsistent_report = match &friend.channel_status {
-            ChannelStatusReport::Consistent(_) => return false,
-            ChannelStatusReport::Inconsistent(channel_inconsistent_report) => {
-                channel_inconsistent_report
-            }
-        };
-        if channel_inconsistent_report.local_reset_terms_balance != 20 {
-            return false;
-        }
-        let reset_terms_report = match &channel_inconsistent_report.opt_remote_reset_terms {
-            None => return false,
-            Some(reset_terms_report) => reset_terms_report,
+        await!(node_controls[i].set_remote_max_debt(&public_keys[i + 1], 100));
+        await!(node_controls[i + 1].set_remote_max_debt(&public_keys[i], 100));
+
+        await!(node_controls[i].set_requests_status(&public_keys[i + 1], RequestsStatus::Open));
+        await!(node_controls[i + 1].set_requests_status(&public_keys[i], RequestsStatus::Open));
+
+        await!(node_controls[i].wait_until_ready(&public_keys[i + 1]));
+        await!(node_controls[i + 1].wait_until_ready(&public_keys[i]));
+    }
+
+    let create_request =
+        |index: u8, public_keys: Vec<PublicKey>, dest_payment| UserRequestSendFunds {
+            request_id: Uid::from(&[index; UID_LEN]),
+            route: FriendsRoute { public_keys },
+            invoice_id: InvoiceId::from(&[index; INVOICE_ID_LEN]),
+            dest_payment,
+            memo: Vec::new(),
+            opt_idempotency_key: None,
         };
-        reset_terms_report.balance_for_reset == -8
-    };
-    await!(node_controls[0].recv_until(pred));
 
-    // Resolve inconsistency
-    // ---------------------
+    // Simulate payments 0 --> 2:
+    let mut simulations = Vec::new();
+    for (index, dest_payment) in [(3u8, 5u128), (4u8, 100u128)].iter() {
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[40 + *index; UID_LEN]),
+            FunderControl::SimulatePayment(create_request(
+                *index,
+                public_keys.clone(),
+                *dest_payment,
+            )),
+        );
+        await!(node_controls[0].send(incoming_control_message)).unwrap();
+        simulations.push(await!(node_controls[0].recv_until_payment_simulation()).unwrap());
+    }
 
-    // Obtain reset terms:
+    // node1 earns one credit for forwarding the payment:
+    assert_eq!(simulations[0].request_id, Uid::from(&[3; UID_LEN]));
+    assert!(simulations[0].feasible);
+    assert_eq!(simulations[0].total_cost, 6);
+    assert_eq!(simulations[0].opt_limiting_hop, None);
+    assert_eq!(simulations[0].max_dest_payment, 99);
+
+    // node0 may not owe node1 more than 100 credits:
+    assert_eq!(simulations[1].request_id, Uid::from(&[4; UID_LEN]));
+    assert!(!simulations[1].feasible);
+    assert_eq!(simulations[1].total_cost, 101);
+    assert_eq!(
+        simulations[1].opt_limiting_hop,
+        Some(public_keys[1].clone())
+    );
+    assert_eq!(simulations[1].max_dest_payment, 99);
+
+    // A simulation has no effect on the channel:
     let friend = node_controls[0]
         .report
         .friends
         .get(&public_keys[1])
         .unwrap();
-    let channel_inconsistent_report = match &friend.channel_status {
-        ChannelStatusReport::Consistent(_) => unreachable!(),
-        ChannelStatusReport::Inconsistent(channel_inconsistent_report) => {
-            channel_inconsistent_report
+    match &friend.channel_status {
+        ChannelStatusReport::Consistent(tc_report) => {
+            assert_eq!(tc_report.balance.balance, 0);
+            assert_eq!(tc_report.balance.local_pending_debt, 0);
         }
-    };
-    let reset_terms_report = match &channel_inconsistent_report.opt_remote_reset_terms {
-        Some(reset_terms_report) => reset_terms_report,
-        None => unreachable!(),
+        _ => unreachable!(),
     };
 
-    let reset_friend_channel = ResetFriendChannel {
-        friend_public_key: public_keys[1].clone(),
-        reset_token: reset_terms_report.reset_token.clone(), // TODO: Rename reset_token to reset_token?
+    // An invalid route (We are not the first on the route):
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[45; UID_LEN]),
+        FunderControl::SimulatePayment(create_request(
+            5,
+            vec![public_keys[1].clone(), public_keys[2].clone()],
+            5,
+        )),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let payment_simulation = await!(node_controls[0].recv_until_payment_simulation()).unwrap();
+    assert!(!payment_simulation.feasible);
+    assert_eq!(payment_simulation.total_cost, 0);
+    assert_eq!(payment_simulation.opt_limiting_hop, None);
+    assert_eq!(payment_simulation.max_dest_payment, 0);
+
+    // The actual payments match the simulations:
+    for (index, dest_payment) in [(3u8, 5u128), (4u8, 100u128)].iter() {
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[50 + *index; UID_LEN]),
+            FunderControl::RequestSendFunds(create_request(
+                *index,
+                public_keys.clone(),
+                *dest_payment,
+            )),
+        );
+        await!(node_controls[0].send(incoming_control_message)).unwrap();
+    }
+
+    // The responses may arrive in any order:
+    for _ in 0..2 {
+        let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+        let is_success = match response_received.result {
+            ResponseSendFundsResult::Success(_) => true,
+            ResponseSendFundsResult::Failure(_) => false,
+        };
+        if response_received.request_id == Uid::from(&[3; UID_LEN]) {
+            assert!(is_success);
+        } else {
+            assert_eq!(response_received.request_id, Uid::from(&[4; UID_LEN]));
+            assert!(!is_success);
+        }
+    }
+
+    // The successful payment cost exactly the simulated total cost:
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[1]).unwrap();
+        let tc_report = match &friend.channel_status {
+            ChannelStatusReport::Consistent(tc_report) => tc_report,
+            _ => return false,
+        };
+        tc_report.balance.balance == -7 && tc_report.balance.local_pending_debt == 0
+    };
+    await!(node_controls[0].recv_until(pred));
+}
+
+#[test]
+fn test_funder_simulate_payment() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_simulate_payment(thread_pool.clone()));
+}
+
+async fn task_funder_max_dest_payment(spawner: impl Spawn + Clone + Send + 'static) {
+    let num_nodes = 3;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // Create a chain of friends: 0 -- 1 -- 2
+    for i in 0..num_nodes - 1 {
+        let relays = vec![dummy_relay_address(i as u8 + 1)];
+        await!(node_controls[i].add_friend(&public_keys[i + 1], relays, "next", 0));
+        let relays = vec![dummy_relay_address(i as u8)];
+        await!(node_controls[i + 1].add_friend(&public_keys[i], relays, "prev", 0));
+
+        await!(node_controls[i].set_friend_status(&public_keys[i + 1], FriendStatus::Enabled));
+        await!(node_controls[i + 1].set_friend_status(&public_keys[i], FriendStatus::Enabled));
+
+        await!(node_controls[i].set_remote_max_debt(&public_keys[i + 1], 100));
+        await!(node_controls[i + 1].set_remote_max_debt(&public_keys[i], 100));
+
+        await!(node_controls[i].set_requests_status(&public_keys[i + 1], RequestsStatus::Open));
+        await!(node_controls[i + 1].set_requests_status(&public_keys[i], RequestsStatus::Open));
+
+        await!(node_controls[i].wait_until_ready(&public_keys[i + 1]));
+        await!(node_controls[i + 1].wait_until_ready(&public_keys[i]));
+    }
+
+    let create_request = |index: u8, dest_payment| UserRequestSendFunds {
+        request_id: Uid::from(&[index; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: public_keys.clone(),
+        },
+        invoice_id: InvoiceId::from(&[index; INVOICE_ID_LEN]),
+        dest_payment,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
+    };
+
+    // node0 pays 5 credits to node2, which costs node0 6 credits:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[40; UID_LEN]),
+        FunderControl::RequestSendFunds(create_request(1, 5)),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+    match response_received.result {
+        ResponseSendFundsResult::Success(_) => {}
+        ResponseSendFundsResult::Failure(_) => unreachable!(),
+    };
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[1]).unwrap();
+        let tc_report = match &friend.channel_status {
+            ChannelStatusReport::Consistent(tc_report) => tc_report,
+            _ => return false,
+        };
+        tc_report.balance.balance == -7 && tc_report.balance.local_pending_debt == 0
+    };
+    await!(node_controls[0].recv_until(pred));
+
+    // balance = -6, local_max_debt = 100, local_pending_debt = 0.
+    // node1 earns one credit for forwarding the payment, so at most 100 - 6 - 1 = 93 credits may
+    // be sent to node2:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[41; UID_LEN]),
+        FunderControl::SimulatePayment(create_request(2, 1)),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let payment_simulation = await!(node_controls[0].recv_until_payment_simulation()).unwrap();
+    assert!(payment_simulation.feasible);
+    assert_eq!(payment_simulation.max_dest_payment, 93);
+
+    // One credit over the maximum fails:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[42; UID_LEN]),
+        FunderControl::RequestSendFunds(create_request(3, 94)),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+    assert_eq!(response_received.request_id, Uid::from(&[3; UID_LEN]));
+    match response_received.result {
+        ResponseSendFundsResult::Failure(_) => {}
+        ResponseSendFundsResult::Success(_) => unreachable!(),
+    };
+
+    // The maximum just fits:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[43; UID_LEN]),
+        FunderControl::RequestSendFunds(create_request(4, 93)),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+    assert_eq!(response_received.request_id, Uid::from(&[4; UID_LEN]));
+    match response_received.result {
+        ResponseSendFundsResult::Success(_) => {}
+        ResponseSendFundsResult::Failure(_) => unreachable!(),
+    };
+
+    // node0 now owes node1 the maximum debt:
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[1]).unwrap();
+        let tc_report = match &friend.channel_status {
+            ChannelStatusReport::Consistent(tc_report) => tc_report,
+            _ => return false,
+        };
+        tc_report.balance.balance == -100 && tc_report.balance.local_pending_debt == 0
+    };
+    await!(node_controls[0].recv_until(pred));
+}
+
+#[test]
+fn test_funder_max_dest_payment() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_max_dest_payment(thread_pool.clone()));
+}
+
+async fn task_funder_zero_payment(spawner: impl Spawn + Clone + Send + 'static) {
+    let num_nodes = 3;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // Create a chain of friends: 0 -- 1 -- 2
+    for i in 0..num_nodes - 1 {
+        let relays = vec![dummy_relay_address(i as u8 + 1)];
+        await!(node_controls[i].add_friend(&public_keys[i + 1], relays, "next", 0));
+        let relays = vec![dummy_relay_address(i as u8)];
+        await!(node_controls[i + 1].add_friend(&public_keys[i], relays, "prev", 0));
+
+        await!(node_controls[i].set_friend_status(&public_keys[i + 1], FriendStatus::Enabled));
+        await!(node_controls[i + 1].set_friend_status(&public_keys[i], FriendStatus::Enabled));
+
+        await!(node_controls[i].set_remote_max_debt(&public_keys[i + 1], 100));
+        await!(node_controls[i + 1].set_remote_max_debt(&public_keys[i], 100));
+
+        await!(node_controls[i].set_requests_status(&public_keys[i + 1], RequestsStatus::Open));
+        await!(node_controls[i + 1].set_requests_status(&public_keys[i], RequestsStatus::Open));
+
+        await!(node_controls[i].wait_until_ready(&public_keys[i + 1]));
+        await!(node_controls[i + 1].wait_until_ready(&public_keys[i]));
+    }
+
+    let create_request = |index: u8, dest_payment| UserRequestSendFunds {
+        request_id: Uid::from(&[index; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: public_keys.clone(),
+        },
+        invoice_id: InvoiceId::from(&[index; INVOICE_ID_LEN]),
+        dest_payment,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
+    };
+
+    // A zero payment 0 --> 2 probes the route:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[40; UID_LEN]),
+        FunderControl::RequestSendFunds(create_request(1, 0)),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+    assert_eq!(response_received.request_id, Uid::from(&[1; UID_LEN]));
+    match response_received.result {
+        ResponseSendFundsResult::Success(receipt) => assert_eq!(receipt.dest_payment, 0),
+        ResponseSendFundsResult::Failure(_) => unreachable!(),
+    };
+
+    // A regular payment. Together with the zero payment, it costs node0 7 credits:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[41; UID_LEN]),
+        FunderControl::RequestSendFunds(create_request(2, 5)),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+    assert_eq!(response_received.request_id, Uid::from(&[2; UID_LEN]));
+    match response_received.result {
+        ResponseSendFundsResult::Success(_) => {}
+        ResponseSendFundsResult::Failure(_) => unreachable!(),
+    };
+
+    // node1 was paid for forwarding both payments. node2 only received the regular payment:
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[1]).unwrap();
+        let tc_report = match &friend.channel_status {
+            ChannelStatusReport::Consistent(tc_report) => tc_report,
+            _ => return false,
+        };
+        tc_report.balance.balance == -7 && tc_report.balance.local_pending_debt == 0
+    };
+    await!(node_controls[0].recv_until(pred));
+
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[1]).unwrap();
+        let tc_report = match &friend.channel_status {
+            ChannelStatusReport::Consistent(tc_report) => tc_report,
+            _ => return false,
+        };
+        tc_report.balance.balance == 5 && tc_report.balance.remote_pending_debt == 0
+    };
+    await!(node_controls[2].recv_until(pred));
+}
+
+#[test]
+fn test_funder_zero_payment() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_zero_payment(thread_pool.clone()));
+}
+
+async fn task_funder_duplicate_invoice_id(spawner: impl Spawn + Clone + Send + 'static) {
+    let num_nodes = 2;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", 8));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", -8));
+
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+
+    // Set remote max debt for both sides:
+    await!(node_controls[0].set_remote_max_debt(&public_keys[1], 200));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 100));
+
+    // Open requests:
+    await!(node_controls[0].set_requests_status(&public_keys[1], RequestsStatus::Open));
+    await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
+
+    // Wait for liveness:
+    await!(node_controls[0].wait_until_ready(&public_keys[1]));
+    await!(node_controls[1].wait_until_ready(&public_keys[0]));
+
+    // Send credits 0 --> 1
+    let user_request_send_funds = UserRequestSendFunds {
+        request_id: Uid::from(&[3; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: vec![
+                node_controls[0].public_key.clone(),
+                node_controls[1].public_key.clone(),
+            ],
+        },
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        dest_payment: 5,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[40; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+
+    assert_eq!(response_received.request_id, Uid::from(&[3; UID_LEN]));
+    match response_received.result {
+        ResponseSendFundsResult::Failure(_) => unreachable!(),
+        ResponseSendFundsResult::Success(_) => {}
+    };
+
+    // Send another request with the same invoice_id. The receipt of the first request was not
+    // acked yet, so we expect this request to be rejected:
+    let user_request_send_funds = UserRequestSendFunds {
+        request_id: Uid::from(&[4; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: vec![
+                node_controls[0].public_key.clone(),
+                node_controls[1].public_key.clone(),
+            ],
+        },
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        dest_payment: 5,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[41; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+
+    assert_eq!(response_received.request_id, Uid::from(&[4; UID_LEN]));
+    match response_received.result {
+        ResponseSendFundsResult::Failure(public_key) => assert_eq!(public_key, public_keys[0]),
+        ResponseSendFundsResult::Success(_) => unreachable!(),
+    };
+}
+
+#[test]
+fn test_funder_duplicate_invoice_id() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_duplicate_invoice_id(thread_pool.clone()));
+}
+
+async fn task_funder_replayed_request(spawner: impl Spawn + Clone + Send + 'static) {
+    let num_nodes = 2;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", 8));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", -8));
+
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+
+    // Set remote max debt for both sides:
+    await!(node_controls[0].set_remote_max_debt(&public_keys[1], 200));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 100));
+
+    // Open requests:
+    await!(node_controls[0].set_requests_status(&public_keys[1], RequestsStatus::Open));
+    await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
+
+    // Wait for liveness:
+    await!(node_controls[0].wait_until_ready(&public_keys[1]));
+    await!(node_controls[1].wait_until_ready(&public_keys[0]));
+
+    // Send credits 0 --> 1
+    let user_request_send_funds = UserRequestSendFunds {
+        request_id: Uid::from(&[3; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: vec![
+                node_controls[0].public_key.clone(),
+                node_controls[1].public_key.clone(),
+            ],
+        },
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        dest_payment: 5,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[40; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds.clone()),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+
+    assert_eq!(response_received.request_id, Uid::from(&[3; UID_LEN]));
+    let receipt = match response_received.result {
+        ResponseSendFundsResult::Failure(_) => unreachable!(),
+        ResponseSendFundsResult::Success(send_funds_receipt) => send_funds_receipt,
+    };
+
+    // Complete the request by acking the receipt:
+    let receipt_ack = ReceiptAck {
+        request_id: Uid::from(&[3; UID_LEN]),
+        receipt_signature: receipt.signature.clone(),
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[41; UID_LEN]),
+        FunderControl::ReceiptAck(receipt_ack),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+
+    let pred = |report: &FunderReport<_>| report.num_ready_receipts == 0;
+    await!(node_controls[0].recv_until(pred));
+
+    // Replay the completed request. We expect it to be rejected:
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[42; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+
+    assert_eq!(response_received.request_id, Uid::from(&[3; UID_LEN]));
+    match response_received.result {
+        ResponseSendFundsResult::Failure(public_key) => assert_eq!(public_key, public_keys[0]),
+        ResponseSendFundsResult::Success(_) => unreachable!(),
+    };
+}
+
+#[test]
+fn test_funder_replayed_request() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_replayed_request(thread_pool.clone()));
+}
+
+async fn task_funder_pause_friend(spawner: impl Spawn + Clone + Send + 'static) {
+    let num_nodes = 2;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", 8));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", -8));
+
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+
+    // Set remote max debt for both sides:
+    await!(node_controls[0].set_remote_max_debt(&public_keys[1], 200));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 100));
+
+    // Open requests:
+    await!(node_controls[0].set_requests_status(&public_keys[1], RequestsStatus::Open));
+    await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
+
+    // Wait for liveness:
+    await!(node_controls[0].wait_until_ready(&public_keys[1]));
+    await!(node_controls[1].wait_until_ready(&public_keys[0]));
+
+    // Node0 pauses node1:
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Paused));
+
+    // A new request from node0 to node1 is rejected:
+    let user_request_send_funds = UserRequestSendFunds {
+        request_id: Uid::from(&[3; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: vec![public_keys[0].clone(), public_keys[1].clone()],
+        },
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        dest_payment: 5,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[43; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+    assert_eq!(response_received.request_id, Uid::from(&[3; UID_LEN]));
+    match response_received.result {
+        ResponseSendFundsResult::Failure(public_key) => assert_eq!(public_key, public_keys[0]),
+        ResponseSendFundsResult::Success(_) => unreachable!(),
+    };
+
+    // A new request from node1 to node0 is rejected by node0:
+    let user_request_send_funds = UserRequestSendFunds {
+        request_id: Uid::from(&[4; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: vec![public_keys[1].clone(), public_keys[0].clone()],
+        },
+        invoice_id: InvoiceId::from(&[2; INVOICE_ID_LEN]),
+        dest_payment: 5,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[44; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds),
+    );
+    await!(node_controls[1].send(incoming_control_message)).unwrap();
+    let response_received = await!(node_controls[1].recv_until_response()).unwrap();
+    assert_eq!(response_received.request_id, Uid::from(&[4; UID_LEN]));
+    match response_received.result {
+        ResponseSendFundsResult::Failure(public_key) => assert_eq!(public_key, public_keys[0]),
+        ResponseSendFundsResult::Success(_) => unreachable!(),
+    };
+
+    // The connection to the paused friend is kept:
+    let friend = node_controls[0]
+        .report
+        .friends
+        .get(&public_keys[1])
+        .unwrap();
+    assert_eq!(friend.liveness, FriendLivenessReport::Online);
+    let friend = node_controls[1]
+        .report
+        .friends
+        .get(&public_keys[0])
+        .unwrap();
+    assert_eq!(friend.liveness, FriendLivenessReport::Online);
+
+    // Resume the friend:
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[0].wait_until_ready(&public_keys[1]));
+}
+
+#[test]
+fn test_funder_pause_friend() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_pause_friend(thread_pool.clone()));
+}
+
+async fn task_funder_warm_friend(spawner: impl Spawn + Clone + Send + 'static) {
+    let num_nodes = 2;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", 8));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", -8));
+
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+
+    // Set remote max debt and open requests for both sides:
+    await!(node_controls[0].set_remote_max_debt(&public_keys[1], 200));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 100));
+    await!(node_controls[0].set_requests_status(&public_keys[1], RequestsStatus::Open));
+    await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
+
+    // Warm the newly enabled friend, instead of waiting for it to become ready:
+    let friend_warmed = await!(node_controls[0].warm_friend(&public_keys[1]));
+    assert_eq!(friend_warmed.friend_public_key, public_keys[1]);
+    assert!(friend_warmed.is_ready);
+
+    // Send credits 0 --> 1. The request should not be rejected for non-readiness:
+    let user_request_send_funds = UserRequestSendFunds {
+        request_id: Uid::from(&[3; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: vec![
+                node_controls[0].public_key.clone(),
+                node_controls[1].public_key.clone(),
+            ],
+        },
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        dest_payment: 5,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[39; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+
+    assert_eq!(response_received.request_id, Uid::from(&[3; UID_LEN]));
+    match response_received.result {
+        ResponseSendFundsResult::Failure(_) => unreachable!(),
+        ResponseSendFundsResult::Success(_) => {}
+    };
+}
+
+#[test]
+fn test_funder_warm_friend() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_warm_friend(thread_pool.clone()));
+}
+
+async fn task_funder_suggest_first_hop(spawner: impl Spawn + Clone + Send + 'static) {
+    let num_nodes = 3;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // Node0 is a friend of both node1 and node2:
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    let relays2 = vec![dummy_relay_address(2)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", 0));
+    await!(node_controls[0].add_friend(&public_keys[2], relays2, "node2", 0));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0.clone(), "node0", 0));
+    await!(node_controls[2].add_friend(&public_keys[0], relays0, "node0", 0));
+
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[0].set_friend_status(&public_keys[2], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+    await!(node_controls[2].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+
+    // Node1 trusts node0 more than node2 does:
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 100));
+    await!(node_controls[2].set_remote_max_debt(&public_keys[0], 20));
+
+    // Open requests:
+    await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
+    await!(node_controls[2].set_requests_status(&public_keys[0], RequestsStatus::Open));
+
+    // Wait for liveness:
+    await!(node_controls[0].wait_until_ready(&public_keys[1]));
+    await!(node_controls[0].wait_until_ready(&public_keys[2]));
+
+    // Wait until node0 knows about the new max debts:
+    for &(i, local_max_debt) in &[(1, 100), (2, 20)] {
+        let friend_public_key = public_keys[i].clone();
+        let pred = |report: &FunderReport<_>| {
+            let friend = report.friends.get(&friend_public_key).unwrap();
+            let tc_report = match &friend.channel_status {
+                ChannelStatusReport::Consistent(tc_report) => tc_report,
+                _ => return false,
+            };
+            tc_report.balance.local_max_debt == local_max_debt
+        };
+        await!(node_controls[0].recv_until(pred));
+    }
+
+    // Ask for a first hop for a large payment. Only node1 has enough capacity:
+    let suggest_first_hop = SuggestFirstHop {
+        request_id: Uid::from(&[5; UID_LEN]),
+        dest_public_key: PublicKey::from(&[0xdd; PUBLIC_KEY_LEN]),
+        dest_payment: 50,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[42; UID_LEN]),
+        FunderControl::SuggestFirstHop(suggest_first_hop),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let first_hop_suggestion = await!(node_controls[0].recv_until_first_hop_suggestion()).unwrap();
+    assert_eq!(first_hop_suggestion.request_id, Uid::from(&[5; UID_LEN]));
+    assert_eq!(
+        first_hop_suggestion.opt_friend_public_key,
+        Some(public_keys[1].clone())
+    );
+
+    // No friend can forward a payment that is too large:
+    let suggest_first_hop = SuggestFirstHop {
+        request_id: Uid::from(&[6; UID_LEN]),
+        dest_public_key: PublicKey::from(&[0xdd; PUBLIC_KEY_LEN]),
+        dest_payment: 200,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[43; UID_LEN]),
+        FunderControl::SuggestFirstHop(suggest_first_hop),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let first_hop_suggestion = await!(node_controls[0].recv_until_first_hop_suggestion()).unwrap();
+    assert_eq!(first_hop_suggestion.request_id, Uid::from(&[6; UID_LEN]));
+    assert_eq!(first_hop_suggestion.opt_friend_public_key, None);
+
+    // A destination that is a friend with enough capacity is paid directly, even if another
+    // friend has more capacity:
+    let suggest_first_hop = SuggestFirstHop {
+        request_id: Uid::from(&[7; UID_LEN]),
+        dest_public_key: public_keys[2].clone(),
+        dest_payment: 10,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[44; UID_LEN]),
+        FunderControl::SuggestFirstHop(suggest_first_hop),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let first_hop_suggestion = await!(node_controls[0].recv_until_first_hop_suggestion()).unwrap();
+    assert_eq!(first_hop_suggestion.request_id, Uid::from(&[7; UID_LEN]));
+    assert_eq!(
+        first_hop_suggestion.opt_friend_public_key,
+        Some(public_keys[2].clone())
+    );
+
+    // If the destination friend can not receive the payment, the best first hop is suggested:
+    let suggest_first_hop = SuggestFirstHop {
+        request_id: Uid::from(&[8; UID_LEN]),
+        dest_public_key: public_keys[2].clone(),
+        dest_payment: 50,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[45; UID_LEN]),
+        FunderControl::SuggestFirstHop(suggest_first_hop),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let first_hop_suggestion = await!(node_controls[0].recv_until_first_hop_suggestion()).unwrap();
+    assert_eq!(first_hop_suggestion.request_id, Uid::from(&[8; UID_LEN]));
+    assert_eq!(
+        first_hop_suggestion.opt_friend_public_key,
+        Some(public_keys[1].clone())
+    );
+}
+
+#[test]
+fn test_funder_suggest_first_hop() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_suggest_first_hop(thread_pool.clone()));
+}
+
+async fn task_funder_forward_payment(spawner: impl Spawn + Clone + Send + 'static) {
+    /*
+     * 0 -- 1 -- 2
+     */
+    let num_nodes = 3;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    // Create topology:
+    // ----------------
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // Add friends:
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    let relays2 = vec![dummy_relay_address(2)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", 8));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0.clone(), "node0", -8));
+    await!(node_controls[1].add_friend(&public_keys[2], relays2, "node2", 6));
+    await!(node_controls[2].add_friend(&public_keys[1], relays0, "node0", -6));
+
+    // Enable friends:
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[2], FriendStatus::Enabled));
+    await!(node_controls[2].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+
+    // Set remote max debt:
+    await!(node_controls[0].set_remote_max_debt(&public_keys[1], 200));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 100));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[2], 300));
+    await!(node_controls[2].set_remote_max_debt(&public_keys[1], 400));
+
+    // Open requests, allowing this route: 0 --> 1 --> 2
+    await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
+    await!(node_controls[2].set_requests_status(&public_keys[1], RequestsStatus::Open));
+
+    // Wait until route is ready (Online + Consistent + open requests)
+    // Note: We don't need the other direction to be ready, because the request is sent
+    // along the following route: 0 --> 1 --> 2
+    await!(node_controls[0].wait_until_ready(&public_keys[1]));
+    await!(node_controls[1].wait_until_ready(&public_keys[2]));
+
+    // Send credits 0 --> 2
+    let user_request_send_funds = UserRequestSendFunds {
+        request_id: Uid::from(&[3; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: vec![
+                public_keys[0].clone(),
+                public_keys[1].clone(),
+                public_keys[2].clone(),
+            ],
+        },
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        dest_payment: 20,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[42; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+    assert_eq!(response_received.request_id, Uid::from(&[3; UID_LEN]));
+    let receipt = match response_received.result {
+        ResponseSendFundsResult::Failure(_) => unreachable!(),
+        ResponseSendFundsResult::Success(send_funds_receipt) => send_funds_receipt,
+    };
+
+    // Send ReceiptAck:
+    let receipt_ack = ReceiptAck {
+        request_id: Uid::from(&[3; UID_LEN]),
+        receipt_signature: receipt.signature.clone(),
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[43; UID_LEN]),
+        FunderControl::ReceiptAck(receipt_ack),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+
+    let pred = |report: &FunderReport<_>| report.num_ready_receipts == 0;
+    await!(node_controls[0].recv_until(pred));
+
+    // Make sure that node2 got the credits:
+    let pred = |report: &FunderReport<_>| {
+        let friend = match report.friends.get(&public_keys[1]) {
+            None => return false,
+            Some(friend) => friend,
+        };
+        let tc_report = match &friend.channel_status {
+            ChannelStatusReport::Consistent(tc_report) => tc_report,
+            _ => return false,
+        };
+        tc_report.balance.balance == -6 + 20
+    };
+    await!(node_controls[2].recv_until(pred));
+}
+
+#[test]
+fn test_funder_forward_payment() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_forward_payment(thread_pool.clone()));
+}
+
+async fn task_funder_rebalance(spawner: impl Spawn + Clone + Send + 'static) {
+    /*
+     * 0 -- 1
+     *  \  /
+     *   2
+     */
+    let num_nodes = 3;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    // Create topology:
+    // ----------------
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // Add friends:
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    let relays2 = vec![dummy_relay_address(2)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1.clone(), "node1", 0));
+    await!(node_controls[0].add_friend(&public_keys[2], relays2.clone(), "node2", 0));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0.clone(), "node0", 0));
+    await!(node_controls[1].add_friend(&public_keys[2], relays2, "node2", 0));
+    await!(node_controls[2].add_friend(&public_keys[0], relays0, "node0", 0));
+    await!(node_controls[2].add_friend(&public_keys[1], relays1, "node1", 0));
+
+    // Enable friends:
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[0].set_friend_status(&public_keys[2], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[2], FriendStatus::Enabled));
+    await!(node_controls[2].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+    await!(node_controls[2].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+
+    // Set remote max debt, allowing this cycle: 0 --> 1 --> 2 --> 0
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 100));
+    await!(node_controls[2].set_remote_max_debt(&public_keys[1], 100));
+    await!(node_controls[0].set_remote_max_debt(&public_keys[2], 100));
+
+    // Open requests along the cycle:
+    await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
+    await!(node_controls[2].set_requests_status(&public_keys[1], RequestsStatus::Open));
+    await!(node_controls[0].set_requests_status(&public_keys[2], RequestsStatus::Open));
+
+    // Wait until the cycle is ready:
+    await!(node_controls[0].wait_until_ready(&public_keys[1]));
+    await!(node_controls[1].wait_until_ready(&public_keys[2]));
+    await!(node_controls[2].wait_until_ready(&public_keys[0]));
+
+    // Move 20 credits from the channel with node1 to the channel with node2:
+    let rebalance = Rebalance {
+        request_id: Uid::from(&[3; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: vec![
+                public_keys[0].clone(),
+                public_keys[1].clone(),
+                public_keys[2].clone(),
+                public_keys[0].clone(),
+            ],
+        },
+        dest_payment: 20,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[42; UID_LEN]),
+        FunderControl::Rebalance(rebalance),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+    assert_eq!(response_received.request_id, Uid::from(&[3; UID_LEN]));
+    match response_received.result {
+        ResponseSendFundsResult::Failure(_) => unreachable!(),
+        ResponseSendFundsResult::Success(_) => {}
+    };
+
+    // Node0 paid node1, and got paid by node2.
+    // Node0 also paid a fee of one credit to each of the mediators (node1, node2):
+    let pred = |report: &FunderReport<_>| {
+        let friend_balance = |friend_public_key: &PublicKey| {
+            let friend = report.friends.get(friend_public_key)?;
+            match &friend.channel_status {
+                ChannelStatusReport::Consistent(tc_report) => Some(tc_report.balance.balance),
+                _ => None,
+            }
+        };
+        friend_balance(&public_keys[1]) == Some(-22) && friend_balance(&public_keys[2]) == Some(20)
+    };
+    await!(node_controls[0].recv_until(pred));
+}
+
+#[test]
+fn test_funder_rebalance() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_rebalance(thread_pool.clone()));
+}
+
+async fn task_funder_payment_failure(spawner: impl Spawn + Clone + Send + 'static) {
+    /*
+     * 0 -- 1 -- 2
+     * We will try to send payment from 0 along the route 0 -- 1 -- 2 -- 3,
+     * where 3 does not exist. We expect that node 2 will return a failure response.
+     */
+    let num_nodes = 4;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    // Create topology:
+    // ----------------
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // Add friends:
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    let relays2 = vec![dummy_relay_address(2)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1.clone(), "node1", 8));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", -8));
+    await!(node_controls[1].add_friend(&public_keys[2], relays2, "node2", 6));
+    await!(node_controls[2].add_friend(&public_keys[1], relays1, "node0", -6));
+
+    // Enable friends:
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[2], FriendStatus::Enabled));
+    await!(node_controls[2].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+
+    // Set remote max debt:
+    await!(node_controls[0].set_remote_max_debt(&public_keys[1], 200));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 100));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[2], 300));
+    await!(node_controls[2].set_remote_max_debt(&public_keys[1], 400));
+
+    // Open requests, allowing this route: 0 --> 1 --> 2
+    await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
+    await!(node_controls[2].set_requests_status(&public_keys[1], RequestsStatus::Open));
+
+    // Wait until route is ready (Online + Consistent + open requests)
+    // Note: We don't need the other direction to be ready, because the request is sent
+    // along the following route: 0 --> 1 --> 2
+    await!(node_controls[0].wait_until_ready(&public_keys[1]));
+    await!(node_controls[1].wait_until_ready(&public_keys[2]));
+
+    // Send credits 0 --> 2
+    let user_request_send_funds = UserRequestSendFunds {
+        request_id: Uid::from(&[3; UID_LEN]),
+        route: FriendsRoute {
+            public_keys: vec![
+                public_keys[0].clone(),
+                public_keys[1].clone(),
+                public_keys[2].clone(),
+                public_keys[3].clone(),
+            ],
+        },
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        dest_payment: 20,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[44; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+    assert_eq!(response_received.request_id, Uid::from(&[3; UID_LEN]));
+    let reporting_public_key = match response_received.result {
+        ResponseSendFundsResult::Failure(reporting_public_key) => reporting_public_key,
+        ResponseSendFundsResult::Success(_) => unreachable!(),
+    };
+
+    assert_eq!(reporting_public_key, public_keys[2]);
+
+    let friend = node_controls[2]
+        .report
+        .friends
+        .get(&public_keys[1])
+        .unwrap();
+    let tc_report = match &friend.channel_status {
+        ChannelStatusReport::Consistent(tc_report) => tc_report,
+        _ => unreachable!(),
+    };
+    assert_eq!(tc_report.balance.balance, -6);
+}
+
+#[test]
+fn test_funder_payment_failure() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_payment_failure(thread_pool.clone()));
+}
+
+async fn task_funder_min_balance(spawner: impl Spawn + Clone + Send + 'static) {
+    /*
+     * 0 -- 1 -- 2
+     * Node 1 keeps a minimum balance of 0 with node 2.
+     * We expect node 1 to refuse forwarding requests that would breach this minimum balance.
+     */
+    let num_nodes = 3;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    // Create topology:
+    // ----------------
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // Add friends:
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    let relays2 = vec![dummy_relay_address(2)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1.clone(), "node1", 8));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", -8));
+    await!(node_controls[1].add_friend(&public_keys[2], relays2, "node2", 6));
+    await!(node_controls[2].add_friend(&public_keys[1], relays1, "node0", -6));
+
+    // Enable friends:
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[2], FriendStatus::Enabled));
+    await!(node_controls[2].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+
+    // Set remote max debt:
+    await!(node_controls[0].set_remote_max_debt(&public_keys[1], 200));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 100));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[2], 300));
+    await!(node_controls[2].set_remote_max_debt(&public_keys[1], 400));
+
+    // Open requests, allowing this route: 0 --> 1 --> 2
+    await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
+    await!(node_controls[2].set_requests_status(&public_keys[1], RequestsStatus::Open));
+
+    // Node 1 keeps a minimum balance of 0 with node 2:
+    let set_friend_min_balance = SetFriendMinBalance {
+        friend_public_key: public_keys[2].clone(),
+        opt_min_balance: Some(0),
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[45; UID_LEN]),
+        FunderControl::SetFriendMinBalance(set_friend_min_balance),
+    );
+    await!(node_controls[1].send(incoming_control_message)).unwrap();
+
+    // Wait until route is ready (Online + Consistent + open requests)
+    await!(node_controls[0].wait_until_ready(&public_keys[1]));
+    await!(node_controls[1].wait_until_ready(&public_keys[2]));
+
+    let route = FriendsRoute {
+        public_keys: vec![
+            public_keys[0].clone(),
+            public_keys[1].clone(),
+            public_keys[2].clone(),
+        ],
+    };
+
+    // Send credits 0 --> 2
+    // Forwarding would leave node 1 with a balance of 6 - 20 = -14 with node 2:
+    let user_request_send_funds = UserRequestSendFunds {
+        request_id: Uid::from(&[3; UID_LEN]),
+        route: route.clone(),
+        invoice_id: InvoiceId::from(&[1; INVOICE_ID_LEN]),
+        dest_payment: 20,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[46; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+    assert_eq!(response_received.request_id, Uid::from(&[3; UID_LEN]));
+    let reporting_public_key = match response_received.result {
+        ResponseSendFundsResult::Failure(reporting_public_key) => reporting_public_key,
+        ResponseSendFundsResult::Success(_) => unreachable!(),
+    };
+    assert_eq!(reporting_public_key, public_keys[1]);
+
+    // A smaller payment keeps node 1 above its minimum balance (6 - 5 = 1):
+    let user_request_send_funds = UserRequestSendFunds {
+        request_id: Uid::from(&[4; UID_LEN]),
+        route,
+        invoice_id: InvoiceId::from(&[2; INVOICE_ID_LEN]),
+        dest_payment: 5,
+        memo: Vec::new(),
+        opt_idempotency_key: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[47; UID_LEN]),
+        FunderControl::RequestSendFunds(user_request_send_funds),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+    assert_eq!(response_received.request_id, Uid::from(&[4; UID_LEN]));
+    match response_received.result {
+        ResponseSendFundsResult::Success(_) => {}
+        ResponseSendFundsResult::Failure(_) => unreachable!(),
+    };
+}
+
+#[test]
+fn test_funder_min_balance() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_min_balance(thread_pool.clone()));
+}
+
+async fn task_funder_max_single_payment(spawner: impl Spawn + Clone + Send + 'static) {
+    /*
+     * 0 -- 1 -- 2
+     * Node 0 caps single payments to node 1 at 10.
+     * Node 1 caps single payments to node 2 at 8.
+     */
+    let num_nodes = 3;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // Add friends:
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    let relays2 = vec![dummy_relay_address(2)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1.clone(), "node1", 0));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", 0));
+    await!(node_controls[1].add_friend(&public_keys[2], relays2, "node2", 0));
+    await!(node_controls[2].add_friend(&public_keys[1], relays1, "node1", 0));
+
+    // Enable friends:
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[2], FriendStatus::Enabled));
+    await!(node_controls[2].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+
+    // Set remote max debt:
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 100));
+    await!(node_controls[2].set_remote_max_debt(&public_keys[1], 100));
+
+    // Open requests, allowing this route: 0 --> 1 --> 2
+    await!(node_controls[1].set_requests_status(&public_keys[0], RequestsStatus::Open));
+    await!(node_controls[2].set_requests_status(&public_keys[1], RequestsStatus::Open));
+
+    // Set caps for single payments:
+    for &(i, j, max_single_payment, app_request_id) in &[(0, 1, 10, 45), (1, 2, 8, 46)] {
+        let set_friend_max_single_payment = SetFriendMaxSinglePayment {
+            friend_public_key: public_keys[j].clone(),
+            opt_max_single_payment: Some(max_single_payment),
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[app_request_id; UID_LEN]),
+            FunderControl::SetFriendMaxSinglePayment(set_friend_max_single_payment),
+        );
+        await!(node_controls[i].send(incoming_control_message)).unwrap();
+    }
+
+    // Wait until route is ready (Online + Consistent + open requests)
+    await!(node_controls[0].wait_until_ready(&public_keys[1]));
+    await!(node_controls[1].wait_until_ready(&public_keys[2]));
+
+    let route = FriendsRoute {
+        public_keys: vec![
+            public_keys[0].clone(),
+            public_keys[1].clone(),
+            public_keys[2].clone(),
+        ],
+    };
+
+    // Node 0 sends dest_payment + 1 credits to node 1 (Including the fee of node 1), and node 1
+    // sends dest_payment credits to node 2.
+    // (dest_payment, expected reporting node for a failure):
+    let payments = vec![
+        // Only the fee of node 1 puts the payment above node 0's cap. Rejected by node 0:
+        (10, Some(public_keys[0].clone())),
+        // At node 0's cap, but above node 1's cap. Rejected by node 1:
+        (9, Some(public_keys[1].clone())),
+        // At node 1's cap:
+        (8, None),
+    ];
+
+    for (i, (dest_payment, opt_reporting_public_key)) in payments.into_iter().enumerate() {
+        let request_id = Uid::from(&[3 + i as u8; UID_LEN]);
+        let user_request_send_funds = UserRequestSendFunds {
+            request_id,
+            route: route.clone(),
+            invoice_id: InvoiceId::from(&[1 + i as u8; INVOICE_ID_LEN]),
+            dest_payment,
+            memo: Vec::new(),
+            opt_idempotency_key: None,
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[50 + i as u8; UID_LEN]),
+            FunderControl::RequestSendFunds(user_request_send_funds),
+        );
+        await!(node_controls[0].send(incoming_control_message)).unwrap();
+        let response_received = await!(node_controls[0].recv_until_response()).unwrap();
+        assert_eq!(response_received.request_id, request_id);
+        match (response_received.result, opt_reporting_public_key) {
+            (ResponseSendFundsResult::Failure(reporting_public_key), Some(expected)) => {
+                assert_eq!(reporting_public_key, expected)
+            }
+            (ResponseSendFundsResult::Success(_), None) => {}
+            _ => unreachable!(),
+        };
+    }
+}
+
+#[test]
+fn test_funder_max_single_payment() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_max_single_payment(thread_pool.clone()));
+}
+
+/// Test a basic inconsistency between two adjacent nodes
+async fn task_funder_inconsistency_basic<S>(spawner: S)
+where
+    S: Spawn + Clone + Send + 'static,
+{
+    let num_nodes = 2;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // We set incompatible initial balances (non zero sum) to cause an inconsistency:
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", 20));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", -8));
+
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+
+    // Expect inconsistency, together with reset terms:
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[1]).unwrap();
+        let channel_inconsistent_report = match &friend.channel_status {
+            ChannelStatusReport::Consistent(_) => return false,
+            ChannelStatusReport::Inconsistent(channel_inconsistent_report) => {
+                channel_inconsistent_report
+            }
+        };
+        if channel_inconsistent_report.local_reset_terms_balance != 20 {
+            return false;
+        }
+        let reset_terms_report = match &channel_inconsistent_report.opt_remote_reset_terms {
+            None => return false,
+            Some(reset_terms_report) => reset_terms_report,
+        };
+        reset_terms_report.balance_for_reset == -8
+    };
+    await!(node_controls[0].recv_until(pred));
+
+    // Resolve inconsistency
+    // ---------------------
+
+    // Obtain reset terms:
+    let friend = node_controls[0]
+        .report
+        .friends
+        .get(&public_keys[1])
+        .unwrap();
+    let channel_inconsistent_report = match &friend.channel_status {
+        ChannelStatusReport::Consistent(_) => unreachable!(),
+        ChannelStatusReport::Inconsistent(channel_inconsistent_report) => {
+            channel_inconsistent_report
+        }
+    };
+    let reset_terms_report = match &channel_inconsistent_report.opt_remote_reset_terms {
+        Some(reset_terms_report) => reset_terms_report,
+        None => unreachable!(),
+    };
+
+    let reset_friend_channel = ResetFriendChannel {
+        friend_public_key: public_keys[1].clone(),
+        reset_token: reset_terms_report.reset_token.clone(), // TODO: Rename reset_token to reset_token?
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[45; UID_LEN]),
+        FunderControl::ResetFriendChannel(reset_friend_channel),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+
+    // Wait until channel is consistent with the correct balance:
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[1]).unwrap();
+        let tc_report = match &friend.channel_status {
+            ChannelStatusReport::Consistent(tc_report) => tc_report,
+            ChannelStatusReport::Inconsistent(_) => return false,
+        };
+        tc_report.balance.balance == 8
+    };
+    await!(node_controls[0].recv_until(pred));
+
+    // Wait until channel is consistent with the correct balance:
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[0]).unwrap();
+        let tc_report = match &friend.channel_status {
+            ChannelStatusReport::Consistent(tc_report) => tc_report,
+            ChannelStatusReport::Inconsistent(_) => return false,
+        };
+        tc_report.balance.balance == -8
+    };
+    await!(node_controls[1].recv_until(pred));
+
+    // Make sure that we manage to send messages over the token channel after resolving the
+    // inconsistency:
+    await!(node_controls[0].set_remote_max_debt(&public_keys[1], 200));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 300));
+}
+
+#[test]
+fn test_funder_inconsistency_basic() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_inconsistency_basic(thread_pool.clone()));
+}
+
+async fn task_funder_reset_confirmed(spawner: impl Spawn + Clone + Send + 'static) {
+    let num_nodes = 2;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // We set incompatible initial balances (non zero sum) to cause an inconsistency:
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", 20));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", -8));
+
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+
+    // Wait until node0 sees the reset terms of node1:
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[1]).unwrap();
+        match &friend.channel_status {
+            ChannelStatusReport::Consistent(_) => false,
+            ChannelStatusReport::Inconsistent(channel_inconsistent_report) => {
+                channel_inconsistent_report.opt_remote_reset_terms.is_some()
+            }
+        }
+    };
+    await!(node_controls[0].recv_until(pred));
+
+    // No reset took place yet:
+    let friend = node_controls[0]
+        .report
+        .friends
+        .get(&public_keys[1])
+        .unwrap();
+    assert!(!friend.reset_confirmed);
+    let reset_token = match &friend.channel_status {
+        ChannelStatusReport::Consistent(_) => unreachable!(),
+        ChannelStatusReport::Inconsistent(channel_inconsistent_report) => {
+            channel_inconsistent_report
+                .opt_remote_reset_terms
+                .clone()
+                .unwrap()
+                .reset_token
+        }
+    };
+
+    let reset_friend_channel = ResetFriendChannel {
+        friend_public_key: public_keys[1].clone(),
+        reset_token,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[46; UID_LEN]),
+        FunderControl::ResetFriendChannel(reset_friend_channel),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+
+    // node1 verifies the signed reset move token of node0 against its own reset terms:
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[0]).unwrap();
+        match &friend.channel_status {
+            ChannelStatusReport::Consistent(tc_report) => {
+                tc_report.balance.balance == -8 && friend.reset_confirmed
+            }
+            ChannelStatusReport::Inconsistent(_) => false,
+        }
+    };
+    await!(node_controls[1].recv_until(pred));
+
+    // node1 acknowledges the reset move token right away, by sending back a move token signed on
+    // top of it. This confirms the reset for node0:
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[1]).unwrap();
+        match &friend.channel_status {
+            ChannelStatusReport::Consistent(tc_report) => {
+                tc_report.balance.balance == 8 && friend.reset_confirmed
+            }
+            ChannelStatusReport::Inconsistent(_) => false,
+        }
+    };
+    await!(node_controls[0].recv_until(pred));
+}
+
+#[test]
+fn test_funder_reset_confirmed() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_reset_confirmed(thread_pool.clone()));
+}
+
+async fn task_funder_get_reset_token(spawner: impl Spawn + Clone + Send + 'static) {
+    let num_nodes = 2;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", 20));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", -8));
+
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+
+    // Wait until node0 sees the reset terms of node1:
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[1]).unwrap();
+        match &friend.channel_status {
+            ChannelStatusReport::Consistent(_) => false,
+            ChannelStatusReport::Inconsistent(channel_inconsistent_report) => {
+                channel_inconsistent_report.opt_remote_reset_terms.is_some()
+            }
+        }
+    };
+    await!(node_controls[0].recv_until(pred));
+
+    let friend = node_controls[0]
+        .report
+        .friends
+        .get(&public_keys[1])
+        .unwrap();
+    let remote_reset_terms = match &friend.channel_status {
+        ChannelStatusReport::Consistent(_) => unreachable!(),
+        ChannelStatusReport::Inconsistent(channel_inconsistent_report) => {
+            channel_inconsistent_report
+                .opt_remote_reset_terms
+                .clone()
+                .unwrap()
+        }
     };
+
+    // The reset token node1 reports locally matches the one node0 received:
     let incoming_control_message = FunderIncomingControl::new(
-        Uid::from(&[45; UID_LEN]),
+        Uid::from(&[40; UID_LEN]),
+        FunderControl::GetResetToken(public_keys[0].clone()),
+    );
+    await!(node_controls[1].send(incoming_control_message)).unwrap();
+    let friend_reset_token = await!(node_controls[1].recv_until_reset_token()).unwrap();
+    assert_eq!(friend_reset_token.friend_public_key, public_keys[0]);
+    assert_eq!(
+        friend_reset_token.reset_token,
+        remote_reset_terms.reset_token
+    );
+    assert_eq!(
+        friend_reset_token.balance_for_reset,
+        remote_reset_terms.balance_for_reset
+    );
+    assert_eq!(friend_reset_token.balance_for_reset, -8);
+}
+
+#[test]
+fn test_funder_get_reset_token() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_get_reset_token(thread_pool.clone()));
+}
+
+/// Force an inconsistency over a channel with balanced terms, and then resolve it using the
+/// regular reset flow:
+#[cfg(feature = "force-inconsistency")]
+async fn task_funder_force_inconsistency<S>(spawner: S)
+where
+    S: Spawn + Clone + Send + 'static,
+{
+    use crypto::identity::{Signature, SIGNATURE_LEN};
+    use proto::funder::messages::{ForceInconsistency, ResetTerms};
+
+    let num_nodes = 2;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", 8));
+    await!(node_controls[1].add_friend(&public_keys[0], relays0, "node0", -8));
+
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+    await!(node_controls[1].set_friend_status(&public_keys[0], FriendStatus::Enabled));
+
+    await!(node_controls[0].set_remote_max_debt(&public_keys[1], 200));
+    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 100));
+
+    // Only the side holding the token may force an inconsistency:
+    let friend = node_controls[0]
+        .report
+        .friends
+        .get(&public_keys[1])
+        .unwrap();
+    let (i, j) = match &friend.channel_status {
+        ChannelStatusReport::Consistent(tc_report) if tc_report.direction.is_incoming() => (0, 1),
+        _ => (1, 0),
+    };
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[j]).unwrap();
+        match &friend.channel_status {
+            ChannelStatusReport::Consistent(tc_report) => tc_report.direction.is_incoming(),
+            ChannelStatusReport::Inconsistent(_) => false,
+        }
+    };
+    await!(node_controls[i].recv_until(pred));
+
+    // Offer reset terms that keep the current balance:
+    let force_inconsistency = ForceInconsistency {
+        friend_public_key: public_keys[j].clone(),
+        reset_terms: ResetTerms {
+            reset_token: Signature::from(&[46; SIGNATURE_LEN]),
+            inconsistency_counter: 1,
+            balance_for_reset: if i == 0 { 8 } else { -8 },
+        },
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[46; UID_LEN]),
+        FunderControl::ForceInconsistency(force_inconsistency),
+    );
+    await!(node_controls[i].send(incoming_control_message)).unwrap();
+
+    // Both sides should become inconsistent, each side having the reset terms of the
+    // remote side:
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[j]).unwrap();
+        match &friend.channel_status {
+            ChannelStatusReport::Consistent(_) => false,
+            ChannelStatusReport::Inconsistent(channel_inconsistent_report) => {
+                channel_inconsistent_report.opt_remote_reset_terms.is_some()
+            }
+        }
+    };
+    await!(node_controls[i].recv_until(pred));
+
+    // Resolve inconsistency
+    // ---------------------
+    let friend = node_controls[i]
+        .report
+        .friends
+        .get(&public_keys[j])
+        .unwrap();
+    let reset_terms_report = match &friend.channel_status {
+        ChannelStatusReport::Consistent(_) => unreachable!(),
+        ChannelStatusReport::Inconsistent(channel_inconsistent_report) => {
+            channel_inconsistent_report
+                .opt_remote_reset_terms
+                .clone()
+                .unwrap()
+        }
+    };
+
+    let reset_friend_channel = ResetFriendChannel {
+        friend_public_key: public_keys[j].clone(),
+        reset_token: reset_terms_report.reset_token.clone(),
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[47; UID_LEN]),
         FunderControl::ResetFriendChannel(reset_friend_channel),
     );
-    await!(node_controls[0].send(incoming_control_message)).unwrap();
+    await!(node_controls[i].send(incoming_control_message)).unwrap();
 
-    // Wait until channel is consistent with the correct balance:
+    // The balances should remain as they were before the inconsistency:
     let pred = |report: &FunderReport<_>| {
         let friend = report.friends.get(&public_keys[1]).unwrap();
-        let tc_report = match &friend.channel_status {
-            ChannelStatusReport::Consistent(tc_report) => tc_report,
-            ChannelStatusReport::Inconsistent(_) => return false,
-        };
-        tc_report.balance.balance == 8
+        match &friend.channel_status {
+            ChannelStatusReport::Consistent(tc_report) => tc_report.balance.balance == 8,
+            ChannelStatusReport::Inconsistent(_) => false,
+        }
     };
     await!(node_controls[0].recv_until(pred));
 
-    // Wait until channel is consistent with the correct balance:
     let pred = |report: &FunderReport<_>| {
         let friend = report.friends.get(&public_keys[0]).unwrap();
-        let tc_report = match &friend.channel_status {
-            ChannelStatusReport::Consistent(tc_report) => tc_report,
-            ChannelStatusReport::Inconsistent(_) => return false,
-        };
-        tc_report.balance.balance == -8
+        match &friend.channel_status {
+            ChannelStatusReport::Consistent(tc_report) => tc_report.balance.balance == -8,
+            ChannelStatusReport::Inconsistent(_) => false,
+        }
     };
     await!(node_controls[1].recv_until(pred));
-
-    // Make sure that we manage to send messages over the token channel after resolving the
-    // inconsistency:
-    await!(node_controls[0].set_remote_max_debt(&public_keys[1], 200));
-    await!(node_controls[1].set_remote_max_debt(&public_keys[0], 300));
 }
 
+#[cfg(feature = "force-inconsistency")]
 #[test]
-fn test_funder_inconsistency_basic() {
+fn test_funder_force_inconsistency() {
     let mut thread_pool = ThreadPool::new().unwrap();
-    thread_pool.run(task_funder_inconsistency_basic(thread_pool.clone()));
+    thread_pool.run(task_funder_force_inconsistency(thread_pool.clone()));
 }
 
 /// Test setting relay address for local node
@@ -433,3 +2122,551 @@ fn test_funder_add_relay() {
     let mut thread_pool = ThreadPool::new().unwrap();
     thread_pool.run(task_funder_add_relay(thread_pool.clone()));
 }
+
+/// A friend that never becomes ready should fail the test after a bounded amount of time,
+/// instead of leaving it stuck.
+async fn task_funder_wait_until_ready_timeout(spawner: impl Spawn + Clone + Send + 'static) {
+    let num_nodes = 2;
+    let mut node_controls = await!(create_node_controls(num_nodes, spawner));
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // Only node0 adds node1 as a friend. node1 never adds node0, so the friendship
+    // is never ready:
+    let relays1 = vec![dummy_relay_address(1)];
+    await!(node_controls[0].add_friend(&public_keys[1], relays1, "node1", 0));
+    await!(node_controls[0].set_friend_status(&public_keys[1], FriendStatus::Enabled));
+
+    await!(node_controls[0].wait_until_ready_ticks(&public_keys[1], 8));
+}
+
+#[test]
+#[should_panic(expected = "Timed out after 8 ticks")]
+fn test_funder_wait_until_ready_timeout() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_wait_until_ready_timeout(thread_pool.clone()));
+}
+
+async fn task_funder_shutdown(mut spawner: impl Spawn + Clone + Send + 'static) {
+    let rng = DummyRandom::new(&[0u8]);
+    let pkcs8 = generate_pkcs8_key_pair(&rng);
+    let identity = SoftwareEd25519Identity::from_pkcs8(&pkcs8).unwrap();
+    let (requests_sender, identity_server) = create_identity(identity);
+    let identity_client = IdentityClient::new(requests_sender);
+    spawner
+        .spawn(identity_server.then(|_| future::ready(())))
+        .unwrap();
+
+    let public_key = await!(identity_client.request_public_key()).unwrap();
+    let funder_state = FunderState::new(public_key, vec![dummy_named_relay_address(0)]);
+
+    let (db_request_sender, mut incoming_db_requests) = mpsc::channel(0);
+    let db_client = DatabaseClient::new(db_request_sender);
+
+    let (mut send_control, incoming_control) = mpsc::channel(0x10);
+    let (control_sender, _recv_control) = mpsc::channel(0x10);
+    let (_send_comm, incoming_comm) = mpsc::channel(0x10);
+    let (comm_sender, _recv_comm) = mpsc::channel(0x10);
+
+    let (mut tick_sender_receiver, timer_client) = dummy_timer_multi_sender(spawner.clone());
+    let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+
+    let funder_fut = inner_funder_loop(
+        identity_client,
+        timer_client,
+        DummyRandom::new(&[0u8]),
+        incoming_control,
+        incoming_comm,
+        control_sender,
+        comm_sender,
+        funder_state,
+        db_client,
+        TEST_FUNDER_CONFIG,
+        ControlStats::new(),
+        None,
+        Some(shutdown_receiver),
+        None,
+        None,
+        None,
+    );
+    let funder_handle = spawner.spawn_with_handle(funder_fut).unwrap();
+    let _tick_sender = await!(tick_sender_receiver.next()).unwrap();
+
+    // Adding a friend results in a mutation:
+    let friend_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+    let add_friend = AddFriend {
+        friend_public_key: friend_public_key.clone(),
+        relays: vec![dummy_relay_address(1)],
+        name: "friend".into(),
+        balance: 0,
+        opt_remote_max_debt: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[0; UID_LEN]),
+        FunderControl::AddFriend(add_friend),
+    );
+    await!(send_control.send(incoming_control_message)).unwrap();
+
+    let db_request = await!(incoming_db_requests.next()).unwrap();
+    match &db_request.mutations[..] {
+        [FunderMutation::AddFriend(add_friend)] => {
+            assert_eq!(add_friend.friend_public_key, friend_public_key)
+        }
+        _ => unreachable!(),
+    };
+
+    // Shutdown before the database acknowledges the mutation:
+    shutdown_sender.send(()).unwrap();
+    let _ = db_request.response_sender.send(());
+
+    // The loop resolves after the mutation was flushed:
+    assert!(await!(funder_handle).is_ok());
+
+    // No more database requests are possible after the loop has exited:
+    assert!(await!(incoming_db_requests.next()).is_none());
+}
+
+#[test]
+fn test_funder_shutdown() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_shutdown(thread_pool.clone()));
+}
+
+async fn task_funder_batch_db_writes(mut spawner: impl Spawn + Clone + Send + 'static) {
+    let rng = DummyRandom::new(&[0u8]);
+    let pkcs8 = generate_pkcs8_key_pair(&rng);
+    let identity = SoftwareEd25519Identity::from_pkcs8(&pkcs8).unwrap();
+    let (requests_sender, identity_server) = create_identity(identity);
+    let identity_client = IdentityClient::new(requests_sender);
+    spawner
+        .spawn(identity_server.then(|_| future::ready(())))
+        .unwrap();
+
+    let public_key = await!(identity_client.request_public_key()).unwrap();
+    let funder_state = FunderState::new(public_key, vec![dummy_named_relay_address(0)]);
+
+    let (db_request_sender, mut incoming_db_requests) = mpsc::channel(0);
+    let db_client = DatabaseClient::new(db_request_sender);
+
+    let (mut send_control, incoming_control) = mpsc::channel(0x10);
+    let (control_sender, mut recv_control) = mpsc::channel(0x10);
+    let (_send_comm, incoming_comm) = mpsc::channel(0x10);
+    let (comm_sender, _recv_comm) = mpsc::channel(0x10);
+
+    let (mut tick_sender_receiver, timer_client) = dummy_timer_multi_sender(spawner.clone());
+
+    // Queue a few control messages before the funder starts, so that they are all immediately
+    // available:
+    let num_friends = 3u8;
+    for i in 0..num_friends {
+        let add_friend = AddFriend {
+            friend_public_key: PublicKey::from(&[0xaa + i; PUBLIC_KEY_LEN]),
+            relays: vec![dummy_relay_address(1)],
+            name: format!("friend{}", i),
+            balance: 0,
+            opt_remote_max_debt: None,
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[i; UID_LEN]),
+            FunderControl::AddFriend(add_friend),
+        );
+        await!(send_control.send(incoming_control_message)).unwrap();
+    }
+
+    let funder_fut = inner_funder_loop(
+        identity_client,
+        timer_client,
+        DummyRandom::new(&[0u8]),
+        incoming_control,
+        incoming_comm,
+        control_sender,
+        comm_sender,
+        funder_state,
+        db_client,
+        TEST_FUNDER_CONFIG,
+        ControlStats::new(),
+        None,
+        None,
+        None,
+        None,
+        None,
+    );
+    spawner
+        .spawn(funder_fut.then(|_| future::ready(())))
+        .unwrap();
+    let _tick_sender = await!(tick_sender_receiver.next()).unwrap();
+
+    // All the mutations are written using a single database request:
+    let db_request = await!(incoming_db_requests.next()).unwrap();
+    assert_eq!(db_request.mutations.len(), usize::from(num_friends));
+    for (i, mutation) in db_request.mutations.iter().enumerate() {
+        match mutation {
+            FunderMutation::AddFriend(add_friend) => assert_eq!(
+                add_friend.friend_public_key,
+                PublicKey::from(&[0xaa + i as u8; PUBLIC_KEY_LEN])
+            ),
+            _ => unreachable!(),
+        }
+    }
+
+    // Nothing is reported to the user before the database acknowledges the mutations:
+    assert!(recv_control.try_next().is_err());
+    db_request.response_sender.send(()).unwrap();
+
+    // Every control message is acknowledged:
+    for i in 0..num_friends {
+        match await!(recv_control.next()).unwrap() {
+            FunderOutgoingControl::ReportMutations(report_mutations) => assert_eq!(
+                report_mutations.opt_app_request_id,
+                Some(Uid::from(&[i; UID_LEN]))
+            ),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[test]
+fn test_funder_batch_db_writes() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_batch_db_writes(thread_pool.clone()));
+}
+
+async fn task_funder_flush(mut spawner: impl Spawn + Clone + Send + 'static) {
+    let rng = DummyRandom::new(&[0u8]);
+    let pkcs8 = generate_pkcs8_key_pair(&rng);
+    let identity = SoftwareEd25519Identity::from_pkcs8(&pkcs8).unwrap();
+    let (requests_sender, identity_server) = create_identity(identity);
+    let identity_client = IdentityClient::new(requests_sender);
+    spawner
+        .spawn(identity_server.then(|_| future::ready(())))
+        .unwrap();
+
+    let public_key = await!(identity_client.request_public_key()).unwrap();
+    let funder_state = FunderState::new(public_key, vec![dummy_named_relay_address(0)]);
+
+    let (db_request_sender, mut incoming_db_requests) = mpsc::channel(0);
+    let db_client = DatabaseClient::new(db_request_sender);
+
+    let (mut send_control, incoming_control) = mpsc::channel(0x10);
+    let (control_sender, _recv_control) = mpsc::channel(0x10);
+    let (_send_comm, incoming_comm) = mpsc::channel(0x10);
+    let (comm_sender, _recv_comm) = mpsc::channel(0x10);
+
+    let (mut tick_sender_receiver, timer_client) = dummy_timer_multi_sender(spawner.clone());
+    let (mut flush_sender, flush_receiver) = mpsc::channel(0);
+
+    let funder_fut = inner_funder_loop(
+        identity_client,
+        timer_client,
+        DummyRandom::new(&[0u8]),
+        incoming_control,
+        incoming_comm,
+        control_sender,
+        comm_sender,
+        funder_state,
+        db_client,
+        TEST_FUNDER_CONFIG,
+        ControlStats::new(),
+        None,
+        None,
+        Some(flush_receiver),
+        None,
+        None,
+    );
+    spawner
+        .spawn(funder_fut.then(|_| future::ready(())))
+        .unwrap();
+    let _tick_sender = await!(tick_sender_receiver.next()).unwrap();
+
+    // Adding a friend results in a mutation:
+    let friend_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+    let add_friend = AddFriend {
+        friend_public_key: friend_public_key.clone(),
+        relays: vec![dummy_relay_address(1)],
+        name: "friend".into(),
+        balance: 0,
+        opt_remote_max_debt: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[0; UID_LEN]),
+        FunderControl::AddFriend(add_friend),
+    );
+    await!(send_control.send(incoming_control_message)).unwrap();
+
+    let db_request = await!(incoming_db_requests.next()).unwrap();
+    match &db_request.mutations[..] {
+        [FunderMutation::AddFriend(add_friend)] => {
+            assert_eq!(add_friend.friend_public_key, friend_public_key)
+        }
+        _ => unreachable!(),
+    };
+
+    // Request a flush before the database acknowledges the mutation:
+    let (flush_done_sender, mut flush_done) = oneshot::channel();
+    await!(flush_sender.send(flush_done_sender)).unwrap();
+    assert_eq!(flush_done.try_recv(), Ok(None));
+
+    // The flush is done only after the pending mutation was acknowledged:
+    db_request.response_sender.send(()).unwrap();
+    await!(flush_done).unwrap();
+}
+
+#[test]
+fn test_funder_flush() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_flush(thread_pool.clone()));
+}
+
+async fn task_funder_tick_flush(mut spawner: impl Spawn + Clone + Send + 'static) {
+    let rng = DummyRandom::new(&[0u8]);
+    let pkcs8 = generate_pkcs8_key_pair(&rng);
+    let identity = SoftwareEd25519Identity::from_pkcs8(&pkcs8).unwrap();
+    let (requests_sender, identity_server) = create_identity(identity);
+    let identity_client = IdentityClient::new(requests_sender);
+    spawner
+        .spawn(identity_server.then(|_| future::ready(())))
+        .unwrap();
+
+    let public_key = await!(identity_client.request_public_key()).unwrap();
+    let funder_state = FunderState::new(public_key, vec![dummy_named_relay_address(0)]);
+
+    let (db_request_sender, mut incoming_db_requests) = mpsc::channel(0);
+    let db_client = DatabaseClient::new(db_request_sender);
+
+    let (mut send_control, incoming_control) = mpsc::channel(0x10);
+    let (control_sender, _recv_control) = mpsc::channel(0x10);
+    let (_send_comm, incoming_comm) = mpsc::channel(0x10);
+    let (comm_sender, _recv_comm) = mpsc::channel(0x10);
+
+    let (mut tick_sender_receiver, timer_client) = dummy_timer_multi_sender(spawner.clone());
+
+    let funder_fut = inner_funder_loop(
+        identity_client,
+        timer_client,
+        DummyRandom::new(&[0u8]),
+        incoming_control,
+        incoming_comm,
+        control_sender,
+        comm_sender,
+        funder_state,
+        db_client,
+        TEST_FUNDER_CONFIG,
+        ControlStats::new(),
+        None,
+        None,
+        None,
+        None,
+        None,
+    );
+    spawner
+        .spawn(funder_fut.then(|_| future::ready(())))
+        .unwrap();
+    let mut tick_sender = await!(tick_sender_receiver.next()).unwrap();
+
+    // Adding a friend results in a mutation:
+    let friend_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+    let add_friend = AddFriend {
+        friend_public_key: friend_public_key.clone(),
+        relays: vec![dummy_relay_address(1)],
+        name: "friend".into(),
+        balance: 0,
+        opt_remote_max_debt: None,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[0; UID_LEN]),
+        FunderControl::AddFriend(add_friend),
+    );
+    await!(send_control.send(incoming_control_message)).unwrap();
+
+    let db_request = await!(incoming_db_requests.next()).unwrap();
+    match &db_request.mutations[..] {
+        [FunderMutation::AddFriend(add_friend)] => {
+            assert_eq!(add_friend.friend_public_key, friend_public_key)
+        }
+        _ => unreachable!(),
+    };
+
+    // While the database has not yet acknowledged the mutation, the friend is enabled and a timer
+    // tick arrives:
+    let set_friend_status = SetFriendStatus {
+        friend_public_key: friend_public_key.clone(),
+        status: FriendStatus::Enabled,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[1; UID_LEN]),
+        FunderControl::SetFriendStatus(set_friend_status),
+    );
+    await!(send_control.send(incoming_control_message)).unwrap();
+    await!(tick_sender.send(TimerTick)).unwrap();
+    db_request.response_sender.send(()).unwrap();
+
+    // The timer tick does not hold back the mutations handled together with it:
+    let db_request = await!(incoming_db_requests.next()).unwrap();
+    assert!(db_request
+        .mutations
+        .iter()
+        .any(|funder_mutation| match funder_mutation {
+            FunderMutation::FriendMutation((
+                public_key,
+                FriendMutation::SetStatus(FriendStatus::Enabled),
+            )) => public_key == &friend_public_key,
+            _ => false,
+        }));
+    db_request.response_sender.send(()).unwrap();
+
+    // The Funder keeps handling timer ticks after the batch was flushed:
+    await!(tick_sender.send(TimerTick)).unwrap();
+    await!(tick_sender.send(TimerTick)).unwrap();
+}
+
+#[test]
+fn test_funder_tick_flush() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_tick_flush(thread_pool.clone()));
+}
+
+async fn task_funder_warm_friend_timeout(spawner: impl Spawn + Clone + Send + 'static) {
+    let mut node_controls = await!(create_node_controls(1, spawner));
+
+    // The friend does not exist, hence it never becomes ready:
+    let friend_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+    let relays = vec![dummy_relay_address(1)];
+    await!(node_controls[0].add_friend(&friend_public_key, relays, "friend", 0));
+    await!(node_controls[0].set_friend_status(&friend_public_key, FriendStatus::Enabled));
+
+    let warm_request_id = Uid::from(&[1; UID_LEN]);
+    let incoming_control_message = FunderIncomingControl::new(
+        warm_request_id.clone(),
+        FunderControl::WarmFriend(friend_public_key.clone()),
+    );
+    await!(node_controls[0].send(incoming_control_message)).unwrap();
+
+    // Wait until the warm request was handled:
+    loop {
+        if let NodeRecv::ReportMutations(report_mutations) =
+            await!(node_controls[0].recv()).unwrap()
+        {
+            if report_mutations.opt_app_request_id == Some(warm_request_id.clone()) {
+                break;
+            }
+        }
+    }
+
+    // The friend is reported as not ready once the timeout elapses:
+    await!(node_controls[0].advance_ticks(WARM_FRIEND_TIMEOUT_TICKS));
+    loop {
+        if let NodeRecv::FriendWarmed(friend_warmed) = await!(node_controls[0].recv()).unwrap() {
+            assert_eq!(friend_warmed.friend_public_key, friend_public_key);
+            assert!(!friend_warmed.is_ready);
+            break;
+        }
+    }
+}
+
+#[test]
+fn test_funder_warm_friend_timeout() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_warm_friend_timeout(thread_pool.clone()));
+}
+
+async fn task_funder_report_subscription(mut spawner: impl Spawn + Clone + Send + 'static) {
+    let rng = DummyRandom::new(&[0u8]);
+    let pkcs8 = generate_pkcs8_key_pair(&rng);
+    let identity = SoftwareEd25519Identity::from_pkcs8(&pkcs8).unwrap();
+    let (requests_sender, identity_server) = create_identity(identity);
+    let identity_client = IdentityClient::new(requests_sender);
+    spawner
+        .spawn(identity_server.then(|_| future::ready(())))
+        .unwrap();
+
+    let public_key = await!(identity_client.request_public_key()).unwrap();
+    let funder_state = FunderState::new(public_key, vec![dummy_named_relay_address(0)]);
+
+    let (db_request_sender, mut incoming_db_requests) = mpsc::channel(0);
+    let db_client = DatabaseClient::new(db_request_sender);
+    spawner
+        .spawn(
+            async move {
+                while let Some(request) = await!(incoming_db_requests.next()) {
+                    let _ = request.response_sender.send(());
+                }
+            },
+        )
+        .unwrap();
+
+    let (mut send_control, incoming_control) = mpsc::channel(0);
+    let (control_sender, mut recv_control) = mpsc::channel(0);
+    let (_send_comm, incoming_comm) = mpsc::channel(0);
+    let (comm_sender, _recv_comm) = mpsc::channel(0);
+    let (report_sender, mut report_receiver) = latest_channel();
+
+    let (mut tick_sender_receiver, timer_client) = dummy_timer_multi_sender(spawner.clone());
+
+    let funder_fut = inner_funder_loop(
+        identity_client,
+        timer_client,
+        DummyRandom::new(&[0u8]),
+        incoming_control,
+        incoming_comm,
+        control_sender,
+        comm_sender,
+        funder_state,
+        db_client,
+        TEST_FUNDER_CONFIG,
+        ControlStats::new(),
+        None,
+        None,
+        None,
+        Some(report_sender),
+        None,
+    );
+    spawner
+        .spawn(funder_fut.then(|_| future::ready(())))
+        .unwrap();
+    let _tick_sender = await!(tick_sender_receiver.next()).unwrap();
+
+    // The report subscriber does not read any reports. The Funder keeps handling control
+    // messages regardless:
+    let num_friends = 8u8;
+    for i in 0..num_friends {
+        let add_friend = AddFriend {
+            friend_public_key: PublicKey::from(&[0xaa + i; PUBLIC_KEY_LEN]),
+            relays: vec![dummy_relay_address(1)],
+            name: format!("friend{}", i),
+            balance: 0,
+            opt_remote_max_debt: None,
+        };
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[i; UID_LEN]),
+            FunderControl::AddFriend(add_friend),
+        );
+        await!(send_control.send(incoming_control_message)).unwrap();
+        match await!(recv_control.next()).unwrap() {
+            FunderOutgoingControl::ReportMutations(report_mutations) => assert_eq!(
+                report_mutations.opt_app_request_id,
+                Some(Uid::from(&[i; UID_LEN]))
+            ),
+            _ => unreachable!(),
+        }
+    }
+
+    // Intermediate reports are skipped, but the subscriber eventually sees the latest report:
+    let mut num_reports = 0;
+    loop {
+        let report = await!(report_receiver.next()).unwrap();
+        num_reports += 1;
+        if report.friends.len() == usize::from(num_friends) {
+            break;
+        }
+    }
+    assert!(num_reports < usize::from(num_friends));
+}
+
+#[test]
+fn test_funder_report_subscription() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_funder_report_subscription(thread_pool.clone()));
+}
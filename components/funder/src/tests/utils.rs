@@ -1,4 +1,6 @@
 use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::time::Duration;
 
 use common::canonical_serialize::CanonicalSerialize;
 use common::mutable_state::MutableState;
@@ -7,9 +9,7 @@ use futures::channel::mpsc;
 use futures::task::{Spawn, SpawnExt};
 use futures::{future, FutureExt, SinkExt, StreamExt};
 
-use crypto::identity::{
-    generate_pkcs8_key_pair, PublicKey, SoftwareEd25519Identity, PUBLIC_KEY_LEN,
-};
+use crypto::identity::{generate_pkcs8_key_pair, PublicKey, SoftwareEd25519Identity};
 use crypto::test_utils::DummyRandom;
 use crypto::uid::{Uid, UID_LEN};
 
@@ -20,26 +20,39 @@ use proto::report::messages::{
 
 use proto::app_server::messages::{NamedRelayAddress, RelayAddress};
 use proto::funder::messages::{
-    AddFriend, FriendStatus, FunderControl, FunderIncomingControl, FunderOutgoingControl,
-    RequestsStatus, ResponseReceived, SetFriendRemoteMaxDebt, SetFriendStatus, SetRequestsStatus,
+    AckedReceipt, AddFriend, FirstHopSuggestion, FriendResetToken, FriendStatus, FriendWarmed,
+    FunderControl, FunderIncomingControl, FunderOutgoingControl, FundsReceived, PaymentSimulation,
+    PendingFriendRequest, RemoveFriendConsequences, RequestsStatus, ResponseReceived,
+    SetFriendRemoteMaxDebt, SetFriendStatus, SetRequestsStatus,
 };
+pub use proto::test_utils::{dummy_named_relay_address, dummy_relay_address};
 
 use database::DatabaseClient;
 
 use identity::{create_identity, IdentityClient};
+use timer::utils::future_timeout;
+use timer::{create_timer, dummy_timer_multi_sender, TimerClient, TimerTick};
 
+use crate::control_stats::ControlStats;
 use crate::ephemeral::Ephemeral;
 use crate::funder::inner_funder_loop;
 use crate::report::create_report;
 use crate::state::FunderState;
 
 use crate::types::{
-    ChannelerConfig, FunderIncomingComm, FunderOutgoingComm, IncomingLivenessMessage,
+    ChannelerConfig, FunderConfig, FunderIncomingComm, FunderOutgoingComm, IncomingLivenessMessage,
 };
 
-const TEST_MAX_NODE_RELAYS: usize = 16;
-const TEST_MAX_OPERATIONS_IN_BATCH: usize = 16;
-const TEST_MAX_PENDING_USER_REQUESTS: usize = 16;
+pub const TEST_FUNDER_CONFIG: FunderConfig = FunderConfig {
+    max_operations_in_batch: 16,
+    max_node_relays: 16,
+    max_pending_user_requests: 16,
+    max_pending_requests: 16,
+    monitor_duplicate_move_tokens: false,
+    reject_duplicate_invoice_id: true,
+    max_recent_receipts: 16,
+    max_send_friends: 16,
+};
 
 // This is required to make sure the tests are not stuck.
 //
@@ -48,19 +61,12 @@ const TEST_MAX_PENDING_USER_REQUESTS: usize = 16;
 // approach makes tests difficult to write.
 const CHANNEL_SIZE: usize = 64;
 
-/// A helper function to quickly create a dummy NamedRelayAddress.
-pub fn dummy_named_relay_address(index: u8) -> NamedRelayAddress<u32> {
-    NamedRelayAddress {
-        public_key: PublicKey::from(&[index; PUBLIC_KEY_LEN]),
-        address: index as u32,
-        name: format!("relay-{}", index),
-    }
-}
+/// Duration of a timer tick, used to bound the waiting time of tests.
+const TEST_TICK_MS: u64 = 10;
 
-/// A helper function to quickly create a dummy RelayAddress.
-pub fn dummy_relay_address(index: u8) -> RelayAddress<u32> {
-    dummy_named_relay_address(index).into()
-}
+/// Default maximum amount of timer ticks we wait for a node to reach an expected state.
+/// When exceeded, the test fails instead of being stuck forever.
+const TEST_MAX_WAIT_TICKS: usize = 0x1000;
 
 #[derive(Debug)]
 struct Node<B> {
@@ -118,7 +124,8 @@ async fn router_handle_outgoing_comm<'a, B: 'a>(
                         await!(comm_out.send(incoming_comm_message)).unwrap();
                     }
                 }
-                ChannelerConfig::RemoveFriend(friend_public_key) => {
+                ChannelerConfig::RemoveFriend(friend_public_key)
+                | ChannelerConfig::PurgeFriend(friend_public_key) => {
                     let node = nodes.get_mut(&src_public_key).unwrap();
                     assert!(node.friends.remove(&friend_public_key));
                     let mut comm_out = node.comm_out.clone();
@@ -198,6 +205,9 @@ pub struct NodeControl<B: Clone> {
     pub public_key: PublicKey,
     send_control: mpsc::Sender<FunderIncomingControl<B>>,
     recv_control: mpsc::Receiver<FunderOutgoingControl<B>>,
+    timer_client: TimerClient,
+    /// Sends timer ticks to the Funder of this node.
+    tick_sender: mpsc::Sender<TimerTick>,
     pub report: FunderReport<B>,
 }
 
@@ -205,16 +215,31 @@ pub struct NodeControl<B: Clone> {
 pub enum NodeRecv<B: Clone> {
     ReportMutations(FunderReportMutations<B>),
     ResponseReceived(ResponseReceived),
+    FirstHopSuggestion(FirstHopSuggestion),
+    PendingRequests(Vec<PendingFriendRequest>),
+    FriendWarmed(FriendWarmed),
+    FundsReceived(FundsReceived),
+    PaymentSimulation(PaymentSimulation),
+    RecentReceipts(Vec<AckedReceipt>),
+    ResetToken(FriendResetToken),
+    RemoveFriendConsequences(RemoveFriendConsequences),
 }
 
 impl<B> NodeControl<B>
 where
-    B: Clone + PartialEq + Eq + CanonicalSerialize,
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
 {
     pub async fn send(&mut self, msg: FunderIncomingControl<B>) -> Option<()> {
         await!(self.send_control.send(msg)).ok().map(|_| ())
     }
 
+    /// Send `num_ticks` timer ticks to the Funder of this node.
+    pub async fn advance_ticks(&mut self, num_ticks: u64) {
+        for _ in 0..num_ticks {
+            await!(self.tick_sender.send(TimerTick)).unwrap();
+        }
+    }
+
     pub async fn recv(&mut self) -> Option<NodeRecv<B>> {
         let funder_outgoing_control = await!(self.recv_control.next())?;
         match funder_outgoing_control {
@@ -227,6 +252,30 @@ where
             FunderOutgoingControl::ResponseReceived(response_received) => {
                 Some(NodeRecv::ResponseReceived(response_received))
             }
+            FunderOutgoingControl::FirstHopSuggestion(first_hop_suggestion) => {
+                Some(NodeRecv::FirstHopSuggestion(first_hop_suggestion))
+            }
+            FunderOutgoingControl::PendingRequests(pending_requests) => {
+                Some(NodeRecv::PendingRequests(pending_requests))
+            }
+            FunderOutgoingControl::FriendWarmed(friend_warmed) => {
+                Some(NodeRecv::FriendWarmed(friend_warmed))
+            }
+            FunderOutgoingControl::FundsReceived(funds_received) => {
+                Some(NodeRecv::FundsReceived(funds_received))
+            }
+            FunderOutgoingControl::PaymentSimulation(payment_simulation) => {
+                Some(NodeRecv::PaymentSimulation(payment_simulation))
+            }
+            FunderOutgoingControl::RecentReceipts(recent_receipts) => {
+                Some(NodeRecv::RecentReceipts(recent_receipts))
+            }
+            FunderOutgoingControl::ResetToken(friend_reset_token) => {
+                Some(NodeRecv::ResetToken(friend_reset_token))
+            }
+            FunderOutgoingControl::RemoveFriendConsequences(remove_friend_consequences) => Some(
+                NodeRecv::RemoveFriendConsequences(remove_friend_consequences),
+            ),
         }
     }
 
@@ -234,19 +283,140 @@ where
     where
         P: Fn(&FunderReport<B>) -> bool,
     {
-        while !predicate(&self.report) {
-            match await!(self.recv()).unwrap() {
-                NodeRecv::ReportMutations(_) => {}
-                NodeRecv::ResponseReceived(_) => unreachable!(),
-            };
+        await!(self.recv_until_ticks(predicate, TEST_MAX_WAIT_TICKS))
+    }
+
+    /// Receive report mutations until the report satisfies the predicate.
+    /// Panics, showing the last seen report, if the predicate is not satisfied within
+    /// max_ticks timer ticks.
+    pub async fn recv_until_ticks<'a, P: 'a>(&'a mut self, predicate: P, max_ticks: usize)
+    where
+        P: Fn(&FunderReport<B>) -> bool,
+    {
+        let timer_stream = await!(self.timer_client.request_timer_stream()).unwrap();
+
+        let c_self = &mut *self;
+        let recv_fut = Box::pin(
+            async move {
+                while !predicate(&c_self.report) {
+                    match await!(c_self.recv()).unwrap() {
+                        NodeRecv::ReportMutations(_) | NodeRecv::FundsReceived(_) => {}
+                        NodeRecv::ResponseReceived(_)
+                        | NodeRecv::FirstHopSuggestion(_)
+                        | NodeRecv::PendingRequests(_)
+                        | NodeRecv::FriendWarmed(_)
+                        | NodeRecv::PaymentSimulation(_)
+                        | NodeRecv::RecentReceipts(_)
+                        | NodeRecv::ResetToken(_)
+                        | NodeRecv::RemoveFriendConsequences(_) => unreachable!(),
+                    };
+                }
+            },
+        );
+
+        if await!(future_timeout(recv_fut, timer_stream, max_ticks)).is_none() {
+            panic!(
+                "recv_until(): Timed out after {} ticks. Last report: {:?}",
+                max_ticks, self.report
+            );
         }
     }
 
     pub async fn recv_until_response(&mut self) -> Option<ResponseReceived> {
         loop {
             match await!(self.recv())? {
-                NodeRecv::ReportMutations(_) => {}
+                NodeRecv::ReportMutations(_) | NodeRecv::FundsReceived(_) => {}
                 NodeRecv::ResponseReceived(response_received) => return Some(response_received),
+                NodeRecv::FirstHopSuggestion(_)
+                | NodeRecv::PendingRequests(_)
+                | NodeRecv::FriendWarmed(_)
+                | NodeRecv::PaymentSimulation(_)
+                | NodeRecv::RecentReceipts(_)
+                | NodeRecv::ResetToken(_)
+                | NodeRecv::RemoveFriendConsequences(_) => unreachable!(),
+            };
+        }
+    }
+
+    pub async fn recv_until_first_hop_suggestion(&mut self) -> Option<FirstHopSuggestion> {
+        loop {
+            match await!(self.recv())? {
+                NodeRecv::ReportMutations(_) | NodeRecv::FundsReceived(_) => {}
+                NodeRecv::ResponseReceived(_)
+                | NodeRecv::PendingRequests(_)
+                | NodeRecv::FriendWarmed(_)
+                | NodeRecv::PaymentSimulation(_)
+                | NodeRecv::RecentReceipts(_)
+                | NodeRecv::ResetToken(_)
+                | NodeRecv::RemoveFriendConsequences(_) => unreachable!(),
+                NodeRecv::FirstHopSuggestion(first_hop_suggestion) => {
+                    return Some(first_hop_suggestion)
+                }
+            };
+        }
+    }
+
+    pub async fn recv_until_funds_received(&mut self) -> Option<FundsReceived> {
+        loop {
+            match await!(self.recv())? {
+                NodeRecv::ReportMutations(_) => {}
+                NodeRecv::FundsReceived(funds_received) => return Some(funds_received),
+                NodeRecv::ResponseReceived(_)
+                | NodeRecv::FirstHopSuggestion(_)
+                | NodeRecv::PendingRequests(_)
+                | NodeRecv::FriendWarmed(_)
+                | NodeRecv::PaymentSimulation(_)
+                | NodeRecv::RecentReceipts(_)
+                | NodeRecv::ResetToken(_)
+                | NodeRecv::RemoveFriendConsequences(_) => unreachable!(),
+            };
+        }
+    }
+
+    pub async fn recv_until_payment_simulation(&mut self) -> Option<PaymentSimulation> {
+        loop {
+            match await!(self.recv())? {
+                NodeRecv::ReportMutations(_) | NodeRecv::FundsReceived(_) => {}
+                NodeRecv::PaymentSimulation(payment_simulation) => return Some(payment_simulation),
+                NodeRecv::ResponseReceived(_)
+                | NodeRecv::FirstHopSuggestion(_)
+                | NodeRecv::PendingRequests(_)
+                | NodeRecv::FriendWarmed(_)
+                | NodeRecv::RecentReceipts(_)
+                | NodeRecv::ResetToken(_)
+                | NodeRecv::RemoveFriendConsequences(_) => unreachable!(),
+            };
+        }
+    }
+
+    pub async fn recv_until_recent_receipts(&mut self) -> Option<Vec<AckedReceipt>> {
+        loop {
+            match await!(self.recv())? {
+                NodeRecv::ReportMutations(_) | NodeRecv::FundsReceived(_) => {}
+                NodeRecv::RecentReceipts(recent_receipts) => return Some(recent_receipts),
+                NodeRecv::ResponseReceived(_)
+                | NodeRecv::FirstHopSuggestion(_)
+                | NodeRecv::PendingRequests(_)
+                | NodeRecv::FriendWarmed(_)
+                | NodeRecv::PaymentSimulation(_)
+                | NodeRecv::ResetToken(_)
+                | NodeRecv::RemoveFriendConsequences(_) => unreachable!(),
+            };
+        }
+    }
+
+    pub async fn recv_until_reset_token(&mut self) -> Option<FriendResetToken> {
+        loop {
+            match await!(self.recv())? {
+                NodeRecv::ReportMutations(_) | NodeRecv::FundsReceived(_) => {}
+                NodeRecv::ResetToken(friend_reset_token) => return Some(friend_reset_token),
+                NodeRecv::ResponseReceived(_)
+                | NodeRecv::FirstHopSuggestion(_)
+                | NodeRecv::PendingRequests(_)
+                | NodeRecv::FriendWarmed(_)
+                | NodeRecv::PaymentSimulation(_)
+                | NodeRecv::RecentReceipts(_)
+                | NodeRecv::RemoveFriendConsequences(_) => unreachable!(),
             };
         }
     }
@@ -290,6 +460,7 @@ where
             relays,
             name: name.into(),
             balance,
+            opt_remote_max_debt: None,
         };
         let incoming_control_message = FunderIncomingControl::new(
             Uid::from(&[35; UID_LEN]),
@@ -379,7 +550,54 @@ where
         await!(self.recv_until(pred));
     }
 
+    /// Warm a friend, and wait for the resulting FriendWarmed message.
+    pub async fn warm_friend<'a>(&'a mut self, friend_public_key: &'a PublicKey) -> FriendWarmed {
+        let incoming_control_message = FunderIncomingControl::new(
+            Uid::from(&[38; UID_LEN]),
+            FunderControl::WarmFriend(friend_public_key.clone()),
+        );
+        await!(self.send(incoming_control_message)).unwrap();
+
+        let timer_stream = await!(self.timer_client.request_timer_stream()).unwrap();
+
+        let c_self = &mut *self;
+        let recv_fut = Box::pin(
+            async move {
+                loop {
+                    match await!(c_self.recv()).unwrap() {
+                        NodeRecv::ReportMutations(_) | NodeRecv::FundsReceived(_) => {}
+                        NodeRecv::FriendWarmed(friend_warmed) => return friend_warmed,
+                        NodeRecv::ResponseReceived(_)
+                        | NodeRecv::FirstHopSuggestion(_)
+                        | NodeRecv::PendingRequests(_)
+                        | NodeRecv::PaymentSimulation(_)
+                        | NodeRecv::RecentReceipts(_)
+                        | NodeRecv::ResetToken(_)
+                        | NodeRecv::RemoveFriendConsequences(_) => unreachable!(),
+                    };
+                }
+            },
+        );
+
+        match await!(future_timeout(recv_fut, timer_stream, TEST_MAX_WAIT_TICKS)) {
+            Some(friend_warmed) => friend_warmed,
+            None => panic!(
+                "warm_friend(): Timed out after {} ticks",
+                TEST_MAX_WAIT_TICKS
+            ),
+        }
+    }
+
     pub async fn wait_until_ready<'a>(&'a mut self, friend_public_key: &'a PublicKey) {
+        await!(self.wait_until_ready_ticks(friend_public_key, TEST_MAX_WAIT_TICKS))
+    }
+
+    /// Wait until the given friend is ready, for at most max_ticks timer ticks.
+    pub async fn wait_until_ready_ticks<'a>(
+        &'a mut self,
+        friend_public_key: &'a PublicKey,
+        max_ticks: usize,
+    ) {
         let pred = |report: &FunderReport<_>| {
             let friend = match report.friends.get(&friend_public_key) {
                 None => return false,
@@ -394,7 +612,7 @@ where
             };
             tc_report.requests_status.remote == RequestsStatusReport::from(&RequestsStatus::Open)
         };
-        await!(self.recv_until(pred));
+        await!(self.recv_until_ticks(pred, max_ticks));
     }
 }
 
@@ -410,6 +628,10 @@ where
         .spawn(router(recv_new_node, spawner.clone()))
         .unwrap();
 
+    // A real timer, used to bound the time we wait for the nodes:
+    let test_timer_client =
+        create_timer(Duration::from_millis(TEST_TICK_MS), spawner.clone()).unwrap();
+
     // Avoid problems with casting to u8:
     assert!(num_nodes < 256);
     let mut node_controls = Vec::new();
@@ -450,8 +672,11 @@ where
         let (send_comm, incoming_comm) = mpsc::channel(CHANNEL_SIZE);
         let (comm_sender, recv_comm) = mpsc::channel(CHANNEL_SIZE);
 
+        let (mut tick_sender_receiver, timer_client) = dummy_timer_multi_sender(spawner.clone());
+
         let funder_fut = inner_funder_loop(
             identity_client.clone(),
+            timer_client,
             DummyRandom::new(&[i as u8]),
             incoming_control,
             incoming_comm,
@@ -459,9 +684,12 @@ where
             comm_sender,
             funder_state,
             db_client,
-            TEST_MAX_NODE_RELAYS,
-            TEST_MAX_OPERATIONS_IN_BATCH,
-            TEST_MAX_PENDING_USER_REQUESTS,
+            TEST_FUNDER_CONFIG,
+            ControlStats::new(),
+            None,
+            None,
+            None,
+            None,
             None,
         );
 
@@ -469,6 +697,9 @@ where
             .spawn(funder_fut.then(|_| future::ready(())))
             .unwrap();
 
+        // Timer ticks are only sent to the Funder on demand:
+        let tick_sender = await!(tick_sender_receiver.next()).unwrap();
+
         /*
         let base_report = match await!(recv_control.next()).unwrap() {
             FunderOutgoingControl::Report(report) => report,
@@ -487,6 +718,8 @@ where
             public_key: await!(identity_client.request_public_key()).unwrap(),
             send_control,
             recv_control,
+            timer_client: test_timer_client.clone(),
+            tick_sender,
             report: base_report,
         });
     }
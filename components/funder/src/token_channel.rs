@@ -14,6 +14,7 @@ use proto::funder::signature_buff::verify_move_token;
 use crate::mutual_credit::incoming::{
     process_operations_list, IncomingMessage, ProcessOperationOutput, ProcessTransListError,
 };
+use crate::mutual_credit::op_timings::OpTimings;
 use crate::mutual_credit::outgoing::OutgoingMc;
 use crate::mutual_credit::types::{McMutation, MutualCredit};
 
@@ -288,13 +289,18 @@ where
         }
     }
 
+    /// If `opt_op_timings` is provided, the processing time of every received operation is
+    /// recorded into it.
     pub fn simulate_receive_move_token(
         &self,
         new_move_token: MoveToken<B>,
+        opt_op_timings: Option<&OpTimings>,
     ) -> Result<ReceiveMoveTokenOutput<B>, ReceiveMoveTokenError> {
         match &self.direction {
             TcDirection::Incoming(tc_incoming) => tc_incoming.handle_incoming(new_move_token),
-            TcDirection::Outgoing(tc_outgoing) => tc_outgoing.handle_incoming(new_move_token),
+            TcDirection::Outgoing(tc_outgoing) => {
+                tc_outgoing.handle_incoming(new_move_token, opt_op_timings)
+            }
         }
     }
 }
@@ -354,6 +360,7 @@ where
     fn handle_incoming(
         &self,
         new_move_token: MoveToken<B>,
+        opt_op_timings: Option<&OpTimings>,
     ) -> Result<ReceiveMoveTokenOutput<B>, ReceiveMoveTokenError> {
         // Make sure that the stated remote public key and local public key match:
         if !((self.mutual_credit.state().idents.local_public_key
@@ -365,7 +372,7 @@ where
         }
 
         if new_move_token.old_token == self.move_token_out.new_token {
-            self.handle_incoming_token_match(new_move_token)
+            self.handle_incoming_token_match(new_move_token, opt_op_timings)
         // self.outgoing_to_incoming(friend_move_token, new_move_token)
         } else if self.move_token_out.old_token == new_move_token.new_token {
             // We should retransmit our move token message to the remote side.
@@ -380,6 +387,7 @@ where
     fn handle_incoming_token_match(
         &self,
         new_move_token: MoveToken<B>,
+        opt_op_timings: Option<&OpTimings>,
     ) -> Result<ReceiveMoveTokenOutput<B>, ReceiveMoveTokenError> {
         // Verify signature:
         // Note that we only verify the signature here, and not at the Incoming part.
@@ -406,7 +414,11 @@ where
         }
 
         let mut mutual_credit = self.mutual_credit.clone();
-        let res = process_operations_list(&mut mutual_credit, new_move_token.operations.clone());
+        let res = process_operations_list(
+            &mut mutual_credit,
+            new_move_token.operations.clone(),
+            opt_op_timings,
+        );
 
         match res {
             Ok(outputs) => {
@@ -603,9 +615,11 @@ mod tests {
 
         assert!(tc2.is_outgoing());
 
+        let op_timings = OpTimings::new();
         let receive_move_token_output = tc1
-            .simulate_receive_move_token(friend_move_token.clone())
+            .simulate_receive_move_token(friend_move_token.clone(), Some(&op_timings))
             .unwrap();
+        assert_eq!(op_timings.get("SetRemoteMaxDebt").count, 1);
 
         let move_token_received = match receive_move_token_output {
             ReceiveMoveTokenOutput::Received(move_token_received) => move_token_received,
@@ -6,8 +6,9 @@ use crypto::identity::{PublicKey, Signature};
 
 use proto::app_server::messages::RelayAddress;
 use proto::funder::messages::{
-    ChannelerUpdateFriend, FailureSendFunds, FriendMessage, FriendTcOp, FunderIncomingControl,
-    FunderOutgoingControl, MoveToken, PendingRequest, RequestSendFunds, ResponseSendFunds,
+    ChannelerRelaysChanged, ChannelerUpdateFriend, FailureSendFunds, FriendMessage, FriendTcOp,
+    FunderIncomingControl, FunderOutgoingControl, MoveToken, PendingRequest, RequestSendFunds,
+    ResponseSendFunds,
 };
 
 use proto::funder::signature_buff::{
@@ -233,7 +234,10 @@ pub enum ChannelerConfig<RA> {
     /// and listen for new connections
     SetRelays(Vec<RA>),
     UpdateFriend(ChannelerUpdateFriend<RA>),
+    /// Disconnect from a friend. The friend might be enabled again later.
     RemoveFriend(PublicKey),
+    /// Disconnect from a friend that was permanently removed.
+    PurgeFriend(PublicKey),
 }
 
 #[derive(Debug, Clone)]
@@ -241,6 +245,8 @@ pub enum ChannelerConfig<RA> {
 pub enum FunderIncomingComm<B> {
     Liveness(IncomingLivenessMessage),
     Friend((PublicKey, FriendMessage<B>)),
+    /// The Channeler changed the relays used to reach a friend.
+    RelaysChanged(ChannelerRelaysChanged<RelayAddress<B>>),
 }
 
 /// An incoming message to the Funder:
@@ -250,6 +256,11 @@ pub enum FunderIncoming<B> {
     Init,
     Control(FunderIncomingControl<B>),
     Comm(FunderIncomingComm<B>),
+    /// A timer tick. Only delivered while there are friends being warmed.
+    TimerTick,
+    /// Continue sending messages that were deferred while handling previous events.
+    /// Only delivered while there are deferred send commands.
+    SendDeferred,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -268,3 +279,24 @@ pub enum FunderOutgoingComm<B> {
     FriendMessage((PublicKey, FriendMessage<B>)),
     ChannelerConfig(ChannelerConfig<RelayAddress<B>>),
 }
+
+/// Limits and policies of the Funder.
+#[derive(Debug, Clone)]
+pub struct FunderConfig {
+    /// Maximum amount of operations in one move token message
+    pub max_operations_in_batch: usize,
+    /// Maximum amount of relays a node may use.
+    pub max_node_relays: usize,
+    /// The size we allocate for the user send funds requests queue.
+    pub max_pending_user_requests: usize,
+    /// Maximum amount of requests we queue for a friend while waiting to forward them.
+    pub max_pending_requests: usize,
+    /// Log and count duplicate move tokens received from friends.
+    pub monitor_duplicate_move_tokens: bool,
+    /// Reject a payment request if its invoice id is already in use.
+    pub reject_duplicate_invoice_id: bool,
+    /// Amount of acked receipts we keep.
+    pub max_recent_receipts: usize,
+    /// Maximum amount of friends we send messages to while handling a single event.
+    pub max_send_friends: usize,
+}
@@ -0,0 +1,190 @@
+use futures::channel::mpsc;
+use futures::{Future, SinkExt, StreamExt};
+
+use crypto::identity::{verify_signature, PublicKey, Signature};
+
+use super::messages::{ResponsePublicKey, ResponseSignature, ToIdentity};
+
+#[derive(Debug)]
+pub enum ExternalIdentityError {
+    ExternalSignerSendFailed,
+    ExternalSignerClosed,
+}
+
+/// A request to sign a message, forwarded to an external signer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalSignRequest {
+    pub message: Vec<u8>,
+}
+
+/// Serve identity requests using an external signer (For example: an air-gapped machine that
+/// holds the private key).
+///
+/// Sign requests are forwarded one at a time through `sign_requests_sender`, and the resulting
+/// signature is read back from `signatures_receiver`. Signatures that do not match
+/// `public_key` are discarded, and the corresponding request is dropped.
+async fn external_identity_loop(
+    public_key: PublicKey,
+    mut requests_receiver: mpsc::Receiver<ToIdentity>,
+    mut sign_requests_sender: mpsc::Sender<ExternalSignRequest>,
+    mut signatures_receiver: mpsc::Receiver<Signature>,
+) -> Result<(), ExternalIdentityError> {
+    while let Some(request) = await!(requests_receiver.next()) {
+        match request {
+            ToIdentity::RequestSignature {
+                message,
+                response_sender,
+            } => {
+                let sign_request = ExternalSignRequest {
+                    message: message.clone(),
+                };
+                await!(sign_requests_sender.send(sign_request))
+                    .map_err(|_| ExternalIdentityError::ExternalSignerSendFailed)?;
+                let signature = await!(signatures_receiver.next())
+                    .ok_or(ExternalIdentityError::ExternalSignerClosed)?;
+
+                if !verify_signature(&message, &public_key, &signature) {
+                    // Dropping response_sender notifies the client that the request failed.
+                    continue;
+                }
+                // It is possible that sending the response didn't work.
+                // We don't care about this.
+                let _ = response_sender.send(ResponseSignature { signature });
+            }
+            ToIdentity::RequestPublicKey { response_sender } => {
+                let _ = response_sender.send(ResponsePublicKey {
+                    public_key: public_key.clone(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Create a new identity service that does not hold the private key. Instead, signatures are
+/// obtained from an external signer. See `external_identity_loop` for details.
+pub fn create_external_identity(
+    public_key: PublicKey,
+    sign_requests_sender: mpsc::Sender<ExternalSignRequest>,
+    signatures_receiver: mpsc::Receiver<Signature>,
+) -> (
+    mpsc::Sender<ToIdentity>,
+    impl Future<Output = Result<(), ExternalIdentityError>>,
+) {
+    let (requests_sender, requests_receiver) = mpsc::channel::<ToIdentity>(0);
+    let identity_fut = external_identity_loop(
+        public_key,
+        requests_receiver,
+        sign_requests_sender,
+        signatures_receiver,
+    );
+    (requests_sender, identity_fut)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::ThreadPool;
+    use futures::task::{Spawn, SpawnExt};
+    use futures::{future, FutureExt};
+
+    use crypto::identity::{generate_pkcs8_key_pair, Identity, SoftwareEd25519Identity};
+    use crypto::test_utils::DummyRandom;
+
+    use crate::client::{IdentityClient, IdentityClientError};
+
+    /// Spawn a mock external signer, signing every incoming request using `identity`.
+    fn spawn_mock_signer<I, S>(
+        identity: I,
+        mut spawner: S,
+    ) -> (mpsc::Sender<ExternalSignRequest>, mpsc::Receiver<Signature>)
+    where
+        I: Identity + Send + 'static,
+        S: Spawn,
+    {
+        let (sign_requests_sender, mut sign_requests_receiver) = mpsc::channel(0);
+        let (mut signatures_sender, signatures_receiver) = mpsc::channel(0);
+        spawner
+            .spawn(
+                async move {
+                    while let Some(sign_request) = await!(sign_requests_receiver.next()) {
+                        let sign_request: ExternalSignRequest = sign_request;
+                        let signature = identity.sign(&sign_request.message);
+                        if await!(signatures_sender.send(signature)).is_err() {
+                            return;
+                        }
+                    }
+                },
+            )
+            .unwrap();
+        (sign_requests_sender, signatures_receiver)
+    }
+
+    async fn task_external_identity_sign(identity_client: IdentityClient, public_key: PublicKey) {
+        assert_eq!(
+            await!(identity_client.request_public_key()).unwrap(),
+            public_key
+        );
+
+        for i in 0..3u8 {
+            let message = vec![i; 0x20];
+            let signature = await!(identity_client.request_signature(message.clone())).unwrap();
+            assert!(verify_signature(&message, &public_key, &signature));
+        }
+    }
+
+    #[test]
+    fn test_external_identity_sign() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+
+        let rng = DummyRandom::new(&[1u8]);
+        let pkcs8 = generate_pkcs8_key_pair(&rng);
+        let identity = SoftwareEd25519Identity::from_pkcs8(&pkcs8).unwrap();
+        let public_key = identity.get_public_key();
+
+        let (sign_requests_sender, signatures_receiver) =
+            spawn_mock_signer(identity, thread_pool.clone());
+        let (requests_sender, identity_fut) = create_external_identity(
+            public_key.clone(),
+            sign_requests_sender,
+            signatures_receiver,
+        );
+        thread_pool
+            .spawn(identity_fut.then(|_| future::ready(())))
+            .unwrap();
+
+        let identity_client = IdentityClient::new(requests_sender);
+        thread_pool.run(task_external_identity_sign(identity_client, public_key));
+    }
+
+    #[test]
+    fn test_external_identity_invalid_signature() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+
+        let rng = DummyRandom::new(&[1u8]);
+        let pkcs8 = generate_pkcs8_key_pair(&rng);
+        let identity = SoftwareEd25519Identity::from_pkcs8(&pkcs8).unwrap();
+        let public_key = identity.get_public_key();
+
+        // The external signer holds a different key:
+        let rng = DummyRandom::new(&[2u8]);
+        let pkcs8 = generate_pkcs8_key_pair(&rng);
+        let other_identity = SoftwareEd25519Identity::from_pkcs8(&pkcs8).unwrap();
+
+        let (sign_requests_sender, signatures_receiver) =
+            spawn_mock_signer(other_identity, thread_pool.clone());
+        let (requests_sender, identity_fut) =
+            create_external_identity(public_key, sign_requests_sender, signatures_receiver);
+        thread_pool
+            .spawn(identity_fut.then(|_| future::ready(())))
+            .unwrap();
+
+        let identity_client = IdentityClient::new(requests_sender);
+        let res = thread_pool.run(identity_client.request_signature(b"message".to_vec()));
+        match res {
+            Err(IdentityClientError::OneshotReceiverCanceled) => {}
+            _ => unreachable!(),
+        }
+    }
+}
@@ -15,8 +15,10 @@
 extern crate futures;
 
 mod client;
+mod external;
 mod identity;
 mod messages;
 
 pub use crate::client::IdentityClient;
+pub use crate::external::{create_external_identity, ExternalIdentityError, ExternalSignRequest};
 pub use crate::identity::create_identity;
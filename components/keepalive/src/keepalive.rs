@@ -56,6 +56,7 @@ async fn inner_keepalive_loop<TR, FR, TU, FU, TS>(
     from_user: FU,
     timer_stream: TS,
     keepalive_ticks: usize,
+    opt_idle_ticks: Option<usize>,
     mut opt_event_sender: Option<mpsc::Sender<KeepAliveEvent>>,
 ) -> Result<(), KeepAliveError>
 where
@@ -88,6 +89,9 @@ where
     // Amount of ticks remaining until we need to send a new keepalive (To make sure remote side
     // knows we are alive).
     let mut ticks_to_send_keepalive = keepalive_ticks / 2;
+    // Amount of ticks remaining until we close this connection because no messages were sent
+    // through it (Keepalives do not count):
+    let mut opt_ticks_to_idle = opt_idle_ticks;
 
     while let Some(event) = await!(events.next()) {
         if let Some(ref mut event_sender) = opt_event_sender {
@@ -99,6 +103,7 @@ where
                     .map_err(|_| KeepAliveError::DeserializeError)?;
                 ticks_to_close = keepalive_ticks;
                 if let KaMessage::Message(message) = ka_message {
+                    opt_ticks_to_idle = opt_idle_ticks;
                     if await!(to_user.send(message)).is_err() {
                         warn!("keepalive_loop(): Can not send to local side");
                         break;
@@ -113,6 +118,7 @@ where
                     break;
                 }
                 ticks_to_send_keepalive = keepalive_ticks / 2;
+                opt_ticks_to_idle = opt_idle_ticks;
             }
             KeepAliveEvent::TimerTick => {
                 ticks_to_close = ticks_to_close.saturating_sub(1);
@@ -120,6 +126,13 @@ where
                 if ticks_to_close == 0 {
                     return Err(KeepAliveError::RemoteTimeout);
                 }
+                if let Some(ref mut ticks_to_idle) = opt_ticks_to_idle {
+                    *ticks_to_idle = ticks_to_idle.saturating_sub(1);
+                    if *ticks_to_idle == 0 {
+                        info!("keepalive_loop(): Closing idle connection");
+                        break;
+                    }
+                }
                 if ticks_to_send_keepalive == 0 {
                     let ka_message = KaMessage::KeepAlive;
                     let ser_ka_message = serialize_ka_message(&ka_message);
@@ -142,6 +155,9 @@ where
 pub struct KeepAliveChannel<S> {
     timer_client: TimerClient,
     keepalive_ticks: usize,
+    /// Amount of ticks without messages after which the connection is closed.
+    /// If None, idle connections are kept open.
+    opt_idle_ticks: Option<usize>,
     spawner: S,
 }
 
@@ -157,6 +173,24 @@ where
         KeepAliveChannel {
             timer_client,
             keepalive_ticks,
+            opt_idle_ticks: None,
+            spawner,
+        }
+    }
+
+    /// Create a KeepAliveChannel that closes connections after `idle_ticks` ticks without
+    /// messages in either direction. The user notices the connection was closed when its
+    /// receiver ends, and may reconnect when needed.
+    pub fn with_idle_ticks(
+        timer_client: TimerClient,
+        keepalive_ticks: usize,
+        idle_ticks: usize,
+        spawner: S,
+    ) -> KeepAliveChannel<S> {
+        KeepAliveChannel {
+            timer_client,
+            keepalive_ticks,
+            opt_idle_ticks: Some(idle_ticks),
             spawner,
         }
     }
@@ -183,6 +217,7 @@ where
                         from_user,
                         timer_stream,
                         self.keepalive_ticks,
+                        self.opt_idle_ticks,
                         None,
                     )
                     .map_err(|e| {
@@ -224,6 +259,7 @@ mod tests {
     use futures::executor::ThreadPool;
     use futures::task::{Spawn, SpawnExt};
     use futures::FutureExt;
+    use proto::consts::KEEPALIVE_TICKS;
     use timer::create_timer_incoming;
 
     /// Util function for tests
@@ -252,6 +288,7 @@ mod tests {
             timer_stream,
             keepalive_ticks,
             None,
+            None,
         )
         .map_err(|e| error!("[KeepAlive] inner_keepalive_loop() error: {:?}", e))
         .then(|_| future::ready(()));
@@ -283,6 +320,7 @@ mod tests {
             from_user,
             timer_stream,
             keepalive_ticks,
+            None,
             Some(event_sender),
         )
         // .map_err(|e| println!("client_tunnel error: {:?}", e))
@@ -401,4 +439,100 @@ mod tests {
         let mut thread_pool = ThreadPool::new().unwrap();
         thread_pool.run(task_keepalive_channel_basic(thread_pool.clone()));
     }
+
+    async fn task_keepalive_channel_short_interval(spawner: impl Spawn + Clone + Send) {
+        // Create a mock time service:
+        let (mut tick_sender, tick_receiver) = mpsc::channel::<()>(0);
+        let timer_client = create_timer_incoming(tick_receiver, spawner.clone()).unwrap();
+
+        let short_keepalive_ticks = 4;
+        let mut short_keepalive =
+            KeepAliveChannel::new(timer_client.clone(), short_keepalive_ticks, spawner.clone());
+        let mut default_keepalive =
+            KeepAliveChannel::new(timer_client.clone(), KEEPALIVE_TICKS, spawner.clone());
+
+        // The remote side of both connections is silent:
+        let (to_remote, _short_remote_receiver) = mpsc::channel(0);
+        let (_short_remote_sender, from_remote) = mpsc::channel(0);
+        let (_short_user_sender, mut short_user_receiver) =
+            await!(short_keepalive.transform((to_remote, from_remote)));
+
+        let (to_remote, _default_remote_receiver) = mpsc::channel(0);
+        let (_default_remote_sender, from_remote) = mpsc::channel(0);
+        let (_default_user_sender, mut default_user_receiver) =
+            await!(default_keepalive.transform((to_remote, from_remote)));
+
+        for _ in 0..short_keepalive_ticks {
+            await!(tick_sender.send(())).unwrap();
+        }
+
+        // The connection with the short interval was closed, because the remote side was idle:
+        assert!(await!(short_user_receiver.next()).is_none());
+        // The connection with the default interval is still open:
+        assert!(default_user_receiver.try_next().is_err());
+    }
+
+    #[test]
+    fn test_keepalive_channel_short_interval() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_keepalive_channel_short_interval(thread_pool.clone()));
+    }
+
+    async fn task_keepalive_channel_idle(spawner: impl Spawn + Clone + Send) {
+        // Create a mock time service:
+        let (mut tick_sender, tick_receiver) = mpsc::channel::<()>(0);
+        let timer_client = create_timer_incoming(tick_receiver, spawner.clone()).unwrap();
+
+        let keepalive_ticks = 16;
+        let idle_ticks = 8;
+        let mut idle_keepalive = KeepAliveChannel::with_idle_ticks(
+            timer_client.clone(),
+            keepalive_ticks,
+            idle_ticks,
+            spawner.clone(),
+        );
+
+        let (to_remote, mut idle_remote_receiver) = mpsc::channel(0);
+        let (mut idle_remote_sender, from_remote) = mpsc::channel(0);
+        let (_idle_user_sender, mut idle_user_receiver) =
+            await!(idle_keepalive.transform((to_remote, from_remote)));
+
+        let (to_remote, mut active_remote_receiver) = mpsc::channel(0);
+        let (mut active_remote_sender, from_remote) = mpsc::channel(0);
+        let (mut active_user_sender, mut active_user_receiver) =
+            await!(idle_keepalive.transform((to_remote, from_remote)));
+
+        for _ in 0..idle_ticks - 1 {
+            await!(tick_sender.send(())).unwrap();
+
+            // Remote keeps both connections alive:
+            let ser_keepalive = serialize_ka_message(&KaMessage::KeepAlive);
+            await!(idle_remote_sender.send(ser_keepalive.clone())).unwrap();
+            await!(active_remote_sender.send(ser_keepalive)).unwrap();
+
+            // Only one of the connections is used:
+            await!(active_user_sender.send(vec![1, 2, 3])).unwrap();
+            let vec = await!(active_remote_receiver.next()).unwrap();
+            assert_eq!(
+                vec,
+                serialize_ka_message(&KaMessage::Message(vec![1, 2, 3]))
+            );
+        }
+        await!(tick_sender.send(())).unwrap();
+
+        // The unused connection was closed, although the remote side sent keepalives:
+        assert!(await!(idle_user_receiver.next()).is_none());
+        assert!(await!(idle_remote_receiver.next()).is_none());
+
+        // The active connection is still open:
+        let vec = serialize_ka_message(&KaMessage::Message(vec![3, 2, 1]));
+        await!(active_remote_sender.send(vec)).unwrap();
+        assert_eq!(await!(active_user_receiver.next()).unwrap(), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn test_keepalive_channel_idle() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_keepalive_channel_idle(thread_pool.clone()));
+    }
 }
@@ -1,6 +1,10 @@
+use std::io;
 use std::net::SocketAddr;
 
+use net2::TcpBuilder;
+
 use tokio::net::TcpListener as TokioTcpListener;
+use tokio::reactor::Handle;
 
 use futures::channel::mpsc;
 use futures::task::{Spawn, SpawnExt};
@@ -14,6 +18,8 @@ use futures::compat::Stream01CompatExt;
 /// Listen for incoming TCP connections
 pub struct TcpListener<S> {
     max_frame_length: usize,
+    /// Size of the queue of pending connections. If None, the default is used.
+    opt_backlog: Option<i32>,
     spawner: S,
 }
 
@@ -21,9 +27,43 @@ impl<S> TcpListener<S> {
     pub fn new(max_frame_length: usize, spawner: S) -> Self {
         TcpListener {
             max_frame_length,
+            opt_backlog: None,
             spawner,
         }
     }
+
+    /// Create a TcpListener with a custom size for the queue of pending connections.
+    /// A larger backlog allows to handle bursts of incoming connections.
+    pub fn with_backlog(max_frame_length: usize, backlog: i32, spawner: S) -> Self {
+        TcpListener {
+            max_frame_length,
+            opt_backlog: Some(backlog),
+            spawner,
+        }
+    }
+}
+
+/// Bind a listening socket to the given address.
+/// If a backlog is specified, it is passed to the listen() call of the socket.
+fn bind_listener(
+    socket_addr: &SocketAddr,
+    opt_backlog: Option<i32>,
+) -> io::Result<TokioTcpListener> {
+    let backlog = match opt_backlog {
+        Some(backlog) => backlog,
+        None => return TokioTcpListener::bind(socket_addr),
+    };
+
+    let tcp_builder = match socket_addr {
+        SocketAddr::V4(_) => TcpBuilder::new_v4()?,
+        SocketAddr::V6(_) => TcpBuilder::new_v6()?,
+    };
+    // Same as TokioTcpListener::bind():
+    if cfg!(unix) {
+        tcp_builder.reuse_address(true)?;
+    }
+    let std_listener = tcp_builder.bind(socket_addr)?.listen(backlog)?;
+    TokioTcpListener::from_std(std_listener, &Handle::default())
 }
 
 impl<S> Listener for TcpListener<S>
@@ -41,7 +81,7 @@ where
         let (config_sender, _config_sender_receiver) = mpsc::channel(0);
         let (mut conn_receiver_sender, conn_receiver) = mpsc::channel(0);
 
-        let listener = match TokioTcpListener::bind(&socket_addr) {
+        let listener = match bind_listener(&socket_addr, self.opt_backlog) {
             Ok(listener) => listener,
             Err(e) => {
                 warn!("Failed listening on {:?}: {:?}", socket_addr, e);
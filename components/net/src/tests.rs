@@ -73,6 +73,42 @@ fn test_tcp_client_server_v4() {
     thread_pool.run(task_tcp_client_server_v4(thread_pool.clone()));
 }
 
+async fn task_tcp_listener_backlog<S>(spawner: S)
+where
+    S: Spawn + Clone + Send + 'static,
+{
+    let available_port = get_available_port_v4();
+    let loopback = Ipv4Addr::new(127, 0, 0, 1);
+    let socket_addr = SocketAddr::new(IpAddr::V4(loopback), available_port);
+
+    let tcp_listener = TcpListener::with_backlog(TEST_MAX_FRAME_LEN, 0x10, spawner.clone());
+    let mut tcp_connector = TcpConnector::new(TEST_MAX_FRAME_LEN, spawner.clone());
+
+    let (_config_sender, mut incoming_connections) = tcp_listener.listen(socket_addr.clone());
+
+    // Open a burst of connections before accepting any of them:
+    let mut client_conns = Vec::new();
+    for _ in 0..8 {
+        client_conns.push(await!(tcp_connector.transform(socket_addr.clone())).unwrap());
+    }
+
+    for (mut client_sender, mut client_receiver) in client_conns {
+        let (mut server_sender, mut server_receiver) = await!(incoming_connections.next()).unwrap();
+
+        await!(client_sender.send(vec![1, 2, 3])).unwrap();
+        assert_eq!(await!(server_receiver.next()).unwrap(), vec![1, 2, 3]);
+
+        await!(server_sender.send(vec![3, 2, 1])).unwrap();
+        assert_eq!(await!(client_receiver.next()).unwrap(), vec![3, 2, 1]);
+    }
+}
+
+#[test]
+fn test_tcp_listener_backlog() {
+    let mut thread_pool = ThreadPool::new().unwrap();
+    thread_pool.run(task_tcp_listener_backlog(thread_pool.clone()));
+}
+
 async fn task_net_connector_v4_basic<S>(spawner: S)
 where
     S: Spawn + Clone + Send + 'static,
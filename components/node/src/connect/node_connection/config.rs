@@ -86,6 +86,7 @@ where
             relays,
             name,
             balance,
+            opt_remote_max_debt: None,
         };
         await!(self.send_request(AppRequest::AddFriend(add_friend)))
     }
@@ -81,6 +81,16 @@ where
             .spawn(send_funds_fut)
             .map_err(|_| NodeConnectionError::SpawnError)?;
 
+        let (mut incoming_funds_received_sender, incoming_funds_received) = mpsc::channel(0);
+        let (requests_sender, incoming_requests) = mpsc::channel(0);
+        let funds_received_mc = MultiConsumerClient::new(requests_sender);
+        let funds_received_fut = multi_consumer_service(incoming_funds_received, incoming_requests)
+            .map_err(|e| error!("FundsReceived multi_consumer_service() error: {:?}", e))
+            .map(|_| ());
+        spawner
+            .spawn(funds_received_fut)
+            .map_err(|_| NodeConnectionError::SpawnError)?;
+
         let (mut incoming_done_app_requests_sender, incoming_done_app_requests) = mpsc::channel(0);
         let (requests_sender, incoming_requests) = mpsc::channel(0);
         let done_app_requests_mc = MultiConsumerClient::new(requests_sender);
@@ -123,6 +133,19 @@ where
                             AppServerToApp::ResponseRoutes(client_response_routes) => {
                                 let _ = await!(incoming_routes_sender.send(client_response_routes));
                             }
+                            AppServerToApp::FundsReceived(funds_received) => {
+                                let _ = await!(incoming_funds_received_sender.send(funds_received));
+                            }
+                            AppServerToApp::FirstHopSuggestion(_)
+                            | AppServerToApp::PendingRequests(_)
+                            | AppServerToApp::FriendWarmed(_)
+                            | AppServerToApp::PaymentSimulation(_)
+                            | AppServerToApp::RecentReceipts(_)
+                            | AppServerToApp::ResetToken(_)
+                            | AppServerToApp::RemoveFriendConsequences(_) => {
+                                // Replies to requests that are not issued through
+                                // NodeConnection. We ignore them.
+                            }
                         }
                     }
                 },
@@ -153,6 +176,7 @@ where
             Some(AppSendFunds::new(
                 sender.clone(),
                 send_funds_mc.clone(),
+                funds_received_mc.clone(),
                 done_app_requests_mc.clone(),
                 rng.clone(),
             ))
@@ -9,7 +9,7 @@ use crypto::uid::Uid;
 
 use proto::app_server::messages::{AppRequest, AppToAppServer};
 use proto::funder::messages::{
-    FriendsRoute, Receipt, ReceiptAck, ResponseReceived, ResponseSendFundsResult,
+    FriendsRoute, FundsReceived, Receipt, ReceiptAck, ResponseReceived, ResponseSendFundsResult,
     UserRequestSendFunds,
 };
 
@@ -34,6 +34,7 @@ pub struct ReceiptAckError;
 pub struct AppSendFunds<R = OffstSystemRandom> {
     sender: mpsc::Sender<AppToAppServer>,
     send_funds_mc: MultiConsumerClient<ResponseReceived>,
+    funds_received_mc: MultiConsumerClient<FundsReceived>,
     done_app_requests_mc: MultiConsumerClient<Uid>,
     rng: R,
 }
@@ -51,6 +52,7 @@ where
         AppSendFunds {
             sender,
             send_funds_mc,
+            funds_received_mc,
             done_app_requests_mc,
             rng,
         }
@@ -62,12 +64,15 @@ where
         route: FriendsRoute,
         invoice_id: InvoiceId,
         dest_payment: u128,
+        memo: Vec<u8>,
     ) -> Result<Receipt, SendFundsError> {
         let user_request_send_funds = UserRequestSendFunds {
             request_id,
             route,
             invoice_id,
             dest_payment,
+            memo,
+            opt_idempotency_key: None,
         };
         let app_request_id = Uid::new(&self.rng);
         let to_app_server = AppToAppServer::new(
@@ -97,6 +102,15 @@ where
         Err(SendFundsError::NoResponse)
     }
 
+    /// Get a stream of the funds received by the node.
+    /// A payment is reported once the node sends the response for a request it is the destination
+    /// of. The stream must be consumed, as other consumers will wait for it.
+    pub async fn incoming_funds_received(
+        &mut self,
+    ) -> Result<mpsc::Receiver<FundsReceived>, SendFundsError> {
+        await!(self.funds_received_mc.request_stream()).map_err(|_| SendFundsError::LocalError)
+    }
+
     pub async fn receipt_ack(
         &mut self,
         request_id: Uid,
@@ -27,3 +27,4 @@ mod types;
 pub use self::net_node::{net_node, NetNodeError};
 pub use self::types::{NodeConfig, NodeState};
 pub use app_server::IncomingAppConnection;
+pub use funder::{ControlStats, OpTimings};
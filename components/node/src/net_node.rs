@@ -6,6 +6,7 @@ use futures::task::{Spawn, SpawnExt};
 use futures::{future, FutureExt, SinkExt, Stream, StreamExt, TryFutureExt};
 
 use common::conn::{BoxFuture, ConnPairVec, FuncFutTransform, FutTransform};
+use common::latest_channel::LatestSender;
 use common::transform_pool::transform_pool_loop;
 
 use crypto::crypto_rand::CryptoRandom;
@@ -17,12 +18,14 @@ use proto::app_server::serialize::{
 };
 use proto::consts::{KEEPALIVE_TICKS, PROTOCOL_VERSION, TICKS_TO_REKEY};
 use proto::net::messages::NetAddress;
+use proto::report::messages::FunderReport;
 
 use database::{database_loop, AtomicDb, DatabaseClient};
 use identity::IdentityClient;
 use timer::TimerClient;
 
 use app_server::IncomingAppConnection;
+use funder::{ControlStats, OpTimings};
 use keepalive::KeepAliveChannel;
 use secure_channel::SecureChannel;
 use version::VersionPrefix;
@@ -163,6 +166,9 @@ pub async fn net_node<IAC, C, R, GT, AD, DS, TS, S>(
     node_config: NodeConfig,
     get_trusted_apps: GT,
     atomic_db: AD,
+    control_stats: ControlStats,
+    opt_op_timings: Option<OpTimings>,
+    opt_report_sender: Option<LatestSender<FunderReport<NetAddress>>>,
     trusted_apps_spawner: TS,
     database_spawner: DS,
     mut spawner: S,
@@ -269,6 +275,9 @@ where
         database_client,
         version_connector,
         incoming_apps,
+        control_stats,
+        opt_op_timings,
+        opt_report_sender,
         rng,
         spawner.clone()
     ))
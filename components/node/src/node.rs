@@ -5,6 +5,7 @@ use futures::{select, Future, FutureExt, SinkExt, Stream, StreamExt};
 use derive_more::*;
 
 use common::conn::{ConnPairVec, FutTransform};
+use common::latest_channel::LatestSender;
 use crypto::crypto_rand::CryptoRandom;
 use crypto::identity::PublicKey;
 
@@ -15,9 +16,9 @@ use timer::TimerClient;
 use app_server::{app_server_loop, AppServerError, IncomingAppConnection};
 use channeler::{spawn_channeler, ChannelerError};
 use funder::types::{
-    ChannelerConfig, FunderIncomingComm, FunderOutgoingComm, IncomingLivenessMessage,
+    ChannelerConfig, FunderConfig, FunderIncomingComm, FunderOutgoingComm, IncomingLivenessMessage,
 };
-use funder::{funder_loop, FunderError, FunderState};
+use funder::{funder_loop, ControlStats, FunderError, FunderState, OpTimings};
 use keepalive::KeepAliveChannel;
 use secure_channel::SecureChannel;
 
@@ -31,6 +32,7 @@ use proto::funder::serialize::{deserialize_friend_message, serialize_friend_mess
 use proto::index_client::messages::{AppServerToIndexClient, IndexClientToAppServer};
 use proto::net::messages::NetAddress;
 use proto::report::convert::funder_report_to_index_client_state;
+use proto::report::messages::FunderReport;
 
 use crate::adapters::{EncKeepaliveConnector, EncRelayConnector};
 use crate::types::{create_node_report, NodeConfig, NodeMutation, NodeState};
@@ -53,7 +55,7 @@ fn node_spawn_channeler<C, R, S>(
     version_connector: C,
     rng: R,
     from_funder: mpsc::Receiver<FunderToChanneler<RelayAddress>>,
-    to_funder: mpsc::Sender<ChannelerToFunder>,
+    to_funder: mpsc::Sender<ChannelerToFunder<RelayAddress>>,
     mut spawner: S,
 ) -> Result<impl Future<Output = Result<(), ChannelerError>>, NodeError>
 where
@@ -73,9 +75,11 @@ where
         spawner.clone(),
     );
 
+    // Connections to friends use their own keepalive interval, which determines how fast we
+    // detect that a friend went offline:
     let keepalive_transform = KeepAliveChannel::new(
         timer_client.clone(),
-        node_config.keepalive_ticks,
+        node_config.liveness_ticks,
         spawner.clone(),
     );
 
@@ -88,6 +92,8 @@ where
             node_config.backoff_ticks,
             node_config.conn_timeout_ticks,
             node_config.max_concurrent_encrypt,
+            node_config.opt_max_concurrent_encrypt_per_relay,
+            node_config.channel_len,
             enc_relay_connector,
             encrypt_transform,
             keepalive_transform,
@@ -101,12 +107,16 @@ where
 fn node_spawn_funder<R, S>(
     node_config: &NodeConfig,
     identity_client: IdentityClient,
+    timer_client: TimerClient,
     funder_state: FunderState<NetAddress>,
     mut database_client: DatabaseClient<NodeMutation<NetAddress>>,
-    mut from_channeler: mpsc::Receiver<ChannelerToFunder>,
+    mut from_channeler: mpsc::Receiver<ChannelerToFunder<RelayAddress>>,
     mut to_channeler: mpsc::Sender<FunderToChanneler<RelayAddress>>,
     from_app_server: mpsc::Receiver<FunderIncomingControl<NetAddress>>,
     to_app_server: mpsc::Sender<FunderOutgoingControl<NetAddress>>,
+    control_stats: ControlStats,
+    opt_op_timings: Option<OpTimings>,
+    opt_report_sender: Option<LatestSender<FunderReport<NetAddress>>>,
     rng: R,
     mut spawner: S,
 ) -> Result<impl Future<Output = Result<(), FunderError>>, NodeError>
@@ -159,6 +169,9 @@ where
                         None
                     }
                 }
+                ChannelerToFunder::RelaysChanged(relays_changed) => {
+                    Some(FunderIncomingComm::RelaysChanged(relays_changed))
+                }
             };
             if let Some(to_funder_message) = opt_to_funder_message {
                 if await!(incoming_comm_sender.send(to_funder_message)).is_err() {
@@ -188,6 +201,9 @@ where
                     ChannelerConfig::RemoveFriend(friend_public_key) => {
                         FunderToChanneler::RemoveFriend(friend_public_key)
                     }
+                    ChannelerConfig::PurgeFriend(friend_public_key) => {
+                        FunderToChanneler::PurgeFriend(friend_public_key)
+                    }
                 },
                 FunderOutgoingComm::FriendMessage((public_key, friend_message)) => {
                     let data = serialize_friend_message(&friend_message);
@@ -204,18 +220,33 @@ where
         .spawn(funder_to_channeler_adapter)
         .map_err(|_| NodeError::SpawnError)?;
 
+    let funder_config = FunderConfig {
+        max_operations_in_batch: node_config.max_operations_in_batch,
+        max_node_relays: node_config.max_node_relays,
+        max_pending_user_requests: node_config.max_pending_user_requests,
+        max_pending_requests: node_config.max_pending_requests,
+        monitor_duplicate_move_tokens: node_config.monitor_duplicate_move_tokens,
+        reject_duplicate_invoice_id: node_config.reject_duplicate_invoice_id,
+        max_recent_receipts: node_config.max_recent_receipts,
+        max_send_friends: node_config.max_send_friends,
+    };
+
     let funder_fut = funder_loop(
         identity_client.clone(),
+        timer_client,
         rng.clone(),
         from_app_server,
         incoming_comm,
         to_app_server,
         outgoing_comm_sender,
-        node_config.max_node_relays,
-        node_config.max_operations_in_batch,
-        node_config.max_pending_user_requests,
         funder_state,
         funder_db_client,
+        funder_config,
+        control_stats,
+        opt_op_timings,
+        None,
+        None,
+        opt_report_sender,
     );
 
     spawner
@@ -324,6 +355,9 @@ pub async fn node<C, IA, R, S>(
     database_client: DatabaseClient<NodeMutation<NetAddress>>,
     version_connector: C,
     incoming_apps: IA,
+    control_stats: ControlStats,
+    opt_op_timings: Option<OpTimings>,
+    opt_report_sender: Option<LatestSender<FunderReport<NetAddress>>>,
     rng: R,
     mut spawner: S,
 ) -> Result<(), NodeError>
@@ -370,12 +404,16 @@ where
     let funder_handle = node_spawn_funder(
         &node_config,
         identity_client.clone(),
+        timer_client.clone(),
         node_state.funder_state.clone(),
         database_client.clone(),
         channeler_to_funder_receiver,
         funder_to_channeler_sender,
         app_server_to_funder_receiver,
         funder_to_app_server_sender,
+        control_stats,
+        opt_op_timings,
+        opt_report_sender,
         rng.clone(),
         spawner.clone(),
     )?;
@@ -88,17 +88,43 @@ pub struct NodeConfig {
     pub backoff_ticks: usize,
     /// The amount of ticks we wait until we decide an idle connection has timed out.
     pub keepalive_ticks: usize,
+    /// The amount of ticks a friend connection may stay idle before we consider the friend
+    /// offline. Smaller values detect disconnected friends faster, at the cost of sending
+    /// keepalives more often. Usually equal to `keepalive_ticks`.
+    /// Both friends must use the same value: keepalives are sent according to the local value,
+    /// hence a friend with a larger value might be considered offline by a friend with a
+    /// smaller value.
+    pub liveness_ticks: usize,
     /// Amount of ticks to wait until the next rekeying (Channel encryption)
     pub ticks_to_rekey: usize,
     /// Maximum amount of encryption set ups (diffie hellman) that we allow to occur at the same
     /// time from external communications (Channeler side)
     pub max_concurrent_encrypt: usize,
+    /// Maximum amount of encryption set ups that we allow to occur at the same time for
+    /// connections arriving from a single relay. None means no limit other than
+    /// `max_concurrent_encrypt`.
+    pub opt_max_concurrent_encrypt_per_relay: Option<usize>,
     /// The amount of ticks we are willing to wait until a connection is established.
     pub conn_timeout_ticks: usize,
     /// Maximum amount of operations in one move token message
     pub max_operations_in_batch: usize,
+    /// Maximum amount of friends we send messages to while handling a single event.
+    /// Messages to the rest of the friends are sent later.
+    pub max_send_friends: usize,
     /// The size we allocate for the user send funds requests queue.
     pub max_pending_user_requests: usize,
+    /// Maximum amount of requests we queue for a friend while waiting to forward them.
+    /// Requests beyond this amount are not forwarded, and a failure is returned to the sender.
+    pub max_pending_requests: usize,
+    /// Log and count duplicate move tokens received from friends, instead of silently
+    /// discarding them. Useful for detecting replays or message loops.
+    pub monitor_duplicate_move_tokens: bool,
+    /// Reject a payment request if its invoice id is already used by an in flight request
+    /// or by a completed request that was not yet acked.
+    pub reject_duplicate_invoice_id: bool,
+    /// Amount of acked receipts we keep. Allows an app to retrieve a receipt again, in case it
+    /// lost the receipt after acking it.
+    pub max_recent_receipts: usize,
     /// Maximum amount of concurrent index client requests:
     pub max_open_index_client_requests: usize,
     /// Maximum amount of relays a node may use.
@@ -4,14 +4,16 @@ use crypto::identity::PublicKey;
 use crypto::uid::Uid;
 
 use crate::funder::messages::{
-    AddFriend, ReceiptAck, ResetFriendChannel, ResponseReceived, SetFriendName, SetFriendRelays,
-    SetFriendRemoteMaxDebt, UserRequestSendFunds,
+    AckedReceipt, AddFriend, FirstHopSuggestion, FriendResetToken, FriendWarmed, FundsReceived,
+    PaymentSimulation, PendingFriendRequest, ReceiptAck, RemoveFriendConsequences,
+    ResetFriendChannel, ResponseReceived, SetFriendName, SetFriendRelays, SetFriendRemoteMaxDebt,
+    SuggestFirstHop, UserRequestSendFunds,
 };
 use crate::index_client::messages::{
     ClientResponseRoutes, IndexClientReport, IndexClientReportMutation,
 };
 use crate::index_server::messages::{NamedIndexServerAddress, RequestRoutes};
-use crate::net::messages::NetAddress;
+use crate::net::messages::{NetAddress, ValidateAddress};
 use crate::report::messages::{FunderReport, FunderReportMutation};
 
 // TODO: Move NamedRelayAddress and RelayAddress to another place in offst-proto?
@@ -49,6 +51,15 @@ where
     }
 }
 
+impl<B> ValidateAddress for RelayAddress<B>
+where
+    B: ValidateAddress,
+{
+    fn is_valid_address(&self) -> bool {
+        self.address.is_valid_address()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct NodeReport<B = NetAddress>
 where
@@ -76,7 +87,7 @@ where
     pub mutations: Vec<NodeReportMutation<B>>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AppServerToApp<B = NetAddress>
 where
     B: Clone,
@@ -87,6 +98,16 @@ where
     Report(NodeReport<B>),
     ReportMutations(ReportMutations<B>),
     ResponseRoutes(ClientResponseRoutes),
+    /// Replies to control requests:
+    FirstHopSuggestion(FirstHopSuggestion),
+    PendingRequests(Vec<PendingFriendRequest>),
+    FriendWarmed(FriendWarmed),
+    PaymentSimulation(PaymentSimulation),
+    RecentReceipts(Vec<AckedReceipt>),
+    ResetToken(FriendResetToken),
+    RemoveFriendConsequences(RemoveFriendConsequences),
+    /// Funds received by us, being the destination of a payment:
+    FundsReceived(FundsReceived),
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -119,6 +140,15 @@ pub enum AppRequest<B = NetAddress> {
     /// Manage index servers:
     AddIndexServer(NamedIndexServerAddress<B>),
     RemoveIndexServer(PublicKey),
+    /// Payment planning:
+    SuggestFirstHop(SuggestFirstHop),
+    SimulatePayment(UserRequestSendFunds),
+    WarmFriend(PublicKey),
+    GetRecentReceipts,
+    /// Friends inspection:
+    GetPendingRequests(PublicKey),
+    GetResetToken(PublicKey),
+    RequestRemoveFriend(PublicKey),
 }
 #[derive(Debug, PartialEq, Eq)]
 pub struct AppToAppServer<B = NetAddress> {
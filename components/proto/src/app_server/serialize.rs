@@ -24,8 +24,10 @@ use index_server::serialize::{
 };
 
 use crate::funder::messages::{
-    AddFriend, ReceiptAck, ResetFriendChannel, ResponseReceived, ResponseSendFundsResult,
-    SetFriendName, SetFriendRelays, SetFriendRemoteMaxDebt, UserRequestSendFunds,
+    AckedReceipt, AddFriend, FirstHopSuggestion, FriendResetToken, FriendWarmed, FundsReceived,
+    PaymentSimulation, PendingFriendRequest, PendingRequest, ReceiptAck, RemoveFriendConsequences,
+    ResetFriendChannel, ResponseReceived, ResponseSendFundsResult, SetFriendName, SetFriendRelays,
+    SetFriendRemoteMaxDebt, SuggestFirstHop, UserRequestSendFunds,
 };
 use crate::funder::serialize::{deser_friends_route, ser_friends_route};
 
@@ -56,16 +58,45 @@ fn ser_user_request_send_funds(
         &user_request_send_funds.invoice_id,
         &mut user_request_send_funds_builder.reborrow().init_invoice_id(),
     );
+
+    user_request_send_funds_builder
+        .reborrow()
+        .set_memo(&user_request_send_funds.memo);
+
+    let mut opt_idempotency_key_builder = user_request_send_funds_builder
+        .reborrow()
+        .init_opt_idempotency_key();
+    match &user_request_send_funds.opt_idempotency_key {
+        Some(idempotency_key) => {
+            let mut idempotency_key_builder = opt_idempotency_key_builder.init_idempotency_key();
+            write_uid(idempotency_key, &mut idempotency_key_builder);
+        }
+        None => {
+            opt_idempotency_key_builder.reborrow().set_empty(());
+        }
+    };
 }
 
 fn deser_user_request_send_funds(
     user_request_send_funds_reader: &app_server_capnp::user_request_send_funds::Reader,
 ) -> Result<UserRequestSendFunds, SerializeError> {
+    let opt_idempotency_key = match user_request_send_funds_reader
+        .get_opt_idempotency_key()
+        .which()?
+    {
+        app_server_capnp::user_request_send_funds::opt_idempotency_key::IdempotencyKey(
+            idempotency_key_reader,
+        ) => Some(read_uid(&idempotency_key_reader?)?),
+        app_server_capnp::user_request_send_funds::opt_idempotency_key::Empty(()) => None,
+    };
+
     Ok(UserRequestSendFunds {
         request_id: read_uid(&user_request_send_funds_reader.get_request_id()?)?,
         route: deser_friends_route(&user_request_send_funds_reader.get_route()?)?,
         dest_payment: read_custom_u_int128(&user_request_send_funds_reader.get_dest_payment()?)?,
         invoice_id: read_invoice_id(&user_request_send_funds_reader.get_invoice_id()?)?,
+        memo: user_request_send_funds_reader.get_memo()?.to_vec(),
+        opt_idempotency_key,
     })
 }
 
@@ -155,6 +186,19 @@ fn ser_add_friend(
         add_friend.balance,
         &mut add_friend_builder.reborrow().init_balance(),
     );
+
+    let mut opt_remote_max_debt_builder = add_friend_builder.reborrow().init_opt_remote_max_debt();
+    match add_friend.opt_remote_max_debt {
+        Some(remote_max_debt) => {
+            write_custom_u_int128(
+                remote_max_debt,
+                &mut opt_remote_max_debt_builder.init_remote_max_debt(),
+            );
+        }
+        None => {
+            opt_remote_max_debt_builder.reborrow().set_empty(());
+        }
+    };
 }
 
 fn deser_add_friend(
@@ -166,11 +210,19 @@ fn deser_add_friend(
         relays.push(read_relay_address(&relay_address)?);
     }
 
+    let opt_remote_max_debt = match add_friend_reader.get_opt_remote_max_debt().which()? {
+        app_server_capnp::add_friend::opt_remote_max_debt::RemoteMaxDebt(
+            remote_max_debt_reader,
+        ) => Some(read_custom_u_int128(&remote_max_debt_reader?)?),
+        app_server_capnp::add_friend::opt_remote_max_debt::Empty(()) => None,
+    };
+
     Ok(AddFriend {
         friend_public_key: read_public_key(&add_friend_reader.get_friend_public_key()?)?,
         relays,
         name: add_friend_reader.get_name()?.to_owned(),
         balance: read_custom_int128(&add_friend_reader.get_balance()?)?,
+        opt_remote_max_debt,
     })
 }
 
@@ -366,6 +418,355 @@ fn deser_add_index_server(add_index_server_reader: &app_server_capnp::add_index_
 }
 */
 
+fn ser_suggest_first_hop(
+    suggest_first_hop: &SuggestFirstHop,
+    suggest_first_hop_builder: &mut app_server_capnp::suggest_first_hop::Builder,
+) {
+    write_uid(
+        &suggest_first_hop.request_id,
+        &mut suggest_first_hop_builder.reborrow().init_request_id(),
+    );
+    write_public_key(
+        &suggest_first_hop.dest_public_key,
+        &mut suggest_first_hop_builder.reborrow().init_dest_public_key(),
+    );
+    write_custom_u_int128(
+        suggest_first_hop.dest_payment,
+        &mut suggest_first_hop_builder.reborrow().init_dest_payment(),
+    );
+}
+
+fn deser_suggest_first_hop(
+    suggest_first_hop_reader: &app_server_capnp::suggest_first_hop::Reader,
+) -> Result<SuggestFirstHop, SerializeError> {
+    Ok(SuggestFirstHop {
+        request_id: read_uid(&suggest_first_hop_reader.get_request_id()?)?,
+        dest_public_key: read_public_key(&suggest_first_hop_reader.get_dest_public_key()?)?,
+        dest_payment: read_custom_u_int128(&suggest_first_hop_reader.get_dest_payment()?)?,
+    })
+}
+
+fn ser_first_hop_suggestion(
+    first_hop_suggestion: &FirstHopSuggestion,
+    first_hop_suggestion_builder: &mut app_server_capnp::first_hop_suggestion::Builder,
+) {
+    write_uid(
+        &first_hop_suggestion.request_id,
+        &mut first_hop_suggestion_builder.reborrow().init_request_id(),
+    );
+
+    let mut opt_friend_public_key_builder = first_hop_suggestion_builder
+        .reborrow()
+        .init_opt_friend_public_key();
+    match &first_hop_suggestion.opt_friend_public_key {
+        Some(friend_public_key) => {
+            let mut friend_public_key_builder =
+                opt_friend_public_key_builder.init_friend_public_key();
+            write_public_key(friend_public_key, &mut friend_public_key_builder);
+        }
+        None => {
+            opt_friend_public_key_builder.reborrow().set_empty(());
+        }
+    };
+}
+
+fn deser_first_hop_suggestion(
+    first_hop_suggestion_reader: &app_server_capnp::first_hop_suggestion::Reader,
+) -> Result<FirstHopSuggestion, SerializeError> {
+    let opt_friend_public_key = match first_hop_suggestion_reader
+        .get_opt_friend_public_key()
+        .which()?
+    {
+        app_server_capnp::first_hop_suggestion::opt_friend_public_key::FriendPublicKey(
+            friend_public_key_reader,
+        ) => Some(read_public_key(&friend_public_key_reader?)?),
+        app_server_capnp::first_hop_suggestion::opt_friend_public_key::Empty(()) => None,
+    };
+
+    Ok(FirstHopSuggestion {
+        request_id: read_uid(&first_hop_suggestion_reader.get_request_id()?)?,
+        opt_friend_public_key,
+    })
+}
+
+fn ser_pending_request(
+    pending_request: &PendingRequest,
+    pending_request_builder: &mut app_server_capnp::pending_request::Builder,
+) {
+    write_uid(
+        &pending_request.request_id,
+        &mut pending_request_builder.reborrow().init_request_id(),
+    );
+
+    let mut route_builder = pending_request_builder.reborrow().init_route();
+    ser_friends_route(&pending_request.route, &mut route_builder);
+
+    write_custom_u_int128(
+        pending_request.dest_payment,
+        &mut pending_request_builder.reborrow().init_dest_payment(),
+    );
+    write_invoice_id(
+        &pending_request.invoice_id,
+        &mut pending_request_builder.reborrow().init_invoice_id(),
+    );
+}
+
+fn deser_pending_request(
+    pending_request_reader: &app_server_capnp::pending_request::Reader,
+) -> Result<PendingRequest, SerializeError> {
+    Ok(PendingRequest {
+        request_id: read_uid(&pending_request_reader.get_request_id()?)?,
+        route: deser_friends_route(&pending_request_reader.get_route()?)?,
+        dest_payment: read_custom_u_int128(&pending_request_reader.get_dest_payment()?)?,
+        invoice_id: read_invoice_id(&pending_request_reader.get_invoice_id()?)?,
+    })
+}
+
+fn ser_pending_friend_request(
+    pending_friend_request: &PendingFriendRequest,
+    pending_friend_request_builder: &mut app_server_capnp::pending_friend_request::Builder,
+) {
+    ser_pending_request(
+        &pending_friend_request.pending_request,
+        &mut pending_friend_request_builder
+            .reborrow()
+            .init_pending_request(),
+    );
+    pending_friend_request_builder
+        .reborrow()
+        .set_is_queued(pending_friend_request.is_queued);
+}
+
+fn deser_pending_friend_request(
+    pending_friend_request_reader: &app_server_capnp::pending_friend_request::Reader,
+) -> Result<PendingFriendRequest, SerializeError> {
+    Ok(PendingFriendRequest {
+        pending_request: deser_pending_request(
+            &pending_friend_request_reader.get_pending_request()?,
+        )?,
+        is_queued: pending_friend_request_reader.get_is_queued(),
+    })
+}
+
+fn ser_friend_warmed(
+    friend_warmed: &FriendWarmed,
+    friend_warmed_builder: &mut app_server_capnp::friend_warmed::Builder,
+) {
+    write_public_key(
+        &friend_warmed.friend_public_key,
+        &mut friend_warmed_builder.reborrow().init_friend_public_key(),
+    );
+    friend_warmed_builder
+        .reborrow()
+        .set_is_ready(friend_warmed.is_ready);
+}
+
+fn deser_friend_warmed(
+    friend_warmed_reader: &app_server_capnp::friend_warmed::Reader,
+) -> Result<FriendWarmed, SerializeError> {
+    Ok(FriendWarmed {
+        friend_public_key: read_public_key(&friend_warmed_reader.get_friend_public_key()?)?,
+        is_ready: friend_warmed_reader.get_is_ready(),
+    })
+}
+
+fn ser_funds_received(
+    funds_received: &FundsReceived,
+    funds_received_builder: &mut app_server_capnp::funds_received::Builder,
+) {
+    write_uid(
+        &funds_received.request_id,
+        &mut funds_received_builder.reborrow().init_request_id(),
+    );
+    write_invoice_id(
+        &funds_received.invoice_id,
+        &mut funds_received_builder.reborrow().init_invoice_id(),
+    );
+    write_custom_u_int128(
+        funds_received.dest_payment,
+        &mut funds_received_builder.reborrow().init_dest_payment(),
+    );
+    funds_received_builder
+        .reborrow()
+        .set_memo(&funds_received.memo);
+}
+
+fn deser_funds_received(
+    funds_received_reader: &app_server_capnp::funds_received::Reader,
+) -> Result<FundsReceived, SerializeError> {
+    Ok(FundsReceived {
+        request_id: read_uid(&funds_received_reader.get_request_id()?)?,
+        invoice_id: read_invoice_id(&funds_received_reader.get_invoice_id()?)?,
+        dest_payment: read_custom_u_int128(&funds_received_reader.get_dest_payment()?)?,
+        memo: funds_received_reader.get_memo()?.to_vec(),
+    })
+}
+
+fn ser_payment_simulation(
+    payment_simulation: &PaymentSimulation,
+    payment_simulation_builder: &mut app_server_capnp::payment_simulation::Builder,
+) {
+    write_uid(
+        &payment_simulation.request_id,
+        &mut payment_simulation_builder.reborrow().init_request_id(),
+    );
+    payment_simulation_builder
+        .reborrow()
+        .set_feasible(payment_simulation.feasible);
+    write_custom_u_int128(
+        payment_simulation.total_cost,
+        &mut payment_simulation_builder.reborrow().init_total_cost(),
+    );
+
+    let mut opt_limiting_hop_builder = payment_simulation_builder
+        .reborrow()
+        .init_opt_limiting_hop();
+    match &payment_simulation.opt_limiting_hop {
+        Some(limiting_hop) => {
+            let mut limiting_hop_builder = opt_limiting_hop_builder.init_limiting_hop();
+            write_public_key(limiting_hop, &mut limiting_hop_builder);
+        }
+        None => {
+            opt_limiting_hop_builder.reborrow().set_empty(());
+        }
+    };
+
+    write_custom_u_int128(
+        payment_simulation.max_dest_payment,
+        &mut payment_simulation_builder
+            .reborrow()
+            .init_max_dest_payment(),
+    );
+}
+
+fn deser_payment_simulation(
+    payment_simulation_reader: &app_server_capnp::payment_simulation::Reader,
+) -> Result<PaymentSimulation, SerializeError> {
+    let opt_limiting_hop = match payment_simulation_reader.get_opt_limiting_hop().which()? {
+        app_server_capnp::payment_simulation::opt_limiting_hop::LimitingHop(
+            limiting_hop_reader,
+        ) => Some(read_public_key(&limiting_hop_reader?)?),
+        app_server_capnp::payment_simulation::opt_limiting_hop::Empty(()) => None,
+    };
+
+    Ok(PaymentSimulation {
+        request_id: read_uid(&payment_simulation_reader.get_request_id()?)?,
+        feasible: payment_simulation_reader.get_feasible(),
+        total_cost: read_custom_u_int128(&payment_simulation_reader.get_total_cost()?)?,
+        opt_limiting_hop,
+        max_dest_payment: read_custom_u_int128(&payment_simulation_reader.get_max_dest_payment()?)?,
+    })
+}
+
+fn ser_acked_receipt(
+    acked_receipt: &AckedReceipt,
+    acked_receipt_builder: &mut app_server_capnp::acked_receipt::Builder,
+) {
+    write_uid(
+        &acked_receipt.request_id,
+        &mut acked_receipt_builder.reborrow().init_request_id(),
+    );
+    write_receipt(
+        &acked_receipt.receipt,
+        &mut acked_receipt_builder.reborrow().init_receipt(),
+    );
+}
+
+fn deser_acked_receipt(
+    acked_receipt_reader: &app_server_capnp::acked_receipt::Reader,
+) -> Result<AckedReceipt, SerializeError> {
+    Ok(AckedReceipt {
+        request_id: read_uid(&acked_receipt_reader.get_request_id()?)?,
+        receipt: read_receipt(&acked_receipt_reader.get_receipt()?)?,
+    })
+}
+
+fn ser_friend_reset_token(
+    friend_reset_token: &FriendResetToken,
+    friend_reset_token_builder: &mut app_server_capnp::friend_reset_token::Builder,
+) {
+    write_public_key(
+        &friend_reset_token.friend_public_key,
+        &mut friend_reset_token_builder
+            .reborrow()
+            .init_friend_public_key(),
+    );
+    write_signature(
+        &friend_reset_token.reset_token,
+        &mut friend_reset_token_builder.reborrow().init_reset_token(),
+    );
+    write_custom_int128(
+        friend_reset_token.balance_for_reset,
+        &mut friend_reset_token_builder
+            .reborrow()
+            .init_balance_for_reset(),
+    );
+}
+
+fn deser_friend_reset_token(
+    friend_reset_token_reader: &app_server_capnp::friend_reset_token::Reader,
+) -> Result<FriendResetToken, SerializeError> {
+    Ok(FriendResetToken {
+        friend_public_key: read_public_key(&friend_reset_token_reader.get_friend_public_key()?)?,
+        reset_token: read_signature(&friend_reset_token_reader.get_reset_token()?)?,
+        balance_for_reset: read_custom_int128(&friend_reset_token_reader.get_balance_for_reset()?)?,
+    })
+}
+
+fn ser_remove_friend_consequences(
+    remove_friend_consequences: &RemoveFriendConsequences,
+    remove_friend_consequences_builder: &mut app_server_capnp::remove_friend_consequences::Builder,
+) {
+    write_public_key(
+        &remove_friend_consequences.friend_public_key,
+        &mut remove_friend_consequences_builder
+            .reborrow()
+            .init_friend_public_key(),
+    );
+    write_custom_int128(
+        remove_friend_consequences.balance,
+        &mut remove_friend_consequences_builder.reborrow().init_balance(),
+    );
+
+    let pending_requests_len =
+        usize_to_u32(remove_friend_consequences.pending_requests.len()).unwrap();
+    let mut pending_requests_builder = remove_friend_consequences_builder
+        .reborrow()
+        .init_pending_requests(pending_requests_len);
+    for (index, pending_friend_request) in remove_friend_consequences
+        .pending_requests
+        .iter()
+        .enumerate()
+    {
+        let mut pending_friend_request_builder = pending_requests_builder
+            .reborrow()
+            .get(usize_to_u32(index).unwrap());
+        ser_pending_friend_request(pending_friend_request, &mut pending_friend_request_builder);
+    }
+
+    remove_friend_consequences_builder
+        .reborrow()
+        .set_num_pending_responses(remove_friend_consequences.num_pending_responses);
+}
+
+fn deser_remove_friend_consequences(
+    remove_friend_consequences_reader: &app_server_capnp::remove_friend_consequences::Reader,
+) -> Result<RemoveFriendConsequences, SerializeError> {
+    let mut pending_requests = Vec::new();
+    for pending_friend_request in remove_friend_consequences_reader.get_pending_requests()? {
+        pending_requests.push(deser_pending_friend_request(&pending_friend_request)?);
+    }
+
+    Ok(RemoveFriendConsequences {
+        friend_public_key: read_public_key(
+            &remove_friend_consequences_reader.get_friend_public_key()?,
+        )?,
+        balance: read_custom_int128(&remove_friend_consequences_reader.get_balance()?)?,
+        pending_requests,
+        num_pending_responses: remove_friend_consequences_reader.get_num_pending_responses(),
+    })
+}
+
 fn ser_app_permissions(
     app_permissions: &AppPermissions,
     app_permissions_builder: &mut app_server_capnp::app_permissions::Builder,
@@ -464,6 +865,65 @@ fn ser_app_server_to_app(
             response_routes,
             &mut app_server_to_app_builder.reborrow().init_response_routes(),
         ),
+        AppServerToApp::FirstHopSuggestion(first_hop_suggestion) => ser_first_hop_suggestion(
+            first_hop_suggestion,
+            &mut app_server_to_app_builder
+                .reborrow()
+                .init_first_hop_suggestion(),
+        ),
+        AppServerToApp::PendingRequests(pending_requests) => {
+            let pending_requests_len = usize_to_u32(pending_requests.len()).unwrap();
+            let mut pending_requests_builder = app_server_to_app_builder
+                .reborrow()
+                .init_pending_requests(pending_requests_len);
+            for (index, pending_friend_request) in pending_requests.iter().enumerate() {
+                let mut pending_friend_request_builder = pending_requests_builder
+                    .reborrow()
+                    .get(usize_to_u32(index).unwrap());
+                ser_pending_friend_request(
+                    pending_friend_request,
+                    &mut pending_friend_request_builder,
+                );
+            }
+        }
+        AppServerToApp::FriendWarmed(friend_warmed) => ser_friend_warmed(
+            friend_warmed,
+            &mut app_server_to_app_builder.reborrow().init_friend_warmed(),
+        ),
+        AppServerToApp::PaymentSimulation(payment_simulation) => ser_payment_simulation(
+            payment_simulation,
+            &mut app_server_to_app_builder
+                .reborrow()
+                .init_payment_simulation(),
+        ),
+        AppServerToApp::RecentReceipts(recent_receipts) => {
+            let recent_receipts_len = usize_to_u32(recent_receipts.len()).unwrap();
+            let mut recent_receipts_builder = app_server_to_app_builder
+                .reborrow()
+                .init_recent_receipts(recent_receipts_len);
+            for (index, acked_receipt) in recent_receipts.iter().enumerate() {
+                let mut acked_receipt_builder = recent_receipts_builder
+                    .reborrow()
+                    .get(usize_to_u32(index).unwrap());
+                ser_acked_receipt(acked_receipt, &mut acked_receipt_builder);
+            }
+        }
+        AppServerToApp::ResetToken(friend_reset_token) => ser_friend_reset_token(
+            friend_reset_token,
+            &mut app_server_to_app_builder.reborrow().init_reset_token(),
+        ),
+        AppServerToApp::RemoveFriendConsequences(remove_friend_consequences) => {
+            ser_remove_friend_consequences(
+                remove_friend_consequences,
+                &mut app_server_to_app_builder
+                    .reborrow()
+                    .init_remove_friend_consequences(),
+            )
+        }
+        AppServerToApp::FundsReceived(funds_received) => ser_funds_received(
+            funds_received,
+            &mut app_server_to_app_builder.reborrow().init_funds_received(),
+        ),
     }
 }
 
@@ -485,6 +945,44 @@ fn deser_app_server_to_app(
                 &client_response_routes_reader?,
             )?)
         }
+        app_server_capnp::app_server_to_app::FirstHopSuggestion(first_hop_suggestion_reader) => {
+            AppServerToApp::FirstHopSuggestion(deser_first_hop_suggestion(
+                &first_hop_suggestion_reader?,
+            )?)
+        }
+        app_server_capnp::app_server_to_app::PendingRequests(pending_requests_reader) => {
+            let mut pending_requests = Vec::new();
+            for pending_friend_request in pending_requests_reader? {
+                pending_requests.push(deser_pending_friend_request(&pending_friend_request)?);
+            }
+            AppServerToApp::PendingRequests(pending_requests)
+        }
+        app_server_capnp::app_server_to_app::FriendWarmed(friend_warmed_reader) => {
+            AppServerToApp::FriendWarmed(deser_friend_warmed(&friend_warmed_reader?)?)
+        }
+        app_server_capnp::app_server_to_app::PaymentSimulation(payment_simulation_reader) => {
+            AppServerToApp::PaymentSimulation(deser_payment_simulation(
+                &payment_simulation_reader?,
+            )?)
+        }
+        app_server_capnp::app_server_to_app::RecentReceipts(recent_receipts_reader) => {
+            let mut recent_receipts = Vec::new();
+            for acked_receipt in recent_receipts_reader? {
+                recent_receipts.push(deser_acked_receipt(&acked_receipt)?);
+            }
+            AppServerToApp::RecentReceipts(recent_receipts)
+        }
+        app_server_capnp::app_server_to_app::ResetToken(friend_reset_token_reader) => {
+            AppServerToApp::ResetToken(deser_friend_reset_token(&friend_reset_token_reader?)?)
+        }
+        app_server_capnp::app_server_to_app::RemoveFriendConsequences(
+            remove_friend_consequences_reader,
+        ) => AppServerToApp::RemoveFriendConsequences(deser_remove_friend_consequences(
+            &remove_friend_consequences_reader?,
+        )?),
+        app_server_capnp::app_server_to_app::FundsReceived(funds_received_reader) => {
+            AppServerToApp::FundsReceived(deser_funds_received(&funds_received_reader?)?)
+        }
     })
 }
 
@@ -567,6 +1065,31 @@ fn ser_app_request(
             public_key,
             &mut app_request_builder.reborrow().init_remove_index_server(),
         ),
+        AppRequest::SuggestFirstHop(suggest_first_hop) => ser_suggest_first_hop(
+            suggest_first_hop,
+            &mut app_request_builder.reborrow().init_suggest_first_hop(),
+        ),
+        AppRequest::SimulatePayment(user_request_send_funds) => ser_user_request_send_funds(
+            user_request_send_funds,
+            &mut app_request_builder.reborrow().init_simulate_payment(),
+        ),
+        AppRequest::WarmFriend(friend_public_key) => write_public_key(
+            friend_public_key,
+            &mut app_request_builder.reborrow().init_warm_friend(),
+        ),
+        AppRequest::GetRecentReceipts => app_request_builder.set_get_recent_receipts(()),
+        AppRequest::GetPendingRequests(friend_public_key) => write_public_key(
+            friend_public_key,
+            &mut app_request_builder.reborrow().init_get_pending_requests(),
+        ),
+        AppRequest::GetResetToken(friend_public_key) => write_public_key(
+            friend_public_key,
+            &mut app_request_builder.reborrow().init_get_reset_token(),
+        ),
+        AppRequest::RequestRemoveFriend(friend_public_key) => write_public_key(
+            friend_public_key,
+            &mut app_request_builder.reborrow().init_request_remove_friend(),
+        ),
     }
 }
 
@@ -631,6 +1154,25 @@ fn deser_app_request(
         app_server_capnp::app_request::RemoveIndexServer(public_key_reader) => {
             AppRequest::RemoveIndexServer(read_public_key(&public_key_reader?)?)
         }
+        app_server_capnp::app_request::SuggestFirstHop(suggest_first_hop_reader) => {
+            AppRequest::SuggestFirstHop(deser_suggest_first_hop(&suggest_first_hop_reader?)?)
+        }
+        app_server_capnp::app_request::SimulatePayment(simulate_payment_reader) => {
+            AppRequest::SimulatePayment(deser_user_request_send_funds(&simulate_payment_reader?)?)
+        }
+        app_server_capnp::app_request::WarmFriend(public_key_reader) => {
+            AppRequest::WarmFriend(read_public_key(&public_key_reader?)?)
+        }
+        app_server_capnp::app_request::GetRecentReceipts(()) => AppRequest::GetRecentReceipts,
+        app_server_capnp::app_request::GetPendingRequests(public_key_reader) => {
+            AppRequest::GetPendingRequests(read_public_key(&public_key_reader?)?)
+        }
+        app_server_capnp::app_request::GetResetToken(public_key_reader) => {
+            AppRequest::GetResetToken(read_public_key(&public_key_reader?)?)
+        }
+        app_server_capnp::app_request::RequestRemoveFriend(public_key_reader) => {
+            AppRequest::RequestRemoveFriend(read_public_key(&public_key_reader?)?)
+        }
     })
 }
 
@@ -724,9 +1266,11 @@ pub fn deserialize_app_to_app_server(data: &[u8]) -> Result<AppToAppServer, Seri
 mod tests {
     use super::*;
     use crate::app_server::messages::{NodeReportMutation, RelayAddress};
+    use crate::funder::messages::FriendsRoute;
     use crate::index_client::messages::IndexClientReportMutation;
     use crate::report::messages::FunderReportMutation;
-    use crypto::identity::{PublicKey, PUBLIC_KEY_LEN};
+    use crypto::identity::{PublicKey, Signature, PUBLIC_KEY_LEN, SIGNATURE_LEN};
+    use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
     use crypto::uid::{Uid, UID_LEN};
     use std::convert::TryInto;
 
@@ -786,6 +1330,7 @@ mod tests {
             relays,
             name: "Friend name".to_owned(),
             balance: -500,
+            opt_remote_max_debt: Some(100),
         };
         let app_to_app_server = AppToAppServer {
             app_request_id: Uid::from(&[1; UID_LEN]),
@@ -797,5 +1342,99 @@ mod tests {
         assert_eq!(app_to_app_server, app_to_app_server2);
     }
 
+    #[test]
+    fn test_serialize_app_server_to_app_control_replies() {
+        let pending_request = PendingRequest {
+            request_id: Uid::from(&[2; UID_LEN]),
+            route: FriendsRoute {
+                public_keys: vec![
+                    PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+                    PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]),
+                ],
+            },
+            dest_payment: 20,
+            invoice_id: InvoiceId::from(&[3; INVOICE_ID_LEN]),
+        };
+        let pending_requests = vec![
+            PendingFriendRequest {
+                pending_request: pending_request.clone(),
+                is_queued: true,
+            },
+            PendingFriendRequest {
+                pending_request,
+                is_queued: false,
+            },
+        ];
+
+        let app_server_to_app_messages = vec![
+            AppServerToApp::FirstHopSuggestion(FirstHopSuggestion {
+                request_id: Uid::from(&[4; UID_LEN]),
+                opt_friend_public_key: Some(PublicKey::from(&[0xbb; PUBLIC_KEY_LEN])),
+            }),
+            AppServerToApp::PendingRequests(pending_requests.clone()),
+            AppServerToApp::FriendWarmed(FriendWarmed {
+                friend_public_key: PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]),
+                is_ready: true,
+            }),
+            AppServerToApp::PaymentSimulation(PaymentSimulation {
+                request_id: Uid::from(&[5; UID_LEN]),
+                feasible: false,
+                total_cost: 21,
+                opt_limiting_hop: Some(PublicKey::from(&[0xbb; PUBLIC_KEY_LEN])),
+                max_dest_payment: 10,
+            }),
+            AppServerToApp::RecentReceipts(Vec::new()),
+            AppServerToApp::ResetToken(FriendResetToken {
+                friend_public_key: PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]),
+                reset_token: Signature::from(&[6; SIGNATURE_LEN]),
+                balance_for_reset: -30,
+            }),
+            AppServerToApp::RemoveFriendConsequences(RemoveFriendConsequences {
+                friend_public_key: PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]),
+                balance: 40,
+                pending_requests,
+                num_pending_responses: 2,
+            }),
+            AppServerToApp::FundsReceived(FundsReceived {
+                request_id: Uid::from(&[7; UID_LEN]),
+                invoice_id: InvoiceId::from(&[8; INVOICE_ID_LEN]),
+                dest_payment: 50,
+                memo: b"Coffee".to_vec(),
+            }),
+        ];
+
+        for app_server_to_app in app_server_to_app_messages {
+            let data = serialize_app_server_to_app(&app_server_to_app);
+            let app_server_to_app2 = deserialize_app_server_to_app(&data).unwrap();
+            assert_eq!(app_server_to_app, app_server_to_app2);
+        }
+    }
+
+    #[test]
+    fn test_serialize_app_to_app_server_control_requests() {
+        let app_requests = vec![
+            AppRequest::SuggestFirstHop(SuggestFirstHop {
+                request_id: Uid::from(&[2; UID_LEN]),
+                dest_public_key: PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+                dest_payment: 20,
+            }),
+            AppRequest::WarmFriend(PublicKey::from(&[0xbb; PUBLIC_KEY_LEN])),
+            AppRequest::GetRecentReceipts,
+            AppRequest::GetPendingRequests(PublicKey::from(&[0xbb; PUBLIC_KEY_LEN])),
+            AppRequest::GetResetToken(PublicKey::from(&[0xbb; PUBLIC_KEY_LEN])),
+            AppRequest::RequestRemoveFriend(PublicKey::from(&[0xbb; PUBLIC_KEY_LEN])),
+        ];
+
+        for app_request in app_requests {
+            let app_to_app_server = AppToAppServer {
+                app_request_id: Uid::from(&[1; UID_LEN]),
+                app_request,
+            };
+            let data = serialize_app_to_app_server(&app_to_app_server);
+            let app_to_app_server2 = deserialize_app_to_app_server(&data).unwrap();
+            assert_eq!(app_to_app_server, app_to_app_server2);
+        }
+    }
+
     // TODO: More tests are required here
 }
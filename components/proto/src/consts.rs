@@ -1,12 +1,27 @@
 /// The current protocol version
-pub const PROTOCOL_VERSION: u32 = 0;
+/// Version 1: Requests carry a hop budget.
+pub const PROTOCOL_VERSION: u32 = 1;
 
 /// Maximum amount of friend operations sent in one move token message.
 pub const MAX_OPERATIONS_IN_BATCH: usize = 16;
 
+/// Maximum amount of friends the Funder sends messages to while handling a single event.
+/// Messages to the rest of the friends are sent later.
+pub const MAX_SEND_FRIENDS: usize = 0x40;
+
 /// Maximum length of route used to pass credit.
 pub const MAX_ROUTE_LEN: usize = 32;
 
+/// Maximum amount of times a request may be forwarded.
+/// Forwarding nodes enforce this bound, regardless of the hop budget set by the origin of the
+/// request.
+pub const MAX_HOP_BUDGET: u32 = 16;
+
+/// Maximum length of a memo attached to a payment, measured in bytes.
+/// Memos travel inside move token messages, so they must be short enough to keep a full batch
+/// of operations below the frame length.
+pub const MAX_MEMO_LEN: usize = 0x80;
+
 /// Amount of milliseconds in one tick:
 pub const TICK_MS: usize = 1000;
 
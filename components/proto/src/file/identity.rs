@@ -19,6 +19,7 @@ pub enum IdentityFileError {
     InvalidPublicKey,
     NetAddressError(NetAddressError),
     Pkcs8ParseError,
+    UnsupportedKeyType,
 }
 
 /// A helper structure for serialize and deserializing IdentityAddress.
@@ -60,10 +61,27 @@ pub fn store_raw_identity_to_file(
     Ok(())
 }
 
+/// The beginning of a PKCS#8 (v2) document holding an Ed25519 private key:
+/// The version, followed by the algorithm identifier (OID 1.3.101.112) and the header of the
+/// private key octet string.
+const ED25519_PKCS8_PREFIX: [u8; 16] = [
+    0x30, 0x53, 0x02, 0x01, 0x01, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+
+/// Make sure that a PKCS#8 document holds an Ed25519 private key.
+fn check_key_type(raw_identity: &[u8]) -> Result<(), IdentityFileError> {
+    if raw_identity.starts_with(&ED25519_PKCS8_PREFIX) {
+        Ok(())
+    } else {
+        Err(IdentityFileError::UnsupportedKeyType)
+    }
+}
+
 /// Load an identity from a file
 /// The file stores the private key according to PKCS#8.
 pub fn load_identity_from_file(path: &Path) -> Result<impl Identity, IdentityFileError> {
     let raw_identity = load_raw_identity_from_file(path)?;
+    check_key_type(&raw_identity)?;
     SoftwareEd25519Identity::from_pkcs8(&raw_identity)
         .map_err(|_| IdentityFileError::Pkcs8ParseError)
 }
@@ -73,6 +91,9 @@ mod tests {
     use super::*;
     use tempfile::tempdir;
 
+    use crypto::identity::generate_pkcs8_key_pair;
+    use crypto::test_utils::DummyRandom;
+
     #[test]
     fn test_identity_file_basic() {
         let identity_file: IdentityFile = toml::from_str(
@@ -99,4 +120,35 @@ mod tests {
         // We convert to vec here because [u8; 85] doesn't implement PartialEq
         assert_eq!(identity.to_vec(), identity2.to_vec());
     }
+
+    /// Store an identity file containing the given raw private key.
+    fn store_private_key(raw_private_key: &[u8; 85], path: &Path) {
+        let identity_file = IdentityFile {
+            private_key: private_key_to_string(raw_private_key),
+        };
+        let data = toml::to_string(&identity_file).unwrap();
+        let mut file = File::create(path).unwrap();
+        file.write_all(&data.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_load_identity_key_type() {
+        // Create a temporary directory:
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("identity_file");
+
+        // A valid Ed25519 key is accepted:
+        let pkcs8 = generate_pkcs8_key_pair(&DummyRandom::new(&[1u8]));
+        store_private_key(&pkcs8, &file_path);
+        assert!(load_identity_from_file(&file_path).is_ok());
+
+        // Replace the algorithm identifier with the X25519 OID (1.3.101.110):
+        let mut wrong_pkcs8 = pkcs8;
+        wrong_pkcs8[11] = 0x6e;
+        store_private_key(&wrong_pkcs8, &file_path);
+        match load_identity_from_file(&file_path) {
+            Err(IdentityFileError::UnsupportedKeyType) => {}
+            _ => unreachable!(),
+        }
+    }
 }
@@ -1,4 +1,5 @@
 use byteorder::{BigEndian, WriteBytesExt};
+use std::cmp;
 use std::collections::HashSet;
 
 use crypto::crypto_rand::RandValue;
@@ -8,11 +9,12 @@ use crypto::invoice_id::InvoiceId;
 use crypto::uid::Uid;
 
 use crate::app_server::messages::{NamedRelayAddress, RelayAddress};
-use crate::consts::MAX_ROUTE_LEN;
+use crate::consts::{MAX_HOP_BUDGET, MAX_ROUTE_LEN};
+use crate::funder::signature_buff::{move_token_checksum, verify_receipt};
 use crate::net::messages::NetAddress;
 use crate::report::messages::FunderReportMutations;
 use common::canonical_serialize::CanonicalSerialize;
-use common::int_convert::usize_to_u64;
+use common::int_convert::{usize_to_u32, usize_to_u64};
 
 #[derive(Debug, Clone)]
 pub struct ChannelerUpdateFriend<RA> {
@@ -33,16 +35,29 @@ pub enum FunderToChanneler<RA> {
     UpdateFriend(ChannelerUpdateFriend<RA>),
     /// Request to remove a friend
     RemoveFriend(PublicKey), // friend_public_key
+    /// Request to remove a friend permanently, forgetting all cached state about it
+    PurgeFriend(PublicKey), // friend_public_key
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelerRelaysChanged<RA> {
+    pub friend_public_key: PublicKey,
+    /// Relays used for this friend before the update:
+    pub old_relays: Vec<RA>,
+    /// Relays used for this friend after the update:
+    pub new_relays: Vec<RA>,
 }
 
 #[derive(Debug)]
-pub enum ChannelerToFunder {
+pub enum ChannelerToFunder<RA> {
     /// A friend is now online
     Online(PublicKey),
     /// A friend is now offline
     Offline(PublicKey),
     /// Incoming message from a remote friend
     Message((PublicKey, Vec<u8>)), // (friend_public_key, message)
+    /// Relays used to reach a friend have changed. Reconnection is in progress.
+    RelaysChanged(ChannelerRelaysChanged<RA>),
 }
 
 // -------------------------------------------
@@ -65,6 +80,12 @@ pub struct RequestSendFunds {
     pub route: FriendsRoute,
     pub dest_payment: u128,
     pub invoice_id: InvoiceId,
+    /// Amount of times this request may still be forwarded.
+    /// Decremented by every forwarding node along the route.
+    pub hop_budget: u32,
+    /// An optional message for the destination (Empty if no memo was attached).
+    /// At most MAX_MEMO_LEN bytes long.
+    pub memo: Vec<u8>,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
@@ -90,6 +111,11 @@ pub enum FriendTcOp {
     RequestSendFunds(RequestSendFunds),
     ResponseSendFunds(ResponseSendFunds),
     FailureSendFunds(FailureSendFunds),
+    /// Propose a new remote_max_debt. It only takes effect after the remote side
+    /// acknowledges it using AckRemoteMaxDebt.
+    ProposeRemoteMaxDebt(u128),
+    /// Acknowledge a remote_max_debt proposed by the remote side.
+    AckRemoteMaxDebt(u128),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
@@ -120,6 +146,8 @@ pub struct MoveTokenRequest<B = NetAddress> {
     pub friend_move_token: MoveToken<B>,
     // Do we want the remote side to return the token:
     pub token_wanted: bool,
+    /// Integrity check over friend_move_token, used to detect corruption during transport:
+    pub checksum: HashResult,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -127,6 +155,9 @@ pub struct MoveTokenRequest<B = NetAddress> {
 pub enum FriendMessage<B = NetAddress> {
     MoveTokenRequest(MoveTokenRequest<B>),
     InconsistencyError(ResetTerms),
+    /// Acknowledge a reset move token received from the remote side.
+    /// Contains the `new_token` of the acknowledged reset move token.
+    ResetAck(Signature),
 }
 
 /// A `Receipt` is received if a `RequestSendFunds` is successful.
@@ -165,6 +196,11 @@ impl CanonicalSerialize for RequestSendFunds {
         res_bytes
             .write_u128::<BigEndian>(self.dest_payment)
             .unwrap();
+        res_bytes.write_u32::<BigEndian>(self.hop_budget).unwrap();
+        res_bytes
+            .write_u64::<BigEndian>(usize_to_u64(self.memo.len()).unwrap())
+            .unwrap();
+        res_bytes.extend_from_slice(&self.memo);
         res_bytes
     }
 }
@@ -215,6 +251,14 @@ impl CanonicalSerialize for FriendTcOp {
                 res_bytes.push(5u8);
                 res_bytes.append(&mut failure_send_funds.canonical_serialize())
             }
+            FriendTcOp::ProposeRemoteMaxDebt(remote_max_debt) => {
+                res_bytes.push(6u8);
+                res_bytes.write_u128::<BigEndian>(*remote_max_debt).unwrap();
+            }
+            FriendTcOp::AckRemoteMaxDebt(remote_max_debt) => {
+                res_bytes.push(7u8);
+                res_bytes.write_u128::<BigEndian>(*remote_max_debt).unwrap();
+            }
         }
         res_bytes
     }
@@ -233,6 +277,21 @@ impl CanonicalSerialize for FriendsRoute {
     }
 }
 
+/// Returned when a route that is required to contain public keys is empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmptyRouteError;
+
+/// The reason for a route being invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidRouteError {
+    /// The route does not contain both a source and a destination.
+    TooShort,
+    /// The route contains more than MAX_ROUTE_LEN public keys.
+    TooLong,
+    /// A public key appears more than once on the route (Other than closing a single cycle).
+    DuplicatePublicKey,
+}
+
 impl FriendsRoute {
     pub fn len(&self) -> usize {
         self.public_keys.len()
@@ -242,29 +301,46 @@ impl FriendsRoute {
         self.public_keys.is_empty()
     }
 
+    /// Make sure that the route contains at least one public key.
+    /// An empty route has no source and no destination, and should be rejected
+    /// as soon as it is received.
+    pub fn validate_nonempty(&self) -> Result<(), EmptyRouteError> {
+        if self.public_keys.is_empty() {
+            Err(EmptyRouteError)
+        } else {
+            Ok(())
+        }
+    }
+
     /// Check if the route is valid.
     /// A valid route must have at least 2 nodes, and is in one of the following forms:
     /// A -- B -- C -- D -- E -- F -- A   (Single cycle, first == last)
     /// A -- B -- C -- D -- E -- F        (A route with no repetitions)
     pub fn is_valid(&self) -> bool {
-        if (self.public_keys.len() < 2) || (self.public_keys.len() > MAX_ROUTE_LEN) {
-            return false;
+        self.validate().is_ok()
+    }
+
+    /// Like `is_valid`, but reports the reason for the route being invalid.
+    pub fn validate(&self) -> Result<(), InvalidRouteError> {
+        if self.public_keys.len() < 2 {
+            return Err(InvalidRouteError::TooShort);
+        }
+        if self.public_keys.len() > MAX_ROUTE_LEN {
+            return Err(InvalidRouteError::TooLong);
         }
 
         let mut seen = HashSet::new();
         for public_key in &self.public_keys[..self.public_keys.len() - 1] {
             if !seen.insert(public_key.clone()) {
-                return false;
+                return Err(InvalidRouteError::DuplicatePublicKey);
             }
         }
         let last_pk = &self.public_keys[self.public_keys.len() - 1];
-        if last_pk == &self.public_keys[0] {
-            // The last public key closes a cycle
-            true
-        } else {
-            // A route with no repetitions
-            seen.insert(last_pk.clone())
+        // The last public key may close a cycle. Otherwise it must not repeat:
+        if last_pk != &self.public_keys[0] && !seen.insert(last_pk.clone()) {
+            return Err(InvalidRouteError::DuplicatePublicKey);
         }
+        Ok(())
     }
 
     /// Find two consecutive public keys (pk1, pk2) inside a friends route.
@@ -299,6 +375,70 @@ impl FriendsRoute {
     pub fn index_to_pk(&self, index: usize) -> Option<&PublicKey> {
         self.public_keys.get(index)
     }
+
+    /// Split the route at a given node.
+    /// Returns the route from the source to the node (upstream), and the route from the node to
+    /// the destination (downstream). The node itself is included in both routes.
+    ///
+    /// Returns None if the node is not on the route.
+    pub fn split_at_node(&self, public_key: &PublicKey) -> Option<(FriendsRoute, FriendsRoute)> {
+        let index = self.pk_to_index(public_key)?;
+        let upstream = FriendsRoute {
+            public_keys: self.public_keys[..=index].to_vec(),
+        };
+        let downstream = FriendsRoute {
+            public_keys: self.public_keys[index..].to_vec(),
+        };
+        Some((upstream, downstream))
+    }
+}
+
+/// Accumulates public keys into a route, validating the route when it is built.
+#[derive(Debug, Clone, Default)]
+pub struct FriendsRouteBuilder {
+    public_keys: Vec<PublicKey>,
+}
+
+impl FriendsRouteBuilder {
+    pub fn new() -> Self {
+        FriendsRouteBuilder {
+            public_keys: Vec::new(),
+        }
+    }
+
+    /// Append a public key to the end of the route.
+    pub fn push(mut self, public_key: PublicKey) -> Self {
+        self.public_keys.push(public_key);
+        self
+    }
+
+    /// Produce the route, making sure that it is valid (See `FriendsRoute::is_valid`).
+    pub fn build(self) -> Result<FriendsRoute, InvalidRouteError> {
+        let route = FriendsRoute {
+            public_keys: self.public_keys,
+        };
+        route.validate()?;
+        Ok(route)
+    }
+}
+
+impl<B> MoveTokenRequest<B>
+where
+    B: CanonicalSerialize,
+{
+    pub fn new(friend_move_token: MoveToken<B>, token_wanted: bool) -> Self {
+        let checksum = move_token_checksum(&friend_move_token);
+        MoveTokenRequest {
+            friend_move_token,
+            token_wanted,
+            checksum,
+        }
+    }
+
+    /// Make sure that friend_move_token matches the attached checksum.
+    pub fn verify_checksum(&self) -> bool {
+        move_token_checksum(&self.friend_move_token) == self.checksum
+    }
 }
 
 impl CanonicalSerialize for Receipt {
@@ -314,6 +454,14 @@ impl CanonicalSerialize for Receipt {
     }
 }
 
+impl Receipt {
+    /// Verify that this receipt was signed by the recipient of the payment.
+    /// Does not require any channel state.
+    pub fn verify(&self, recipient_public_key: &PublicKey) -> bool {
+        verify_receipt(self, recipient_public_key)
+    }
+}
+
 // AppServer <-> Funder communication:
 // ===================================
 
@@ -321,6 +469,9 @@ impl CanonicalSerialize for Receipt {
 pub enum FriendStatus {
     Enabled,
     Disabled,
+    /// The connection to the friend is kept (allowing in-flight requests to be settled), but no
+    /// new requests are sent to or accepted from the friend.
+    Paused,
 }
 
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
@@ -345,6 +496,9 @@ pub struct AddFriend<B = NetAddress> {
     pub relays: Vec<RelayAddress<B>>,
     pub name: String,
     pub balance: i128, // Initial balance
+    /// Initial wanted remote max debt. Set together with the new friend, so that no separate
+    /// SetFriendRemoteMaxDebt is required.
+    pub opt_remote_max_debt: Option<u128>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -370,6 +524,30 @@ pub struct SetFriendRemoteMaxDebt {
     pub remote_max_debt: u128,
 }
 
+/// Enable a friend, open requests and set the remote max debt, all in one step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActivateFriend {
+    pub friend_public_key: PublicKey,
+    pub remote_max_debt: u128,
+}
+
+/// Set a floor for our balance with a friend.
+/// Requests that would push our balance with this friend below the floor will not be forwarded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetFriendMinBalance {
+    pub friend_public_key: PublicKey,
+    pub opt_min_balance: Option<i128>,
+}
+
+/// Set a cap on the amount of any single payment we send to a friend, either as the origin of
+/// the payment or when forwarding it. The amount includes the fees of the nodes along the rest of
+/// the route. Payments above the cap will be rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetFriendMaxSinglePayment {
+    pub friend_public_key: PublicKey,
+    pub opt_max_single_payment: Option<u128>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SetFriendName {
     pub friend_public_key: PublicKey,
@@ -388,13 +566,38 @@ pub struct ResetFriendChannel {
     pub reset_token: Signature,
 }
 
-/// A request to send funds that originates from the user
+/// Drive the channel with a friend into an inconsistent state, offering the given reset terms
+/// to the friend.
+#[cfg(feature = "force-inconsistency")]
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForceInconsistency {
+    pub friend_public_key: PublicKey,
+    pub reset_terms: ResetTerms,
+}
+
+/// The local reset terms we sent to a friend during an inconsistency.
+/// Allows both sides to confirm out of band that they agree on the reset terms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FriendResetToken {
+    pub friend_public_key: PublicKey,
+    pub reset_token: Signature,
+    pub balance_for_reset: i128,
+}
+
+/// A request to send funds that originates from the user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct UserRequestSendFunds {
     pub request_id: Uid,
     pub route: FriendsRoute,
     pub invoice_id: InvoiceId,
     pub dest_payment: u128,
+    /// An optional message for the destination (Empty if no memo was attached).
+    pub memo: Vec<u8>,
+    /// Retries of the same logical payment share the same idempotency key, even if they use
+    /// different request ids, and must use the same route and dest_payment.
+    /// The Funder remembers the most recent idempotency keys. A retry with a remembered key never
+    /// causes a second payment.
+    pub opt_idempotency_key: Option<Uid>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -403,20 +606,121 @@ pub struct ReceiptAck {
     pub receipt_signature: Signature,
 }
 
+/// A receipt that was already acked, together with the id of the request it belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AckedReceipt {
+    pub request_id: Uid,
+    pub receipt: Receipt,
+}
+
+/// Ask the Funder to suggest a first hop friend for a payment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuggestFirstHop {
+    pub request_id: Uid,
+    /// If the destination is a friend that can receive dest_payment, it is suggested directly.
+    pub dest_public_key: PublicKey,
+    pub dest_payment: u128,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirstHopSuggestion {
+    pub request_id: Uid,
+    /// The ready friend with the most available capacity,
+    /// or None if no ready friend can forward dest_payment.
+    pub opt_friend_public_key: Option<PublicKey>,
+}
+
+/// The outcome of simulating a payment request, without sending it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentSimulation {
+    pub request_id: Uid,
+    /// Would the payment currently be accepted by us and by the first hop?
+    pub feasible: bool,
+    /// Total amount of credits the payment costs us, including the credits paid to the
+    /// intermediate nodes. 0 if the route is invalid.
+    pub total_cost: u128,
+    /// The hop that prevents the payment, if known. Only the first hop can be checked locally.
+    pub opt_limiting_hop: Option<PublicKey>,
+    /// The largest dest_payment the first hop would currently accept along this route.
+    /// 0 if the route is invalid or the first hop is not ready.
+    pub max_dest_payment: u128,
+}
+
+/// Move credits between our friends, by sending a payment to ourselves along a cycle.
+/// The route must begin and end with us.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rebalance {
+    pub request_id: Uid,
+    pub route: FriendsRoute,
+    pub dest_payment: u128,
+}
+
+/// A request that is currently pending with a friend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingFriendRequest {
+    pub pending_request: PendingRequest,
+    /// true if the request is still waiting in the user requests queue,
+    /// false if it was already sent to the friend and awaits a response.
+    pub is_queued: bool,
+}
+
+/// What would be lost by removing a friend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoveFriendConsequences {
+    pub friend_public_key: PublicKey,
+    /// The balance with the friend. If the channel with the friend is inconsistent, this is the
+    /// balance of our local reset terms.
+    pub balance: i128,
+    /// Requests that will be cancelled by the removal.
+    pub pending_requests: Vec<PendingFriendRequest>,
+    /// Amount of responses and failures that were not yet sent to the friend.
+    pub num_pending_responses: u64,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FunderControl<B> {
     AddRelay(NamedRelayAddress<B>),
     RemoveRelay(PublicKey),
     AddFriend(AddFriend<B>),
     RemoveFriend(RemoveFriend),
+    /// Check the consequences of removing a friend, without removing it.
+    /// Answered with a RemoveFriendConsequences message.
+    RequestRemoveFriend(PublicKey),
     SetRequestsStatus(SetRequestsStatus),
     SetFriendStatus(SetFriendStatus),
+    /// Enable a friend, open requests and set the remote max debt. Either all the changes are
+    /// applied, or none of them.
+    ActivateFriend(ActivateFriend),
     SetFriendRemoteMaxDebt(SetFriendRemoteMaxDebt),
+    /// Same as SetFriendRemoteMaxDebt, but the new remote_max_debt only takes effect after
+    /// the friend confirms it.
+    ProposeFriendRemoteMaxDebt(SetFriendRemoteMaxDebt),
+    SetFriendMinBalance(SetFriendMinBalance),
+    SetFriendMaxSinglePayment(SetFriendMaxSinglePayment),
     SetFriendRelays(SetFriendRelays<B>),
     SetFriendName(SetFriendName),
     ResetFriendChannel(ResetFriendChannel),
     RequestSendFunds(UserRequestSendFunds),
+    /// Check the cost and feasibility of a payment without sending it.
+    /// Answered with a PaymentSimulation message.
+    SimulatePayment(UserRequestSendFunds),
     ReceiptAck(ReceiptAck),
+    /// Get the receipts that were recently acked, oldest first.
+    /// Answered with a RecentReceipts message.
+    GetRecentReceipts,
+    SuggestFirstHop(SuggestFirstHop),
+    GetPendingRequests(PublicKey),
+    /// Get the local reset terms of an inconsistent channel with a friend.
+    /// Answered with a ResetToken message.
+    GetResetToken(PublicKey),
+    Rebalance(Rebalance),
+    /// Connect to a friend and exchange an empty move token, so that the friend becomes ready
+    /// before a payment is attempted. Answered with a FriendWarmed message.
+    WarmFriend(PublicKey),
+    /// Deliberately drive the channel with a friend into an inconsistent state.
+    /// Only meant for exercising the channel reset flow during testing.
+    #[cfg(feature = "force-inconsistency")]
+    ForceInconsistency(ForceInconsistency),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -436,11 +740,17 @@ impl<B> FunderIncomingControl<B> {
 
 impl UserRequestSendFunds {
     pub fn into_request(self) -> RequestSendFunds {
+        // Every node on the route except for the source and the destination forwards the
+        // request exactly once. Longer routes are cut off at MAX_HOP_BUDGET forwards:
+        let route_hop_budget = usize_to_u32(self.route.len().saturating_sub(2)).unwrap();
+        let hop_budget = cmp::min(route_hop_budget, MAX_HOP_BUDGET);
         RequestSendFunds {
             request_id: self.request_id,
             route: self.route,
             invoice_id: self.invoice_id,
             dest_payment: self.dest_payment,
+            hop_budget,
+            memo: self.memo,
         }
     }
 
@@ -466,8 +776,196 @@ pub struct ResponseReceived {
     pub result: ResponseSendFundsResult,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FriendWarmed {
+    pub friend_public_key: PublicKey,
+    /// false if the friend did not become ready before the timeout elapsed.
+    pub is_ready: bool,
+}
+
+/// Funds were received by us, being the destination of a request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FundsReceived {
+    pub request_id: Uid,
+    pub invoice_id: InvoiceId,
+    pub dest_payment: u128,
+    pub memo: Vec<u8>,
+}
+
 #[derive(Debug)]
 pub enum FunderOutgoingControl<B: Clone> {
     ResponseReceived(ResponseReceived),
     ReportMutations(FunderReportMutations<B>),
+    FirstHopSuggestion(FirstHopSuggestion),
+    PendingRequests(Vec<PendingFriendRequest>),
+    FriendWarmed(FriendWarmed),
+    FundsReceived(FundsReceived),
+    PaymentSimulation(PaymentSimulation),
+    RecentReceipts(Vec<AckedReceipt>),
+    ResetToken(FriendResetToken),
+    RemoveFriendConsequences(RemoveFriendConsequences),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::identity::PUBLIC_KEY_LEN;
+    use crypto::invoice_id::INVOICE_ID_LEN;
+    use crypto::uid::UID_LEN;
+
+    #[test]
+    fn test_friends_route_empty() {
+        let route = FriendsRoute {
+            public_keys: Vec::new(),
+        };
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+
+        assert_eq!(route.len(), 0);
+        assert!(route.is_empty());
+        assert_eq!(route.validate_nonempty(), Err(EmptyRouteError));
+        assert!(!route.is_valid());
+        assert_eq!(route.find_pk_pair(&pk_a, &pk_a), None);
+        assert_eq!(route.pk_to_index(&pk_a), None);
+        assert_eq!(route.index_to_pk(0), None);
+    }
+
+    #[test]
+    fn test_friends_route_single() {
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let route = FriendsRoute {
+            public_keys: vec![pk_a.clone()],
+        };
+
+        assert_eq!(route.len(), 1);
+        assert!(!route.is_empty());
+        assert_eq!(route.validate_nonempty(), Ok(()));
+        // A route must contain at least a source and a destination:
+        assert!(!route.is_valid());
+        assert_eq!(route.find_pk_pair(&pk_a, &pk_a), None);
+        assert_eq!(route.find_pk_pair(&pk_a, &pk_b), None);
+        assert_eq!(route.pk_to_index(&pk_a), Some(0));
+        assert_eq!(route.pk_to_index(&pk_b), None);
+        assert_eq!(route.index_to_pk(0), Some(&pk_a));
+        assert_eq!(route.index_to_pk(1), None);
+
+        // The hash depends on the contents of the route:
+        let empty_route = FriendsRoute {
+            public_keys: Vec::new(),
+        };
+        assert_ne!(route.hash(), empty_route.hash());
+    }
+    #[test]
+    fn test_friends_route_builder() {
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let pk_c = PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]);
+
+        let route = FriendsRouteBuilder::new()
+            .push(pk_a.clone())
+            .push(pk_b.clone())
+            .push(pk_c.clone())
+            .build()
+            .unwrap();
+        assert_eq!(
+            route.public_keys,
+            vec![pk_a.clone(), pk_b.clone(), pk_c.clone()]
+        );
+        assert!(route.is_valid());
+
+        // A single cycle is allowed:
+        let route = FriendsRouteBuilder::new()
+            .push(pk_a.clone())
+            .push(pk_b.clone())
+            .push(pk_a.clone())
+            .build()
+            .unwrap();
+        assert!(route.is_valid());
+
+        // Too short:
+        assert_eq!(
+            FriendsRouteBuilder::new().build(),
+            Err(InvalidRouteError::TooShort)
+        );
+        assert_eq!(
+            FriendsRouteBuilder::new().push(pk_a.clone()).build(),
+            Err(InvalidRouteError::TooShort)
+        );
+
+        // Too long:
+        let mut builder = FriendsRouteBuilder::new();
+        for i in 0..=MAX_ROUTE_LEN {
+            builder = builder.push(PublicKey::from(&[i as u8; PUBLIC_KEY_LEN]));
+        }
+        assert_eq!(builder.build(), Err(InvalidRouteError::TooLong));
+
+        // Duplicates:
+        assert_eq!(
+            FriendsRouteBuilder::new()
+                .push(pk_a.clone())
+                .push(pk_b.clone())
+                .push(pk_b.clone())
+                .build(),
+            Err(InvalidRouteError::DuplicatePublicKey)
+        );
+        assert_eq!(
+            FriendsRouteBuilder::new()
+                .push(pk_a.clone())
+                .push(pk_b.clone())
+                .push(pk_a.clone())
+                .push(pk_c.clone())
+                .build(),
+            Err(InvalidRouteError::DuplicatePublicKey)
+        );
+    }
+
+    #[test]
+    fn test_friends_route_split_at_node() {
+        let pk_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let pk_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let pk_c = PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]);
+        let pk_d = PublicKey::from(&[0xdd; PUBLIC_KEY_LEN]);
+        let route = FriendsRoute {
+            public_keys: vec![pk_a.clone(), pk_b.clone(), pk_c.clone()],
+        };
+
+        // Source:
+        let (upstream, downstream) = route.split_at_node(&pk_a).unwrap();
+        assert_eq!(upstream.public_keys, vec![pk_a.clone()]);
+        assert_eq!(downstream, route);
+
+        // Middle node:
+        let (upstream, downstream) = route.split_at_node(&pk_b).unwrap();
+        assert_eq!(upstream.public_keys, vec![pk_a.clone(), pk_b.clone()]);
+        assert_eq!(downstream.public_keys, vec![pk_b.clone(), pk_c.clone()]);
+
+        // Destination:
+        let (upstream, downstream) = route.split_at_node(&pk_c).unwrap();
+        assert_eq!(upstream, route);
+        assert_eq!(downstream.public_keys, vec![pk_c.clone()]);
+
+        // A node that is not on the route:
+        assert_eq!(route.split_at_node(&pk_d), None);
+    }
+
+    #[test]
+    fn test_user_request_send_funds_serde() {
+        let user_request_send_funds = UserRequestSendFunds {
+            request_id: Uid::from(&[1; UID_LEN]),
+            route: FriendsRoute {
+                public_keys: vec![
+                    PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+                    PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]),
+                ],
+            },
+            invoice_id: InvoiceId::from(&[2; INVOICE_ID_LEN]),
+            dest_payment: 100,
+            memo: b"memo".to_vec(),
+            opt_idempotency_key: Some(Uid::from(&[3; UID_LEN])),
+        };
+
+        let ser = serde_json::to_vec(&user_request_send_funds).unwrap();
+        let user_request_send_funds2: UserRequestSendFunds = serde_json::from_slice(&ser).unwrap();
+        assert_eq!(user_request_send_funds, user_request_send_funds2);
+    }
 }
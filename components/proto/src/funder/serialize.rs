@@ -1,8 +1,8 @@
 use crate::capnp_common::{
-    read_custom_int128, read_custom_u_int128, read_invoice_id, read_public_key, read_rand_nonce,
-    read_relay_address, read_signature, read_uid, write_custom_int128, write_custom_u_int128,
-    write_invoice_id, write_public_key, write_rand_nonce, write_relay_address, write_signature,
-    write_uid,
+    read_custom_int128, read_custom_u_int128, read_hash, read_invoice_id, read_public_key,
+    read_rand_nonce, read_relay_address, read_signature, read_uid, write_custom_int128,
+    write_custom_u_int128, write_hash, write_invoice_id, write_public_key, write_rand_nonce,
+    write_relay_address, write_signature, write_uid,
 };
 use capnp;
 use capnp::serialize_packed;
@@ -56,6 +56,14 @@ fn ser_request_send_funds_op(
         &request_send_funds.invoice_id,
         &mut request_send_funds_op_builder.reborrow().init_invoice_id(),
     );
+
+    request_send_funds_op_builder
+        .reborrow()
+        .set_hop_budget(request_send_funds.hop_budget);
+
+    request_send_funds_op_builder
+        .reborrow()
+        .set_memo(&request_send_funds.memo);
 }
 
 fn ser_response_send_funds_op(
@@ -122,6 +130,16 @@ fn ser_friend_operation(
                 operation_builder.reborrow().init_response_send_funds();
             ser_response_send_funds_op(response_send_funds, &mut response_send_funds_builder);
         }
+        FriendTcOp::ProposeRemoteMaxDebt(remote_max_debt) => {
+            let mut propose_remote_max_debt_builder =
+                operation_builder.reborrow().init_propose_remote_max_debt();
+            write_custom_u_int128(*remote_max_debt, &mut propose_remote_max_debt_builder);
+        }
+        FriendTcOp::AckRemoteMaxDebt(remote_max_debt) => {
+            let mut ack_remote_max_debt_builder =
+                operation_builder.reborrow().init_ack_remote_max_debt();
+            write_custom_u_int128(*remote_max_debt, &mut ack_remote_max_debt_builder);
+        }
         FriendTcOp::FailureSendFunds(failure_send_funds) => {
             let mut failure_send_funds_builder =
                 operation_builder.reborrow().init_failure_send_funds();
@@ -214,6 +232,11 @@ fn ser_move_token_request(
     );
 
     move_token_request_builder.set_token_wanted(move_token_request.token_wanted);
+
+    write_hash(
+        &move_token_request.checksum,
+        &mut move_token_request_builder.reborrow().init_checksum(),
+    );
 }
 
 fn ser_inconsistency_error(
@@ -246,6 +269,10 @@ fn ser_friend_message(
                 friend_message_builder.reborrow().init_inconsistency_error();
             ser_inconsistency_error(inconsistency_error, &mut inconsistency_error_builder);
         }
+        FriendMessage::ResetAck(reset_token) => {
+            let mut reset_ack_builder = friend_message_builder.reborrow().init_reset_ack();
+            write_signature(reset_token, &mut reset_ack_builder);
+        }
     };
 }
 
@@ -261,6 +288,18 @@ pub fn serialize_friend_message(friend_message: &FriendMessage) -> Vec<u8> {
     ser_buff
 }
 
+/// Serialize a MoveToken into a vector of bytes
+pub fn serialize_move_token(move_token: &MoveToken) -> Vec<u8> {
+    let mut builder = capnp::message::Builder::new_default();
+    let mut move_token_builder = builder.init_root::<funder_capnp::move_token::Builder>();
+
+    ser_move_token(move_token, &mut move_token_builder);
+
+    let mut ser_buff = Vec::new();
+    serialize_packed::write_message(&mut ser_buff, &builder).unwrap();
+    ser_buff
+}
+
 // ------------ Deserialization -----------------------
 // ----------------------------------------------------
 
@@ -272,7 +311,9 @@ pub fn deser_friends_route(
         public_keys.push(read_public_key(&public_key_reader)?);
     }
 
-    Ok(FriendsRoute { public_keys })
+    let friends_route = FriendsRoute { public_keys };
+    friends_route.validate_nonempty()?;
+    Ok(friends_route)
 }
 
 fn deser_request_send_funds_op(
@@ -283,6 +324,8 @@ fn deser_request_send_funds_op(
         route: deser_friends_route(&request_send_funds_op_reader.get_route()?)?,
         dest_payment: read_custom_u_int128(&request_send_funds_op_reader.get_dest_payment()?)?,
         invoice_id: read_invoice_id(&request_send_funds_op_reader.get_invoice_id()?)?,
+        hop_budget: request_send_funds_op_reader.get_hop_budget(),
+        memo: request_send_funds_op_reader.get_memo()?.to_vec(),
     })
 }
 
@@ -329,6 +372,14 @@ fn deser_friend_operation(
         funder_capnp::friend_operation::FailureSendFunds(failure_send_funds_reader) => {
             FriendTcOp::FailureSendFunds(deser_failure_send_funds_op(&failure_send_funds_reader?)?)
         }
+        funder_capnp::friend_operation::ProposeRemoteMaxDebt(propose_remote_max_debt_reader) => {
+            FriendTcOp::ProposeRemoteMaxDebt(read_custom_u_int128(
+                &propose_remote_max_debt_reader?,
+            )?)
+        }
+        funder_capnp::friend_operation::AckRemoteMaxDebt(ack_remote_max_debt_reader) => {
+            FriendTcOp::AckRemoteMaxDebt(read_custom_u_int128(&ack_remote_max_debt_reader?)?)
+        }
     })
 }
 
@@ -377,6 +428,7 @@ fn deser_move_token_request(
     Ok(MoveTokenRequest {
         friend_move_token: move_token,
         token_wanted: move_token_request_reader.get_token_wanted(),
+        checksum: read_hash(&move_token_request_reader.get_checksum()?)?,
     })
 }
 
@@ -404,6 +456,9 @@ fn deser_friend_message(
                 &inconsistency_error_reader?,
             )?)
         }
+        funder_capnp::friend_message::ResetAck(reset_ack_reader) => {
+            FriendMessage::ResetAck(read_signature(&reset_ack_reader?)?)
+        }
     })
 }
 
@@ -417,6 +472,16 @@ pub fn deserialize_friend_message(data: &[u8]) -> Result<FriendMessage, Serializ
     deser_friend_message(&friend_message_reader)
 }
 
+/// Deserialize MoveToken from an array of bytes
+pub fn deserialize_move_token(data: &[u8]) -> Result<MoveToken, SerializeError> {
+    let mut cursor = io::Cursor::new(data);
+    let reader =
+        serialize_packed::read_message(&mut cursor, ::capnp::message::ReaderOptions::new())?;
+    let move_token_reader = reader.get_root::<funder_capnp::move_token::Reader>()?;
+
+    deser_move_token(&move_token_reader)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -427,8 +492,8 @@ mod tests {
     use crypto::uid::{Uid, UID_LEN};
     use std::convert::TryInto;
 
-    /// Create an example FriendMessage::MoveTokenRequest:
-    fn create_move_token_request() -> FriendMessage {
+    /// Create an example MoveToken, containing all kinds of operations:
+    fn create_move_token() -> MoveToken {
         let route = FriendsRoute {
             public_keys: vec![
                 PublicKey::from(&[0x5; PUBLIC_KEY_LEN]),
@@ -443,6 +508,8 @@ mod tests {
             route,
             dest_payment: 48,
             invoice_id: InvoiceId::from(&[0x99; INVOICE_ID_LEN]),
+            hop_budget: 2,
+            memo: b"memo".to_vec(),
         };
         let response_send_funds = ResponseSendFunds {
             request_id: Uid::from(&[10; UID_LEN]),
@@ -464,6 +531,8 @@ mod tests {
             FriendTcOp::RequestSendFunds(request_send_funds),
             FriendTcOp::ResponseSendFunds(response_send_funds),
             FriendTcOp::FailureSendFunds(failure_send_funds),
+            FriendTcOp::ProposeRemoteMaxDebt(202),
+            FriendTcOp::AckRemoteMaxDebt(303),
         ];
 
         let relay_address4 = RelayAddress {
@@ -476,7 +545,7 @@ mod tests {
             address: "MyAddress:1338".to_owned().try_into().unwrap(),
         };
 
-        let move_token = MoveToken {
+        MoveToken {
             operations,
             opt_local_relays: Some(vec![relay_address4, relay_address6]),
             old_token: Signature::from(&[0; SIGNATURE_LEN]),
@@ -489,11 +558,12 @@ mod tests {
             remote_pending_debt: 80,
             rand_nonce: RandValue::from(&[0xaa; RAND_VALUE_LEN]),
             new_token: Signature::from(&[1; SIGNATURE_LEN]),
-        };
-        let move_token_request = MoveTokenRequest {
-            friend_move_token: move_token,
-            token_wanted: true,
-        };
+        }
+    }
+
+    /// Create an example FriendMessage::MoveTokenRequest:
+    fn create_move_token_request() -> FriendMessage {
+        let move_token_request = MoveTokenRequest::new(create_move_token(), true);
 
         FriendMessage::MoveTokenRequest(move_token_request)
     }
@@ -508,6 +578,11 @@ mod tests {
         FriendMessage::InconsistencyError(reset_terms)
     }
 
+    /// Create an example FriendMessage::ResetAck
+    fn create_reset_ack() -> FriendMessage {
+        FriendMessage::ResetAck(Signature::from(&[3; SIGNATURE_LEN]))
+    }
+
     #[test]
     fn test_serialize_friend_message_move_token_request() {
         let friend_message = create_move_token_request();
@@ -516,6 +591,29 @@ mod tests {
         assert_eq!(friend_message, friend_message2);
     }
 
+    #[test]
+    fn test_serialize_move_token() {
+        let move_token = create_move_token();
+        let ser_buff = serialize_move_token(&move_token);
+        let move_token2 = deserialize_move_token(&ser_buff).unwrap();
+        assert_eq!(move_token, move_token2);
+    }
+
+    #[test]
+    fn test_deserialize_empty_friends_route() {
+        let mut move_token = create_move_token();
+        if let FriendTcOp::RequestSendFunds(request_send_funds) = &mut move_token.operations[3] {
+            request_send_funds.route.public_keys.clear();
+        } else {
+            unreachable!();
+        }
+        let ser_buff = serialize_move_token(&move_token);
+        match deserialize_move_token(&ser_buff) {
+            Err(SerializeError::EmptyRouteError(_)) => {}
+            _ => unreachable!(),
+        }
+    }
+
     #[test]
     fn test_serialize_friend_message_inconsistency_error() {
         let friend_message = create_inconsistency_error();
@@ -523,4 +621,12 @@ mod tests {
         let friend_message2 = deserialize_friend_message(&ser_buff).unwrap();
         assert_eq!(friend_message, friend_message2);
     }
+
+    #[test]
+    fn test_serialize_friend_message_reset_ack() {
+        let friend_message = create_reset_ack();
+        let ser_buff = serialize_friend_message(&friend_message);
+        let friend_message2 = deserialize_friend_message(&ser_buff).unwrap();
+        assert_eq!(friend_message, friend_message2);
+    }
 }
@@ -185,6 +185,18 @@ where
     sig_buffer
 }
 
+/// Calculate a checksum over all the fields of a move token, including the signature.
+/// The checksum is cheap to verify, and allows to detect corruption of a move token during
+/// transport before its signature is verified.
+pub fn move_token_checksum<B>(move_token: &MoveToken<B>) -> HashResult
+where
+    B: CanonicalSerialize,
+{
+    let mut checksum_buff = move_token_signature_buff(move_token);
+    checksum_buff.extend_from_slice(&move_token.new_token);
+    sha_512_256(&checksum_buff)
+}
+
 /// Verify that new_token is a valid signature over the rest of the fields.
 pub fn verify_move_token<B>(move_token: &MoveToken<B>, public_key: &PublicKey) -> bool
 where
@@ -195,3 +207,67 @@ where
 }
 
 // TODO: How to test this?
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::funder::messages::FriendsRoute;
+    use crypto::crypto_rand::{RandValue, RAND_VALUE_LEN};
+    use crypto::identity::{
+        generate_pkcs8_key_pair, Identity, Signature, SoftwareEd25519Identity, PUBLIC_KEY_LEN,
+    };
+    use crypto::invoice_id::{InvoiceId, INVOICE_ID_LEN};
+    use crypto::test_utils::DummyRandom;
+    use crypto::uid::{Uid, UID_LEN};
+
+    /// Create a receipt signed by `identity`, the destination of the payment.
+    fn create_signed_receipt(identity: &SoftwareEd25519Identity) -> Receipt {
+        let pending_request = PendingRequest {
+            request_id: Uid::from(&[1; UID_LEN]),
+            route: FriendsRoute {
+                public_keys: vec![
+                    PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+                    identity.get_public_key(),
+                ],
+            },
+            dest_payment: 10,
+            invoice_id: InvoiceId::from(&[2; INVOICE_ID_LEN]),
+        };
+
+        let mut response_send_funds = ResponseSendFunds {
+            request_id: pending_request.request_id.clone(),
+            rand_nonce: RandValue::from(&[3; RAND_VALUE_LEN]),
+            signature: Signature::zero(),
+        };
+
+        let signature_buff =
+            create_response_signature_buffer(&response_send_funds, &pending_request);
+        response_send_funds.signature = identity.sign(&signature_buff);
+
+        prepare_receipt(&response_send_funds, &pending_request)
+    }
+
+    #[test]
+    fn test_receipt_verify() {
+        let rng = DummyRandom::new(&[1u8]);
+        let pkcs8 = generate_pkcs8_key_pair(&rng);
+        let identity = SoftwareEd25519Identity::from_pkcs8(&pkcs8).unwrap();
+
+        let receipt = create_signed_receipt(&identity);
+        assert!(receipt.verify(&identity.get_public_key()));
+
+        // Signed by someone else:
+        assert!(!receipt.verify(&PublicKey::from(&[0xaa; PUBLIC_KEY_LEN])));
+    }
+
+    #[test]
+    fn test_receipt_verify_tampered_dest_payment() {
+        let rng = DummyRandom::new(&[1u8]);
+        let pkcs8 = generate_pkcs8_key_pair(&rng);
+        let identity = SoftwareEd25519Identity::from_pkcs8(&pkcs8).unwrap();
+
+        let mut receipt = create_signed_receipt(&identity);
+        receipt.dest_payment += 1;
+        assert!(!receipt.verify(&identity.get_public_key()));
+    }
+}
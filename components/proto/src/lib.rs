@@ -46,6 +46,8 @@ pub mod relay;
 pub mod report;
 pub mod secure_channel;
 pub mod serialize;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_utils;
 
 include_schema!(report_capnp, "report_capnp");
 include_schema!(app_server_capnp, "app_server_capnp");
@@ -13,6 +13,36 @@ impl NetAddress {
     }
 }
 
+/// Syntactic validation of an address.
+/// This does not check that the address is reachable.
+pub trait ValidateAddress {
+    fn is_valid_address(&self) -> bool;
+}
+
+impl ValidateAddress for NetAddress {
+    /// A valid address is of the form host:port
+    fn is_valid_address(&self) -> bool {
+        let mut split = self.0.rsplitn(2, ':');
+        let port_valid = match split.next() {
+            Some(port) => port.parse::<u16>().is_ok(),
+            None => false,
+        };
+        let host_valid = match split.next() {
+            Some(host) => !host.is_empty(),
+            None => false,
+        };
+        port_valid && host_valid
+    }
+}
+
+/// u32 is used as a dummy address in tests. Every u32 is considered valid.
+#[cfg(any(test, feature = "test-util"))]
+impl ValidateAddress for u32 {
+    fn is_valid_address(&self) -> bool {
+        true
+    }
+}
+
 impl CanonicalSerialize for NetAddress {
     fn canonical_serialize(&self) -> Vec<u8> {
         self.0.canonical_serialize()
@@ -29,7 +29,7 @@ fn calc_friend_capacities<B>(friend_report: &FriendReport<B>) -> (u128, u128)
 where
     B: Clone,
 {
-    if friend_report.status == FriendStatusReport::Disabled
+    if friend_report.status != FriendStatusReport::Enabled
         || friend_report.liveness == FriendLivenessReport::Offline
     {
         return (0, 0);
@@ -0,0 +1,219 @@
+use std::collections::BTreeSet;
+
+use crypto::identity::PublicKey;
+
+use crate::report::messages::{
+    ChannelStatusReport, FriendReport, FriendStatusReport, FunderReport,
+};
+
+/// A single difference between two snapshots of a FunderReport.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReportChange {
+    FriendAdded(PublicKey),
+    FriendRemoved(PublicKey),
+    /// The balance is None if the channel is inconsistent.
+    BalanceChanged {
+        friend_public_key: PublicKey,
+        old_balance: Option<i128>,
+        new_balance: Option<i128>,
+    },
+    StatusChanged {
+        friend_public_key: PublicKey,
+        old_status: FriendStatusReport,
+        new_status: FriendStatusReport,
+    },
+}
+
+fn friend_balance<B>(friend_report: &FriendReport<B>) -> Option<i128>
+where
+    B: Clone,
+{
+    match &friend_report.channel_status {
+        ChannelStatusReport::Inconsistent(_) => None,
+        ChannelStatusReport::Consistent(tc_report) => Some(tc_report.balance.balance),
+    }
+}
+
+/// Describe the changes between two snapshots of a FunderReport.
+/// Changes are ordered by the public key of the relevant friend.
+pub fn report_diff<B>(old: &FunderReport<B>, new: &FunderReport<B>) -> Vec<ReportChange>
+where
+    B: Clone,
+{
+    let friend_public_keys = old
+        .friends
+        .keys()
+        .chain(new.friends.keys())
+        .cloned()
+        .collect::<BTreeSet<PublicKey>>();
+
+    let mut changes = Vec::new();
+    for friend_public_key in friend_public_keys {
+        let (old_friend, new_friend) = match (
+            old.friends.get(&friend_public_key),
+            new.friends.get(&friend_public_key),
+        ) {
+            (Some(old_friend), Some(new_friend)) => (old_friend, new_friend),
+            (None, Some(_)) => {
+                changes.push(ReportChange::FriendAdded(friend_public_key));
+                continue;
+            }
+            (Some(_), None) => {
+                changes.push(ReportChange::FriendRemoved(friend_public_key));
+                continue;
+            }
+            (None, None) => unreachable!(),
+        };
+
+        if old_friend.status != new_friend.status {
+            changes.push(ReportChange::StatusChanged {
+                friend_public_key: friend_public_key.clone(),
+                old_status: old_friend.status.clone(),
+                new_status: new_friend.status.clone(),
+            });
+        }
+
+        let old_balance = friend_balance(old_friend);
+        let new_balance = friend_balance(new_friend);
+        if old_balance != new_balance {
+            changes.push(ReportChange::BalanceChanged {
+                friend_public_key,
+                old_balance,
+                new_balance,
+            });
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use im::hashmap::HashMap as ImHashMap;
+    use im::vector::Vector as ImVec;
+
+    use common::mutable_state::MutableState;
+    use crypto::identity::PUBLIC_KEY_LEN;
+
+    use crate::report::messages::{
+        AddFriendReport, ChannelInconsistentReport, DirectionReport, FriendReportMutation,
+        FunderReportMutation, McBalanceReport, McRequestsStatusReport, RequestsStatusReport,
+        TcReport,
+    };
+
+    fn create_channel_status(balance: i128) -> ChannelStatusReport {
+        ChannelStatusReport::Consistent(TcReport {
+            direction: DirectionReport::Incoming,
+            balance: McBalanceReport {
+                balance,
+                local_max_debt: 100,
+                remote_max_debt: 100,
+                local_pending_debt: 0,
+                remote_pending_debt: 0,
+            },
+            requests_status: McRequestsStatusReport {
+                local: RequestsStatusReport::Open,
+                remote: RequestsStatusReport::Open,
+            },
+            num_local_pending_requests: 0,
+            num_remote_pending_requests: 0,
+        })
+    }
+
+    fn create_add_friend(index: u8, balance: i128) -> FunderReportMutation<u32> {
+        FunderReportMutation::AddFriend(AddFriendReport {
+            friend_public_key: PublicKey::from(&[index; PUBLIC_KEY_LEN]),
+            name: format!("friend-{}", index),
+            relays: Vec::new(),
+            balance,
+            opt_last_incoming_move_token: None,
+            channel_status: create_channel_status(balance),
+        })
+    }
+
+    fn friend_mutation(
+        index: u8,
+        friend_mutation: FriendReportMutation<u32>,
+    ) -> FunderReportMutation<u32> {
+        FunderReportMutation::FriendReportMutation((
+            PublicKey::from(&[index; PUBLIC_KEY_LEN]),
+            friend_mutation,
+        ))
+    }
+
+    #[test]
+    fn test_report_diff() {
+        let mut old_report = FunderReport::<u32> {
+            local_public_key: PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+            relays: ImVec::new(),
+            friends: ImHashMap::new(),
+            num_ready_receipts: 0,
+            total_credit_extended: 0,
+            total_credit_received: 0,
+        };
+        for index in 0..4 {
+            old_report.mutate(&create_add_friend(index, 0)).unwrap();
+        }
+        assert_eq!(report_diff(&old_report, &old_report), Vec::new());
+
+        let mut new_report = old_report.clone();
+        let mutations = vec![
+            FunderReportMutation::RemoveFriend(PublicKey::from(&[0; PUBLIC_KEY_LEN])),
+            friend_mutation(
+                1,
+                FriendReportMutation::SetStatus(FriendStatusReport::Enabled),
+            ),
+            friend_mutation(
+                1,
+                FriendReportMutation::SetChannelStatus(create_channel_status(-5)),
+            ),
+            friend_mutation(
+                2,
+                FriendReportMutation::SetChannelStatus(ChannelStatusReport::Inconsistent(
+                    ChannelInconsistentReport {
+                        local_reset_terms_balance: 0,
+                        opt_remote_reset_terms: None,
+                    },
+                )),
+            ),
+            // Changes that are not tracked by the diff:
+            friend_mutation(3, FriendReportMutation::SetName("friend".to_owned())),
+            create_add_friend(4, 10),
+        ];
+        for mutation in &mutations {
+            new_report.mutate(mutation).unwrap();
+        }
+
+        let old_status = old_report
+            .friends
+            .get(&PublicKey::from(&[1; PUBLIC_KEY_LEN]))
+            .unwrap()
+            .status
+            .clone();
+        assert_ne!(old_status, FriendStatusReport::Enabled);
+
+        assert_eq!(
+            report_diff(&old_report, &new_report),
+            vec![
+                ReportChange::FriendRemoved(PublicKey::from(&[0; PUBLIC_KEY_LEN])),
+                ReportChange::StatusChanged {
+                    friend_public_key: PublicKey::from(&[1; PUBLIC_KEY_LEN]),
+                    old_status,
+                    new_status: FriendStatusReport::Enabled,
+                },
+                ReportChange::BalanceChanged {
+                    friend_public_key: PublicKey::from(&[1; PUBLIC_KEY_LEN]),
+                    old_balance: Some(0),
+                    new_balance: Some(-5),
+                },
+                ReportChange::BalanceChanged {
+                    friend_public_key: PublicKey::from(&[2; PUBLIC_KEY_LEN]),
+                    old_balance: Some(0),
+                    new_balance: None,
+                },
+                ReportChange::FriendAdded(PublicKey::from(&[4; PUBLIC_KEY_LEN])),
+            ]
+        );
+    }
+}
@@ -40,6 +40,7 @@ where
 pub enum FriendStatusReport {
     Enabled,
     Disabled,
+    Paused,
 }
 
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
@@ -120,6 +121,17 @@ pub struct TcReport {
     pub num_remote_pending_requests: u64,
 }
 
+/// Round trip times (Measured in timer ticks) between sending a request to a friend and
+/// receiving the matching response.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RttReport {
+    pub last_rtt: u64,
+    /// A moving average over the measured round trip times.
+    pub avg_rtt: u64,
+    /// Amount of measurements. If 0, `last_rtt` and `avg_rtt` are meaningless.
+    pub num_samples: u64,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ResetTermsReport {
     pub reset_token: Signature,
@@ -162,6 +174,12 @@ where
     pub num_pending_user_requests: u64,
     // Request that the user has sent to this neighbor,
     // but have not been processed yet. Bounded in size.
+    pub last_move_token_tick: u64,
+    // Timer tick (counted since the Funder started) of the last move token
+    // sent to or received from this friend. 0 if no move token was exchanged yet.
+    pub rtt: RttReport,
+    // Did the remote side sign a move token over the terms of the last channel reset?
+    pub reset_confirmed: bool,
 }
 
 /// A FunderReport is a summary of a FunderState.
@@ -176,6 +194,12 @@ where
     pub relays: ImVec<NamedRelayAddress<B>>,
     pub friends: ImHashMap<PublicKey, FriendReport<B>>,
     pub num_ready_receipts: u64,
+    /// Sum of all positive balances (Credit we have extended to our friends).
+    /// Derived from `friends`.
+    pub total_credit_extended: u128,
+    /// Sum of all negative balances (Credit our friends have extended to us).
+    /// Derived from `friends`.
+    pub total_credit_received: u128,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -196,6 +220,9 @@ where
     SetNumPendingUserRequests(u64),
     SetOptLastIncomingMoveToken(Option<MoveTokenHashedReport>),
     SetLiveness(FriendLivenessReport),
+    SetLastMoveTokenTick(u64),
+    SetRtt(RttReport),
+    SetResetConfirmed(bool),
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -233,6 +260,7 @@ impl From<&FriendStatus> for FriendStatusReport {
         match friend_status {
             FriendStatus::Enabled => FriendStatusReport::Enabled,
             FriendStatus::Disabled => FriendStatusReport::Disabled,
+            FriendStatus::Paused => FriendStatusReport::Paused,
         }
     }
 }
@@ -246,6 +274,68 @@ impl From<&RequestsStatus> for RequestsStatusReport {
     }
 }
 
+/// Calculate (total_credit_extended, total_credit_received) over all friends.
+/// Only friends with a consistent channel are taken into account.
+pub fn calc_credit_totals<B>(friends: &ImHashMap<PublicKey, FriendReport<B>>) -> (u128, u128)
+where
+    B: Clone,
+{
+    let mut total_credit_extended = 0u128;
+    let mut total_credit_received = 0u128;
+
+    for friend_report in friends.values() {
+        let tc_report = match &friend_report.channel_status {
+            ChannelStatusReport::Inconsistent(_) => continue,
+            ChannelStatusReport::Consistent(tc_report) => tc_report,
+        };
+        let balance = tc_report.balance.balance;
+        if balance >= 0 {
+            total_credit_extended = total_credit_extended.saturating_add(balance as u128);
+        } else {
+            // Absolute value of a negative balance (Works for i128::MIN too):
+            total_credit_received =
+                total_credit_received.saturating_add((balance as u128).wrapping_neg());
+        }
+    }
+
+    (total_credit_extended, total_credit_received)
+}
+
+impl<B> FunderReport<B>
+where
+    B: Clone,
+{
+    /// Recalculate the derived credit totals from the friends' balances.
+    fn update_credit_totals(&mut self) {
+        let (total_credit_extended, total_credit_received) = calc_credit_totals(&self.friends);
+        self.total_credit_extended = total_credit_extended;
+        self.total_credit_received = total_credit_received;
+    }
+}
+
+impl<B> FriendReport<B>
+where
+    B: Clone,
+{
+    /// The local requests status that is currently in effect.
+    /// `wanted_local_requests_status` only takes effect after it was sent to the remote side
+    /// inside a move token. Returns None if the channel is inconsistent.
+    pub fn effective_local_requests_status(&self) -> Option<&RequestsStatusReport> {
+        match &self.channel_status {
+            ChannelStatusReport::Inconsistent(_) => None,
+            ChannelStatusReport::Consistent(tc_report) => Some(&tc_report.requests_status.local),
+        }
+    }
+
+    /// Is there a change of the local requests status that did not take effect yet?
+    pub fn is_local_requests_status_pending(&self) -> bool {
+        match self.effective_local_requests_status() {
+            None => false,
+            Some(effective) => *effective != self.wanted_local_requests_status,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum FunderReportMutateError {
     FriendDoesNotExist,
@@ -297,6 +387,15 @@ where
             FriendReportMutation::SetLiveness(friend_liveness_report) => {
                 self.liveness = friend_liveness_report.clone();
             }
+            FriendReportMutation::SetLastMoveTokenTick(last_move_token_tick) => {
+                self.last_move_token_tick = *last_move_token_tick;
+            }
+            FriendReportMutation::SetRtt(rtt_report) => {
+                self.rtt = rtt_report.clone();
+            }
+            FriendReportMutation::SetResetConfirmed(reset_confirmed) => {
+                self.reset_confirmed = *reset_confirmed;
+            }
         };
         Ok(())
     }
@@ -344,6 +443,9 @@ where
                     num_pending_requests: 0,
                     status: FriendStatusReport::from(&FriendStatus::Disabled),
                     num_pending_user_requests: 0,
+                    last_move_token_tick: 0,
+                    rtt: RttReport::default(),
+                    reset_confirmed: false,
                 };
                 if self
                     .friends
@@ -352,6 +454,7 @@ where
                 {
                     Err(FunderReportMutateError::FriendAlreadyExists)
                 } else {
+                    self.update_credit_totals();
                     Ok(())
                 }
             }
@@ -359,6 +462,7 @@ where
                 if self.friends.remove(&friend_public_key).is_none() {
                     Err(FunderReportMutateError::FriendDoesNotExist)
                 } else {
+                    self.update_credit_totals();
                     Ok(())
                 }
             }
@@ -371,6 +475,9 @@ where
                     .get_mut(friend_public_key)
                     .ok_or(FunderReportMutateError::FriendDoesNotExist)?;
                 friend.mutate(friend_report_mutation)?;
+                if let FriendReportMutation::SetChannelStatus(_) = friend_report_mutation {
+                    self.update_credit_totals();
+                }
                 Ok(())
             }
             FunderReportMutation::SetNumReadyReceipts(num_ready_receipts) => {
@@ -380,3 +487,100 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::identity::PUBLIC_KEY_LEN;
+
+    fn create_channel_status(balance: i128) -> ChannelStatusReport {
+        ChannelStatusReport::Consistent(TcReport {
+            direction: DirectionReport::Incoming,
+            balance: McBalanceReport {
+                balance,
+                local_max_debt: 100,
+                remote_max_debt: 100,
+                local_pending_debt: 0,
+                remote_pending_debt: 0,
+            },
+            requests_status: McRequestsStatusReport {
+                local: RequestsStatusReport::Open,
+                remote: RequestsStatusReport::Open,
+            },
+            num_local_pending_requests: 0,
+            num_remote_pending_requests: 0,
+        })
+    }
+
+    fn create_add_friend(
+        index: u8,
+        channel_status: ChannelStatusReport,
+    ) -> FunderReportMutation<u32> {
+        FunderReportMutation::AddFriend(AddFriendReport {
+            friend_public_key: PublicKey::from(&[index; PUBLIC_KEY_LEN]),
+            name: format!("friend-{}", index),
+            relays: Vec::new(),
+            balance: 0,
+            opt_last_incoming_move_token: None,
+            channel_status,
+        })
+    }
+
+    #[test]
+    fn test_funder_report_credit_totals() {
+        let mut funder_report = FunderReport::<u32> {
+            local_public_key: PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]),
+            relays: ImVec::new(),
+            friends: ImHashMap::new(),
+            num_ready_receipts: 0,
+            total_credit_extended: 0,
+            total_credit_received: 0,
+        };
+
+        let inconsistent = ChannelStatusReport::Inconsistent(ChannelInconsistentReport {
+            local_reset_terms_balance: 50,
+            opt_remote_reset_terms: None,
+        });
+
+        let mutations = vec![
+            create_add_friend(0, create_channel_status(10)),
+            create_add_friend(1, create_channel_status(-3)),
+            create_add_friend(2, create_channel_status(7)),
+            create_add_friend(3, create_channel_status(-20)),
+            // Inconsistent channels are not counted:
+            create_add_friend(4, inconsistent),
+        ];
+        for mutation in &mutations {
+            funder_report.mutate(mutation).unwrap();
+        }
+
+        assert_eq!(funder_report.total_credit_extended, 10 + 7);
+        assert_eq!(funder_report.total_credit_received, 3 + 20);
+
+        // Change the balance of one friend:
+        let friend_mutation = FriendReportMutation::SetChannelStatus(create_channel_status(-5));
+        let mutation = FunderReportMutation::FriendReportMutation((
+            PublicKey::from(&[0; PUBLIC_KEY_LEN]),
+            friend_mutation,
+        ));
+        funder_report.mutate(&mutation).unwrap();
+
+        assert_eq!(funder_report.total_credit_extended, 7);
+        assert_eq!(funder_report.total_credit_received, 5 + 3 + 20);
+
+        // Remove a friend:
+        let mutation = FunderReportMutation::RemoveFriend(PublicKey::from(&[3; PUBLIC_KEY_LEN]));
+        funder_report.mutate(&mutation).unwrap();
+
+        assert_eq!(funder_report.total_credit_extended, 7);
+        assert_eq!(funder_report.total_credit_received, 5 + 3);
+
+        assert_eq!(
+            calc_credit_totals(&funder_report.friends),
+            (
+                funder_report.total_credit_extended,
+                funder_report.total_credit_received
+            )
+        );
+    }
+}
@@ -1,4 +1,5 @@
 pub mod convert;
+pub mod diff;
 pub mod messages;
 pub mod serialize;
 pub mod signature_buff;
@@ -1,3 +1,4 @@
+use capnp;
 use im::hashmap::HashMap as ImHashMap;
 use im::vector::Vector as ImVec;
 
@@ -12,10 +13,11 @@ use common::int_convert::usize_to_u32;
 use crypto::identity::PublicKey;
 
 use crate::report::messages::{
-    AddFriendReport, ChannelInconsistentReport, ChannelStatusReport, DirectionReport,
-    FriendLivenessReport, FriendReport, FriendReportMutation, FriendStatusReport, FunderReport,
-    FunderReportMutation, McBalanceReport, McRequestsStatusReport, MoveTokenHashedReport,
-    RequestsStatusReport, ResetTermsReport, SentLocalRelaysReport, TcReport,
+    calc_credit_totals, AddFriendReport, ChannelInconsistentReport, ChannelStatusReport,
+    DirectionReport, FriendLivenessReport, FriendReport, FriendReportMutation, FriendStatusReport,
+    FunderReport, FunderReportMutation, McBalanceReport, McRequestsStatusReport,
+    MoveTokenHashedReport, RequestsStatusReport, ResetTermsReport, RttReport,
+    SentLocalRelaysReport, TcReport,
 };
 use crate::serialize::SerializeError;
 use report_capnp;
@@ -25,6 +27,19 @@ use crate::app_server::messages::{NodeReport, NodeReportMutation};
 use crate::index_client::messages::{IndexClientReport, IndexClientReportMutation};
 use crate::net::messages::NetAddress;
 
+/// Get a pointer field of a report reader.
+/// On failure, the returned error names the field, so that corrupt reports can be diagnosed.
+fn get_field<T>(
+    is_present: bool,
+    res: capnp::Result<T>,
+    field_name: &'static str,
+) -> Result<T, SerializeError> {
+    if !is_present {
+        return Err(SerializeError::MissingField(field_name));
+    }
+    res.map_err(|e| SerializeError::FieldError((field_name, e)))
+}
+
 fn ser_move_token_hashed_report(
     move_token_hashed_report: &MoveTokenHashedReport,
     move_token_hashed_report_builder: &mut report_capnp::move_token_hashed_report::Builder,
@@ -97,26 +112,52 @@ fn deser_move_token_hashed_report(
     move_token_hashed_report_reader: &report_capnp::move_token_hashed_report::Reader,
 ) -> Result<MoveTokenHashedReport, SerializeError> {
     Ok(MoveTokenHashedReport {
-        prefix_hash: read_hash(&move_token_hashed_report_reader.get_prefix_hash()?)?,
-        local_public_key: read_public_key(
-            &move_token_hashed_report_reader.get_local_public_key()?,
-        )?,
-        remote_public_key: read_public_key(
-            &move_token_hashed_report_reader.get_remote_public_key()?,
-        )?,
+        prefix_hash: read_hash(&get_field(
+            move_token_hashed_report_reader.has_prefix_hash(),
+            move_token_hashed_report_reader.get_prefix_hash(),
+            "prefix_hash",
+        )?)?,
+        local_public_key: read_public_key(&get_field(
+            move_token_hashed_report_reader.has_local_public_key(),
+            move_token_hashed_report_reader.get_local_public_key(),
+            "local_public_key",
+        )?)?,
+        remote_public_key: read_public_key(&get_field(
+            move_token_hashed_report_reader.has_remote_public_key(),
+            move_token_hashed_report_reader.get_remote_public_key(),
+            "remote_public_key",
+        )?)?,
         inconsistency_counter: move_token_hashed_report_reader.get_inconsistency_counter(),
-        move_token_counter: read_custom_u_int128(
-            &move_token_hashed_report_reader.get_move_token_counter()?,
-        )?,
-        balance: read_custom_int128(&move_token_hashed_report_reader.get_balance()?)?,
-        local_pending_debt: read_custom_u_int128(
-            &move_token_hashed_report_reader.get_local_pending_debt()?,
-        )?,
-        remote_pending_debt: read_custom_u_int128(
-            &move_token_hashed_report_reader.get_remote_pending_debt()?,
-        )?,
-        rand_nonce: read_rand_nonce(&move_token_hashed_report_reader.get_rand_nonce()?)?,
-        new_token: read_signature(&move_token_hashed_report_reader.get_new_token()?)?,
+        move_token_counter: read_custom_u_int128(&get_field(
+            move_token_hashed_report_reader.has_move_token_counter(),
+            move_token_hashed_report_reader.get_move_token_counter(),
+            "move_token_counter",
+        )?)?,
+        balance: read_custom_int128(&get_field(
+            move_token_hashed_report_reader.has_balance(),
+            move_token_hashed_report_reader.get_balance(),
+            "balance",
+        )?)?,
+        local_pending_debt: read_custom_u_int128(&get_field(
+            move_token_hashed_report_reader.has_local_pending_debt(),
+            move_token_hashed_report_reader.get_local_pending_debt(),
+            "local_pending_debt",
+        )?)?,
+        remote_pending_debt: read_custom_u_int128(&get_field(
+            move_token_hashed_report_reader.has_remote_pending_debt(),
+            move_token_hashed_report_reader.get_remote_pending_debt(),
+            "remote_pending_debt",
+        )?)?,
+        rand_nonce: read_rand_nonce(&get_field(
+            move_token_hashed_report_reader.has_rand_nonce(),
+            move_token_hashed_report_reader.get_rand_nonce(),
+            "rand_nonce",
+        )?)?,
+        new_token: read_signature(&get_field(
+            move_token_hashed_report_reader.has_new_token(),
+            move_token_hashed_report_reader.get_new_token(),
+            "new_token",
+        )?)?,
     })
 }
 
@@ -127,6 +168,7 @@ fn ser_friend_status_report(
     match friend_status_report {
         FriendStatusReport::Enabled => friend_status_report_builder.set_enabled(()),
         FriendStatusReport::Disabled => friend_status_report_builder.set_disabled(()),
+        FriendStatusReport::Paused => friend_status_report_builder.set_paused(()),
     }
 }
 
@@ -136,6 +178,7 @@ fn deser_friend_status_report(
     Ok(match friend_status_report_reader.which()? {
         report_capnp::friend_status_report::Disabled(()) => FriendStatusReport::Disabled,
         report_capnp::friend_status_report::Enabled(()) => FriendStatusReport::Enabled,
+        report_capnp::friend_status_report::Paused(()) => FriendStatusReport::Paused,
     })
 }
 
@@ -258,15 +301,31 @@ fn deser_mc_balance_report(
     mc_balance_report_reader: &report_capnp::mc_balance_report::Reader,
 ) -> Result<McBalanceReport, SerializeError> {
     Ok(McBalanceReport {
-        balance: read_custom_int128(&mc_balance_report_reader.get_balance()?)?,
-        local_max_debt: read_custom_u_int128(&mc_balance_report_reader.get_local_max_debt()?)?,
-        remote_max_debt: read_custom_u_int128(&mc_balance_report_reader.get_remote_max_debt()?)?,
-        local_pending_debt: read_custom_u_int128(
-            &mc_balance_report_reader.get_local_pending_debt()?,
-        )?,
-        remote_pending_debt: read_custom_u_int128(
-            &mc_balance_report_reader.get_remote_pending_debt()?,
-        )?,
+        balance: read_custom_int128(&get_field(
+            mc_balance_report_reader.has_balance(),
+            mc_balance_report_reader.get_balance(),
+            "balance",
+        )?)?,
+        local_max_debt: read_custom_u_int128(&get_field(
+            mc_balance_report_reader.has_local_max_debt(),
+            mc_balance_report_reader.get_local_max_debt(),
+            "local_max_debt",
+        )?)?,
+        remote_max_debt: read_custom_u_int128(&get_field(
+            mc_balance_report_reader.has_remote_max_debt(),
+            mc_balance_report_reader.get_remote_max_debt(),
+            "remote_max_debt",
+        )?)?,
+        local_pending_debt: read_custom_u_int128(&get_field(
+            mc_balance_report_reader.has_local_pending_debt(),
+            mc_balance_report_reader.get_local_pending_debt(),
+            "local_pending_debt",
+        )?)?,
+        remote_pending_debt: read_custom_u_int128(&get_field(
+            mc_balance_report_reader.has_remote_pending_debt(),
+            mc_balance_report_reader.get_remote_pending_debt(),
+            "remote_pending_debt",
+        )?)?,
     })
 }
 
@@ -298,14 +357,43 @@ fn deser_tc_report(
     tc_report_reader: &report_capnp::tc_report::Reader,
 ) -> Result<TcReport, SerializeError> {
     Ok(TcReport {
-        direction: deser_direction_report(&tc_report_reader.get_direction()?)?,
-        balance: deser_mc_balance_report(&tc_report_reader.get_balance()?)?,
-        requests_status: deser_mc_requests_status_report(&tc_report_reader.get_requests_status()?)?,
+        direction: deser_direction_report(&get_field(
+            tc_report_reader.has_direction(),
+            tc_report_reader.get_direction(),
+            "direction",
+        )?)?,
+        balance: deser_mc_balance_report(&get_field(
+            tc_report_reader.has_balance(),
+            tc_report_reader.get_balance(),
+            "balance",
+        )?)?,
+        requests_status: deser_mc_requests_status_report(&get_field(
+            tc_report_reader.has_requests_status(),
+            tc_report_reader.get_requests_status(),
+            "requests_status",
+        )?)?,
         num_local_pending_requests: tc_report_reader.get_num_local_pending_requests(),
         num_remote_pending_requests: tc_report_reader.get_num_remote_pending_requests(),
     })
 }
 
+fn ser_rtt_report(
+    rtt_report: &RttReport,
+    rtt_report_builder: &mut report_capnp::rtt_report::Builder,
+) {
+    rtt_report_builder.set_last_rtt(rtt_report.last_rtt);
+    rtt_report_builder.set_avg_rtt(rtt_report.avg_rtt);
+    rtt_report_builder.set_num_samples(rtt_report.num_samples);
+}
+
+fn deser_rtt_report(rtt_report_reader: &report_capnp::rtt_report::Reader) -> RttReport {
+    RttReport {
+        last_rtt: rtt_report_reader.get_last_rtt(),
+        avg_rtt: rtt_report_reader.get_avg_rtt(),
+        num_samples: rtt_report_reader.get_num_samples(),
+    }
+}
+
 fn ser_reset_terms_report(
     reset_terms_report: &ResetTermsReport,
     reset_terms_report_builder: &mut report_capnp::reset_terms_report::Builder,
@@ -327,8 +415,16 @@ fn deser_reset_terms_report(
     reset_terms_report_reader: &report_capnp::reset_terms_report::Reader,
 ) -> Result<ResetTermsReport, SerializeError> {
     Ok(ResetTermsReport {
-        reset_token: read_signature(&reset_terms_report_reader.get_reset_token()?)?,
-        balance_for_reset: read_custom_int128(&reset_terms_report_reader.get_balance_for_reset()?)?,
+        reset_token: read_signature(&get_field(
+            reset_terms_report_reader.has_reset_token(),
+            reset_terms_report_reader.get_reset_token(),
+            "reset_token",
+        )?)?,
+        balance_for_reset: read_custom_int128(&get_field(
+            reset_terms_report_reader.has_balance_for_reset(),
+            reset_terms_report_reader.get_balance_for_reset(),
+            "balance_for_reset",
+        )?)?,
     })
 }
 
@@ -373,9 +469,11 @@ fn deser_channel_inconsistent_report(
     };
 
     Ok(ChannelInconsistentReport {
-        local_reset_terms_balance: read_custom_int128(
-            &channel_inconsistent_report_reader.get_local_reset_terms_balance()?,
-        )?,
+        local_reset_terms_balance: read_custom_int128(&get_field(
+            channel_inconsistent_report_reader.has_local_reset_terms_balance(),
+            channel_inconsistent_report_reader.get_local_reset_terms_balance(),
+            "local_reset_terms_balance",
+        )?)?,
         opt_remote_reset_terms,
     })
 }
@@ -481,12 +579,20 @@ fn deser_relays_transition(
     relays_transition_reader: &report_capnp::relays_transition::Reader,
 ) -> Result<RelaysTransitions, SerializeError> {
     let mut last_sent = ImVec::new();
-    for named_relay_address in relays_transition_reader.get_last_sent()? {
+    for named_relay_address in get_field(
+        relays_transition_reader.has_last_sent(),
+        relays_transition_reader.get_last_sent(),
+        "last_sent",
+    )? {
         last_sent.push_back(read_named_relay_address(&named_relay_address)?);
     }
 
     let mut before_last_sent = ImVec::new();
-    for named_relay_address in relays_transition_reader.get_before_last_sent()? {
+    for named_relay_address in get_field(
+        relays_transition_reader.has_before_last_sent(),
+        relays_transition_reader.get_before_last_sent(),
+        "before_last_sent",
+    )? {
         before_last_sent.push_back(read_named_relay_address(&named_relay_address)?);
     }
 
@@ -603,37 +709,80 @@ fn ser_friend_report(
     );
 
     friend_report_builder.set_num_pending_user_requests(friend_report.num_pending_user_requests);
+    friend_report_builder.set_last_move_token_tick(friend_report.last_move_token_tick);
+
+    ser_rtt_report(
+        &friend_report.rtt,
+        &mut friend_report_builder.reborrow().init_rtt(),
+    );
+    friend_report_builder.set_reset_confirmed(friend_report.reset_confirmed);
 }
 
 fn deser_friend_report(
     friend_report_reader: &report_capnp::friend_report::Reader,
 ) -> Result<FriendReport, SerializeError> {
     let mut remote_relays = Vec::new();
-    for relay_address in friend_report_reader.get_remote_relays()? {
+    for relay_address in get_field(
+        friend_report_reader.has_remote_relays(),
+        friend_report_reader.get_remote_relays(),
+        "remote_relays",
+    )? {
         remote_relays.push(read_relay_address(&relay_address)?);
     }
 
     Ok(FriendReport {
-        name: friend_report_reader.get_name()?.to_owned(),
+        name: get_field(
+            friend_report_reader.has_name(),
+            friend_report_reader.get_name(),
+            "name",
+        )?
+        .to_owned(),
         remote_relays,
-        sent_local_relays: deser_sent_local_relays_report(
-            &friend_report_reader.get_sent_local_relays()?,
-        )?,
-        opt_last_incoming_move_token: deser_opt_last_incoming_move_token(
-            &friend_report_reader.get_opt_last_incoming_move_token()?,
-        )?,
-        liveness: deser_friend_liveness_report(&friend_report_reader.get_liveness()?)?,
-        channel_status: deser_channel_status_report(&friend_report_reader.get_channel_status()?)?,
-        wanted_remote_max_debt: read_custom_u_int128(
-            &friend_report_reader.get_wanted_remote_max_debt()?,
-        )?,
-        wanted_local_requests_status: deser_requests_status_report(
-            &friend_report_reader.get_wanted_local_requests_status()?,
-        )?,
+        sent_local_relays: deser_sent_local_relays_report(&get_field(
+            friend_report_reader.has_sent_local_relays(),
+            friend_report_reader.get_sent_local_relays(),
+            "sent_local_relays",
+        )?)?,
+        opt_last_incoming_move_token: deser_opt_last_incoming_move_token(&get_field(
+            friend_report_reader.has_opt_last_incoming_move_token(),
+            friend_report_reader.get_opt_last_incoming_move_token(),
+            "opt_last_incoming_move_token",
+        )?)?,
+        liveness: deser_friend_liveness_report(&get_field(
+            friend_report_reader.has_liveness(),
+            friend_report_reader.get_liveness(),
+            "liveness",
+        )?)?,
+        channel_status: deser_channel_status_report(&get_field(
+            friend_report_reader.has_channel_status(),
+            friend_report_reader.get_channel_status(),
+            "channel_status",
+        )?)?,
+        wanted_remote_max_debt: read_custom_u_int128(&get_field(
+            friend_report_reader.has_wanted_remote_max_debt(),
+            friend_report_reader.get_wanted_remote_max_debt(),
+            "wanted_remote_max_debt",
+        )?)?,
+        wanted_local_requests_status: deser_requests_status_report(&get_field(
+            friend_report_reader.has_wanted_local_requests_status(),
+            friend_report_reader.get_wanted_local_requests_status(),
+            "wanted_local_requests_status",
+        )?)?,
         num_pending_requests: friend_report_reader.get_num_pending_requests(),
         num_pending_responses: friend_report_reader.get_num_pending_responses(),
-        status: deser_friend_status_report(&friend_report_reader.get_status()?)?,
+        status: deser_friend_status_report(&get_field(
+            friend_report_reader.has_status(),
+            friend_report_reader.get_status(),
+            "status",
+        )?)?,
         num_pending_user_requests: friend_report_reader.get_num_pending_user_requests(),
+        last_move_token_tick: friend_report_reader.get_last_move_token_tick(),
+        rtt: deser_rtt_report(&get_field(
+            friend_report_reader.has_rtt(),
+            friend_report_reader.get_rtt(),
+            "rtt",
+        )?),
+        reset_confirmed: friend_report_reader.get_reset_confirmed(),
     })
 }
 
@@ -655,8 +804,16 @@ fn ser_pk_friend_report(
 fn deser_pk_friend_report(
     pk_friend_report_reader: &report_capnp::pk_friend_report::Reader,
 ) -> Result<(PublicKey, FriendReport), SerializeError> {
-    let friend_public_key = read_public_key(&pk_friend_report_reader.get_friend_public_key()?)?;
-    let friend_report = deser_friend_report(&pk_friend_report_reader.get_friend_report()?)?;
+    let friend_public_key = read_public_key(&get_field(
+        pk_friend_report_reader.has_friend_public_key(),
+        pk_friend_report_reader.get_friend_public_key(),
+        "friend_public_key",
+    )?)?;
+    let friend_report = deser_friend_report(&get_field(
+        pk_friend_report_reader.has_friend_report(),
+        pk_friend_report_reader.get_friend_report(),
+        "friend_report",
+    )?)?;
 
     Ok((friend_public_key, friend_report))
 }
@@ -692,21 +849,38 @@ fn deser_funder_report(
     funder_report_reader: &report_capnp::funder_report::Reader,
 ) -> Result<FunderReport, SerializeError> {
     let mut named_relays = Vec::new();
-    for named_relay_address in funder_report_reader.get_relays()? {
+    for named_relay_address in get_field(
+        funder_report_reader.has_relays(),
+        funder_report_reader.get_relays(),
+        "relays",
+    )? {
         named_relays.push(read_named_relay_address(&named_relay_address)?);
     }
 
     let mut friends = ImHashMap::new();
-    for pk_friend in funder_report_reader.get_friends()? {
+    for pk_friend in get_field(
+        funder_report_reader.has_friends(),
+        funder_report_reader.get_friends(),
+        "friends",
+    )? {
         let (friend_public_key, friend_report) = deser_pk_friend_report(&pk_friend)?;
         friends.insert(friend_public_key, friend_report);
     }
 
+    // Credit totals are derived from the friends, and are not serialized:
+    let (total_credit_extended, total_credit_received) = calc_credit_totals(&friends);
+
     Ok(FunderReport {
-        local_public_key: read_public_key(&funder_report_reader.get_local_public_key()?)?,
+        local_public_key: read_public_key(&get_field(
+            funder_report_reader.has_local_public_key(),
+            funder_report_reader.get_local_public_key(),
+            "local_public_key",
+        )?)?,
         relays: named_relays.into_iter().collect(),
         friends,
         num_ready_receipts: funder_report_reader.get_num_ready_receipts(),
+        total_credit_extended,
+        total_credit_received,
     })
 }
 
@@ -752,21 +926,42 @@ fn deser_add_friend_report(
     add_friend_report_reader: &report_capnp::add_friend_report::Reader,
 ) -> Result<AddFriendReport, SerializeError> {
     let mut relays = Vec::new();
-    for relay_address in add_friend_report_reader.get_relays()? {
+    for relay_address in get_field(
+        add_friend_report_reader.has_relays(),
+        add_friend_report_reader.get_relays(),
+        "relays",
+    )? {
         relays.push(read_relay_address(&relay_address)?);
     }
 
     Ok(AddFriendReport {
-        friend_public_key: read_public_key(&add_friend_report_reader.get_friend_public_key()?)?,
-        name: add_friend_report_reader.get_name()?.to_owned(),
+        friend_public_key: read_public_key(&get_field(
+            add_friend_report_reader.has_friend_public_key(),
+            add_friend_report_reader.get_friend_public_key(),
+            "friend_public_key",
+        )?)?,
+        name: get_field(
+            add_friend_report_reader.has_name(),
+            add_friend_report_reader.get_name(),
+            "name",
+        )?
+        .to_owned(),
         relays,
-        balance: read_custom_int128(&add_friend_report_reader.get_balance()?)?,
-        opt_last_incoming_move_token: deser_opt_last_incoming_move_token(
-            &add_friend_report_reader.get_opt_last_incoming_move_token()?,
-        )?,
-        channel_status: deser_channel_status_report(
-            &add_friend_report_reader.get_channel_status()?,
-        )?,
+        balance: read_custom_int128(&get_field(
+            add_friend_report_reader.has_balance(),
+            add_friend_report_reader.get_balance(),
+            "balance",
+        )?)?,
+        opt_last_incoming_move_token: deser_opt_last_incoming_move_token(&get_field(
+            add_friend_report_reader.has_opt_last_incoming_move_token(),
+            add_friend_report_reader.get_opt_last_incoming_move_token(),
+            "opt_last_incoming_move_token",
+        )?)?,
+        channel_status: deser_channel_status_report(&get_field(
+            add_friend_report_reader.has_channel_status(),
+            add_friend_report_reader.get_channel_status(),
+            "channel_status",
+        )?)?,
     })
 }
 
@@ -852,6 +1047,18 @@ fn ser_friend_report_mutation(
                 .reborrow()
                 .init_set_liveness(),
         ),
+        FriendReportMutation::SetLastMoveTokenTick(last_move_token_tick) => {
+            friend_report_mutation_builder
+                .reborrow()
+                .set_set_last_move_token_tick(*last_move_token_tick)
+        }
+        FriendReportMutation::SetRtt(rtt_report) => ser_rtt_report(
+            rtt_report,
+            &mut friend_report_mutation_builder.reborrow().init_set_rtt(),
+        ),
+        FriendReportMutation::SetResetConfirmed(reset_confirmed) => friend_report_mutation_builder
+            .reborrow()
+            .set_set_reset_confirmed(*reset_confirmed),
     };
 }
 
@@ -913,6 +1120,15 @@ fn deser_friend_report_mutation(
                 &friend_liveness_report_reader?,
             )?)
         }
+        report_capnp::friend_report_mutation::SetLastMoveTokenTick(last_move_token_tick) => {
+            FriendReportMutation::SetLastMoveTokenTick(last_move_token_tick)
+        }
+        report_capnp::friend_report_mutation::SetRtt(rtt_report_reader) => {
+            FriendReportMutation::SetRtt(deser_rtt_report(&rtt_report_reader?))
+        }
+        report_capnp::friend_report_mutation::SetResetConfirmed(reset_confirmed) => {
+            FriendReportMutation::SetResetConfirmed(reset_confirmed)
+        }
     })
 }
 
@@ -938,11 +1154,16 @@ fn ser_pk_friend_report_mutation(
 fn deser_pk_friend_report_mutation(
     pk_friend_report_mutation_reader: &report_capnp::pk_friend_report_mutation::Reader,
 ) -> Result<(PublicKey, FriendReportMutation), SerializeError> {
-    let friend_public_key =
-        read_public_key(&pk_friend_report_mutation_reader.get_friend_public_key()?)?;
-    let friend_report_mutation = deser_friend_report_mutation(
-        &pk_friend_report_mutation_reader.get_friend_report_mutation()?,
-    )?;
+    let friend_public_key = read_public_key(&get_field(
+        pk_friend_report_mutation_reader.has_friend_public_key(),
+        pk_friend_report_mutation_reader.get_friend_public_key(),
+        "friend_public_key",
+    )?)?;
+    let friend_report_mutation = deser_friend_report_mutation(&get_field(
+        pk_friend_report_mutation_reader.has_friend_report_mutation(),
+        pk_friend_report_mutation_reader.get_friend_report_mutation(),
+        "friend_report_mutation",
+    )?)?;
 
     Ok((friend_public_key, friend_report_mutation))
 }
@@ -1058,7 +1279,11 @@ fn deser_index_client_report(
     index_client_report_reader: &report_capnp::index_client_report::Reader,
 ) -> Result<IndexClientReport<NetAddress>, SerializeError> {
     let mut index_servers = Vec::new();
-    for named_index_server_reader in index_client_report_reader.get_index_servers()? {
+    for named_index_server_reader in get_field(
+        index_client_report_reader.has_index_servers(),
+        index_client_report_reader.get_index_servers(),
+        "index_servers",
+    )? {
         index_servers.push(read_named_index_server_address(&named_index_server_reader)?);
     }
 
@@ -1157,10 +1382,16 @@ pub fn deser_node_report(
     node_report_reader: &report_capnp::node_report::Reader,
 ) -> Result<NodeReport, SerializeError> {
     Ok(NodeReport {
-        funder_report: deser_funder_report(&node_report_reader.get_funder_report()?)?,
-        index_client_report: deser_index_client_report(
-            &node_report_reader.get_index_client_report()?,
-        )?,
+        funder_report: deser_funder_report(&get_field(
+            node_report_reader.has_funder_report(),
+            node_report_reader.get_funder_report(),
+            "funder_report",
+        )?)?,
+        index_client_report: deser_index_client_report(&get_field(
+            node_report_reader.has_index_client_report(),
+            node_report_reader.get_index_client_report(),
+            "index_client_report",
+        )?)?,
     })
 }
 
@@ -1198,3 +1429,55 @@ pub fn deser_node_report_mutation(
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use capnp::serialize_packed;
+    use std::io;
+
+    #[test]
+    fn test_deser_report_missing_field() {
+        let mut builder = capnp::message::Builder::new_default();
+        let mut mc_balance_report_builder =
+            builder.init_root::<report_capnp::mc_balance_report::Builder>();
+
+        // All the fields are set, except for the balance:
+        write_custom_u_int128(
+            100,
+            &mut mc_balance_report_builder.reborrow().init_local_max_debt(),
+        );
+        write_custom_u_int128(
+            200,
+            &mut mc_balance_report_builder.reborrow().init_remote_max_debt(),
+        );
+        write_custom_u_int128(
+            0,
+            &mut mc_balance_report_builder
+                .reborrow()
+                .init_local_pending_debt(),
+        );
+        write_custom_u_int128(
+            0,
+            &mut mc_balance_report_builder
+                .reborrow()
+                .init_remote_pending_debt(),
+        );
+
+        let mut ser_buff = Vec::new();
+        serialize_packed::write_message(&mut ser_buff, &builder).unwrap();
+
+        let mut cursor = io::Cursor::new(&ser_buff);
+        let reader =
+            serialize_packed::read_message(&mut cursor, ::capnp::message::ReaderOptions::new())
+                .unwrap();
+        let mc_balance_report_reader = reader
+            .get_root::<report_capnp::mc_balance_report::Reader>()
+            .unwrap();
+
+        match deser_mc_balance_report(&mc_balance_report_reader) {
+            Err(SerializeError::MissingField(field_name)) => assert_eq!(field_name, "balance"),
+            _ => unreachable!(),
+        };
+    }
+}
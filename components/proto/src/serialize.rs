@@ -1,3 +1,4 @@
+use crate::funder::messages::EmptyRouteError;
 use crate::net::messages::NetAddressError;
 use capnp;
 use std::io;
@@ -8,4 +9,9 @@ pub enum SerializeError {
     NotInSchema(capnp::NotInSchema),
     IoError(io::Error),
     NetAddressError(NetAddressError),
+    EmptyRouteError(EmptyRouteError),
+    /// A required field is not present in the message.
+    MissingField(&'static str),
+    /// A field of the message could not be read.
+    FieldError((&'static str, capnp::Error)),
 }
@@ -0,0 +1,36 @@
+//! Helpers for creating deterministic protocol values in tests.
+//! Available to other crates through the `test-util` feature.
+
+use crypto::identity::{PublicKey, PUBLIC_KEY_LEN};
+
+use crate::app_server::messages::{NamedRelayAddress, RelayAddress};
+
+/// A helper function to quickly create a dummy NamedRelayAddress.
+///
+/// ```
+/// use offst_proto::test_utils::dummy_named_relay_address;
+///
+/// let named_relay_address = dummy_named_relay_address(3);
+/// assert_eq!(named_relay_address.address, 3);
+/// assert_eq!(named_relay_address.name, "relay-3");
+/// ```
+pub fn dummy_named_relay_address(index: u8) -> NamedRelayAddress<u32> {
+    NamedRelayAddress {
+        public_key: PublicKey::from(&[index; PUBLIC_KEY_LEN]),
+        address: u32::from(index),
+        name: format!("relay-{}", index),
+    }
+}
+
+/// A helper function to quickly create a dummy RelayAddress.
+///
+/// ```
+/// use offst_proto::test_utils::{dummy_named_relay_address, dummy_relay_address};
+///
+/// let relay_address = dummy_relay_address(3);
+/// assert_eq!(relay_address.address, 3);
+/// assert_eq!(relay_address.public_key, dummy_named_relay_address(3).public_key);
+/// ```
+pub fn dummy_relay_address(index: u8) -> RelayAddress<u32> {
+    dummy_named_relay_address(index).into()
+}
@@ -368,6 +368,7 @@ mod tests {
 
     use proto::relay::serialize::{deserialize_reject_connection, serialize_incoming_connection};
 
+    use common::access_control::AccessControlMode;
     use common::conn::FuncFutTransform;
     use common::dummy_connector::DummyConnector;
 
@@ -539,7 +540,7 @@ mod tests {
 
         // Open access for a certain public key:
         let public_key_a = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
-        await!(acl_sender.send(AccessControlOp::Add(public_key_a.clone()))).unwrap();
+        await!(acl_sender.send(AccessControlOp::Allow(public_key_a.clone()))).unwrap();
         await!(event_receiver.next()).unwrap();
 
         // First message to the relay should be InitConnection::Listen:
@@ -598,6 +599,97 @@ mod tests {
         thread_pool.run(task_client_listener_basic(thread_pool.clone()));
     }
 
+    async fn task_client_listener_deny_list(mut spawner: impl Spawn + Clone + Send + 'static) {
+        let (req_sender, mut req_receiver) = mpsc::channel(0);
+        let connector = DummyConnector::new(req_sender);
+        let (connections_sender, _connections_receiver) = mpsc::channel(0);
+        let conn_timeout_ticks = 8;
+        let (_tick_sender, tick_receiver) = mpsc::channel(0);
+        let timer_client = create_timer_incoming(tick_receiver, spawner.clone()).unwrap();
+
+        let (mut acl_sender, mut incoming_access_control) = mpsc::channel(0);
+        let (event_sender, mut event_receiver) = mpsc::channel(0);
+        let keepalive_transform = FuncFutTransform::new(|x| Box::pin(future::ready(x)));
+
+        let c_spawner = spawner.clone();
+        let fut_listener = async move {
+            let mut access_control = AccessControlPk::with_mode(AccessControlMode::DenyList);
+            await!(inner_client_listener(
+                connector,
+                &mut access_control,
+                &mut incoming_access_control,
+                connections_sender,
+                keepalive_transform,
+                conn_timeout_ticks,
+                timer_client,
+                c_spawner,
+                Some(event_sender)
+            ))
+        }
+            .map_err(|e| warn!("inner_client_listener error: {:?}", e))
+            .map(|_| ());
+
+        spawner.spawn(fut_listener).unwrap();
+
+        // listener will attempt to start a main connection to the relay:
+        let (mut relay_sender, local_receiver) = mpsc::channel(0);
+        let (local_sender, mut relay_receiver) = mpsc::channel(0);
+        let conn_pair = (local_sender, local_receiver);
+        let req = await!(req_receiver.next()).unwrap();
+        req.reply(Some(conn_pair));
+
+        // Ban a certain public key:
+        let public_key_b = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        await!(acl_sender.send(AccessControlOp::Deny(public_key_b.clone()))).unwrap();
+        await!(event_receiver.next()).unwrap();
+
+        let _vec_init_connection = await!(relay_receiver.next()).unwrap();
+
+        // Incoming connection from the banned public key:
+        let incoming_connection = IncomingConnection {
+            public_key: public_key_b.clone(),
+        };
+        let vec_incoming_connection = serialize_incoming_connection(&incoming_connection);
+        await!(relay_sender.send(vec_incoming_connection)).unwrap();
+        await!(event_receiver.next()).unwrap();
+
+        // Listener will reject the connection:
+        let vec_relay_listen_in = await!(relay_receiver.next()).unwrap();
+        let reject_connection = deserialize_reject_connection(&vec_relay_listen_in).unwrap();
+        assert_eq!(reject_connection.public_key, public_key_b);
+
+        // Incoming connection from an arbitrary public key, that was never mentioned before:
+        let public_key_c = PublicKey::from(&[0xcc; PUBLIC_KEY_LEN]);
+        let incoming_connection = IncomingConnection {
+            public_key: public_key_c.clone(),
+        };
+        let vec_incoming_connection = serialize_incoming_connection(&incoming_connection);
+        await!(relay_sender.send(vec_incoming_connection)).unwrap();
+        await!(event_receiver.next()).unwrap();
+
+        // Listener will accept the connection:
+        let (_remote_sender, local_receiver) = mpsc::channel(0);
+        let (local_sender, mut remote_receiver) = mpsc::channel(0);
+        let conn_pair = (local_sender, local_receiver);
+
+        let req = await!(req_receiver.next()).unwrap();
+        req.reply(Some(conn_pair));
+
+        let vec_init_connection = await!(remote_receiver.next()).unwrap();
+        let init_connection = deserialize_init_connection(&vec_init_connection).unwrap();
+        if let InitConnection::Accept(accepted_public_key) = init_connection {
+            assert_eq!(accepted_public_key, public_key_c);
+        } else {
+            unreachable!();
+        }
+    }
+
+    #[test]
+    fn test_client_listener_deny_list() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_client_listener_deny_list(thread_pool.clone()));
+    }
+
     // TODO: Add a test for ClientListener.
 
 }
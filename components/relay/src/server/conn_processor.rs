@@ -10,19 +10,41 @@ use timer::utils::future_timeout;
 use timer::TimerClient;
 
 use super::types::{
-    IncomingAccept, IncomingConn, IncomingConnInner, IncomingConnect, IncomingListen,
+    FrameLimits, IncomingAccept, IncomingConn, IncomingConnInner, IncomingConnect, IncomingListen,
 };
 use proto::relay::messages::{IncomingConnection, InitConnection, RejectConnection};
 use proto::relay::serialize::{
     deserialize_init_connection, deserialize_reject_connection, serialize_incoming_connection,
 };
 
+/// Close the stream once a message longer than `max_frame_length` is received.
+fn limit_frame_length<M>(
+    receiver: M,
+    max_frame_length: usize,
+) -> impl Stream<Item = Vec<u8>> + Unpin
+where
+    M: Stream<Item = Vec<u8>> + Unpin,
+{
+    receiver.take_while(move |data| {
+        if data.len() > max_frame_length {
+            warn!(
+                "limit_frame_length(): Frame of length {} exceeds {}. Closing connection",
+                data.len(),
+                max_frame_length
+            );
+            return future::ready(false);
+        }
+        future::ready(true)
+    })
+}
+
 async fn dispatch_conn<FT>(
     sender: mpsc::Sender<Vec<u8>>,
     receiver: mpsc::Receiver<Vec<u8>>,
     public_key: PublicKey,
     first_msg: Vec<u8>,
     mut keepalive_transform: FT,
+    frame_limits: FrameLimits,
 ) -> Option<
     IncomingConn<
         impl Stream<Item = RejectConnection> + Unpin,
@@ -41,20 +63,20 @@ where
     let sender = sender.sink_map_err(|_| ());
     let inner = match deserialize_init_connection(&first_msg).ok()? {
         InitConnection::Listen => IncomingConnInner::Listen(IncomingListen {
-            receiver: receiver
+            receiver: limit_frame_length(receiver, frame_limits.max_listen_frame_length)
                 .map(|data| deserialize_reject_connection(&data))
                 .take_while(|res| future::ready(res.is_ok()))
                 .map(Result::unwrap),
             sender: sender.with(|msg| future::ready(Ok(serialize_incoming_connection(&msg)))),
         }),
         InitConnection::Accept(accept_public_key) => IncomingConnInner::Accept(IncomingAccept {
-            receiver,
+            receiver: limit_frame_length(receiver, frame_limits.max_tunnel_frame_length),
             sender,
             accept_public_key,
         }),
         InitConnection::Connect(connect_public_key) => {
             IncomingConnInner::Connect(IncomingConnect {
-                receiver,
+                receiver: limit_frame_length(receiver, frame_limits.max_tunnel_frame_length),
                 sender,
                 connect_public_key,
             })
@@ -71,6 +93,7 @@ async fn process_conn<FT>(
     keepalive_transform: FT,
     mut timer_client: TimerClient,
     conn_timeout_ticks: usize,
+    frame_limits: FrameLimits,
 ) -> Option<
     IncomingConn<
         impl Stream<Item = RejectConnection> + Unpin,
@@ -92,7 +115,8 @@ where
                     receiver,
                     public_key,
                     first_msg,
-                    keepalive_transform
+                    keepalive_transform,
+                    frame_limits
                 ));
                 if dispatch_res.is_none() {
                     warn!("process_conn(): dispatch_conn() failure");
@@ -120,11 +144,13 @@ where
 /// For each connection obtain the first message, and prepare the correct type according to this
 /// first messages.
 /// If waiting for the first message takes too long, discard the connection.
+/// Every connection is closed once a message longer than the limit for its type is received.
 pub fn conn_processor<T, FT>(
     incoming_conns: T,
     keepalive_transform: FT,
     timer_client: TimerClient,
     conn_timeout_ticks: usize,
+    frame_limits: FrameLimits,
 ) -> impl Stream<
     Item = IncomingConn<
         impl Stream<Item = RejectConnection>,
@@ -148,6 +174,7 @@ where
                 keepalive_transform.clone(),
                 timer_client.clone(),
                 conn_timeout_ticks,
+                frame_limits,
             )
         })
         .filter_map(|opt_conn| opt_conn)
@@ -184,7 +211,8 @@ mod tests {
             receiver,
             public_key.clone(),
             ser_first_msg,
-            keepalive_transform
+            keepalive_transform,
+            FrameLimits::default()
         ))
         .unwrap();
 
@@ -205,7 +233,8 @@ mod tests {
             receiver,
             public_key.clone(),
             ser_first_msg,
-            keepalive_transform
+            keepalive_transform,
+            FrameLimits::default()
         ))
         .unwrap();
 
@@ -228,7 +257,8 @@ mod tests {
             receiver,
             public_key.clone(),
             ser_first_msg,
-            keepalive_transform
+            keepalive_transform,
+            FrameLimits::default()
         ))
         .unwrap();
 
@@ -261,7 +291,8 @@ mod tests {
             receiver,
             public_key.clone(),
             ser_first_msg,
-            keepalive_transform
+            keepalive_transform,
+            FrameLimits::default()
         ));
         assert!(res.is_none());
     }
@@ -272,6 +303,72 @@ mod tests {
         thread_pool.run(task_dispatch_conn_invalid_first_msg(thread_pool.clone()));
     }
 
+    async fn task_dispatch_conn_frame_limits() {
+        let frame_limits = FrameLimits {
+            max_listen_frame_length: 16,
+            max_tunnel_frame_length: 64,
+        };
+
+        let (sender, _remote_receiver) = mpsc::channel::<Vec<u8>>(0);
+        let (mut remote_sender, receiver) = mpsc::channel::<Vec<u8>>(0);
+        let ser_first_msg = serialize_init_connection(&InitConnection::Listen);
+        let public_key = PublicKey::from(&[0x77; PUBLIC_KEY_LEN]);
+        let keepalive_transform = FuncFutTransform::new(|x| Box::pin(future::ready(x)));
+        let incoming_conn = await!(dispatch_conn(
+            sender,
+            receiver,
+            public_key.clone(),
+            ser_first_msg,
+            keepalive_transform,
+            frame_limits
+        ))
+        .unwrap();
+
+        let mut incoming_listen = match incoming_conn.inner {
+            IncomingConnInner::Listen(incoming_listen) => incoming_listen,
+            _ => panic!("Wrong IncomingConnInner"),
+        };
+        // A frame longer than the listen limit closes the connection:
+        await!(remote_sender.send(vec![0; 32])).unwrap();
+        assert!(await!(incoming_listen.receiver.next()).is_none());
+
+        let (sender, _remote_receiver) = mpsc::channel::<Vec<u8>>(0);
+        let (mut remote_sender, receiver) = mpsc::channel::<Vec<u8>>(0);
+        let connect_public_key = PublicKey::from(&[0x33; PUBLIC_KEY_LEN]);
+        let ser_first_msg =
+            serialize_init_connection(&InitConnection::Connect(connect_public_key.clone()));
+        let keepalive_transform = FuncFutTransform::new(|x| Box::pin(future::ready(x)));
+        let incoming_conn = await!(dispatch_conn(
+            sender,
+            receiver,
+            public_key.clone(),
+            ser_first_msg,
+            keepalive_transform,
+            frame_limits
+        ))
+        .unwrap();
+
+        let mut incoming_connect = match incoming_conn.inner {
+            IncomingConnInner::Connect(incoming_connect) => incoming_connect,
+            _ => panic!("Wrong IncomingConnInner"),
+        };
+        // The same frame is allowed on a tunnel connection:
+        await!(remote_sender.send(vec![0; 32])).unwrap();
+        assert_eq!(
+            await!(incoming_connect.receiver.next()).unwrap(),
+            vec![0; 32]
+        );
+        // A frame longer than the tunnel limit closes the connection:
+        await!(remote_sender.send(vec![0; 65])).unwrap();
+        assert!(await!(incoming_connect.receiver.next()).is_none());
+    }
+
+    #[test]
+    fn test_dispatch_conn_frame_limits() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_dispatch_conn_frame_limits());
+    }
+
     #[test]
     fn test_conn_processor_basic() {
         let mut thread_pool = ThreadPool::new().unwrap();
@@ -295,6 +392,7 @@ mod tests {
             keepalive_transform,
             timer_client,
             conn_timeout_ticks,
+            FrameLimits::default(),
         );
 
         let processed_conns = Box::pin(processed_conns);
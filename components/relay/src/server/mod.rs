@@ -2,4 +2,7 @@ mod conn_limiter;
 mod conn_processor;
 pub mod net_server;
 mod server;
+mod tunnel;
 mod types;
+
+pub use self::types::FrameLimits;
@@ -24,6 +24,7 @@ use version::VersionPrefix;
 use super::conn_processor::conn_processor;
 use super::server::relay_server_loop;
 pub use super::server::RelayServerError;
+use super::types::FrameLimits;
 
 /// A relay server loop. Incoming connections should contain both (sender, receiver) and a
 /// public_key of the remote side (Should be obtained after authentication).
@@ -32,11 +33,17 @@ pub use super::server::RelayServerError;
 /// its purpose.
 /// `keepalive_ticks` is the amount of time we are willing to let the remote side to be idle before
 /// we disconnect. It is also used to timeout open half tunnels that were not claimed.
+/// `opt_max_tunnel_bytes_per_tick` optionally limits the rate of data passing through every
+/// tunnel, in each direction.
+/// `frame_limits` limits the length of a single message, according to the type of the
+/// connection.
 async fn relay_server<IC, S>(
     incoming_conns: IC,
     timer_client: TimerClient,
     conn_timeout_ticks: usize,
     keepalive_ticks: usize,
+    opt_max_tunnel_bytes_per_tick: Option<usize>,
+    frame_limits: FrameLimits,
     spawner: S,
 ) -> Result<(), RelayServerError>
 where
@@ -52,6 +59,7 @@ where
         keepalive_transform,
         timer_client.clone(),
         conn_timeout_ticks,
+        frame_limits,
     ));
 
     // TODO:
@@ -66,6 +74,7 @@ where
         timer_client,
         processed_conns,
         half_tunnel_ticks,
+        opt_max_tunnel_bytes_per_tick,
         spawner
     ))
 }
@@ -74,6 +83,7 @@ where
 pub enum NetRelayServerError {
     RelayServerError(RelayServerError),
     SpawnError,
+    InvalidFrameLimits,
 }
 
 /// Start a secure channel without knowing the identity of the remote
@@ -110,6 +120,8 @@ pub async fn net_relay_server<IRC, R, S>(
     timer_client: TimerClient,
     rng: R,
     max_concurrent_encrypt: usize,
+    opt_max_tunnel_bytes_per_tick: Option<usize>,
+    frame_limits: FrameLimits,
     mut spawner: S,
 ) -> Result<(), NetRelayServerError>
 where
@@ -117,6 +129,10 @@ where
     R: CryptoRandom + Clone + 'static,
     S: Spawn + Clone + Send + Sync + 'static,
 {
+    if !frame_limits.is_valid() {
+        return Err(NetRelayServerError::InvalidFrameLimits);
+    }
+
     let version_transform = VersionPrefix::new(PROTOCOL_VERSION, spawner.clone());
 
     let encrypt_transform = SecureChannel::new(
@@ -157,6 +173,8 @@ where
         timer_client,
         CONN_TIMEOUT_TICKS,
         KEEPALIVE_TICKS,
+        opt_max_tunnel_bytes_per_tick,
+        frame_limits,
         spawner.clone()
     ))?;
     Ok(())
@@ -12,6 +12,7 @@ use timer::TimerClient;
 
 use proto::relay::messages::{IncomingConnection, RejectConnection};
 
+use super::tunnel::{rate_limited_forward, TunnelCloseReason, TunnelStats};
 use super::types::{IncomingAccept, IncomingConn, IncomingConnInner};
 
 struct ConnPair<M, K> {
@@ -87,6 +88,28 @@ pub enum RelayServerError {
     NoPendingHalfTunnel,
     AlreadyListening,
     EventReceiverError,
+    SpawnError,
+}
+
+/// Limit the rate of data received from `receiver` to `max_bytes_per_tick`.
+fn rate_limit_receiver<M>(
+    receiver: M,
+    timer_client: TimerClient,
+    max_bytes_per_tick: usize,
+    mut spawner: impl Spawn,
+) -> Result<mpsc::Receiver<Vec<u8>>, RelayServerError>
+where
+    M: Stream<Item = Vec<u8>> + Unpin + Send + 'static,
+{
+    let (limited_sender, limited_receiver) = mpsc::channel(0);
+    let forward_fut =
+        rate_limited_forward(receiver, limited_sender, timer_client, max_bytes_per_tick)
+            .map_err(|e| warn!("rate_limited_forward() error: {:?}", e))
+            .map(|_| ());
+    spawner
+        .spawn(forward_fut)
+        .map_err(|_| RelayServerError::SpawnError)?;
+    Ok(limited_receiver)
 }
 
 fn handle_accept<MT, KT, MA, KA, TCL>(
@@ -95,6 +118,8 @@ fn handle_accept<MT, KT, MA, KA, TCL>(
     incoming_accept: IncomingAccept<MA, KA>,
     // TODO: This should be a oneshot:
     tunnel_closed_sender: TCL,
+    timer_client: TimerClient,
+    opt_max_tunnel_bytes_per_tick: Option<usize>,
     mut spawner: impl Spawn,
 ) -> Result<(), RelayServerError>
 where
@@ -109,7 +134,7 @@ where
         None => return Err(RelayServerError::ListeningNotInProgress),
     };
     let IncomingAccept {
-        mut receiver,
+        receiver,
         mut sender,
         accept_public_key,
     } = incoming_accept;
@@ -121,9 +146,33 @@ where
 
     let ConnPair {
         sender: mut remote_sender,
-        receiver: mut remote_receiver,
+        receiver: remote_receiver,
     } = conn_pair;
 
+    // Optionally limit the rate of data passing through the tunnel, in both directions:
+    let (receiver, remote_receiver): (BoxStream<'static, _>, BoxStream<'static, _>) =
+        match opt_max_tunnel_bytes_per_tick {
+            Some(max_bytes_per_tick) => (
+                Box::pin(rate_limit_receiver(
+                    receiver,
+                    timer_client.clone(),
+                    max_bytes_per_tick,
+                    &mut spawner,
+                )?),
+                Box::pin(rate_limit_receiver(
+                    remote_receiver,
+                    timer_client,
+                    max_bytes_per_tick,
+                    &mut spawner,
+                )?),
+            ),
+            None => (Box::pin(receiver), Box::pin(remote_receiver)),
+        };
+
+    let tunnel_stats = TunnelStats::new(c_accept_public_key.clone(), acceptor_public_key.clone());
+    let mut receiver = tunnel_stats.count_listen(receiver);
+    let mut remote_receiver = tunnel_stats.count_init(remote_receiver);
+
     let send_fut1 = async move {
         await!(remote_sender
             .send_all(&mut receiver)
@@ -134,7 +183,12 @@ where
         await!(sender
             .send_all(&mut remote_receiver)
             .map_err(|e| error!("send_fut2 error: {:?}", e))
-            .then(move |_| {
+            .then(move |res| {
+                let close_reason = match res {
+                    Ok(()) => TunnelCloseReason::Normal,
+                    Err(()) => TunnelCloseReason::SendFailed,
+                };
+                info!("{}", tunnel_stats.close(close_reason));
                 let tunnel_closed = TunnelClosed {
                     init_public_key: c_accept_public_key,
                     listen_public_key: acceptor_public_key,
@@ -153,6 +207,7 @@ pub async fn relay_server_loop<ML, KL, MA, KA, MC, KC, S>(
     mut timer_client: TimerClient,
     incoming_conns: S,
     half_tunnel_ticks: usize,
+    opt_max_tunnel_bytes_per_tick: Option<usize>,
     mut spawner: impl Spawn + Clone,
 ) -> Result<(), RelayServerError>
 where
@@ -244,6 +299,8 @@ where
                             public_key.clone(),
                             incoming_accept,
                             tunnel_closed_sender,
+                            timer_client.clone(),
+                            opt_max_tunnel_bytes_per_tick,
                             spawner.clone(),
                         )
                         .map_err(|e| warn!("handle_accept() error: {:?}", e));
@@ -361,6 +418,7 @@ mod tests {
             timer_client,
             incoming_conns,
             half_tunnel_ticks,
+            None,
             spawner.clone(),
         );
 
@@ -478,6 +536,7 @@ mod tests {
             timer_client,
             incoming_conns,
             half_tunnel_ticks,
+            None,
             spawner.clone(),
         );
 
@@ -0,0 +1,318 @@
+use std::cmp;
+use std::fmt;
+use std::marker::Unpin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::channel::mpsc;
+use futures::{select, FutureExt, SinkExt, Stream, StreamExt};
+
+use crypto::identity::PublicKey;
+use timer::TimerClient;
+
+#[derive(Debug)]
+pub enum TunnelError {
+    RequestTimerStreamError,
+    TimerClosed,
+}
+
+/// The reason a tunnel was closed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TunnelCloseReason {
+    /// The initiating side closed its connection.
+    Normal,
+    /// Sending data to the listening side failed.
+    SendFailed,
+}
+
+/// A summary of the life of a single tunnel, created when the tunnel is closed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TunnelRecord {
+    pub init_public_key: PublicKey,
+    pub listen_public_key: PublicKey,
+    /// How long the tunnel was open
+    pub duration: Duration,
+    /// Amount of bytes sent by the initiating side
+    pub init_bytes: usize,
+    /// Amount of bytes sent by the listening side
+    pub listen_bytes: usize,
+    pub close_reason: TunnelCloseReason,
+}
+
+impl fmt::Display for TunnelRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "tunnel closed: init={:?} listen={:?} duration={:?} init_bytes={} listen_bytes={} reason={:?}",
+            self.init_public_key,
+            self.listen_public_key,
+            self.duration,
+            self.init_bytes,
+            self.listen_bytes,
+            self.close_reason
+        )
+    }
+}
+
+/// Accumulates statistics about a tunnel while it is open.
+pub struct TunnelStats {
+    init_public_key: PublicKey,
+    listen_public_key: PublicKey,
+    opened: Instant,
+    init_bytes: Arc<AtomicUsize>,
+    listen_bytes: Arc<AtomicUsize>,
+}
+
+fn count_bytes<M>(receiver: M, counter: Arc<AtomicUsize>) -> impl Stream<Item = Vec<u8>> + Unpin
+where
+    M: Stream<Item = Vec<u8>> + Unpin,
+{
+    receiver.inspect(move |data| {
+        counter.fetch_add(data.len(), Ordering::Relaxed);
+    })
+}
+
+impl TunnelStats {
+    pub fn new(init_public_key: PublicKey, listen_public_key: PublicKey) -> Self {
+        TunnelStats {
+            init_public_key,
+            listen_public_key,
+            opened: Instant::now(),
+            init_bytes: Arc::new(AtomicUsize::new(0)),
+            listen_bytes: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Count the bytes received from the initiating side.
+    pub fn count_init<M>(&self, receiver: M) -> impl Stream<Item = Vec<u8>> + Unpin
+    where
+        M: Stream<Item = Vec<u8>> + Unpin,
+    {
+        count_bytes(receiver, self.init_bytes.clone())
+    }
+
+    /// Count the bytes received from the listening side.
+    pub fn count_listen<M>(&self, receiver: M) -> impl Stream<Item = Vec<u8>> + Unpin
+    where
+        M: Stream<Item = Vec<u8>> + Unpin,
+    {
+        count_bytes(receiver, self.listen_bytes.clone())
+    }
+
+    pub fn close(self, close_reason: TunnelCloseReason) -> TunnelRecord {
+        TunnelRecord {
+            init_public_key: self.init_public_key,
+            listen_public_key: self.listen_public_key,
+            duration: self.opened.elapsed(),
+            init_bytes: self.init_bytes.load(Ordering::Relaxed),
+            listen_bytes: self.listen_bytes.load(Ordering::Relaxed),
+            close_reason,
+        }
+    }
+}
+
+/// A token bucket, refilled every timer tick.
+/// The bucket holds at most `max_bytes_per_tick` bytes.
+struct TokenBucket {
+    max_bytes_per_tick: usize,
+    tokens: usize,
+    /// Bytes that were forwarded beyond the amount of available tokens.
+    /// Will be paid using future refills.
+    debt: usize,
+}
+
+impl TokenBucket {
+    fn new(max_bytes_per_tick: usize) -> Self {
+        TokenBucket {
+            max_bytes_per_tick,
+            tokens: max_bytes_per_tick,
+            debt: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tokens == 0
+    }
+
+    fn consume(&mut self, num_bytes: usize) {
+        if num_bytes <= self.tokens {
+            self.tokens -= num_bytes;
+        } else {
+            self.debt = self.debt.saturating_add(num_bytes - self.tokens);
+            self.tokens = 0;
+        }
+    }
+
+    fn refill(&mut self) {
+        let paid = cmp::min(self.debt, self.max_bytes_per_tick);
+        self.debt -= paid;
+        let refill = self.max_bytes_per_tick - paid;
+        self.tokens = cmp::min(self.tokens.saturating_add(refill), self.max_bytes_per_tick);
+    }
+}
+
+/// Forward messages from `receiver` to `sender`, forwarding at most `max_bytes_per_tick` bytes
+/// per timer tick on average. When the bucket is empty, we stop reading from `receiver` until
+/// the next timer tick.
+pub async fn rate_limited_forward<M>(
+    mut receiver: M,
+    mut sender: mpsc::Sender<Vec<u8>>,
+    mut timer_client: TimerClient,
+    max_bytes_per_tick: usize,
+) -> Result<(), TunnelError>
+where
+    M: Stream<Item = Vec<u8>> + Unpin,
+{
+    let mut timer_stream = await!(timer_client.request_timer_stream())
+        .map_err(|_| TunnelError::RequestTimerStreamError)?;
+
+    let mut bucket = TokenBucket::new(max_bytes_per_tick);
+
+    loop {
+        if bucket.is_empty() {
+            // Throttle: Wait for the next refill before reading more data:
+            await!(timer_stream.next()).ok_or(TunnelError::TimerClosed)?;
+            bucket.refill();
+            continue;
+        }
+
+        let mut fut_tick = timer_stream.next().fuse();
+        let mut fut_data = receiver.next().fuse();
+        select! {
+            opt_tick = fut_tick => {
+                opt_tick.ok_or(TunnelError::TimerClosed)?;
+                bucket.refill();
+            },
+            opt_data = fut_data => {
+                let data = match opt_data {
+                    Some(data) => data,
+                    None => return Ok(()),
+                };
+                bucket.consume(data.len());
+                if await!(sender.send(data)).is_err() {
+                    return Ok(());
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::ThreadPool;
+    use futures::task::{Spawn, SpawnExt};
+
+    use crypto::identity::PUBLIC_KEY_LEN;
+    use timer::create_timer_incoming;
+
+    #[test]
+    fn test_token_bucket_debt() {
+        let mut bucket = TokenBucket::new(100);
+        assert!(!bucket.is_empty());
+        bucket.consume(250);
+        assert!(bucket.is_empty());
+
+        // The debt of 150 bytes is paid during the next two refills:
+        bucket.refill();
+        assert!(bucket.is_empty());
+        bucket.refill();
+        assert_eq!(bucket.tokens, 50);
+        bucket.refill();
+        assert_eq!(bucket.tokens, 100);
+    }
+
+    async fn task_rate_limited_forward(mut spawner: impl Spawn + Clone + Send + 'static) {
+        let (mut tick_sender, tick_receiver) = mpsc::channel::<()>(0);
+        let timer_client = create_timer_incoming(tick_receiver, spawner.clone()).unwrap();
+
+        let max_bytes_per_tick = 200;
+        let (mut data_sender, data_receiver) = mpsc::channel::<Vec<u8>>(0);
+        let (output_sender, mut output_receiver) = mpsc::channel::<Vec<u8>>(0);
+        spawner
+            .spawn(
+                rate_limited_forward(
+                    data_receiver,
+                    output_sender,
+                    timer_client,
+                    max_bytes_per_tick,
+                )
+                .map(|_| ()),
+            )
+            .unwrap();
+
+        // Push data much faster than the limit allows:
+        spawner
+            .spawn(
+                async move {
+                    for i in 0..16u8 {
+                        if await!(data_sender.send(vec![i; 100])).is_err() {
+                            return;
+                        }
+                    }
+                },
+            )
+            .unwrap();
+
+        let mut total_bytes = 0;
+        for tick in 0..4 {
+            // Every tick we may read exactly the amount of bytes allowed by the limit:
+            for _ in 0..2 {
+                total_bytes += await!(output_receiver.next()).unwrap().len();
+            }
+            // The bucket is empty, no more data may be forwarded until the next tick:
+            assert!(output_receiver.try_next().is_err());
+            assert_eq!(total_bytes, (tick + 1) * max_bytes_per_tick);
+
+            await!(tick_sender.send(())).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_rate_limited_forward() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_rate_limited_forward(thread_pool.clone()));
+    }
+
+    async fn task_tunnel_stats() {
+        let init_public_key = PublicKey::from(&[0xaa; PUBLIC_KEY_LEN]);
+        let listen_public_key = PublicKey::from(&[0xbb; PUBLIC_KEY_LEN]);
+        let tunnel_stats = TunnelStats::new(init_public_key.clone(), listen_public_key.clone());
+
+        let (mut init_sender, init_receiver) = mpsc::channel::<Vec<u8>>(8);
+        let (mut listen_sender, listen_receiver) = mpsc::channel::<Vec<u8>>(8);
+        let (mut to_listen, mut from_init) = mpsc::channel::<Vec<u8>>(8);
+        let (mut to_init, mut from_listen) = mpsc::channel::<Vec<u8>>(8);
+
+        let mut init_receiver = tunnel_stats.count_init(init_receiver);
+        let mut listen_receiver = tunnel_stats.count_listen(listen_receiver);
+
+        await!(init_sender.send(vec![1; 10])).unwrap();
+        await!(init_sender.send(vec![2; 20])).unwrap();
+        await!(listen_sender.send(vec![3; 5])).unwrap();
+        drop(init_sender);
+        drop(listen_sender);
+
+        await!(to_listen.send_all(&mut init_receiver)).unwrap();
+        await!(to_init.send_all(&mut listen_receiver)).unwrap();
+
+        assert_eq!(await!(from_init.next()).unwrap(), vec![1; 10]);
+        assert_eq!(await!(from_init.next()).unwrap(), vec![2; 20]);
+        assert_eq!(await!(from_listen.next()).unwrap(), vec![3; 5]);
+
+        let tunnel_record = tunnel_stats.close(TunnelCloseReason::Normal);
+        assert_eq!(tunnel_record.init_public_key, init_public_key);
+        assert_eq!(tunnel_record.listen_public_key, listen_public_key);
+        assert_eq!(tunnel_record.init_bytes, 30);
+        assert_eq!(tunnel_record.listen_bytes, 5);
+        assert_eq!(tunnel_record.close_reason, TunnelCloseReason::Normal);
+    }
+
+    #[test]
+    fn test_tunnel_stats() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+        thread_pool.run(task_tunnel_stats());
+    }
+}
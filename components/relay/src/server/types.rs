@@ -1,5 +1,35 @@
 use crypto::identity::PublicKey;
 
+use proto::consts::MAX_FRAME_LENGTH;
+
+/// Maximum length of a single message, according to the type of the connection.
+/// A connection that sends a longer message is closed.
+/// Both limits may not exceed `MAX_FRAME_LENGTH`, as longer frames are never delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameLimits {
+    /// Listen connections, used only for control messages.
+    pub max_listen_frame_length: usize,
+    /// Accept and Connect connections, used for tunneling data.
+    pub max_tunnel_frame_length: usize,
+}
+
+impl FrameLimits {
+    /// Are the limits within `MAX_FRAME_LENGTH`?
+    pub fn is_valid(&self) -> bool {
+        self.max_listen_frame_length <= MAX_FRAME_LENGTH
+            && self.max_tunnel_frame_length <= MAX_FRAME_LENGTH
+    }
+}
+
+impl Default for FrameLimits {
+    fn default() -> Self {
+        FrameLimits {
+            max_listen_frame_length: MAX_FRAME_LENGTH,
+            max_tunnel_frame_length: MAX_FRAME_LENGTH,
+        }
+    }
+}
+
 pub struct IncomingListen<M, K> {
     pub receiver: M,
     pub sender: K,
@@ -27,3 +57,26 @@ pub struct IncomingConn<ML, KL, MA, KA, MC, KC> {
     pub public_key: PublicKey,
     pub inner: IncomingConnInner<ML, KL, MA, KA, MC, KC>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_limits_is_valid() {
+        assert!(FrameLimits::default().is_valid());
+
+        let frame_limits = FrameLimits {
+            max_listen_frame_length: 0x100,
+            max_tunnel_frame_length: MAX_FRAME_LENGTH,
+        };
+        assert!(frame_limits.is_valid());
+
+        // Frames longer than MAX_FRAME_LENGTH are never delivered:
+        let frame_limits = FrameLimits {
+            max_listen_frame_length: 0x100,
+            max_tunnel_frame_length: MAX_FRAME_LENGTH + 1,
+        };
+        assert!(!frame_limits.is_valid());
+    }
+}
@@ -10,6 +10,7 @@ use common::select_streams::{select_streams, BoxStream};
 use crypto::crypto_rand::CryptoRandom;
 use crypto::identity::PublicKey;
 use identity::IdentityClient;
+use timer::utils::future_timeout;
 use timer::TimerClient;
 
 use crate::state::{ScState, ScStateError, ScStateInitial};
@@ -19,9 +20,13 @@ use proto::secure_channel::serialize::{
     serialize_exchange_rand_nonce,
 };
 
+/// Maximum amount of ticks to wait for the identity service to provide our public key.
+const REQUEST_PUBLIC_KEY_TIMEOUT_TICKS: usize = 0x40;
+
 #[derive(Debug)]
 enum SecureChannelError {
     IdentityFailure,
+    IdentityTimeout,
     WriterError,
     ReaderClosed,
     DeserializeRandNonceError,
@@ -40,14 +45,23 @@ async fn initial_exchange<EK, M: 'static, K: 'static, R: CryptoRandom + 'static>
     identity_client: IdentityClient,
     opt_expected_remote: Option<PublicKey>,
     rng: R,
+    mut timer_client: TimerClient,
 ) -> Result<(ScState, K, M), SecureChannelError>
 where
     R: CryptoRandom + Clone,
     M: Stream<Item = Vec<u8>> + Unpin,
     K: Sink<SinkItem = Vec<u8>, SinkError = EK> + Unpin,
 {
-    let local_public_key = await!(identity_client.request_public_key())
-        .map_err(|_| SecureChannelError::IdentityFailure)?;
+    // Don't wait forever for the identity service, to avoid stalling the handshake:
+    let timer_stream = await!(timer_client.request_timer_stream())
+        .map_err(|_| SecureChannelError::RequestTimerStreamError)?;
+    let local_public_key = await!(future_timeout(
+        Box::pin(identity_client.request_public_key()),
+        timer_stream,
+        REQUEST_PUBLIC_KEY_TIMEOUT_TICKS
+    ))
+    .ok_or(SecureChannelError::IdentityTimeout)?
+    .map_err(|_| SecureChannelError::IdentityFailure)?;
 
     let (dh_state_initial, exchange_rand_nonce) = ScStateInitial::new(&local_public_key, &rng);
     let ser_exchange_rand_nonce = serialize_exchange_rand_nonce(&exchange_rand_nonce);
@@ -206,7 +220,8 @@ where
         reader,
         identity_client,
         opt_expected_remote,
-        rng.clone()
+        rng.clone(),
+        timer_client.clone()
     ))?;
 
     let remote_public_key = dh_state.get_remote_public_key().clone();
@@ -309,6 +324,7 @@ mod tests {
     use super::*;
     use futures::channel::oneshot;
     use futures::Future;
+    use std::time::Instant;
     use timer::create_timer_incoming;
 
     use futures::executor::ThreadPool;
@@ -431,4 +447,198 @@ mod tests {
         assert_eq!(true, thread_pool.run(output_receiver1).unwrap());
         assert_eq!(true, thread_pool.run(output_receiver2).unwrap());
     }
+
+    #[test]
+    fn test_secure_channel_remote_closed_during_exchange() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+
+        // Create a mock time service:
+        let (_tick_sender, tick_receiver) = mpsc::channel::<()>(0);
+        let timer_client = create_timer_incoming(tick_receiver, thread_pool.clone()).unwrap();
+
+        let rng1 = DummyRandom::new(&[1u8]);
+        let pkcs8 = generate_pkcs8_key_pair(&rng1);
+        let identity1 = SoftwareEd25519Identity::from_pkcs8(&pkcs8).unwrap();
+        let (requests_sender1, identity_server1) = create_identity(identity1);
+        let identity_client1 = IdentityClient::new(requests_sender1);
+        thread_pool
+            .spawn(identity_server1.then(|_| future::ready(())))
+            .unwrap();
+
+        let (sender1, _receiver2) = mpsc::channel::<Vec<u8>>(0);
+        let (sender2, receiver1) = mpsc::channel::<Vec<u8>>(0);
+
+        // The remote side closes the connection before sending its ExchangeRandNonce:
+        drop(sender2);
+
+        let fut_sc1 = create_secure_channel(
+            sender1.sink_map_err(|_| ()),
+            receiver1,
+            identity_client1,
+            None,
+            rng1.clone(),
+            timer_client,
+            16,
+            thread_pool.clone(),
+        );
+
+        match thread_pool.run(fut_sc1) {
+            Err(SecureChannelError::ReaderClosed) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_secure_channel_identity_timeout() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+
+        // Create a mock time service:
+        let (mut tick_sender, tick_receiver) = mpsc::channel::<()>(0);
+        let timer_client = create_timer_incoming(tick_receiver, thread_pool.clone()).unwrap();
+
+        // An identity service that never responds:
+        let (requests_sender1, _requests_receiver1) = mpsc::channel(0);
+        let identity_client1 = IdentityClient::new(requests_sender1);
+
+        let (sender1, _receiver2) = mpsc::channel::<Vec<u8>>(0);
+        let (_sender2, receiver1) = mpsc::channel::<Vec<u8>>(0);
+
+        let fut_sc1 = create_secure_channel(
+            sender1.sink_map_err(|_| ()),
+            receiver1,
+            identity_client1,
+            None,
+            DummyRandom::new(&[1u8]),
+            timer_client,
+            16,
+            thread_pool.clone(),
+        );
+        let (output_sender, mut output_receiver) = oneshot::channel();
+        thread_pool
+            .spawn(fut_sc1.map(|res| output_sender.send(res).unwrap()))
+            .unwrap();
+
+        // Move time forward, until the request for the public key times out:
+        let res = thread_pool.run(
+            async move {
+                loop {
+                    await!(tick_sender.send(())).unwrap();
+                    if let Some(res) = output_receiver.try_recv().unwrap() {
+                        return res;
+                    }
+                }
+            },
+        );
+
+        match res {
+            Err(SecureChannelError::IdentityTimeout) => {}
+            _ => unreachable!(),
+        }
+    }
+
+    /// Amount of handshakes performed by the handshake harness test.
+    const NUM_HARNESS_HANDSHAKES: usize = 16;
+
+    /// Run `num_handshakes` full handshakes between two in memory peers.
+    /// Returns the amount of handshakes that completed successfully.
+    async fn run_handshakes<S>(
+        num_handshakes: usize,
+        identity_client1: IdentityClient,
+        public_key1: PublicKey,
+        identity_client2: IdentityClient,
+        public_key2: PublicKey,
+        timer_client: TimerClient,
+        spawner: S,
+    ) -> usize
+    where
+        S: Spawn + Clone,
+    {
+        let ticks_to_rekey: usize = 16;
+        let mut num_completed = 0;
+
+        for i in 0..num_handshakes {
+            let (sender1, receiver2) = mpsc::channel::<Vec<u8>>(0);
+            let (sender2, receiver1) = mpsc::channel::<Vec<u8>>(0);
+
+            let fut_sc1 = create_secure_channel(
+                sender1.sink_map_err(|_| ()),
+                receiver1,
+                identity_client1.clone(),
+                Some(public_key2.clone()),
+                DummyRandom::new(&[1u8, i as u8]),
+                timer_client.clone(),
+                ticks_to_rekey,
+                spawner.clone(),
+            );
+
+            let fut_sc2 = create_secure_channel(
+                sender2.sink_map_err(|_| ()),
+                receiver2,
+                identity_client2.clone(),
+                Some(public_key1.clone()),
+                DummyRandom::new(&[2u8, i as u8]),
+                timer_client.clone(),
+                ticks_to_rekey,
+                spawner.clone(),
+            );
+
+            match await!(future::join(fut_sc1, fut_sc2)) {
+                (Ok((remote_public_key1, _conn1)), Ok((remote_public_key2, _conn2))) => {
+                    assert_eq!(remote_public_key1, public_key2);
+                    assert_eq!(remote_public_key2, public_key1);
+                    num_completed += 1;
+                }
+                (res1, res2) => error!("Handshake failed: {:?}, {:?}", res1.err(), res2.err()),
+            }
+        }
+        num_completed
+    }
+
+    /// A harness for measuring the performance of handshakes.
+    /// Runs a configurable amount of full handshakes (Including serialization and crypto)
+    /// between two in memory peers, using a dummy timer.
+    #[test]
+    fn test_secure_channel_handshakes_harness() {
+        let mut thread_pool = ThreadPool::new().unwrap();
+
+        // Create a mock time service:
+        let (_tick_sender, tick_receiver) = mpsc::channel::<()>(0);
+        let timer_client = create_timer_incoming(tick_receiver, thread_pool.clone()).unwrap();
+
+        let rng1 = DummyRandom::new(&[1u8]);
+        let pkcs8 = generate_pkcs8_key_pair(&rng1);
+        let identity1 = SoftwareEd25519Identity::from_pkcs8(&pkcs8).unwrap();
+        let public_key1 = identity1.get_public_key();
+        let (requests_sender1, identity_server1) = create_identity(identity1);
+        let identity_client1 = IdentityClient::new(requests_sender1);
+
+        let rng2 = DummyRandom::new(&[2u8]);
+        let pkcs8 = generate_pkcs8_key_pair(&rng2);
+        let identity2 = SoftwareEd25519Identity::from_pkcs8(&pkcs8).unwrap();
+        let public_key2 = identity2.get_public_key();
+        let (requests_sender2, identity_server2) = create_identity(identity2);
+        let identity_client2 = IdentityClient::new(requests_sender2);
+
+        thread_pool
+            .spawn(identity_server1.then(|_| future::ready(())))
+            .unwrap();
+        thread_pool
+            .spawn(identity_server2.then(|_| future::ready(())))
+            .unwrap();
+
+        let start = Instant::now();
+        let num_completed = thread_pool.run(run_handshakes(
+            NUM_HARNESS_HANDSHAKES,
+            identity_client1,
+            public_key1,
+            identity_client2,
+            public_key2,
+            timer_client,
+            thread_pool.clone(),
+        ));
+        let elapsed = start.elapsed();
+
+        assert_eq!(num_completed, NUM_HARNESS_HANDSHAKES);
+        info!("Completed {} handshakes in {:?}", num_completed, elapsed);
+    }
 }
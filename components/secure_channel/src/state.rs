@@ -5,6 +5,10 @@ use crypto::crypto_rand::{CryptoRandom, RandValue};
 use crypto::dh::{DhPrivateKey, Salt};
 use crypto::identity::{verify_signature, PublicKey, Signature};
 use crypto::sym_encrypt::{Decryptor, Encryptor};
+#[cfg(feature = "debug-key-fingerprint")]
+use crypto::hash::{sha_512_256, HashResult};
+#[cfg(feature = "debug-key-fingerprint")]
+use crypto::sym_encrypt::SymmetricKey;
 use identity::IdentityClient;
 use proto::secure_channel::messages::{
     ChannelContent, ChannelMessage, EncryptedData, ExchangeDh, ExchangeRandNonce, PlainData, Rekey,
@@ -57,6 +61,24 @@ pub struct ScState {
     /// messages for the new receiver.
     opt_old_receiver: Option<Decryptor>,
     opt_pending_rekey: Option<PendingRekey>,
+    #[cfg(feature = "debug-key-fingerprint")]
+    key_fingerprint: HashResult,
+}
+
+/// Calculate a fingerprint of the current symmetric keys of a channel.
+/// The send key of one side is the receive key of the other side, so we hash the keys in a
+/// canonical order. This way both sides of the channel obtain the same fingerprint.
+#[cfg(feature = "debug-key-fingerprint")]
+fn calc_key_fingerprint(send_key: &SymmetricKey, recv_key: &SymmetricKey) -> HashResult {
+    let (first_key, second_key) = if send_key <= recv_key {
+        (send_key, recv_key)
+    } else {
+        (recv_key, send_key)
+    };
+    let mut data = Vec::new();
+    data.extend_from_slice(first_key);
+    data.extend_from_slice(second_key);
+    sha_512_256(&data)
 }
 
 impl ScStateInitial {
@@ -146,6 +168,8 @@ impl ScStateHalf {
                 .map_err(|_| ScStateError::CreateDecryptorFailure)?,
             opt_old_receiver: None,
             opt_pending_rekey: None,
+            #[cfg(feature = "debug-key-fingerprint")]
+            key_fingerprint: calc_key_fingerprint(&send_key, &recv_key),
         })
     }
 }
@@ -273,6 +297,10 @@ impl ScState {
                     Decryptor::new(&recv_key).map_err(|_| ScStateError::CreateDecryptorFailure)?;
 
                 self.opt_old_receiver = Some(mem::replace(&mut self.receiver, new_receiver));
+                #[cfg(feature = "debug-key-fingerprint")]
+                {
+                    self.key_fingerprint = calc_key_fingerprint(&send_key, &recv_key);
+                }
 
                 // Create our Rekey message using the old sender:
                 let rekey = Rekey {
@@ -302,6 +330,10 @@ impl ScState {
                 let new_receiver =
                     Decryptor::new(&recv_key).map_err(|_| ScStateError::CreateDecryptorFailure)?;
                 self.opt_old_receiver = Some(mem::replace(&mut self.receiver, new_receiver));
+                #[cfg(feature = "debug-key-fingerprint")]
+                {
+                    self.key_fingerprint = calc_key_fingerprint(&send_key, &recv_key);
+                }
                 Ok(HandleIncomingOutput {
                     rekey_occurred: true,
                     opt_send_message: None,
@@ -331,6 +363,14 @@ impl ScState {
     pub fn get_remote_public_key(&self) -> &PublicKey {
         &self.remote_public_key
     }
+
+    /// A hash of the current symmetric keys (Not the keys themselves).
+    /// Both sides of a channel have the same fingerprint, hence two operators can compare
+    /// fingerprints out of band when diagnosing encryption mismatches.
+    #[cfg(feature = "debug-key-fingerprint")]
+    pub fn debug_key_fingerprint(&self) -> HashResult {
+        self.key_fingerprint.clone()
+    }
 }
 
 #[cfg(test)]
@@ -471,6 +511,22 @@ mod tests {
         (sc_state1, sc_state2, rng1, rng2)
     }
 
+    #[cfg(feature = "debug-key-fingerprint")]
+    #[test]
+    fn test_sc_state_debug_key_fingerprint() {
+        let (mut sc_state1, mut sc_state2, rng1, rng2) = prepare_dh_test();
+        let fingerprint = sc_state1.debug_key_fingerprint();
+        assert_eq!(fingerprint, sc_state2.debug_key_fingerprint());
+
+        // Fingerprints are updated when rekeying:
+        rekey_sequential(&mut sc_state1, &mut sc_state2, &rng1, &rng2);
+        assert_eq!(
+            sc_state1.debug_key_fingerprint(),
+            sc_state2.debug_key_fingerprint()
+        );
+        assert_ne!(sc_state1.debug_key_fingerprint(), fingerprint);
+    }
+
     #[test]
     fn test_basic_sc_state() {
         let (mut sc_state1, mut sc_state2, rng1, rng2) = prepare_dh_test();
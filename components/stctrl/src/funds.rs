@@ -25,6 +25,9 @@ pub struct SendFundsCmd {
     /// Output receipt file
     #[structopt(parse(from_os_str), short = "r", long = "receipt")]
     pub opt_receipt_file: Option<PathBuf>,
+    /// A message for the recipient
+    #[structopt(short = "m", long = "memo")]
+    pub opt_memo: Option<String>,
 }
 
 /// Pay an invoice
@@ -117,6 +120,7 @@ async fn funds_send_funds(
         destination_str,
         dest_payment,
         opt_receipt_file,
+        opt_memo,
     } = send_raw_cmd;
 
     // In case the user wants a receipt, make sure that we will be able to write the receipt
@@ -151,9 +155,16 @@ async fn funds_send_funds(
     let request_id = gen_uid();
     let invoice_id = InvoiceId::from(&[0; INVOICE_ID_LEN]);
 
-    let receipt =
-        await!(app_send_funds.request_send_funds(request_id, route, invoice_id, dest_payment))
-            .map_err(|_| FundsError::SendFundsError)?;
+    let memo = opt_memo.map(String::into_bytes).unwrap_or_default();
+
+    let receipt = await!(app_send_funds.request_send_funds(
+        request_id,
+        route,
+        invoice_id,
+        dest_payment,
+        memo
+    ))
+    .map_err(|_| FundsError::SendFundsError)?;
 
     writeln!(writer, "Payment successful!").map_err(|_| FundsError::WriteError)?;
     writeln!(writer, "Fees: {}", fees).map_err(|_| FundsError::WriteError)?;
@@ -214,7 +225,8 @@ async fn funds_pay_invoice(
         request_id,
         route,
         invoice.invoice_id,
-        invoice.dest_payment
+        invoice.dest_payment,
+        Vec::new()
     ))
     .map_err(|_| FundsError::SendFundsError)?;
 
@@ -242,10 +242,10 @@ pub async fn info_friends(
 
     for (_friend_public_key, friend_report) in &report.funder_report.friends {
         // Is the friend enabled?
-        let status_str = if friend_report.status == FriendStatusReport::Enabled {
-            "E"
-        } else {
-            "D"
+        let status_str = match friend_report.status {
+            FriendStatusReport::Enabled => "E",
+            FriendStatusReport::Disabled => "D",
+            FriendStatusReport::Paused => "P",
         };
 
         let liveness_str = if friend_report.liveness.is_online() {
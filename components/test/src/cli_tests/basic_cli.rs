@@ -64,6 +64,11 @@ fn spawn_entities(stctrl_setup: &StCtrlSetup) {
             .join("relay0")
             .join("relay0.ident"),
         laddr: stctrl_setup.relay0_addr.parse().unwrap(),
+        opt_health_addr: None,
+        opt_backlog: None,
+        opt_max_tunnel_bytes_per_tick: None,
+        opt_max_listen_frame_length: None,
+        opt_max_tunnel_frame_length: None,
     };
     // TODO: How can we close this thread?
     thread::spawn(move || {
@@ -78,6 +83,11 @@ fn spawn_entities(stctrl_setup: &StCtrlSetup) {
             .join("relay1")
             .join("relay1.ident"),
         laddr: stctrl_setup.relay1_addr.parse().unwrap(),
+        opt_health_addr: None,
+        opt_backlog: None,
+        opt_max_tunnel_bytes_per_tick: None,
+        opt_max_listen_frame_length: None,
+        opt_max_tunnel_frame_length: None,
     };
     // TODO: How can we close this thread?
     thread::spawn(move || {
@@ -455,6 +465,7 @@ fn send_funds(stctrl_setup: &StCtrlSetup) {
                 .join("app1")
                 .join("receipt_50.receipt"),
         ),
+        opt_memo: Some("payment".to_owned()),
     };
     let funds_cmd = FundsCmd::SendFunds(send_funds_cmd);
     let subcommand = StCtrlSubcommand::Funds(funds_cmd);
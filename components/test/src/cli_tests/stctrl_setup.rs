@@ -119,11 +119,12 @@ pub fn create_stctrl_setup(temp_dir_path: &Path) -> StCtrlSetup {
     }
 
     // Prepare files for nodes:
-    for node in &["node0", "node1"] {
-        // Create initial database:
+    for (node, json) in &[("node0", true), ("node1", false)] {
+        // Create initial database. node0 uses the human readable format:
         let init_node_db_cmd = InitNodeDbCmd {
             idfile: temp_dir_path.join(node).join(format!("{}.ident", node)),
             output: temp_dir_path.join(node).join(format!("{}.db", node)),
+            json: *json,
         };
         stmgr(StMgrCmd::InitNodeDb(init_node_db_cmd)).unwrap();
     }
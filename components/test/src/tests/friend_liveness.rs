@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use futures::channel::mpsc;
+
+use tempfile::tempdir;
+
+use common::test_executor::TestExecutor;
+
+use proto::app_server::messages::AppPermissions;
+use proto::consts::KEEPALIVE_TICKS;
+use timer::create_timer_incoming;
+
+use node::connect::AppReport;
+use node::NodeConfig;
+
+use crate::utils::{
+    advance_time, create_app, create_node_with_config, create_relay, default_node_config,
+    named_relay_address, node_public_key, relay_address, SimDb,
+};
+
+use crate::sim_network::create_sim_network;
+
+const TIMER_CHANNEL_LEN: usize = 0;
+
+/// A liveness interval much shorter than the default one
+const SHORT_LIVENESS_TICKS: usize = 4;
+
+/// Checks if a friend is online
+/// panics if the friend does not exist.
+async fn is_friend_online(report: &mut AppReport, index: u8) -> bool {
+    let (node_report, mutations_receiver) = await!(report.incoming_reports()).unwrap();
+    drop(mutations_receiver);
+
+    let friend_report = match node_report
+        .funder_report
+        .friends
+        .get(&node_public_key(index))
+    {
+        None => unreachable!(),
+        Some(friend_report) => friend_report,
+    };
+    friend_report.liveness.is_online()
+}
+
+async fn task_friend_liveness_short_interval(mut test_executor: TestExecutor) {
+    // Create timer_client:
+    let (mut tick_sender, tick_receiver) = mpsc::channel(TIMER_CHANNEL_LEN);
+    let timer_client = create_timer_incoming(tick_receiver, test_executor.clone()).unwrap();
+
+    // Create a temporary directory.
+    // Should be deleted when gets out of scope:
+    let temp_dir = tempdir().unwrap();
+
+    // Create a database manager at the temporary directory:
+    let sim_db = SimDb::new(temp_dir.path().to_path_buf());
+
+    // A network simulator:
+    let sim_net_client = create_sim_network(&mut test_executor);
+
+    // Both nodes agree on the short liveness interval:
+    let node_config = NodeConfig {
+        liveness_ticks: SHORT_LIVENESS_TICKS,
+        ..default_node_config()
+    };
+
+    let mut apps = Vec::new();
+    let mut node_handles = Vec::new();
+    for index in 0..2 {
+        sim_db.init_db(index);
+
+        let mut trusted_apps = HashMap::new();
+        trusted_apps.insert(
+            index,
+            AppPermissions {
+                routes: true,
+                send_funds: true,
+                config: true,
+            },
+        );
+        node_handles.push(await!(create_node_with_config(
+            index,
+            sim_db.clone(),
+            timer_client.clone(),
+            sim_net_client.clone(),
+            trusted_apps,
+            node_config.clone(),
+            test_executor.clone()
+        )));
+
+        apps.push(
+            await!(create_app(
+                index,
+                sim_net_client.clone(),
+                timer_client.clone(),
+                index,
+                test_executor.clone()
+            ))
+            .unwrap(),
+        );
+
+        await!(create_relay(
+            index,
+            timer_client.clone(),
+            sim_net_client.clone(),
+            test_executor.clone()
+        ));
+    }
+
+    let mut config0 = apps[0].config().unwrap().clone();
+    let mut config1 = apps[1].config().unwrap().clone();
+
+    let mut report0 = apps[0].report().clone();
+    let mut report1 = apps[1].report().clone();
+
+    // Configure relays:
+    await!(config0.add_relay(named_relay_address(0))).unwrap();
+    await!(config1.add_relay(named_relay_address(1))).unwrap();
+
+    await!(advance_time(40, &mut tick_sender, &test_executor));
+
+    await!(config0.add_friend(
+        node_public_key(1),
+        vec![relay_address(1)],
+        String::from("node1"),
+        100
+    ))
+    .unwrap();
+    await!(config1.add_friend(
+        node_public_key(0),
+        vec![relay_address(0)],
+        String::from("node0"),
+        -100
+    ))
+    .unwrap();
+
+    await!(config0.enable_friend(node_public_key(1))).unwrap();
+    await!(config1.enable_friend(node_public_key(0))).unwrap();
+
+    await!(advance_time(40, &mut tick_sender, &test_executor));
+
+    assert!(await!(is_friend_online(&mut report0, 1)));
+    assert!(await!(is_friend_online(&mut report1, 0)));
+
+    // The friends are idle for much longer than the liveness interval. Keepalives are sent
+    // according to the short interval, hence both friends stay online:
+    await!(advance_time(
+        4 * KEEPALIVE_TICKS,
+        &mut tick_sender,
+        &test_executor
+    ));
+
+    assert!(await!(is_friend_online(&mut report0, 1)));
+    assert!(await!(is_friend_online(&mut report1, 0)));
+}
+
+#[test]
+fn test_friend_liveness_short_interval() {
+    let test_executor = TestExecutor::new();
+    let res = test_executor.run(task_friend_liveness_short_interval(test_executor.clone()));
+    assert!(res.is_output());
+}
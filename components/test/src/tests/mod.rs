@@ -1,3 +1,4 @@
+mod friend_liveness;
 mod nodes_chain;
 mod relay_migration;
 mod resolve_inconsistency;
@@ -388,7 +388,8 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
         request_id.clone(),
         chosen_route,
         invoice_id,
-        dest_payment
+        dest_payment,
+        Vec::new()
     ))
     .unwrap();
     await!(apps[0]
@@ -422,7 +423,8 @@ async fn task_nodes_chain(mut test_executor: TestExecutor) {
         request_id.clone(),
         chosen_route,
         invoice_id,
-        dest_payment
+        dest_payment,
+        Vec::new()
     ))
     .unwrap();
     await!(apps[5]
@@ -260,18 +260,30 @@ async fn task_two_nodes_payment(mut test_executor: TestExecutor) {
     assert_eq!(chosen_route_with_capacity.capacity, 100);
     let chosen_route = chosen_route_with_capacity.route;
 
+    // Node1 listens to the funds it receives:
+    let mut funds_received1 = await!(send_funds1.incoming_funds_received()).unwrap();
+
     let request_id = Uid::from(&[0x0; UID_LEN]);
     let invoice_id = InvoiceId::from(&[0; INVOICE_ID_LEN]);
     let dest_payment = 10;
     let receipt = await!(send_funds0.request_send_funds(
         request_id.clone(),
         chosen_route,
-        invoice_id,
-        dest_payment
+        invoice_id.clone(),
+        dest_payment,
+        b"memo".to_vec()
     ))
     .unwrap();
     await!(send_funds0.receipt_ack(request_id, receipt.clone())).unwrap();
 
+    // Node1 received the payment, together with the memo:
+    let funds_received = await!(funds_received1.next()).unwrap();
+    assert_eq!(funds_received.request_id, request_id);
+    assert_eq!(funds_received.invoice_id, invoice_id);
+    assert_eq!(funds_received.dest_payment, dest_payment);
+    assert_eq!(funds_received.memo, b"memo".to_vec());
+    drop(funds_received1);
+
     // Node0 allows node1 to have maximum debt of 100
     // (This should allow to node1 to pay back).
     await!(config0.set_friend_remote_max_debt(node_public_key(1), 100)).unwrap();
@@ -295,7 +307,8 @@ async fn task_two_nodes_payment(mut test_executor: TestExecutor) {
         request_id,
         chosen_route.clone(),
         invoice_id.clone(),
-        dest_payment
+        dest_payment,
+        Vec::new()
     ))
     .unwrap();
     await!(send_funds1.receipt_ack(request_id, receipt.clone())).unwrap();
@@ -309,7 +322,8 @@ async fn task_two_nodes_payment(mut test_executor: TestExecutor) {
         request_id,
         chosen_route.clone(),
         invoice_id,
-        dest_payment
+        dest_payment,
+        Vec::new()
     ));
     assert!(res.is_err());
 }
@@ -14,19 +14,21 @@ use crypto::test_utils::DummyRandom;
 use common::test_executor::TestExecutor;
 
 use proto::app_server::messages::{AppPermissions, NamedRelayAddress, RelayAddress};
-use proto::consts::{KEEPALIVE_TICKS, MAX_NODE_RELAYS, MAX_OPERATIONS_IN_BATCH, TICKS_TO_REKEY};
+use proto::consts::{
+    KEEPALIVE_TICKS, MAX_NODE_RELAYS, MAX_OPERATIONS_IN_BATCH, MAX_SEND_FRIENDS, TICKS_TO_REKEY,
+};
 use proto::index_server::messages::NamedIndexServerAddress;
 use proto::net::messages::NetAddress;
 
 use identity::{create_identity, IdentityClient};
 
 use node::connect::{node_connect, NodeConnection};
-use node::{net_node, NodeConfig, NodeState};
+use node::{net_node, ControlStats, NodeConfig, NodeState};
 
 use database::file_db::FileDb;
 
 use index_server::net_index_server;
-use relay::net_relay_server;
+use relay::{net_relay_server, FrameLimits};
 
 use timer::TimerClient;
 
@@ -41,6 +43,14 @@ const BACKOFF_TICKS: usize = 0x8;
 const MAX_CONCURRENT_ENCRYPT: usize = 0x8;
 /// The size we allocate for the user send funds requests queue.
 const MAX_PENDING_USER_REQUESTS: usize = 0x20;
+/// Maximum amount of requests we queue for a friend while waiting to forward them.
+const MAX_PENDING_REQUESTS: usize = 0x40;
+/// Log and count duplicate move tokens received from friends
+const MONITOR_DUPLICATE_MOVE_TOKENS: bool = false;
+/// Reject payment requests that reuse an invoice id of an in flight request
+const REJECT_DUPLICATE_INVOICE_ID: bool = false;
+/// Amount of acked receipts we keep, so that they can be retrieved again
+const MAX_RECENT_RECEIPTS: usize = 0x20;
 /// Maximum amount of concurrent index client requests:
 const MAX_OPEN_INDEX_CLIENT_REQUESTS: usize = 0x8;
 /// The amount of ticks we are willing to wait until a connection is established (Through
@@ -118,7 +128,7 @@ fn get_relay_identity(index: u8) -> impl Identity {
     gen_identity(&rng)
 }
 
-fn default_node_config() -> NodeConfig {
+pub fn default_node_config() -> NodeConfig {
     NodeConfig {
         /// Memory allocated to a channel in memory (Used to connect two components)
         channel_len: CHANNEL_LEN,
@@ -126,18 +136,30 @@ fn default_node_config() -> NodeConfig {
         backoff_ticks: BACKOFF_TICKS,
         /// The amount of ticks we wait until we decide an idle connection has timed out.
         keepalive_ticks: KEEPALIVE_TICKS,
+        /// The amount of ticks a friend connection may stay idle before we consider the friend
+        /// offline.
+        liveness_ticks: KEEPALIVE_TICKS,
         /// Amount of ticks to wait until the next rekeying (Channel encryption)
         ticks_to_rekey: TICKS_TO_REKEY,
         /// Maximum amount of encryption set ups (diffie hellman) that we allow to occur at the same
         /// time.
         max_concurrent_encrypt: MAX_CONCURRENT_ENCRYPT,
+        opt_max_concurrent_encrypt_per_relay: None,
         /// The amount of ticks we are willing to wait until a connection is established (Through
         /// the relay)
         conn_timeout_ticks: CONN_TIMEOUT_TICKS,
         /// Maximum amount of operations in one move token message
         max_operations_in_batch: MAX_OPERATIONS_IN_BATCH,
+        /// Maximum amount of friends we send messages to while handling a single event
+        max_send_friends: MAX_SEND_FRIENDS,
         /// The size we allocate for the user send funds requests queue.
         max_pending_user_requests: MAX_PENDING_USER_REQUESTS,
+        max_pending_requests: MAX_PENDING_REQUESTS,
+        monitor_duplicate_move_tokens: MONITOR_DUPLICATE_MOVE_TOKENS,
+        /// Reject payment requests that reuse an invoice id of an in flight request
+        reject_duplicate_invoice_id: REJECT_DUPLICATE_INVOICE_ID,
+        /// Amount of acked receipts we keep, so that they can be retrieved again
+        max_recent_receipts: MAX_RECENT_RECEIPTS,
         /// Maximum amount of concurrent index client requests:
         max_open_index_client_requests: MAX_OPEN_INDEX_CLIENT_REQUESTS,
         /// Maximum amount of relays a node may use.
@@ -254,11 +276,35 @@ where
 }
 
 pub async fn create_node<S>(
+    index: u8,
+    sim_db: SimDb,
+    timer_client: TimerClient,
+    sim_network_client: SimNetworkClient,
+    trusted_apps: HashMap<u8, AppPermissions>,
+    spawner: S,
+) -> RemoteHandle<()>
+where
+    S: Spawn + Send + Sync + Clone + 'static,
+{
+    await!(create_node_with_config(
+        index,
+        sim_db,
+        timer_client,
+        sim_network_client,
+        trusted_apps,
+        default_node_config(),
+        spawner
+    ))
+}
+
+/// Create a node using the given node configuration
+pub async fn create_node_with_config<S>(
     index: u8,
     sim_db: SimDb,
     timer_client: TimerClient,
     mut sim_network_client: SimNetworkClient,
     trusted_apps: HashMap<u8, AppPermissions>,
+    node_config: NodeConfig,
     mut spawner: S,
 ) -> RemoteHandle<()>
 where
@@ -285,9 +331,12 @@ where
         timer_client,
         identity_client,
         rng,
-        default_node_config(),
+        node_config,
         get_trusted_apps,
         sim_db.load_db(index),
+        ControlStats::new(),
+        None,
+        None,
         spawner.clone(), // trusted_apps_spawner
         spawner.clone(), // database_spawner
         spawner.clone(),
@@ -371,6 +420,8 @@ pub async fn create_relay<S>(
         timer_client,
         rng,
         MAX_CONCURRENT_ENCRYPT,
+        None,
+        FrameLimits::default(),
         spawner.clone(),
     )
     .map_err(|e| error!("net_relay_server() error: {:?}", e))